@@ -0,0 +1,117 @@
+use crate::dirs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// A backup dbdm made of a destination
+//
+// Backups can be put in any directory from the sync prompt, so their name
+// alone can't tell where they came from.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Record {
+    // The destination that was backed up
+    pub original: PathBuf,
+    // Where it was moved to
+    pub backup: PathBuf,
+    // When the sync making it started, in seconds since the epoch
+    pub time: u64,
+    // The id of that sync in the history, None if it couldn't be recorded
+    pub run: Option<u64>,
+}
+
+// Helper to get the file the registry is kept in
+//
+// @return Result<PathBuf> - `backups.jsonl` in the state directory
+pub fn registry_path() -> std::io::Result<PathBuf> {
+    dirs::backup_registry()
+}
+
+// Adds backups to the registry
+//
+// @param records: &[Record] - the backups that were made
+// @return Result<()> - an error if the registry can't be written
+pub fn record(records: &[Record]) -> std::io::Result<()> {
+    if records.is_empty() {
+        return Ok(());
+    }
+    let path = registry_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    let lines: String = records
+        .iter()
+        .map(|record| format!("{}\n", to_json(record)))
+        .collect();
+    file.write_all(lines.as_bytes())
+}
+
+// Reads every backup in the registry
+//
+// Lines that can't be read, e.g. from an interrupted write, are left out.
+//
+// @return Result<Vec<Record>> - the backups, oldest first, empty if none were recorded yet
+pub fn load() -> std::io::Result<Vec<Record>> {
+    let contents = match std::fs::read_to_string(registry_path()?) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .filter_map(|json| from_json(&json))
+        .collect())
+}
+
+// Drops backups from the registry, once they were removed
+//
+// @param backups: &[PathBuf] - the paths of the backups to drop
+// @return Result<()> - an error if the registry can't be rewritten
+pub fn forget(backups: &[PathBuf]) -> std::io::Result<()> {
+    let records = load()?;
+    let kept: Vec<&Record> = records
+        .iter()
+        .filter(|record| !backups.contains(&record.backup))
+        .collect();
+    if kept.len() == records.len() {
+        return Ok(());
+    }
+    let lines: String = kept
+        .iter()
+        .map(|record| format!("{}\n", to_json(record)))
+        .collect();
+    std::fs::write(registry_path()?, lines)
+}
+
+// Helper to find the recorded backups of a destination
+//
+// @param records: &[Record] - the registry
+// @param original: &Path - the destination
+// @return impl Iterator<Item = &Record> - its backups, oldest first
+pub fn of<'a>(records: &'a [Record], original: &'a Path) -> impl Iterator<Item = &'a Record> {
+    records
+        .iter()
+        .filter(move |record| record.original == original)
+}
+
+fn to_json(record: &Record) -> serde_json::Value {
+    serde_json::json!({
+        "original": record.original.display().to_string(),
+        "backup": record.backup.display().to_string(),
+        "time": record.time,
+        "run": record.run,
+    })
+}
+
+fn from_json(json: &serde_json::Value) -> Option<Record> {
+    let path = |name: &str| Some(PathBuf::from(json.get(name)?.as_str()?));
+    Some(Record {
+        original: path("original")?,
+        backup: path("backup")?,
+        time: json.get("time")?.as_u64()?,
+        run: json.get("run").and_then(|run| run.as_u64()),
+    })
+}
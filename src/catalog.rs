@@ -0,0 +1,148 @@
+use std::ffi::OsStr;
+
+// Where a well-known application reads its config, per OS
+//
+// Destinations are written with keywords, the way they go into a config line.
+// None means the application doesn't run on that OS or keeps its config
+// somewhere no keyword stands for.
+pub struct App {
+    // The name of the file or directory in the dotfiles repository, without a leading dot
+    pub name: &'static str,
+    // Linux and the BSDs
+    pub unix: Option<&'static str>,
+    pub macos: Option<&'static str>,
+    pub windows: Option<&'static str>,
+}
+
+impl App {
+    // Helper to pick the destination for an OS
+    //
+    // @param os: &str - the OS, as in `std::env::consts::OS`
+    // @return Option<&'static str> - the destination, None if the app has none there
+    pub fn destination(&self, os: &str) -> Option<&'static str> {
+        match os {
+            "macos" => self.macos,
+            "windows" => self.windows,
+            _ => self.unix,
+        }
+    }
+}
+
+// Helper to describe an app that reads the same path everywhere it runs
+const fn everywhere(name: &'static str, dest: &'static str) -> App {
+    App {
+        name,
+        unix: Some(dest),
+        macos: Some(dest),
+        windows: Some(dest),
+    }
+}
+
+// Helper to describe an app that runs on Linux and macOS with the same path
+const fn unix_and_macos(name: &'static str, dest: &'static str) -> App {
+    App {
+        name,
+        unix: Some(dest),
+        macos: Some(dest),
+        windows: None,
+    }
+}
+
+// Helper to describe an app that only runs on Linux and the BSDs
+const fn unix_only(name: &'static str, dest: &'static str) -> App {
+    App {
+        name,
+        unix: Some(dest),
+        macos: None,
+        windows: None,
+    }
+}
+
+// Helper to describe an app that only runs on macOS
+const fn macos_only(name: &'static str, dest: &'static str) -> App {
+    App {
+        name,
+        unix: None,
+        macos: Some(dest),
+        windows: None,
+    }
+}
+
+// The known applications, by the name their config has in a dotfiles repository
+pub const APPS: &[App] = &[
+    // Shells
+    unix_and_macos("zshrc", "!home/.zshrc"),
+    unix_and_macos("zshenv", "!home/.zshenv"),
+    unix_and_macos("zprofile", "!home/.zprofile"),
+    unix_and_macos("bashrc", "!home/.bashrc"),
+    unix_and_macos("bash_profile", "!home/.bash_profile"),
+    unix_and_macos("profile", "!home/.profile"),
+    unix_and_macos("inputrc", "!home/.inputrc"),
+    unix_and_macos("fish", "!xdg_conf/fish"),
+    unix_and_macos("nushell", "!app_support/nushell"),
+    everywhere("starship.toml", "!xdg_conf/starship.toml"),
+    // Terminals and multiplexers
+    unix_and_macos("alacritty", "!xdg_conf/alacritty"),
+    unix_and_macos("kitty", "!xdg_conf/kitty"),
+    unix_and_macos("wezterm", "!xdg_conf/wezterm"),
+    unix_and_macos("ghostty", "!xdg_conf/ghostty"),
+    unix_and_macos("foot", "!xdg_conf/foot"),
+    unix_and_macos("tmux", "!xdg_conf/tmux"),
+    unix_and_macos("tmux.conf", "!home/.tmux.conf"),
+    unix_and_macos("zellij", "!xdg_conf/zellij"),
+    // Editors
+    unix_and_macos("nvim", "!xdg_conf/nvim"),
+    unix_and_macos("helix", "!xdg_conf/helix"),
+    unix_and_macos("vimrc", "!home/.vimrc"),
+    unix_and_macos("vim", "!home/.vim"),
+    unix_and_macos("emacs.d", "!home/.emacs.d"),
+    // Tools
+    everywhere("gitconfig", "!home/.gitconfig"),
+    unix_and_macos("git", "!xdg_conf/git"),
+    App {
+        name: "lazygit",
+        unix: Some("!xdg_conf/lazygit"),
+        macos: Some("!app_support/lazygit"),
+        windows: None,
+    },
+    unix_and_macos("bat", "!xdg_conf/bat"),
+    unix_and_macos("btop", "!xdg_conf/btop"),
+    unix_and_macos("htop", "!xdg_conf/htop"),
+    unix_and_macos("ripgreprc", "!home/.ripgreprc"),
+    unix_and_macos("yazi", "!xdg_conf/yazi"),
+    unix_and_macos("mpv", "!xdg_conf/mpv"),
+    // Linux desktops
+    unix_only("i3", "!xdg_conf/i3"),
+    unix_only("sway", "!xdg_conf/sway"),
+    unix_only("hypr", "!xdg_conf/hypr"),
+    unix_only("waybar", "!xdg_conf/waybar"),
+    unix_only("rofi", "!xdg_conf/rofi"),
+    unix_only("wofi", "!xdg_conf/wofi"),
+    unix_only("dunst", "!xdg_conf/dunst"),
+    unix_only("mako", "!xdg_conf/mako"),
+    unix_only("picom", "!xdg_conf/picom"),
+    unix_only("polybar", "!xdg_conf/polybar"),
+    unix_only("fontconfig", "!xdg_conf/fontconfig"),
+    unix_only("Xresources", "!home/.Xresources"),
+    // macOS window managers and keyboards
+    macos_only("aerospace.toml", "!home/.aerospace.toml"),
+    macos_only("yabairc", "!home/.yabairc"),
+    macos_only("skhdrc", "!home/.skhdrc"),
+    macos_only("karabiner", "!xdg_conf/karabiner"),
+    macos_only("hammerspoon", "!home/.hammerspoon"),
+];
+
+// Looks up where a file or directory of a dotfiles repository is usually linked to
+//
+// A leading dot is ignored, so `.zshrc` and `zshrc` are both found.
+//
+// @param name: &OsStr - the file name in the repository
+// @param os: &str - the OS, as in `std::env::consts::OS`
+// @return Option<&'static str> - the destination with keywords, None if the name isn't known there
+pub fn destination(name: &OsStr, os: &str) -> Option<&'static str> {
+    let name = name.to_str()?;
+    let name = name.strip_prefix('.').unwrap_or(name);
+    APPS.iter()
+        .find(|app| app.name == name)
+        .and_then(|app| app.destination(os))
+}
@@ -0,0 +1,226 @@
+use crate::commands::{RunMode, print_shared_sources, shared_sources};
+use crate::config_parser::{Config, Link, LinkKind, Secrets};
+use crate::output::{Color, paint};
+use crate::state::{LinkState, link_state_cached, points_directly};
+use crate::{
+    CanonicalCache, FsSnapshot, app_println, copies, parallel_map, permissions, replace_link,
+    resolve_link_destination, secrets,
+};
+use std::path::PathBuf;
+use tracing::error;
+
+// One of the command handlers
+// Allows to check if the current state of the system matches
+// the desired state that is specified in the provided config
+//
+// With `fix`, only the mismatches that can't lose data are repaired: missing
+// destinations, broken symlinks and symlinks to the wrong target. Real files
+// and directories are never touched, and neither are `system` links, which
+// change files outside $HOME and are only repaired by `sync` once confirmed.
+//
+// A symlink that reaches the source through other symlinks is reported as
+// indirectly linked. With `strict_target` it is a mismatch instead, which
+// `fix` repairs by linking straight to the source. Sources linked to several
+// destinations are listed at the end.
+//
+// @param config: &Config - the parsed config state
+// @param fix: bool - if safe mismatches should be repaired
+// @param strict_target: bool - if the symlink has to name the source itself
+pub fn check(config: &Config, mode: &RunMode, fix: bool, strict_target: bool) {
+    // Every link is looked at first, in parallel, then reported and fixed in order
+    let cache = CanonicalCache::default();
+    let findings = parallel_map(&config.links, |link| {
+        (link.kind != LinkKind::Encrypted).then(|| inspect_link(link, &cache, strict_target))
+    });
+
+    for (link, finding) in config.links.iter().zip(findings) {
+        let Some(finding) = finding else {
+            check_encrypted(link, &config.secrets, mode, fix);
+            continue;
+        };
+        let CheckFinding {
+            line,
+            resolved_to,
+            label,
+            color,
+            safe,
+            permissions,
+            copy,
+            ..
+        } = finding;
+
+        // `sync` asks before changing files outside $HOME, so `check` leaves them to it
+        let system = link.kind == LinkKind::System;
+        if fix && safe && !system {
+            let fixed = match copy {
+                Some(_) => copies::install(&link.from, &resolved_to),
+                None => replace_link(&link.from, &resolved_to),
+            };
+            match fixed {
+                Ok(()) => {
+                    app_println!(
+                        mode,
+                        "{}",
+                        paint(&format!("{} (fixed)", line), Color::Green)
+                    );
+                }
+                Err(err) => {
+                    error!("{}: {}", resolved_to.display(), err);
+                    app_println!(mode, "{}", paint(&line, color));
+                }
+            }
+            continue;
+        }
+
+        match label {
+            Some(label) => app_println!(mode, "{}", paint(&format!("{} ({})", line, label), color)),
+            None => app_println!(mode, "{}", paint(&line, color)),
+        }
+        if fix && safe && system {
+            app_println!(
+                mode,
+                "  {}",
+                paint(
+                    "not fixed, system links are only changed by sync",
+                    Color::Yellow
+                )
+            );
+        }
+
+        match permissions {
+            Ok(found) => {
+                for mismatch in found {
+                    app_println!(mode, "  {}", paint(&mismatch, Color::Red));
+                }
+            }
+            Err(err) => error!(
+                "Failed to read permissions of {}: {}",
+                link.from.display(),
+                err
+            ),
+        }
+    }
+
+    print_shared_sources(
+        mode,
+        &shared_sources(config.links.iter().map(|link| link.from.as_path())),
+    );
+}
+
+// What `check` found for a single link, before anything is fixed
+pub struct CheckFinding {
+    // `<from> -> <to>`, both canonicalized
+    pub line: String,
+    pub resolved_to: PathBuf,
+    pub state: LinkState,
+    // Where the copy at the destination stands, if dbdm copied instead of linking
+    pub copy: Option<copies::State>,
+    pub label: Option<&'static str>,
+    pub color: Color,
+    // If `--fix` can replace the destination without losing anything
+    pub safe: bool,
+    pub permissions: std::io::Result<Vec<String>>,
+}
+
+// Helper to look at a single link without changing anything
+//
+// @param link: &Link - the link to look at
+// @param cache: &CanonicalCache - shared between the links, they often have directories in common
+// @param strict_target: bool - if a symlink that reaches the source through others is a mismatch
+// @return CheckFinding - how the destination compares to the source
+pub fn inspect_link(link: &Link, cache: &CanonicalCache, strict_target: bool) -> CheckFinding {
+    let from_full = cache.canonicalize_or_fallback(&link.from);
+    let resolved_to = match resolve_link_destination(&link.from, &link.to) {
+        Ok(path) => path,
+        Err(_) => link.to.clone(),
+    };
+    let to_full = cache.canonicalize_or_fallback(&resolved_to);
+
+    let line = format!("{} -> {}", from_full.display(), to_full.display());
+    let state = link_state_cached(link, cache);
+    // Copies made because symlinks aren't supported there are compared by hash
+    let copy = match state {
+        LinkState::RegularFileConflict | LinkState::DirectoryConflict => {
+            copies::state(&link.from, &resolved_to).ok().flatten()
+        }
+        _ => None,
+    };
+    let indirect = state == LinkState::LinkedCorrectly
+        && !points_directly(&FsSnapshot::capture(&resolved_to), &link.from);
+    let (label, color) = match copy {
+        Some(copies::State::Stale) => (Some("stale copy"), Color::Yellow),
+        Some(copies::State::Modified) => (Some("modified copy"), Color::Red),
+        Some(copies::State::Diverged) => (Some("diverged copy"), Color::Red),
+        Some(_) => (Some("copy"), Color::Green),
+        None => match state {
+            LinkState::LinkedCorrectly if indirect && strict_target => {
+                (Some("indirect"), Color::Red)
+            }
+            LinkState::LinkedCorrectly if indirect => (Some("indirectly linked"), Color::Green),
+            LinkState::LinkedCorrectly => (None, Color::Green),
+            LinkState::BrokenSymlink => (Some("broken"), Color::Yellow),
+            LinkState::SourceMissing => (Some("source missing"), Color::Red),
+            LinkState::WrongTarget(_)
+            | LinkState::Missing
+            | LinkState::RegularFileConflict
+            | LinkState::DirectoryConflict => (None, Color::Red),
+        },
+    };
+
+    CheckFinding {
+        line,
+        resolved_to,
+        label,
+        color,
+        safe: state.is_safe_to_replace()
+            || copy == Some(copies::State::Stale)
+            || (indirect && strict_target),
+        state,
+        copy,
+        permissions: permissions::mismatches(&link.from, &link.options),
+    }
+}
+
+// Helper to check a single encrypted link
+//
+// Green if the destination was decrypted from the current source, yellow
+// `(stale)` if the source changed since, red if it's missing or was changed.
+// With `--fix`, missing and stale destinations are decrypted again.
+//
+// @param link: &Link - the encrypted link
+// @param secrets: &Secrets - the keys to decrypt with
+// @param fix: bool - if missing and stale destinations should be decrypted
+fn check_encrypted(link: &Link, secrets: &Secrets, mode: &RunMode, fix: bool) {
+    let line = format!("{} -> {}", link.from.display(), link.to.display());
+    let (label, color, safe) = match secrets::state(&link.from, &link.to) {
+        Ok(secrets::State::UpToDate) => (None, Color::Green, false),
+        Ok(secrets::State::Stale) => (Some("stale"), Color::Yellow, true),
+        Ok(secrets::State::Missing) => (None, Color::Red, true),
+        Ok(secrets::State::Modified | secrets::State::Diverged) => {
+            (Some("modified"), Color::Red, false)
+        }
+        Err(err) => {
+            error!("{}: {}", link.to.display(), err);
+            (None, Color::Red, false)
+        }
+    };
+
+    if fix && safe {
+        match secrets::install(&link.from, &link.to, secrets) {
+            Ok(()) => {
+                app_println!(
+                    mode,
+                    "{}",
+                    paint(&format!("{} (fixed)", line), Color::Green)
+                );
+                return;
+            }
+            Err(err) => error!("{}: {}", link.to.display(), err),
+        }
+    }
+
+    match label {
+        Some(label) => app_println!(mode, "{}", paint(&format!("{} ({})", line, label), color)),
+        None => app_println!(mode, "{}", paint(&line, color)),
+    }
+}
@@ -0,0 +1,242 @@
+use crate::commands::RunMode;
+use crate::config_parser::{Config, Retention};
+use crate::{
+    BackupNaming, app_println, backups, default_backup_dir, is_backup_name, remove_existing,
+    resolve_link_destination,
+};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tracing::{debug, warn};
+
+// The length of the `YYYYMMDDTHHMM` stamp in backups named by timestamp
+const STAMP_LEN: usize = 13;
+const SECS_PER_DAY: u64 = 24 * 60 * 60;
+
+// A backup of a destination, found next to the source of its link or in the backup directory
+#[derive(Debug, PartialEq)]
+pub struct Backup {
+    pub path: PathBuf,
+    // When the backup was made
+    pub made: SystemTime,
+}
+
+// One of the command handlers
+// Removes the backups that are past the retention limits, for every link of
+// the config. Backups are looked for next to the sources and in the backup
+// directory of dbdm, where sync puts them depending on `location`, and in the
+// backup registry, for the ones moved elsewhere from the prompt.
+//
+// @param config: &Config - the parsed config state
+// @param retention: &Retention - the limits to apply
+// @param naming: &BackupNaming - how backups are named
+// @param dry_run: bool - if the backups are only listed, not removed
+// @return Vec<PathBuf> - the backups that were removed, or would be with `dry_run`
+pub fn clean(
+    config: &Config,
+    mode: &RunMode,
+    retention: &Retention,
+    naming: &BackupNaming,
+    dry_run: bool,
+) -> Vec<PathBuf> {
+    let registry = backups::load().unwrap_or_else(|err| {
+        warn!("Could not read the backup registry: {}", err);
+        Vec::new()
+    });
+    let mut removed = Vec::new();
+    for link in &config.links {
+        let dest = match resolve_link_destination(&link.from, &link.to) {
+            Ok(dest) => dest,
+            Err(err) => {
+                debug!(to = %link.to.display(), %err, "can't resolve, no backups to clean");
+                continue;
+            }
+        };
+        for path in prune(&link.from, &dest, &registry, retention, naming, dry_run) {
+            // Links sharing a source directory and a destination name share the backups next to it
+            if removed.contains(&path) {
+                continue;
+            }
+            let verb = if dry_run { "Would remove" } else { "Removed" };
+            app_println!(mode, "{} {}", verb, path.display());
+            removed.push(path);
+        }
+    }
+    removed
+}
+
+// Removes the backups of one destination that are past the retention limits
+//
+// Backups that can't be removed are warned about and left in place. The
+// removed ones are dropped from the registry.
+//
+// @param from: &Path - the source of the link
+// @param dest: &Path - the resolved destination
+// @param registry: &[backups::Record] - the recorded backups
+// @param retention: &Retention - the limits to apply
+// @param naming: &BackupNaming - how backups are named
+// @param dry_run: bool - if the backups are only listed, not removed
+// @return Vec<PathBuf> - the backups that were removed, or would be with `dry_run`
+pub fn prune(
+    from: &Path,
+    dest: &Path,
+    registry: &[backups::Record],
+    retention: &Retention,
+    naming: &BackupNaming,
+    dry_run: bool,
+) -> Vec<PathBuf> {
+    let backups = match backups_of(from, dest, registry, naming) {
+        Ok(backups) => backups,
+        Err(err) => {
+            warn!("Could not look for backups of {}: {}", dest.display(), err);
+            return Vec::new();
+        }
+    };
+
+    let mut removed = Vec::new();
+    for backup in expired(&backups, retention, SystemTime::now()) {
+        if !dry_run && let Err(err) = remove_existing(&backup.path) {
+            warn!("Could not remove {}: {}", backup.path.display(), err);
+            continue;
+        }
+        removed.push(backup.path.clone());
+    }
+    if !dry_run && let Err(err) = backups::forget(&removed) {
+        warn!("Could not update the backup registry: {}", err);
+    }
+    removed
+}
+
+// Finds the backups sync made of a destination
+//
+// Backups are looked for next to the source and in the backup directory of
+// dbdm. Recorded backups that no longer exist are left out. For the ones that
+// do, the recorded time is used, as moving a file can change its ctime.
+//
+// @param from: &Path - the source of the link, backups may be next to it
+// @param dest: &Path - the resolved destination
+// @param registry: &[backups::Record] - the recorded backups
+// @param naming: &BackupNaming - how backups are named
+// @return Result<Vec<Backup>> - the backups, newest first
+pub fn backups_of(
+    from: &Path,
+    dest: &Path,
+    registry: &[backups::Record],
+    naming: &BackupNaming,
+) -> std::io::Result<Vec<Backup>> {
+    let mut backups: Vec<Backup> = backups::of(registry, dest)
+        .filter(|record| std::fs::symlink_metadata(&record.backup).is_ok())
+        .map(|record| Backup {
+            path: record.backup.clone(),
+            made: UNIX_EPOCH + Duration::from_secs(record.time),
+        })
+        .collect();
+
+    let Some(base) = dest.file_name() else {
+        return Ok(sorted(backups));
+    };
+    // Both places are looked in, so changing `location` doesn't leave backups behind
+    let mut dirs = vec![default_backup_dir(from)];
+    match crate::dirs::backup_dir_for(dest) {
+        Ok(dir) => dirs.push(dir),
+        Err(err) => debug!(%err, "no backup directory to look in"),
+    }
+    for dir in dirs {
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+
+        for entry in entries {
+            let entry = entry?;
+            if !is_backup_of(&entry.file_name(), base, naming)
+                || backups.iter().any(|backup| backup.path == entry.path())
+            {
+                continue;
+            }
+            let meta = std::fs::symlink_metadata(entry.path())?;
+            backups.push(Backup {
+                path: entry.path(),
+                made: crate::platform::changed_time(&meta),
+            });
+        }
+    }
+    Ok(sorted(backups))
+}
+
+// Helper to order backups newest first
+fn sorted(mut backups: Vec<Backup>) -> Vec<Backup> {
+    backups.sort_by(|a, b| b.made.cmp(&a.made).then_with(|| b.path.cmp(&a.path)));
+    backups
+}
+
+// Helper to pick the backups that are past the retention limits
+//
+// @param backups: &[Backup] - the backups of one destination, newest first
+// @param retention: &Retention - the limits to apply
+// @param now: SystemTime - the time the ages are taken at
+// @return Vec<&Backup> - the backups to remove
+pub fn expired<'a>(
+    backups: &'a [Backup],
+    retention: &Retention,
+    now: SystemTime,
+) -> Vec<&'a Backup> {
+    let max_age = retention
+        .keep_days
+        .map(|days| Duration::from_secs(days.saturating_mul(SECS_PER_DAY)));
+    backups
+        .iter()
+        .enumerate()
+        .filter(|(idx, backup)| {
+            let too_many = retention.keep.is_some_and(|keep| *idx >= keep);
+            let too_old = max_age.is_some_and(|max_age| {
+                now.duration_since(backup.made)
+                    .is_ok_and(|age| age > max_age)
+            });
+            too_many || too_old
+        })
+        .map(|(_, backup)| backup)
+        .collect()
+}
+
+// Helper to tell if a file name is a backup of a destination named `base`
+//
+// Backups are named `<base><suffix>` or `<base>.<timestamp><suffix>`, either
+// optionally followed by `.N`. Both are matched, whatever the current naming.
+//
+// @param name: &OsStr - the file name to check
+// @param base: &OsStr - the file name of the destination
+// @param naming: &BackupNaming - how backups are named
+// @return bool - if the name is one of the backups of `base`
+fn is_backup_of(name: &OsStr, base: &OsStr, naming: &BackupNaming) -> bool {
+    if !is_backup_name(name, naming) {
+        return false;
+    }
+    let Some(rest) = name
+        .as_encoded_bytes()
+        .strip_prefix(base.as_encoded_bytes())
+    else {
+        return false;
+    };
+
+    let rest = rest
+        .strip_prefix(b".")
+        .filter(|stamped| stamped.len() >= STAMP_LEN && is_stamp(&stamped[..STAMP_LEN]))
+        .map_or(rest, |stamped| &stamped[STAMP_LEN..]);
+    let Some(counter) = rest.strip_prefix(naming.suffix.as_bytes()) else {
+        return false;
+    };
+    counter.is_empty()
+        || counter
+            .strip_prefix(b".")
+            .is_some_and(|n| !n.is_empty() && n.iter().all(u8::is_ascii_digit))
+}
+
+// Helper to tell if bytes are a `YYYYMMDDTHHMM` stamp
+fn is_stamp(bytes: &[u8]) -> bool {
+    bytes.iter().enumerate().all(|(idx, byte)| match idx {
+        8 => *byte == b'T',
+        _ => byte.is_ascii_digit(),
+    })
+}
@@ -0,0 +1,127 @@
+pub mod check;
+pub mod clean;
+pub mod present;
+pub mod sync;
+
+use crate::output::plural;
+use std::path::{Path, PathBuf};
+
+// How a command talks to the user
+pub struct RunMode {
+    // Prompts are read from stdin, false with `--non-interactive`
+    pub interactive: bool,
+    // Stdout carries one JSON event per line instead of text
+    pub ndjson: bool,
+    // How many lines of a file are shown in conflict previews
+    pub preview_lines: usize,
+    // How many prompts were answered so far
+    pub answers: std::cell::Cell<usize>,
+    // How much of a sync is printed as text
+    pub detail: Detail,
+}
+
+// How much of a sync is printed, prompts are always shown
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Detail {
+    // Plans, conflict previews, outcomes and the final numbers
+    #[default]
+    Full,
+    // Only the final counts, from `--summary`
+    Summary,
+    // Nothing but errors, from `--quiet`
+    Quiet,
+}
+
+impl RunMode {
+    pub fn prints_text(&self) -> bool {
+        !self.ndjson
+    }
+
+    // If plans, previews and other per-item listings are printed
+    pub fn lists_items(&self) -> bool {
+        self.prints_text() && self.detail == Detail::Full
+    }
+
+    // If the final counts of a sync are printed
+    pub fn prints_summary(&self) -> bool {
+        self.prints_text() && self.detail != Detail::Quiet
+    }
+}
+
+// Helper to write a single NDJSON event to stdout, if that format is selected
+//
+// Every event gets its name and a timestamp in milliseconds since the epoch.
+//
+// @param event: &str - the name of the event
+// @param fields: serde_json::Value - the event specific fields, as a JSON object
+pub fn emit(mode: &RunMode, event: &str, mut fields: serde_json::Value) {
+    if !mode.ndjson {
+        return;
+    }
+
+    let ts = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|time| time.as_millis())
+        .unwrap_or(0);
+    fields["event"] = serde_json::json!(event);
+    fields["ts"] = serde_json::json!(ts);
+
+    println!("{}", fields);
+    let _ = std::io::Write::flush(&mut std::io::stdout());
+}
+
+#[macro_export]
+macro_rules! app_println {
+    ($mode:expr, $($arg:tt)*) => {
+        if $mode.prints_text() {
+            println!($($arg)*);
+        }
+    };
+}
+
+#[macro_export]
+macro_rules! app_print {
+    ($mode:expr, $($arg:tt)*) => {
+        if $mode.prints_text() {
+            print!($($arg)*);
+        }
+    };
+}
+
+// Helper to find the sources that are linked to more than one destination
+//
+// Sources are compared canonicalized, so one file written two ways counts once.
+//
+// @param sources: impl Iterator<Item = &Path> - the source of every link
+// @return Vec<(PathBuf, usize)> - every shared source and its number of destinations, in the order they first appear
+pub fn shared_sources<'a>(sources: impl Iterator<Item = &'a Path>) -> Vec<(PathBuf, usize)> {
+    let cache = crate::CanonicalCache::default();
+    let mut counted: Vec<(PathBuf, usize)> = Vec::new();
+    for source in sources {
+        let source = cache.canonicalize_or_fallback(source);
+        match counted.iter_mut().find(|(seen, _)| *seen == source) {
+            Some((_, count)) => *count += 1,
+            None => counted.push((source, 1)),
+        }
+    }
+    counted.retain(|(_, count)| *count > 1);
+    counted
+}
+
+// Helper to print the sources shared by several links, one `source X -> N destinations` line each
+//
+// @param shared: &[(PathBuf, usize)] - the shared sources, as found by `shared_sources`
+pub fn print_shared_sources(mode: &RunMode, shared: &[(PathBuf, usize)]) {
+    if shared.is_empty() {
+        return;
+    }
+    app_println!(mode, "\nShared sources\n");
+    for (source, count) in shared {
+        app_println!(
+            mode,
+            "source {} -> {}",
+            source.display(),
+            plural(*count, "destination")
+        );
+    }
+}
@@ -0,0 +1,228 @@
+use crate::app_println;
+use crate::commands::sync::{ACTIONS, PlanItem, SyncAction, SyncReport};
+use crate::commands::{RunMode, emit, print_shared_sources, shared_sources};
+use crate::config_parser::LinkKind;
+use crate::output::{Cell, Color, format_size, paint, plural, table};
+use tracing::error;
+
+// Prints what a sync did: the outcome of every item, what failed and the numbers
+//
+// The numbers are also emitted as the `done` event. An aborted sync has nothing to show.
+// With `--summary` only the counts per action and the numbers are printed, and
+// with `--quiet` only the failures.
+//
+// @param report: &SyncReport - the finished sync
+pub fn print_report(mode: &RunMode, report: &SyncReport) {
+    if report.aborted {
+        return;
+    }
+    let items = report.executed.iter().map(|executed| &executed.item);
+    if mode.lists_items() {
+        print_items(mode, "Outcome", items);
+    } else if mode.prints_summary() {
+        app_println!(mode, "{}", count_line(&items.collect::<Vec<_>>()));
+    }
+    for failed in &report.errors {
+        error!(
+            "{}: {} failed: {}",
+            failed.to.display(),
+            failed.action.label(),
+            failed.error
+        );
+    }
+    print_stats(mode, report);
+}
+
+// Helper to print the planned or executed actions
+//
+// Items from `system =` lines are printed in a group of their own, and so
+// is every module. A module with nothing to do is folded into a single line.
+// Sources linked to several destinations are listed after the groups.
+//
+// @param title: &str - the title of the summary section
+// @param plan: &[PlanItem] - items to print
+pub fn print_plan(mode: &RunMode, title: &str, plan: &[PlanItem]) {
+    print_items(mode, title, plan.iter());
+    print_shared_sources(
+        mode,
+        &shared_sources(plan.iter().map(|item| item.from.as_path())),
+    );
+}
+
+// Prints what a resolved plan will change, one numbered line per destination like a diff
+//
+// Links created where nothing exists are marked `+`, destinations that are
+// replaced or retargeted `~`, and items left as they are have no mark.
+//
+// @param items: &[&PlanItem] - the items to list
+pub fn print_changes(mode: &RunMode, items: &[&PlanItem]) {
+    app_println!(mode, "\nChanges\n");
+    for (number, item) in items.iter().enumerate() {
+        let marker = if item.action.is_destructive() {
+            "~"
+        } else if item.action.changes_destination() {
+            "+"
+        } else {
+            " "
+        };
+        app_println!(
+            mode,
+            "{:>3}. {} {} -> {} ({})",
+            number + 1,
+            paint(marker, item.action.color()),
+            item.from.display(),
+            item.to.display(),
+            paint(item.action.label(), item.action.color())
+        );
+    }
+    app_println!(mode, "\n{}", count_line(items));
+}
+
+// Helper to print plan items in their groups
+//
+// @param title: &str - the title of the summary section
+// @param items: impl Iterator<Item = &PlanItem> - items to print
+fn print_items<'a>(mode: &RunMode, title: &str, items: impl Iterator<Item = &'a PlanItem>) {
+    // Items outside of modules first, then every module in the order it appears
+    let mut groups: Vec<(Option<&str>, Vec<&PlanItem>)> = vec![(None, Vec::new())];
+    for item in items {
+        let module = item.module.as_deref();
+        match groups.iter_mut().find(|(group, _)| *group == module) {
+            Some((_, group)) => group.push(item),
+            None => groups.push((module, vec![item])),
+        }
+    }
+
+    let only_group = groups.len() == 1;
+    for (module, items) in groups {
+        match module {
+            None if items.is_empty() && !only_group => {}
+            None => print_kinds(mode, title, items),
+            Some(module) if items.iter().all(|item| item.action == SyncAction::Ignore) => {
+                app_println!(
+                    mode,
+                    "\n{}, module {}: {} already in place",
+                    title,
+                    module,
+                    plural(items.len(), "link")
+                );
+            }
+            Some(module) => print_kinds(mode, &format!("{}, module {}", title, module), items),
+        }
+    }
+}
+
+// Helper to print a group of plan items, with the system links apart
+//
+// @param title: &str - the title of the summary section
+// @param items: Vec<&PlanItem> - items to print
+fn print_kinds(mode: &RunMode, title: &str, items: Vec<&PlanItem>) {
+    let (system, user): (Vec<&PlanItem>, Vec<&PlanItem>) = items
+        .into_iter()
+        .partition(|item| item.kind == LinkKind::System);
+    if !user.is_empty() || system.is_empty() {
+        print_plan_table(mode, title, &user);
+    }
+    if !system.is_empty() {
+        print_plan_table(mode, &format!("{} (system)", title), &system);
+    }
+}
+
+// Helper to print the numbers of a finished sync, and emit them as the `done` event
+//
+// @param report: &SyncReport - the finished sync
+fn print_stats(mode: &RunMode, report: &SyncReport) {
+    if mode.prints_summary() {
+        app_println!(
+            mode,
+            "Done in {:.2}s, {} backed up, {} answered",
+            report.elapsed.as_secs_f64(),
+            format_size(report.bytes_backed_up),
+            plural(report.prompts_answered, "prompt")
+        );
+    }
+
+    let counts: serde_json::Map<String, serde_json::Value> = ACTIONS
+        .iter()
+        .map(|action| (action.as_str().to_string(), report.count(*action).into()))
+        .collect();
+    emit(
+        mode,
+        "done",
+        serde_json::json!({
+            "counts": counts,
+            "destructive": report
+                .executed
+                .iter()
+                .filter(|executed| executed.item.action.is_destructive())
+                .count(),
+            "bytes_backed_up": report.bytes_backed_up,
+            "elapsed_ms": report.elapsed.as_millis() as u64,
+            "prompts_answered": report.prompts_answered,
+        }),
+    );
+}
+
+// Helper to print a table of planned or executed actions
+//
+// Items are listed in plan order, followed by a summary row with the count of
+// items per action.
+//
+// @param title: &str - the title of the summary section
+// @param plan: &[&PlanItem] - items to print
+fn print_plan_table(mode: &RunMode, title: &str, plan: &[&PlanItem]) {
+    app_println!(mode, "\n{}\n", title);
+
+    let rows: Vec<Vec<Cell>> = plan
+        .iter()
+        .map(|item| {
+            vec![
+                Cell::plain(item.from.display().to_string()),
+                Cell::plain(item.to.display().to_string()),
+                Cell::colored(item.action.label(), item.action.color()),
+                Cell::plain(
+                    item.reason
+                        .as_ref()
+                        .map(ToString::to_string)
+                        .unwrap_or_default(),
+                ),
+            ]
+        })
+        .collect();
+    for line in table(&["SOURCE", "DESTINATION", "ACTION", "REASON"], &rows) {
+        app_println!(mode, "{}", line);
+    }
+
+    app_println!(mode, "\n{}", count_line(plan));
+}
+
+// Helper to count items per action, e.g. `3 items: 1 create, 1 replace, 1 skip (1 destructive)`
+//
+// Items that change something already at their destination are counted
+// apart, so a plan that only creates links is told apart at a glance.
+//
+// @param items: &[&PlanItem] - the items to count
+// @return String - the counts, with every action in its color
+fn count_line(items: &[&PlanItem]) -> String {
+    let counts: Vec<String> = ACTIONS
+        .iter()
+        .filter_map(|action| {
+            let count = items.iter().filter(|item| item.action == *action).count();
+            (count > 0).then(|| paint(&format!("{} {}", count, action.label()), action.color()))
+        })
+        .collect();
+    let destructive = items
+        .iter()
+        .filter(|item| item.action.is_destructive())
+        .count();
+    if destructive == 0 {
+        format!("{} items: {}", items.len(), counts.join(", "))
+    } else {
+        format!(
+            "{} items: {} ({})",
+            items.len(),
+            counts.join(", "),
+            paint(&format!("{} destructive", destructive), Color::Red)
+        )
+    }
+}
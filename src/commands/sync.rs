@@ -0,0 +1,2177 @@
+use crate::commands::{RunMode, emit, present};
+use crate::config_parser::{Config, Link, LinkKind, LinkOptions, Secrets};
+use crate::expand::{VarMap, expand};
+use crate::fs::FileStat;
+use crate::ignore::Ignore;
+use crate::output::{Color, format_size, plural};
+use crate::rollback::Journal;
+use crate::state::LinkState;
+use crate::{
+    BackupNaming, BackupOutcome, ConflictPolicy, FsSnapshot, app_print, app_println,
+    backup_and_replace_resolved, backup_dir_of, backups, canonicalize_or_fallback, copies,
+    created_dirs, dangerous_destination, elevate, git, history, is_backup_name, is_source_itself,
+    lock, permissions, replace_link, replace_snapshot, resolve_link_destination, same_content,
+    secrets, snapshot_link_destination, unix_time,
+};
+use std::io::BufRead;
+use std::path::{Path, PathBuf};
+use tracing::{debug, error, info, warn};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SyncAction {
+    Ignore,
+    // Link a destination where nothing exists yet
+    Create,
+    Replace,
+    // Replace a destination that already has the same content as the source
+    Identical,
+    // Point an existing symlink at the source instead of its old target
+    Retarget,
+    BackupReplace,
+    // Merge the changes of the source into a copy that was edited since it was made
+    Merge,
+    Skip,
+    Pending, // Temp state to mark files that need to be acted upon
+}
+
+// Every action, in the order they are counted in summaries
+pub const ACTIONS: [SyncAction; 9] = [
+    SyncAction::Ignore,
+    SyncAction::Create,
+    SyncAction::Replace,
+    SyncAction::Identical,
+    SyncAction::Retarget,
+    SyncAction::BackupReplace,
+    SyncAction::Merge,
+    SyncAction::Skip,
+    SyncAction::Pending,
+];
+
+impl SyncAction {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SyncAction::Ignore => "ignore",
+            SyncAction::Create => "create",
+            SyncAction::Replace => "replace",
+            SyncAction::Identical => "identical",
+            SyncAction::Retarget => "retarget",
+            SyncAction::BackupReplace => "backup_replace",
+            SyncAction::Merge => "merge",
+            SyncAction::Skip => "skip",
+            SyncAction::Pending => "pending",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            SyncAction::Ignore => "ignore",
+            SyncAction::Create => "create",
+            SyncAction::Replace => "replace",
+            SyncAction::Identical => "identical",
+            SyncAction::Retarget => "retarget",
+            SyncAction::BackupReplace => "backup+replace",
+            SyncAction::Merge => "merge",
+            SyncAction::Skip => "skip",
+            SyncAction::Pending => "pending",
+        }
+    }
+
+    // If executing the action changes what is at the destination
+    pub fn changes_destination(&self) -> bool {
+        !matches!(
+            self,
+            SyncAction::Ignore | SyncAction::Skip | SyncAction::Pending
+        )
+    }
+
+    // If executing the action changes something that is already at the destination
+    //
+    // Only creating a link where nothing exists yet leaves everything as it was.
+    pub fn is_destructive(&self) -> bool {
+        self.changes_destination() && *self != SyncAction::Create
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            SyncAction::Ignore => Color::Green,
+            SyncAction::Create => Color::Green,
+            SyncAction::Replace => Color::Yellow,
+            SyncAction::Identical => Color::Green,
+            SyncAction::Retarget => Color::Yellow,
+            SyncAction::BackupReplace => Color::Blue,
+            SyncAction::Merge => Color::Yellow,
+            SyncAction::Skip => Color::Dim,
+            SyncAction::Pending => Color::Red,
+        }
+    }
+}
+
+// Why an item was planned or ended up the way it did
+//
+// Mostly why it was skipped, but actions that need explaining, like a
+// retarget, have one too. `as_str` is a stable name for scripts, `Display`
+// the text shown in the tables.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SkipReason {
+    // The source doesn't exist
+    SourceMissing,
+    // The directory the destination goes in doesn't exist
+    DestinationMissingParent,
+    // The destination couldn't be resolved or looked at, with the error
+    Unresolvable(std::io::ErrorKind, String),
+    // The link can't be made as written, like a destination inside its source, with why
+    InvalidLink(String),
+    // Replacing the destination would wipe `/` or the home directory, with which one
+    Dangerous(String),
+    // The destination was a symlink to something else, with its target
+    LinkedElsewhere(PathBuf),
+    // The destination was a symlink to something that doesn't exist, with its target
+    BrokenLink(PathBuf),
+    // The source changed since it was copied to the destination
+    StaleCopy,
+    // A copy that was edited since it was made
+    EditedCopy,
+    // A copy that was edited since it was made, while the source changed too
+    DivergedCopy,
+    // An encrypted source that wasn't decrypted yet
+    NotDecrypted,
+    // An encrypted source that changed since it was decrypted
+    SourceChanged,
+    // A decrypted destination that was changed since
+    ChangedSinceDecrypted,
+    // A conflict resolved by `--on-conflict`
+    ResolvedByPolicy,
+    // A conflict left for an interactive sync
+    ConflictUnresolved,
+    // A system link that needs a confirmation nobody can give
+    SystemNeedsConfirmation,
+    // A system destination that is backed up whatever was chosen
+    SystemAlwaysBackedUp,
+    // Applying the item failed
+    ExecutionFailed(std::io::ErrorKind),
+    // The user chose to skip the item
+    UserSkipped,
+    // The destination already had the content of the source
+    Identical,
+    // Not applied, the user aborted the sync
+    Aborted,
+    // Not applied, an earlier item failed
+    EarlierFailure,
+    // Applied and undone again, another item failed
+    RolledBack,
+}
+
+impl SkipReason {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SkipReason::SourceMissing => "source_missing",
+            SkipReason::DestinationMissingParent => "destination_missing_parent",
+            SkipReason::Unresolvable(..) => "unresolvable",
+            SkipReason::InvalidLink(_) => "invalid_link",
+            SkipReason::Dangerous(_) => "dangerous",
+            SkipReason::LinkedElsewhere(_) => "linked_elsewhere",
+            SkipReason::BrokenLink(_) => "broken_link",
+            SkipReason::StaleCopy => "stale_copy",
+            SkipReason::EditedCopy => "edited_copy",
+            SkipReason::DivergedCopy => "diverged_copy",
+            SkipReason::NotDecrypted => "not_decrypted",
+            SkipReason::SourceChanged => "source_changed",
+            SkipReason::ChangedSinceDecrypted => "changed_since_decrypted",
+            SkipReason::ResolvedByPolicy => "resolved_by_policy",
+            SkipReason::ConflictUnresolved => "conflict_unresolved",
+            SkipReason::SystemNeedsConfirmation => "system_needs_confirmation",
+            SkipReason::SystemAlwaysBackedUp => "system_always_backed_up",
+            SkipReason::ExecutionFailed(_) => "execution_failed",
+            SkipReason::UserSkipped => "user_skipped",
+            SkipReason::Identical => "identical",
+            SkipReason::Aborted => "aborted",
+            SkipReason::EarlierFailure => "earlier_failure",
+            SkipReason::RolledBack => "rolled_back",
+        }
+    }
+
+    // If the item wasn't applied because something went wrong, which fails the sync
+    pub fn is_failure(&self) -> bool {
+        matches!(
+            self,
+            SkipReason::SourceMissing
+                | SkipReason::DestinationMissingParent
+                | SkipReason::Unresolvable(..)
+                | SkipReason::ExecutionFailed(_)
+        )
+    }
+
+    // Helper to tell why a destination couldn't be planned
+    //
+    // @param from: &Path - the source of the link
+    // @param err: &std::io::Error - the error resolving the destination
+    // @return SkipReason - SourceMissing or InvalidLink if that's the cause, Unresolvable otherwise
+    fn unresolvable(from: &Path, err: &std::io::Error) -> SkipReason {
+        match err.kind() {
+            std::io::ErrorKind::NotFound if std::fs::symlink_metadata(from).is_err() => {
+                SkipReason::SourceMissing
+            }
+            std::io::ErrorKind::InvalidInput => SkipReason::InvalidLink(err.to_string()),
+            kind => SkipReason::Unresolvable(kind, err.to_string()),
+        }
+    }
+
+    // Helper to tell why an item failed to apply
+    //
+    // @param to: &Path - the resolved destination
+    // @param err: &std::io::Error - the error applying it
+    // @return SkipReason - DestinationMissingParent if that's the cause, ExecutionFailed otherwise
+    fn failed(to: &Path, err: &std::io::Error) -> SkipReason {
+        let parent_missing = to
+            .parent()
+            .is_some_and(|parent| std::fs::symlink_metadata(parent).is_err());
+        if err.kind() == std::io::ErrorKind::NotFound && parent_missing {
+            SkipReason::DestinationMissingParent
+        } else {
+            SkipReason::ExecutionFailed(err.kind())
+        }
+    }
+}
+
+impl std::fmt::Display for SkipReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SkipReason::SourceMissing => write!(f, "source missing"),
+            SkipReason::DestinationMissingParent => write!(f, "parent directory missing"),
+            SkipReason::Unresolvable(_, message)
+            | SkipReason::InvalidLink(message)
+            | SkipReason::Dangerous(message) => write!(f, "{}", message),
+            SkipReason::LinkedElsewhere(target) => write!(f, "was {}", target.display()),
+            SkipReason::BrokenLink(target) => write!(f, "broken, was {}", target.display()),
+            SkipReason::StaleCopy => write!(f, "source changed since it was copied"),
+            SkipReason::EditedCopy => write!(f, "copy edited since it was made"),
+            SkipReason::DivergedCopy => write!(f, "copy edited and source changed"),
+            SkipReason::NotDecrypted => write!(f, "decrypt"),
+            SkipReason::SourceChanged => write!(f, "source changed"),
+            SkipReason::ChangedSinceDecrypted => write!(f, "changed since it was decrypted"),
+            SkipReason::ResolvedByPolicy => write!(f, "conflict, resolved by --on-conflict"),
+            SkipReason::ConflictUnresolved => write!(f, "conflict, resolve it with dbdm sync"),
+            SkipReason::SystemNeedsConfirmation => write!(f, "system links need confirmation"),
+            SkipReason::SystemAlwaysBackedUp => {
+                write!(f, "system destinations are always backed up")
+            }
+            SkipReason::ExecutionFailed(kind) => write!(f, "failed: {}", kind),
+            SkipReason::UserSkipped => write!(f, "skipped at the prompt"),
+            SkipReason::Identical => write!(f, "identical, no backup needed"),
+            SkipReason::Aborted => write!(f, "not applied, the sync was aborted"),
+            SkipReason::EarlierFailure => {
+                write!(f, "not applied, an earlier item failed (--fail-fast)")
+            }
+            SkipReason::RolledBack => write!(f, "rolled back, another item failed (--atomic)"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct PlanItem {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub action: SyncAction,
+    pub reason: Option<SkipReason>,
+    // Where to back up the destination, instead of where the config puts backups
+    pub backup_dir: Option<PathBuf>,
+    // The kind of line the item was planned from
+    pub kind: LinkKind,
+    // What was at the destination when the item was planned, None if it wasn't looked at
+    pub snapshot: Option<FsSnapshot>,
+    // The module of the link the item was planned from
+    pub module: Option<String>,
+    // If the link is `protect=true`, so the item is never replaced without asking
+    pub protect: bool,
+}
+
+// A plan item once it was executed
+#[derive(Debug)]
+pub struct ExecutedItem {
+    // The item, with the action that was taken in the end
+    pub item: PlanItem,
+    // Where the old destination was moved, if it was backed up
+    pub backup: Option<PathBuf>,
+}
+
+// A plan item that failed, it is also in `executed` as skipped
+#[derive(Debug)]
+pub struct SyncError {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    // The action that was tried
+    pub action: SyncAction,
+    pub error: std::io::Error,
+}
+
+// What a sync did, with the numbers shown at the end of it
+//
+// Nothing of it is printed by the sync, see `present::print_report`.
+pub struct SyncReport {
+    // The executed plan, empty if the sync was aborted
+    pub executed: Vec<ExecutedItem>,
+    pub errors: Vec<SyncError>,
+    pub aborted: bool,
+    // When the sync started, in seconds since the epoch
+    pub started_at: u64,
+    // The size of everything that was moved to a backup
+    pub bytes_backed_up: u64,
+    pub prompts_answered: usize,
+    pub elapsed: std::time::Duration,
+}
+
+impl SyncReport {
+    // @param action: SyncAction - the action to count
+    // @return usize - how many executed items ended with the action
+    pub fn count(&self, action: SyncAction) -> usize {
+        self.executed
+            .iter()
+            .filter(|executed| executed.item.action == action)
+            .count()
+    }
+
+    // If an item failed, either while it was applied or already when it was
+    // planned, like one with a missing source
+    pub fn failed(&self) -> bool {
+        !self.errors.is_empty()
+            || self.executed.iter().any(|executed| {
+                executed
+                    .item
+                    .reason
+                    .as_ref()
+                    .is_some_and(SkipReason::is_failure)
+            })
+    }
+}
+
+// Why the links can't be put in an order to apply them in
+#[derive(Debug, PartialEq)]
+pub struct OrderError {
+    // The index in the config of a link the error is about
+    pub link: usize,
+    pub message: String,
+}
+
+// Flags that change how sync behaves
+#[derive(Clone)]
+pub struct SyncOptions {
+    // Replace conflicts without prompting
+    pub force: bool,
+    // Replace destinations that could wipe the home directory or the source
+    pub allow_dangerous: bool,
+    // Resolves the plan in the interactive TUI instead of line prompts, false if aborted
+    pub tui: Option<fn(&mut [PlanItem], &BackupNaming) -> bool>,
+    // How backups of replaced destinations are named
+    pub backup_naming: BackupNaming,
+    // Retry changes that fail for lack of permissions through sudo
+    pub sudo: bool,
+    // Skip anything that would need an answer from the user, instead of asking
+    pub skip_conflicts: bool,
+    // How conflicts are resolved before anything is asked
+    pub on_conflict: ConflictPolicy,
+    // Refuse to sync from a repository with uncommitted changes or missing commits
+    pub require_clean: bool,
+    // Only the links of these modules are synced, every link if empty
+    pub modules: Vec<String>,
+    // Permission bits for the directories sync creates, the default honoring the umask if None
+    pub dir_mode: Option<u32>,
+    // Stop at the first item that fails, instead of applying the rest
+    pub fail_fast: bool,
+    // Put back everything the sync changed if an item fails
+    pub atomic: bool,
+    // Ask before applying any change, even when nothing is replaced or backed up
+    pub confirm_always: bool,
+    // Answer yes to the plan confirmation, conflicts are still asked about
+    pub yes: bool,
+}
+
+// One of the command handlers
+// Allows to perform a sync of system state to the desired state specified in the config.
+//
+// Accepts a `--force` flag if a non-interactive execution is preferred.
+//
+// Otherwise tires to sync the state described in the config with the system state
+//
+// @param config: &Config - the parsed config state
+// @param options: &SyncOptions - the flags for this sync
+// @return SyncReport - the executed plan and what failed, empty if the sync was aborted
+pub fn sync(config: &Config, mode: &RunMode, options: &SyncOptions) -> SyncReport {
+    let started = std::time::Instant::now();
+    let started_at = unix_time();
+    let answered = mode.answers.get();
+    let report = |executed: Vec<ExecutedItem>,
+                  errors: Vec<SyncError>,
+                  bytes_backed_up: u64,
+                  aborted: bool| SyncReport {
+        executed,
+        errors,
+        aborted,
+        started_at,
+        bytes_backed_up,
+        prompts_answered: mode.answers.get() - answered,
+        elapsed: started.elapsed(),
+    };
+
+    // Held until the sync returns, so two syncs of the config never run at once.
+    // Sync always runs from the directory with dbdm.conf.
+    let config_dir = std::env::current_dir().unwrap_or_default();
+    let _lock = match lock::acquire(&config_dir) {
+        Ok(lock) => lock,
+        Err(err) => {
+            error!("Could not start the sync, {}", err);
+            emit(
+                mode,
+                "aborted",
+                serde_json::json!({ "error": err.to_string() }),
+            );
+            return report(Vec::new(), Vec::new(), 0, true);
+        }
+    };
+
+    let force = options.force;
+    if !check_source_repos(config, mode) && options.require_clean {
+        error!("Sources are not clean and --require-clean is set, nothing was changed");
+        emit(mode, "aborted", serde_json::json!({}));
+        return report(Vec::new(), Vec::new(), 0, true);
+    }
+    // Links are applied after the ones they depend on, in config order otherwise.
+    // The whole config is ordered, as a module can depend on links of another.
+    let links: Vec<&Link> = config.links.iter().collect();
+    let links: Vec<&Link> = match link_order(&links) {
+        Ok(order) => order
+            .into_iter()
+            .map(|idx| links[idx])
+            .filter(|link| in_modules(link, &options.modules))
+            .collect(),
+        Err(err) => {
+            error!("Could not order the links, {}", err.message);
+            emit(mode, "aborted", serde_json::json!({ "error": err.message }));
+            return report(Vec::new(), Vec::new(), 0, true);
+        }
+    };
+    // Protected links are planned without --force, so their conflicts are left to ask about
+    let unforced = SyncOptions {
+        force: false,
+        ..options.clone()
+    };
+    // The plan to be previewed and then executed
+    let mut plan: Vec<PlanItem> = links
+        .iter()
+        .map(|link| PlanItem {
+            module: link.module.clone(),
+            protect: link.options.protect,
+            ..plan_item(
+                link.from.clone(),
+                link.to.clone(),
+                link.kind,
+                if link.options.protect {
+                    &unforced
+                } else {
+                    options
+                },
+            )
+        })
+        .collect();
+    // To have a quicker lookup for which plan items require care
+    let mut pending_indices: Vec<usize> = plan
+        .iter()
+        .enumerate()
+        .filter(|(_, item)| item.action == SyncAction::Pending)
+        .map(|(idx, _)| idx)
+        .collect();
+
+    for item in &plan {
+        emit_item(mode, "planned", item, serde_json::json!({}));
+    }
+
+    // The `on_conflict=` of a link comes before the flag, and protected links are never replaced without asking
+    pending_indices.retain(|&idx| {
+        let link = links[idx];
+        let policy = match link.options.on_conflict.unwrap_or(options.on_conflict) {
+            ConflictPolicy::Replace if link.options.protect => ConflictPolicy::Ask,
+            policy => policy,
+        };
+        let Some(action) = policy_action(policy) else {
+            return true;
+        };
+        plan[idx].action = action;
+        plan[idx].reason = Some(SkipReason::ResolvedByPolicy);
+        emit_item(mode, "decided", &plan[idx], serde_json::json!({}));
+        false
+    });
+
+    if options.skip_conflicts {
+        for &idx in pending_indices.iter() {
+            plan[idx].action = SyncAction::Skip;
+            plan[idx].reason = Some(SkipReason::ConflictUnresolved);
+        }
+        pending_indices.clear();
+    }
+
+    enforce_system_backups(&mut plan);
+
+    // The whole plan is shown before any conflict is asked about, the TUI shows it on its own
+    if options.tui.is_none() && mode.lists_items() {
+        present::print_plan(mode, "Planned actions", &plan);
+    }
+
+    if let Some(run_tui) = options.tui {
+        if !run_tui(&mut plan, &options.backup_naming) {
+            info!("Aborted.");
+            emit(mode, "aborted", serde_json::json!({}));
+            return report(Vec::new(), Vec::new(), 0, true);
+        }
+        pending_indices.clear();
+    }
+
+    for &idx in pending_indices.iter() {
+        if !resolve_conflict(mode, &mut plan[idx], options) {
+            info!("Aborted.");
+            emit(mode, "aborted", serde_json::json!({}));
+            return report(Vec::new(), Vec::new(), 0, true);
+        }
+        emit_item(mode, "decided", &plan[idx], serde_json::json!({}));
+    }
+
+    // Conflicts resolved to a replace can need a backup now
+    enforce_system_backups(&mut plan);
+
+    // Creating links can't lose anything, so only conflicts that replace something are confirmed
+    let needs_confirmation = if options.confirm_always {
+        // With --non-interactive nothing is asked, and the TUI got its own confirmation
+        mode.interactive
+            && options.tui.is_none()
+            && plan.iter().any(|item| item.action.changes_destination())
+    } else {
+        !pending_indices.is_empty() && plan.iter().any(|item| item.action.is_destructive())
+    };
+    if !force
+        && !options.yes
+        && needs_confirmation
+        && !confirm_plan(mode, &mut plan, &pending_indices, options)
+    {
+        info!("Aborted.");
+        emit(mode, "aborted", serde_json::json!({}));
+        return report(Vec::new(), Vec::new(), 0, true);
+    }
+
+    if options.skip_conflicts {
+        for item in plan.iter_mut() {
+            if item.kind == LinkKind::System && item.action.changes_destination() {
+                item.action = SyncAction::Skip;
+                item.reason = Some(SkipReason::SystemNeedsConfirmation);
+            }
+        }
+    }
+
+    // Changes outside $HOME are confirmed on their own, even after the TUI
+    let system_changes = plan
+        .iter()
+        .filter(|item| item.kind == LinkKind::System && item.action.changes_destination())
+        .count();
+    if !force
+        && system_changes > 0
+        && !confirm_proceed(
+            mode,
+            &format!(
+                "{} system links will change files outside $HOME. Proceed?",
+                system_changes
+            ),
+        )
+    {
+        info!("Aborted.");
+        emit(mode, "aborted", serde_json::json!({}));
+        return report(Vec::new(), Vec::new(), 0, true);
+    }
+
+    // Every destination is saved before it's changed, to undo the sync if an item fails
+    let mut journal = match options.atomic.then(Journal::new).transpose() {
+        Ok(journal) => journal,
+        Err(err) => {
+            error!("Could not start the rollback journal, {}", err);
+            emit(
+                mode,
+                "aborted",
+                serde_json::json!({ "error": err.to_string() }),
+            );
+            return report(Vec::new(), Vec::new(), 0, true);
+        }
+    };
+
+    let mut executed: Vec<ExecutedItem> = Vec::new();
+    let mut errors: Vec<SyncError> = Vec::new();
+    let mut bytes_backed_up = 0;
+
+    // The plan has one item per link, in the same order
+    // Set once the user answers abort to a failed item
+    let mut stopped = false;
+    for (mut item, link) in plan.into_iter().zip(links) {
+        let mut backup = None;
+        // What's left after a failure is reported, but not applied
+        if item.action.changes_destination() {
+            if stopped {
+                item.action = SyncAction::Skip;
+                item.reason = Some(SkipReason::Aborted);
+            } else if (options.fail_fast || options.atomic) && !errors.is_empty() {
+                item.action = SyncAction::Skip;
+                item.reason = Some(SkipReason::EarlierFailure);
+            }
+        }
+        match item.action {
+            SyncAction::Ignore | SyncAction::Skip => {
+                if item.action == SyncAction::Ignore {
+                    apply_permissions(&item.from, &link.options);
+                }
+                emit_item(
+                    mode,
+                    "executed",
+                    &item,
+                    serde_json::json!({ "outcome": "ok" }),
+                );
+            }
+            SyncAction::Create
+            | SyncAction::Replace
+            | SyncAction::Identical
+            | SyncAction::Retarget
+            | SyncAction::Merge => {
+                let outcome = save_for_rollback(journal.as_mut(), &item).and_then(|()| {
+                    execute_with_retry(mode, &item, &mut stopped, || {
+                        execute_replace(&item, link, &config.secrets, options)
+                    })
+                });
+                if let Err(error) = &outcome {
+                    item.reason = Some(SkipReason::failed(&item.to, error));
+                }
+                emit_item(mode, "executed", &item, outcome_fields(&outcome));
+                match outcome {
+                    Ok(()) => apply_permissions(&item.from, &link.options),
+                    Err(error) => {
+                        errors.push(SyncError {
+                            from: item.from.clone(),
+                            to: item.to.clone(),
+                            action: item.action,
+                            error,
+                        });
+                        item.action = SyncAction::Skip;
+                    }
+                }
+            }
+            SyncAction::BackupReplace => {
+                let outcome = save_for_rollback(journal.as_mut(), &item).and_then(|()| {
+                    execute_with_retry(mode, &item, &mut stopped, || {
+                        execute_backup(&item, link, config, options)
+                    })
+                });
+                match &outcome {
+                    Ok(BackupOutcome::Identical) => {
+                        item.reason = Some(SkipReason::Identical);
+                    }
+                    Ok(BackupOutcome::BackedUp(path)) => {
+                        bytes_backed_up += backup_size(path, &options.backup_naming);
+                        backup = Some(path.clone());
+                        if let Some(journal) = journal.as_mut() {
+                            journal.backed_up(path);
+                        }
+                    }
+                    Err(error) => item.reason = Some(SkipReason::failed(&item.to, error)),
+                }
+                emit_item(mode, "executed", &item, outcome_fields(&outcome));
+                match outcome {
+                    Ok(_) => apply_permissions(&item.from, &link.options),
+                    Err(error) => {
+                        errors.push(SyncError {
+                            from: item.from.clone(),
+                            to: item.to.clone(),
+                            action: item.action,
+                            error,
+                        });
+                        item.action = SyncAction::Skip;
+                    }
+                }
+            }
+            SyncAction::Pending => {
+                // TODO: I don't even know how to handle the ones that are still pending.
+                // This technically shouldn't even happen, so yea
+                continue;
+            }
+        }
+        executed.push(ExecutedItem { item, backup });
+    }
+
+    if let Some(journal) = journal
+        && !errors.is_empty()
+    {
+        roll_back(mode, journal, &mut executed, &mut errors);
+        bytes_backed_up = 0;
+    }
+
+    report(executed, errors, bytes_backed_up, false)
+}
+
+// Helper to save the destination of an item before it's executed, with --atomic
+//
+// @param journal: Option<&mut Journal> - the rollback journal, None without --atomic
+// @param item: &PlanItem - the item about to be executed
+// @return Result<()> - an error if the destination couldn't be saved
+fn save_for_rollback(journal: Option<&mut Journal>, item: &PlanItem) -> std::io::Result<()> {
+    let Some(journal) = journal else {
+        return Ok(());
+    };
+    journal.save(&rollback_destination(item)?)
+}
+
+// Helper to get the destination an item changes
+fn rollback_destination(item: &PlanItem) -> std::io::Result<PathBuf> {
+    match &item.snapshot {
+        Some(snapshot) => Ok(snapshot.path.clone()),
+        None => resolve_link_destination(&item.from, &item.to),
+    }
+}
+
+// Helper to undo everything a failed sync applied, with --atomic
+//
+// Items that were put back are reported as skipped, the ones that couldn't be
+// are added to the errors.
+//
+// @param journal: Journal - the destinations saved during the sync
+// @param executed: &mut [ExecutedItem] - the executed plan, updated in place
+// @param errors: &mut Vec<SyncError> - what failed
+fn roll_back(
+    mode: &RunMode,
+    journal: Journal,
+    executed: &mut [ExecutedItem],
+    errors: &mut Vec<SyncError>,
+) {
+    warn!("An item failed and --atomic is set, putting back what was changed");
+    let failed = journal.rollback();
+    for executed in executed.iter_mut() {
+        let item = &mut executed.item;
+        if !item.action.changes_destination() {
+            continue;
+        }
+        let dest = rollback_destination(item).unwrap_or_else(|_| item.to.clone());
+        match failed.iter().find(|(failed, _)| *failed == dest) {
+            Some((_, err)) => errors.push(SyncError {
+                from: item.from.clone(),
+                to: item.to.clone(),
+                action: item.action,
+                error: std::io::Error::new(err.kind(), format!("rollback failed: {}", err)),
+            }),
+            None => {
+                item.action = SyncAction::Skip;
+                item.reason = Some(SkipReason::RolledBack);
+                executed.backup = None;
+                emit_item(mode, "rolled_back", item, serde_json::json!({}));
+            }
+        }
+    }
+}
+
+// Helper to tell if a link is in one of the modules picked for a sync
+//
+// @param link: &Link - the link to check
+// @param modules: &[String] - the picked modules, empty for all links
+// @return bool - if the link is synced
+pub fn in_modules(link: &Link, modules: &[String]) -> bool {
+    modules.is_empty()
+        || link
+            .module
+            .as_ref()
+            .is_some_and(|module| modules.contains(module))
+}
+
+// Puts the links in the order they are applied in
+//
+// A link comes after the links named by its `after=` options and after any
+// link whose destination contains its own, so a directory is in place before
+// the links into it. Links that don't depend on each other keep their config
+// order.
+//
+// @param links: &[&Link] - the links of the config
+// @return Result<Vec<usize>, OrderError> - the indices of the links in order, an error for an unknown `after=` or a cycle
+pub fn link_order(links: &[&Link]) -> Result<Vec<usize>, OrderError> {
+    let destinations: Vec<PathBuf> = links.iter().map(|link| link.destination()).collect();
+
+    // The links each link has to come after
+    let mut deps: Vec<Vec<usize>> = vec![Vec::new(); links.len()];
+    for (idx, link) in links.iter().enumerate() {
+        for after in &link.options.after {
+            let after_dest = canonicalize_or_fallback(after);
+            let Some(dep) = destinations.iter().position(|dest| *dest == after_dest) else {
+                return Err(OrderError {
+                    link: idx,
+                    message: format!(
+                        "after={} of the link to {} is not the destination of a link",
+                        after.display(),
+                        link.to.display()
+                    ),
+                });
+            };
+            deps[idx].push(dep);
+        }
+        for (dep, dest) in destinations.iter().enumerate() {
+            if destinations[idx] != *dest && destinations[idx].starts_with(dest) {
+                deps[idx].push(dep);
+            }
+        }
+    }
+
+    let mut order = Vec::with_capacity(links.len());
+    let mut placed = vec![false; links.len()];
+    while order.len() < links.len() {
+        let next =
+            (0..links.len()).find(|&idx| !placed[idx] && deps[idx].iter().all(|&dep| placed[dep]));
+        let Some(next) = next else {
+            return Err(cycle_error(links, &deps, &placed));
+        };
+        placed[next] = true;
+        order.push(next);
+    }
+    Ok(order)
+}
+
+// Helper to describe a cycle among the links that couldn't be placed
+//
+// Every link left has a dependency that is left too, so following them from
+// any of those links runs into a cycle.
+fn cycle_error(links: &[&Link], deps: &[Vec<usize>], placed: &[bool]) -> OrderError {
+    let mut chain: Vec<usize> = Vec::new();
+    let mut current = placed.iter().position(|placed| !placed).unwrap_or_default();
+    while !chain.contains(&current) {
+        chain.push(current);
+        current = deps[current]
+            .iter()
+            .copied()
+            .find(|&dep| !placed[dep])
+            .unwrap_or(current);
+    }
+
+    let start = chain
+        .iter()
+        .position(|&idx| idx == current)
+        .unwrap_or_default();
+    let cycle: Vec<String> = chain[start..]
+        .iter()
+        .chain([&current])
+        .map(|&idx| links[idx].to.display().to_string())
+        .collect();
+    OrderError {
+        link: current,
+        message: format!(
+            "the links have to come after each other in a cycle: {}",
+            cycle.join(" after ")
+        ),
+    }
+}
+
+// Helper to warn about sources that come from a stale or modified checkout
+//
+// Every repository the sources live in is checked once, sources outside of
+// git are left alone.
+//
+// @param config: &Config - the parsed config state
+// @return bool - true if every repository is clean and up to date
+fn check_source_repos(config: &Config, mode: &RunMode) -> bool {
+    let mut roots: Vec<PathBuf> = config
+        .links
+        .iter()
+        .filter(|link| link.from.exists())
+        .filter_map(|link| git::repo_root(&link.from))
+        .collect();
+    roots.sort();
+    roots.dedup();
+
+    let mut clean = true;
+    for root in roots {
+        let status = match git::status(&root) {
+            Ok(status) => status,
+            Err(err) => {
+                debug!("Could not get the status of {}: {}", root.display(), err);
+                continue;
+            }
+        };
+        if status.is_clean() {
+            continue;
+        }
+        clean = false;
+        if status.changed > 0 {
+            warn!(
+                "{} has {} uncommitted changes",
+                root.display(),
+                status.changed
+            );
+        }
+        if status.behind > 0 {
+            warn!(
+                "{} is {} commits behind its upstream, pull first?",
+                root.display(),
+                status.behind
+            );
+        }
+        emit(
+            mode,
+            "repo_status",
+            serde_json::json!({
+                "root": root.display().to_string(),
+                "changed": status.changed,
+                "behind": status.behind,
+            }),
+        );
+    }
+    clean
+}
+
+// Helper to get the action a conflict policy resolves conflicts with
+//
+// @param policy: ConflictPolicy - the policy from `--on-conflict` or `on_conflict=`
+// @return Option<SyncAction> - the action, None if the user is asked
+fn policy_action(policy: ConflictPolicy) -> Option<SyncAction> {
+    match policy {
+        ConflictPolicy::Ask => None,
+        ConflictPolicy::Replace => Some(SyncAction::Replace),
+        ConflictPolicy::Backup => Some(SyncAction::BackupReplace),
+        ConflictPolicy::Skip => Some(SyncAction::Skip),
+    }
+}
+
+// Helper to make sure system destinations are never replaced without a backup
+//
+// Retargeted links and identical destinations have nothing to lose, anything
+// else that exists is backed up.
+//
+// @param plan: &mut [PlanItem] - the plan to update in place
+fn enforce_system_backups(plan: &mut [PlanItem]) {
+    for item in plan.iter_mut() {
+        if item.kind == LinkKind::System
+            && item.action == SyncAction::Replace
+            && item.snapshot.as_ref().map_or_else(
+                || std::fs::symlink_metadata(&item.to).is_ok(),
+                FsSnapshot::exists,
+            )
+        {
+            item.action = SyncAction::BackupReplace;
+            item.reason = Some(SkipReason::SystemAlwaysBackedUp);
+        }
+    }
+}
+
+// Helper to replace the destination of a plan item with a link
+//
+// Links with `root=true` always go through sudo. With `--sudo`, a replace that
+// fails for lack of permissions is retried through sudo.
+//
+// @param item: &PlanItem - the item to execute
+// @param link: &Link - the link the item was planned from
+// @param secrets: &Secrets - the keys for encrypted links
+// @param options: &SyncOptions - the flags for this sync
+// @return Result<()> - if replacement was successful
+fn execute_replace(
+    item: &PlanItem,
+    link: &Link,
+    secrets: &Secrets,
+    options: &SyncOptions,
+) -> std::io::Result<()> {
+    if link.kind == LinkKind::Encrypted {
+        return secrets::install(&item.from, &item.to, secrets);
+    }
+    if item.action == SyncAction::Merge {
+        return copies::merge(&item.from, &item.to);
+    }
+    if link.options.root {
+        return elevate::replace_link(&item.from, &item.to);
+    }
+    let replaced = if !copies::symlinks_supported(&item.to) {
+        warn_copying(item);
+        copies::install(&item.from, &item.to)
+    } else {
+        match &item.snapshot {
+            Some(snapshot) => replace_snapshot(&item.from, snapshot),
+            None => replace_link(&item.from, &item.to),
+        }
+    };
+    match replaced {
+        Err(err) if options.sudo && err.kind() == std::io::ErrorKind::PermissionDenied => {
+            info!(
+                "Permission denied for {}, retrying with sudo",
+                item.to.display()
+            );
+            elevate::replace_link(&item.from, &item.to)
+        }
+        outcome => outcome,
+    }
+}
+
+// Helper to back up the destination of a plan item and replace it with a link
+//
+// Goes through sudo the same way as `execute_replace`.
+//
+// @param item: &PlanItem - the item to execute
+// @param link: &Link - the link the item was planned from
+// @param config: &Config - the parsed config state, for the keys and where backups go
+// @param options: &SyncOptions - the flags for this sync
+// @return Result<BackupOutcome> - what happened to the old destination
+fn execute_backup(
+    item: &PlanItem,
+    link: &Link,
+    config: &Config,
+    options: &SyncOptions,
+) -> std::io::Result<BackupOutcome> {
+    let backup_dir = match &item.backup_dir {
+        Some(dir) => dir.clone(),
+        None => backup_dir_of(
+            config.backup_location.unwrap_or_default(),
+            &item.from,
+            &item.to,
+        )?,
+    };
+    let naming = &options.backup_naming;
+
+    // The backup directory is made here, so it gets the mode and is remembered.
+    // With sudo it's made by the retry, if it can't be made without.
+    if !link.options.root {
+        match created_dirs::create(&backup_dir, options.dir_mode) {
+            Ok(created) => {
+                if let Err(err) = created_dirs::record(&created) {
+                    warn!(
+                        "Could not record the directories that were created: {}",
+                        err
+                    );
+                }
+            }
+            Err(err) if options.sudo && err.kind() == std::io::ErrorKind::PermissionDenied => {}
+            Err(err) => return Err(err),
+        }
+    }
+
+    if link.kind == LinkKind::Encrypted {
+        return secrets::backup_and_install(
+            &item.from,
+            &item.to,
+            &backup_dir,
+            naming,
+            &config.secrets,
+        );
+    }
+
+    if link.options.root {
+        return elevate::backup_and_replace_in(&item.from, &item.to, &backup_dir, naming);
+    }
+    // The destination was resolved while planning
+    let replaced = if !copies::symlinks_supported(&item.to) {
+        warn_copying(item);
+        copies::backup_and_install(&item.from, &item.to, &backup_dir, naming)
+    } else {
+        backup_and_replace_resolved(&item.from, &item.to, &backup_dir, naming)
+    };
+    match replaced {
+        Err(err) if options.sudo && err.kind() == std::io::ErrorKind::PermissionDenied => {
+            info!(
+                "Permission denied for {}, retrying with sudo",
+                item.to.display()
+            );
+            elevate::backup_and_replace_in(&item.from, &item.to, &backup_dir, naming)
+        }
+        outcome => outcome,
+    }
+}
+
+// Helper to tell the user a link is copied, as the destination can't hold a symlink
+fn warn_copying(item: &PlanItem) {
+    warn!(
+        "{} doesn't support symlinks, copying {} there instead",
+        item.to.parent().unwrap_or(&item.to).display(),
+        item.from.display()
+    );
+}
+
+// Helper to enforce the `mode=`, `owner=` and `readonly=` options of a link on its source
+//
+// Failures are logged, they don't undo the link itself.
+//
+// @param from: &Path - the source of the link
+// @param options: &LinkOptions - the options of the link
+fn apply_permissions(from: &Path, options: &LinkOptions) {
+    match permissions::apply(from, options) {
+        Ok(true) => info!("Updated permissions of {}", from.display()),
+        Ok(false) => {}
+        Err(err) => error!("Failed to set permissions of {}: {}", from.display(), err),
+    }
+}
+
+// Helper to decide what to do with a single link
+//
+// @param from: PathBuf - the source of the link
+// @param to: PathBuf - the destination of the link from the config
+// @param kind: LinkKind - the kind of line the link is from
+// @param options: &SyncOptions - the flags for this sync
+// @return PlanItem - the planned item, Pending if the user has to decide
+pub fn plan_item(from: PathBuf, to: PathBuf, kind: LinkKind, options: &SyncOptions) -> PlanItem {
+    let snapshot = match snapshot_link_destination(&from, &to) {
+        Ok(snapshot) => snapshot,
+        Err(err) => {
+            debug!(from = %from.display(), to = %to.display(), %err, "skipping link");
+            return PlanItem {
+                reason: Some(SkipReason::unresolvable(&from, &err)),
+                from,
+                to,
+                action: SyncAction::Skip,
+                backup_dir: None,
+                kind,
+                snapshot: None,
+                module: None,
+                protect: false,
+            };
+        }
+    };
+    let resolved_to = snapshot.path.clone();
+
+    // Replacing these would remove everything below them
+    if !options.allow_dangerous
+        && let Some(reason) = dangerous_destination(&resolved_to)
+    {
+        warn!(
+            "Refusing to replace {}: {} (pass --allow-dangerous to override)",
+            resolved_to.display(),
+            reason
+        );
+        return PlanItem {
+            from,
+            to: resolved_to,
+            action: SyncAction::Skip,
+            reason: Some(SkipReason::Dangerous(reason)),
+            backup_dir: None,
+            kind,
+            snapshot: None,
+            module: None,
+            protect: false,
+        };
+    }
+
+    if kind == LinkKind::Encrypted {
+        return plan_encrypted(from, resolved_to, options);
+    }
+
+    let (action, reason) = match LinkState::of(&snapshot, &canonicalize_or_fallback(&from)) {
+        LinkState::LinkedCorrectly => {
+            debug!(to = %resolved_to.display(), "already linked");
+            (SyncAction::Ignore, None)
+        }
+        // Only the link itself is replaced, so nothing can be lost
+        state @ (LinkState::WrongTarget(_) | LinkState::BrokenSymlink) => {
+            let target = snapshot.link_target.clone().unwrap_or_default();
+            debug!(to = %resolved_to.display(), target = %target.display(), "linked elsewhere");
+            let reason = if state == LinkState::BrokenSymlink {
+                SkipReason::BrokenLink(target)
+            } else {
+                SkipReason::LinkedElsewhere(target)
+            };
+            (SyncAction::Retarget, Some(reason))
+        }
+        // Its content is trivially the same, but replacing it removes the source
+        LinkState::RegularFileConflict | LinkState::DirectoryConflict
+            if is_source_itself(&from, &resolved_to) =>
+        {
+            let message = format!("destination is the source: {}", resolved_to.display());
+            (SyncAction::Skip, Some(SkipReason::InvalidLink(message)))
+        }
+        LinkState::RegularFileConflict | LinkState::DirectoryConflict => {
+            // A copy made because symlinks aren't supported there is kept up to date
+            match copies::state(&from, &resolved_to) {
+                Ok(Some(copies::State::UpToDate)) => {
+                    debug!(to = %resolved_to.display(), "already copied");
+                    (SyncAction::Ignore, None)
+                }
+                Ok(Some(copies::State::Stale)) => {
+                    (SyncAction::Replace, Some(SkipReason::StaleCopy))
+                }
+                // Edits made to the copy are a conflict, which both sides changing can be merged out of
+                Ok(Some(state @ (copies::State::Modified | copies::State::Diverged))) => {
+                    let action = plan_conflict(&from, &snapshot, options);
+                    let reason = (action == SyncAction::Pending).then(|| {
+                        if state == copies::State::Diverged {
+                            SkipReason::DivergedCopy
+                        } else {
+                            SkipReason::EditedCopy
+                        }
+                    });
+                    (action, reason)
+                }
+                _ => (plan_conflict(&from, &snapshot, options), None),
+            }
+        }
+
+        // Nothing is there yet: safe to create without prompt
+        LinkState::Missing | LinkState::SourceMissing => {
+            debug!(to = %resolved_to.display(), "destination missing");
+            (SyncAction::Create, None)
+        }
+    };
+
+    PlanItem {
+        from,
+        to: resolved_to,
+        action,
+        reason,
+        backup_dir: None,
+        kind,
+        snapshot: Some(snapshot),
+        module: None,
+        protect: false,
+    }
+}
+
+// Helper to decide what to do with a file or directory at the destination
+//
+// @param from: &Path - the source of the link
+// @param snapshot: &FsSnapshot - the resolved destination and what is there
+// @param options: &SyncOptions - the flags for this sync
+// @return SyncAction - Pending if the user has to decide
+fn plan_conflict(from: &Path, snapshot: &FsSnapshot, options: &SyncOptions) -> SyncAction {
+    let resolved_to = &snapshot.path;
+    let is_empty = snapshot.meta.is_some_and(|meta| {
+        is_empty_path(resolved_to, &meta, &options.backup_naming).unwrap_or(false)
+    });
+    let is_conflict = !is_empty;
+    debug!(to = %resolved_to.display(), is_conflict, "destination exists");
+
+    // Same content as the source, so it can be replaced without asking
+    if is_conflict && same_content(from, resolved_to).unwrap_or(false) {
+        SyncAction::Identical
+    // Account for the flag
+    } else if options.force || !is_conflict {
+        SyncAction::Replace
+    } else {
+        SyncAction::Pending
+    }
+}
+
+// Helper to decide what to do with a single encrypted link
+//
+// The destination is a decrypted copy, so it's compared with what dbdm last
+// wrote there instead of the source.
+//
+// @param from: PathBuf - the encrypted source
+// @param to: PathBuf - the resolved destination
+// @param options: &SyncOptions - the flags for this sync
+// @return PlanItem - the planned item, Pending if the destination was changed
+fn plan_encrypted(from: PathBuf, to: PathBuf, options: &SyncOptions) -> PlanItem {
+    let (action, reason) = match secrets::state(&from, &to) {
+        Ok(secrets::State::Missing) => (SyncAction::Create, Some(SkipReason::NotDecrypted)),
+        Ok(secrets::State::UpToDate) => (SyncAction::Ignore, None),
+        Ok(secrets::State::Stale) => (SyncAction::Replace, Some(SkipReason::SourceChanged)),
+        Ok(secrets::State::Modified | secrets::State::Diverged) if options.force => {
+            (SyncAction::Replace, None)
+        }
+        Ok(secrets::State::Modified | secrets::State::Diverged) => {
+            (SyncAction::Pending, Some(SkipReason::ChangedSinceDecrypted))
+        }
+        Err(err) => {
+            return PlanItem {
+                reason: Some(SkipReason::unresolvable(&from, &err)),
+                from,
+                to,
+                action: SyncAction::Skip,
+                backup_dir: None,
+                kind: LinkKind::Encrypted,
+                snapshot: None,
+                module: None,
+                protect: false,
+            };
+        }
+    };
+    PlanItem {
+        from,
+        to,
+        action,
+        reason,
+        backup_dir: None,
+        kind: LinkKind::Encrypted,
+        snapshot: None,
+        module: None,
+        protect: false,
+    }
+}
+
+// Helper to let the user decide on a single conflict
+//
+// Editing the destination plans the item again, so the new destination may be
+// resolved right away or turn out to be a conflict of its own.
+//
+// @param item: &mut PlanItem - the conflicting item, updated in place
+// @param options: &SyncOptions - the flags for this sync
+// @return bool - false if the user chose to abort the sync
+fn resolve_conflict(mode: &RunMode, item: &mut PlanItem, options: &SyncOptions) -> bool {
+    while item.action == SyncAction::Pending {
+        emit_item(mode, "conflict", item, serde_json::json!({}));
+        app_println!(mode, "\nConflict at: {}", item.to.display());
+        if mode.lists_items() {
+            let ignore = Ignore::load(&item.from);
+            if let Err(err) = print_preview(mode, &item.to, &options.backup_naming, &ignore) {
+                warn!("Preview error: {}", err);
+            }
+        }
+
+        let can_merge =
+            item.reason == Some(SkipReason::DivergedCopy) && copies::can_merge(&item.to);
+        match prompt_action(mode, can_merge) {
+            Choice::Action(SyncAction::Skip) => {
+                item.action = SyncAction::Skip;
+                item.reason = Some(SkipReason::UserSkipped);
+            }
+            Choice::Action(SyncAction::Merge) => {
+                if merges_cleanly(mode, item) {
+                    item.action = SyncAction::Merge;
+                }
+            }
+            Choice::Action(action) => item.action = action,
+            Choice::Edit => {
+                if !edit_item(mode, item, options) {
+                    return false;
+                }
+            }
+            Choice::Quit => return false,
+        }
+    }
+    true
+}
+
+// Helper to let the user change where a conflicting item goes
+//
+// The user either types a new destination, or a directory where the existing
+// destination is backed up to before it's replaced.
+//
+// @param item: &mut PlanItem - the conflicting item, updated in place
+// @param options: &SyncOptions - the flags for this sync
+// @return bool - false if input was closed
+fn edit_item(mode: &RunMode, item: &mut PlanItem, options: &SyncOptions) -> bool {
+    loop {
+        app_print!(mode, "Edit [d]estination or backup [l]ocation: ");
+        emit(
+            mode,
+            "prompt",
+            serde_json::json!({ "choices": ["destination", "location"] }),
+        );
+        let Some(input) = read_answer(mode) else {
+            return false;
+        };
+
+        match input.trim().to_lowercase().as_str() {
+            "d" | "destination" => {
+                let Some(path) = read_path(mode, "New destination") else {
+                    return false;
+                };
+                if let Some(path) = path {
+                    // A protected link is planned without --force, like in `sync`
+                    let unforced = SyncOptions {
+                        force: false,
+                        ..options.clone()
+                    };
+                    let options = if item.protect { &unforced } else { options };
+                    // The item stays in its module
+                    *item = PlanItem {
+                        module: item.module.take(),
+                        protect: item.protect,
+                        ..plan_item(item.from.clone(), path, item.kind, options)
+                    };
+                }
+                return true;
+            }
+            "l" | "location" => {
+                let Some(path) = read_path(mode, "Backup directory") else {
+                    return false;
+                };
+                let Some(path) = path else {
+                    return true;
+                };
+                match check_backup_dir(item, &path) {
+                    Ok(()) => {
+                        item.backup_dir = Some(path);
+                        item.action = SyncAction::BackupReplace;
+                        return true;
+                    }
+                    Err(reason) => {
+                        app_println!(mode, "Can't back up to {}: {}", path.display(), reason)
+                    }
+                }
+            }
+            _ => app_println!(mode, "Invalid choice. Use d or l."),
+        }
+    }
+}
+
+// Helper to check a backup directory typed at the prompt
+//
+// The same checks as for a destination apply: it can't be `/` or `$HOME`,
+// and it can't be inside the source or the destination, where the backup
+// would end up in the repository or be moved into itself.
+//
+// @param item: &PlanItem - the item to back up
+// @param dir: &Path - the backup directory, absolute
+// @return Result<(), String> - why the directory can't be used
+fn check_backup_dir(item: &PlanItem, dir: &Path) -> Result<(), String> {
+    if let Some(reason) = dangerous_destination(dir) {
+        return Err(reason);
+    }
+    let dir_full = canonicalize_or_fallback(dir);
+    if dir_full.starts_with(canonicalize_or_fallback(&item.from)) {
+        return Err(String::from("directory is inside the source"));
+    }
+    // The destination itself isn't followed, a link is moved as it is
+    let dest_full = match (item.to.parent(), item.to.file_name()) {
+        (Some(parent), Some(name)) => canonicalize_or_fallback(parent).join(name),
+        _ => canonicalize_or_fallback(&item.to),
+    };
+    if dir_full.starts_with(dest_full) {
+        return Err(String::from("directory is inside the destination"));
+    }
+    Ok(())
+}
+
+// Helper to ask the user for a path
+//
+// The path is expanded like the ones in the config, and asked for again
+// until it can be used.
+//
+// @param label: &str - what the path is for
+// @return Option<Option<PathBuf>> - None if input was closed, Some(None) if left empty
+fn read_path(mode: &RunMode, label: &str) -> Option<Option<PathBuf>> {
+    loop {
+        app_print!(mode, "{} (empty to go back): ", label);
+        emit(mode, "prompt_path", serde_json::json!({ "label": label }));
+        let input = read_answer(mode)?;
+        let input = input.trim();
+        if input.is_empty() {
+            return Some(None);
+        }
+        match expand_typed_path(input) {
+            Ok(path) => return Some(Some(path)),
+            Err(err) => app_println!(mode, "{}", err),
+        }
+    }
+}
+
+// Helper to expand a path typed at a prompt
+//
+// Keywords like `!home` are expanded as in the config, and a leading `~`
+// is the home directory like in a shell. Relative paths would be taken from
+// the directory of the config, so they are refused.
+//
+// @param input: &str - the path as typed
+// @return Result<PathBuf, String> - the absolute path, or why it can't be used
+pub fn expand_typed_path(input: &str) -> Result<PathBuf, String> {
+    let input = match input.strip_prefix('~') {
+        Some(rest) if rest.is_empty() || rest.starts_with('/') => format!("!home{}", rest),
+        _ => input.to_string(),
+    };
+    let path = PathBuf::from(expand(&input, &VarMap::default())?);
+    if !path.is_absolute() {
+        return Err(format!(
+            "{} is relative, use an absolute path, ~ or a !keyword",
+            path.display()
+        ));
+    }
+    Ok(path)
+}
+
+// Helper to write an NDJSON event describing a plan item
+//
+// @param event: &str - the name of the event
+// @param item: &PlanItem - the plan item the event is about
+// @param fields: serde_json::Value - extra fields, as a JSON object
+fn emit_item(mode: &RunMode, event: &str, item: &PlanItem, mut fields: serde_json::Value) {
+    if !mode.ndjson {
+        return;
+    }
+
+    fields["from"] = serde_json::json!(item.from.display().to_string());
+    fields["to"] = serde_json::json!(item.to.display().to_string());
+    fields["action"] = serde_json::json!(item.action.as_str());
+    fields["reason"] = serde_json::json!(item.reason.as_ref().map(ToString::to_string));
+    fields["reason_code"] = serde_json::json!(item.reason.as_ref().map(SkipReason::as_str));
+    emit(mode, event, fields);
+}
+
+// Helper to describe the outcome of executing a plan item as event fields
+//
+// @param outcome: &std::io::Result<T> - the result of the execution
+// @return serde_json::Value - the outcome fields
+fn outcome_fields<T>(outcome: &std::io::Result<T>) -> serde_json::Value {
+    match outcome {
+        Ok(_) => serde_json::json!({ "outcome": "ok" }),
+        Err(err) => serde_json::json!({ "outcome": "error", "error": err.to_string() }),
+    }
+}
+
+// Helper to print out a preview of what the utility is going to do
+//
+// @param path: &Path - the path to the symlink
+// @param naming: &BackupNaming - how backups are named, to leave them out of previews
+// @return Result<()> - if print was successful
+fn print_preview(
+    mode: &RunMode,
+    path: &Path,
+    naming: &BackupNaming,
+    ignore: &Ignore,
+) -> std::io::Result<()> {
+    let meta = std::fs::symlink_metadata(path)?;
+
+    if meta.file_type().is_symlink() {
+        let target = std::fs::read_link(path)?;
+        app_println!(mode, "SYMLINK: {} -> {}", path.display(), target.display());
+        return Ok(());
+    }
+
+    if meta.is_file() {
+        print_file_preview(mode, path)?;
+        return Ok(());
+    }
+
+    if meta.is_dir() {
+        print_dir_preview(mode, path, naming, ignore)?;
+    }
+
+    Ok(())
+}
+
+// How many levels of a directory are shown in its preview
+const MAX_PREVIEW_DEPTH: usize = 2;
+// How many entries of a single directory are shown before the rest is collapsed
+const MAX_PREVIEW_ENTRIES: usize = 20;
+
+// Totals of everything inside a directory, recursively
+#[derive(Default)]
+pub struct DirSummary {
+    pub files: u64,
+    pub dirs: u64,
+    pub bytes: u64,
+}
+
+impl DirSummary {
+    pub fn describe(&self) -> String {
+        format!(
+            "{} files, {} dirs, {}",
+            self.files,
+            self.dirs,
+            format_size(self.bytes)
+        )
+    }
+}
+
+// Helper to print a depth-limited tree of a directory with its totals
+//
+// Instead of dumping every file, only names are shown. Files get their size,
+// sub directories get their totals, and anything deeper than
+// MAX_PREVIEW_DEPTH is only counted.
+//
+// @param path: &Path - the directory path to preview
+// @param naming: &BackupNaming - how backups are named, to leave them out
+// @param ignore: &Ignore - what the source's `.dbdmignore` leaves out
+// @return Result<()> - if print was successful
+fn print_dir_preview(
+    mode: &RunMode,
+    path: &Path,
+    naming: &BackupNaming,
+    ignore: &Ignore,
+) -> std::io::Result<()> {
+    let summary = summarize_dir(path, naming, ignore)?;
+    app_println!(
+        mode,
+        "\nDIRECTORY: {} ({})",
+        path.display(),
+        summary.describe()
+    );
+    print_dir_tree(mode, path, Path::new(""), naming, ignore, "", 1)
+}
+
+// Helper to print one level of the directory tree
+//
+// @param path: &Path - the directory to list
+// @param rel: &Path - the directory, relative to the previewed one
+// @param naming: &BackupNaming - how backups are named, to leave them out
+// @param ignore: &Ignore - what the source's `.dbdmignore` leaves out
+// @param prefix: &str - the tree drawing prefix of the current level
+// @param depth: usize - the current depth, starting at 1
+// @return Result<()> - if print was successful
+fn print_dir_tree(
+    mode: &RunMode,
+    path: &Path,
+    rel: &Path,
+    naming: &BackupNaming,
+    ignore: &Ignore,
+    prefix: &str,
+    depth: usize,
+) -> std::io::Result<()> {
+    let mut entries: Vec<PathBuf> = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<Vec<_>>>()?
+        .into_iter()
+        .filter(|entry| {
+            entry
+                .file_name()
+                .is_some_and(|name| !is_backup_name(name, naming))
+                && !is_ignored_entry(entry, rel, ignore)
+        })
+        .collect();
+    entries.sort();
+
+    let hidden = entries.len().saturating_sub(MAX_PREVIEW_ENTRIES);
+    entries.truncate(MAX_PREVIEW_ENTRIES);
+
+    let count = entries.len();
+    for (idx, entry_path) in entries.iter().enumerate() {
+        let is_last = idx + 1 == count && hidden == 0;
+        let branch = if is_last { "└── " } else { "├── " };
+        let name = entry_path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        let meta = std::fs::symlink_metadata(entry_path)?;
+
+        if meta.file_type().is_symlink() {
+            let target = std::fs::read_link(entry_path)?;
+            app_println!(mode, "{}{}{} -> {}", prefix, branch, name, target.display());
+        } else if meta.is_dir() {
+            let entry_rel = rel.join(&name);
+            let summary = summarize_tree(entry_path, &entry_rel, naming, ignore)?;
+            app_println!(
+                mode,
+                "{}{}{}/ ({})",
+                prefix,
+                branch,
+                name,
+                summary.describe()
+            );
+            if depth < MAX_PREVIEW_DEPTH {
+                let nested = format!("{}{}", prefix, if is_last { "    " } else { "│   " });
+                print_dir_tree(
+                    mode,
+                    entry_path,
+                    &entry_rel,
+                    naming,
+                    ignore,
+                    &nested,
+                    depth + 1,
+                )?;
+            }
+        } else {
+            app_println!(
+                mode,
+                "{}{}{} ({})",
+                prefix,
+                branch,
+                name,
+                format_size(meta.len())
+            );
+        }
+    }
+
+    if hidden > 0 {
+        app_println!(mode, "{}└── … {} more", prefix, hidden);
+    }
+
+    Ok(())
+}
+
+// Helper to get the size of a backup
+//
+// @param path: &Path - the backup
+// @param naming: &BackupNaming - how backups are named, to leave older ones out
+// @return u64 - the size in bytes, 0 if it can't be read
+fn backup_size(path: &Path, naming: &BackupNaming) -> u64 {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => summarize_dir(path, naming, &Ignore::default())
+            .map(|summary| summary.bytes)
+            .unwrap_or(0),
+        Ok(meta) if meta.is_file() => meta.len(),
+        _ => 0,
+    }
+}
+
+// Helper to count the files, directories and bytes inside a directory
+//
+// Symlinks are counted as files but not followed.
+//
+// @param path: &Path - the directory to summarize
+// @param naming: &BackupNaming - how backups are named, to leave them out
+// @param ignore: &Ignore - what a `.dbdmignore` leaves out
+// @return Result<DirSummary> - the totals
+pub fn summarize_dir(
+    path: &Path,
+    naming: &BackupNaming,
+    ignore: &Ignore,
+) -> std::io::Result<DirSummary> {
+    summarize_tree(path, Path::new(""), naming, ignore)
+}
+
+// Helper to summarize a directory below the one the ignore rules are for
+fn summarize_tree(
+    path: &Path,
+    rel: &Path,
+    naming: &BackupNaming,
+    ignore: &Ignore,
+) -> std::io::Result<DirSummary> {
+    let mut summary = DirSummary::default();
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        // Old backups inside a linked tree aren't part of its contents
+        if is_backup_name(&entry.file_name(), naming) {
+            continue;
+        }
+        let entry_path = entry.path();
+        let meta = std::fs::symlink_metadata(&entry_path)?;
+        let entry_rel = rel.join(entry.file_name());
+        if ignore.is_ignored(&entry_rel, meta.is_dir()) {
+            continue;
+        }
+
+        if meta.is_dir() {
+            let nested = summarize_tree(&entry_path, &entry_rel, naming, ignore)?;
+            summary.dirs += 1 + nested.dirs;
+            summary.files += nested.files;
+            summary.bytes += nested.bytes;
+        } else {
+            summary.files += 1;
+            summary.bytes += meta.len();
+        }
+    }
+    Ok(summary)
+}
+
+// Helper to check a directory entry against the ignore rules
+//
+// @param entry: &Path - the entry
+// @param rel: &Path - the directory it is in, relative to the one the rules are for
+// @param ignore: &Ignore - the rules
+// @return bool - true if the entry is left out
+fn is_ignored_entry(entry: &Path, rel: &Path, ignore: &Ignore) -> bool {
+    let Some(name) = entry.file_name() else {
+        return false;
+    };
+    let is_dir = std::fs::symlink_metadata(entry).is_ok_and(|meta| meta.is_dir());
+    ignore.is_ignored(&rel.join(name), is_dir)
+}
+
+// Helper to print the head of a single file
+//
+// Only the first `mode.preview_lines` lines are read, so large files don't
+// have to be loaded. Binary detection is done on the sampled prefix.
+//
+// @param path: &Path - the file path to preview
+// @return Result<()> - if print was successful
+fn print_file_preview(mode: &RunMode, path: &Path) -> std::io::Result<()> {
+    // How much of the file is looked at to decide if it's binary
+    const BINARY_SAMPLE_SIZE: usize = 8 * 1024;
+
+    let meta = std::fs::metadata(path)?;
+    app_println!(
+        mode,
+        "\nFILE: {} ({})",
+        path.display(),
+        format_size(meta.len())
+    );
+
+    if meta.len() == 0 {
+        app_println!(mode, "(empty)");
+        return Ok(());
+    }
+
+    let mut reader =
+        std::io::BufReader::with_capacity(BINARY_SAMPLE_SIZE, std::fs::File::open(path)?);
+    let sample = reader.fill_buf()?;
+    if is_binary(sample) {
+        app_println!(mode, "BINARY FILE");
+        return Ok(());
+    }
+
+    let mut line = Vec::new();
+    for _ in 0..mode.preview_lines {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 {
+            break;
+        }
+        let text = String::from_utf8_lossy(&line);
+        app_println!(mode, "{}", text.trim_end_matches(['\n', '\r']));
+    }
+
+    if !reader.fill_buf()?.is_empty() {
+        app_println!(mode, "… truncated after {} lines", mode.preview_lines);
+    }
+
+    Ok(())
+}
+
+// Helper to guess if a chunk of a file is binary
+//
+// A NUL byte or invalid UTF-8 means binary. A multi-byte character cut off at
+// the end of the chunk is not counted as invalid.
+//
+// @param sample: &[u8] - the start of the file
+// @return bool - if the content looks binary
+fn is_binary(sample: &[u8]) -> bool {
+    if sample.contains(&0) {
+        return true;
+    }
+
+    match std::str::from_utf8(sample) {
+        Ok(_) => false,
+        Err(err) => err.error_len().is_some(),
+    }
+}
+
+// What the user answered to a conflict prompt
+enum Choice {
+    Action(SyncAction),
+    Edit,
+    Quit,
+}
+
+// Helper to get user choice on how to resolve a conflict
+//
+// @param can_merge: bool - if merging is offered too
+// @return Choice - the chosen answer, Quit if input was closed
+fn prompt_action(mode: &RunMode, can_merge: bool) -> Choice {
+    loop {
+        if can_merge {
+            app_print!(
+                mode,
+                "Action [r]eplace, [b]ackup, [m]erge, [s]kip, [e]dit, [q]uit: "
+            );
+        } else {
+            app_print!(mode, "Action [r]eplace, [b]ackup, [s]kip, [e]dit, [q]uit: ");
+        }
+        let choices: &[&str] = if can_merge {
+            &["replace", "backup", "merge", "skip", "edit", "quit"]
+        } else {
+            &["replace", "backup", "skip", "edit", "quit"]
+        };
+        emit(mode, "prompt", serde_json::json!({ "choices": choices }));
+        let Some(input) = read_answer(mode) else {
+            return Choice::Quit;
+        };
+
+        let choice = input.trim().to_lowercase();
+        match choice.as_str() {
+            "r" | "replace" => return Choice::Action(SyncAction::Replace),
+            "b" | "backup" => return Choice::Action(SyncAction::BackupReplace),
+            "m" | "merge" if can_merge => return Choice::Action(SyncAction::Merge),
+            "s" | "skip" => return Choice::Action(SyncAction::Skip),
+            "e" | "edit" => return Choice::Edit,
+            "q" | "quit" => return Choice::Quit,
+            _ if can_merge => app_println!(mode, "Invalid choice. Use r, b, m, s, e, or q."),
+            _ => app_println!(mode, "Invalid choice. Use r, b, s, e, or q."),
+        }
+    }
+}
+
+// Helper to try merging a copy before merge is chosen for it
+//
+// When both sides changed the same lines, the merge is printed with its
+// conflict markers, so the user can pick another action.
+//
+// @param item: &PlanItem - the diverged copy
+// @return bool - if the merge has no conflicts
+fn merges_cleanly(mode: &RunMode, item: &PlanItem) -> bool {
+    match copies::merge_preview(&item.from, &item.to) {
+        Ok(Some(merge)) if merge.conflicts == 0 => true,
+        Ok(Some(merge)) => {
+            app_println!(mode, "\n{}", String::from_utf8_lossy(&merge.content));
+            app_println!(
+                mode,
+                "{} with the source, pick another action",
+                plural(merge.conflicts, "conflict")
+            );
+            false
+        }
+        Ok(None) => {
+            warn!("Nothing to merge {} from", item.to.display());
+            false
+        }
+        Err(err) => {
+            warn!("Could not merge {}: {}", item.to.display(), err);
+            false
+        }
+    }
+}
+
+// What the user answered to a failed item
+enum OnError {
+    Retry,
+    Skip,
+    Abort,
+}
+
+// Helper to get user choice on what to do about an item that failed
+//
+// @param err: &std::io::Error - why it failed
+// @return OnError - the chosen answer, Abort if input was closed
+fn prompt_on_error(mode: &RunMode, item: &PlanItem, err: &std::io::Error) -> OnError {
+    app_println!(mode, "\nFailed to apply {}: {}", item.to.display(), err);
+    loop {
+        app_print!(mode, "Action [r]etry, [s]kip, [a]bort: ");
+        emit_item(
+            mode,
+            "prompt",
+            item,
+            serde_json::json!({
+                "error": err.to_string(),
+                "choices": ["retry", "skip", "abort"],
+            }),
+        );
+        let Some(input) = read_answer(mode) else {
+            return OnError::Abort;
+        };
+
+        let choice = input.trim().to_lowercase();
+        match choice.as_str() {
+            "r" | "retry" => return OnError::Retry,
+            "s" | "skip" => return OnError::Skip,
+            "a" | "abort" => return OnError::Abort,
+            _ => app_println!(mode, "Invalid choice. Use r, s, or a."),
+        }
+    }
+}
+
+// Helper to execute an item, asking what to do each time it fails
+//
+// Without a terminal to ask, the first failure is returned as is.
+//
+// @param stopped: &mut bool - set when the user aborts, so the items left aren't applied
+// @param execute: FnMut() -> Result<T> - applies the item
+// @return Result<T> - the last outcome
+fn execute_with_retry<T>(
+    mode: &RunMode,
+    item: &PlanItem,
+    stopped: &mut bool,
+    mut execute: impl FnMut() -> std::io::Result<T>,
+) -> std::io::Result<T> {
+    loop {
+        let outcome = execute();
+        let Err(err) = &outcome else {
+            return outcome;
+        };
+        if !mode.interactive {
+            return outcome;
+        }
+        match prompt_on_error(mode, item, err) {
+            OnError::Retry => debug!(to = %item.to.display(), "retrying"),
+            OnError::Skip => return outcome,
+            OnError::Abort => {
+                *stopped = true;
+                return outcome;
+            }
+        }
+    }
+}
+
+// Helper to ask for the final confirmation of a plan, letting the user go back on a conflict
+//
+// The answers changed the plan, so what will change is listed once more, with
+// the conflicts among it even when they were skipped. Entering the number of a
+// conflict asks about it again, and the changes are listed anew.
+//
+// @param plan: &mut [PlanItem] - the resolved plan, updated in place
+// @param decided: &[usize] - the indices of the conflicts the user was asked about
+// @param options: &SyncOptions - the flags for this sync
+// @return bool - true if confirmed, false otherwise
+fn confirm_plan(
+    mode: &RunMode,
+    plan: &mut [PlanItem],
+    decided: &[usize],
+    options: &SyncOptions,
+) -> bool {
+    let mut changed = true;
+    loop {
+        let listed: Vec<usize> = (0..plan.len())
+            .filter(|idx| plan[*idx].action.changes_destination() || decided.contains(idx))
+            .collect();
+        if changed && mode.lists_items() {
+            let items: Vec<&PlanItem> = listed.iter().map(|&idx| &plan[idx]).collect();
+            present::print_changes(mode, &items);
+        }
+        if decided.is_empty() {
+            return confirm_proceed(mode, "Proceed?");
+        }
+
+        app_print!(
+            mode,
+            "\nProceed? [y/N], or the number of a conflict to change it: "
+        );
+        emit(
+            mode,
+            "confirm",
+            serde_json::json!({ "choices": ["yes", "no", "number"] }),
+        );
+        let Some(input) = read_answer(mode) else {
+            return false;
+        };
+        let input = input.trim().to_lowercase();
+        let Ok(number) = input.parse::<usize>() else {
+            return matches!(input.as_str(), "y" | "yes");
+        };
+
+        match number.checked_sub(1).and_then(|pos| listed.get(pos)) {
+            Some(&idx) if decided.contains(&idx) => {
+                plan[idx].action = SyncAction::Pending;
+                plan[idx].reason = None;
+                if !resolve_conflict(mode, &mut plan[idx], options) {
+                    return false;
+                }
+                emit_item(mode, "decided", &plan[idx], serde_json::json!({}));
+                enforce_system_backups(plan);
+                changed = true;
+            }
+            _ => {
+                app_println!(mode, "Item {} is not one of the conflicts.", number);
+                changed = false;
+            }
+        }
+    }
+}
+
+// Helper to ask for a final confirmation before executing actions
+//
+// @param question: &str - what is being confirmed
+// @return bool - true if confirmed, false otherwise
+fn confirm_proceed(mode: &RunMode, question: &str) -> bool {
+    app_print!(mode, "\n{} [y/N]: ", question);
+    emit(
+        mode,
+        "confirm",
+        serde_json::json!({ "choices": ["yes", "no"] }),
+    );
+    let Some(input) = read_answer(mode) else {
+        return false;
+    };
+
+    matches!(input.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+// Helper to read a single line of user input
+//
+// Once stdin is closed, or can't be read anymore, no answer will ever come,
+// so this reports it and gives up instead of asking again. With
+// `--non-interactive` stdin is never read.
+//
+// @return Option<String> - the line read, None if input is closed
+fn read_answer(mode: &RunMode) -> Option<String> {
+    if !mode.interactive {
+        app_println!(mode, "");
+        warn!("An answer is needed, but --non-interactive is set, aborting");
+        emit(
+            mode,
+            "input_closed",
+            serde_json::json!({ "error": "non-interactive" }),
+        );
+        return None;
+    }
+
+    let mut stdout = std::io::stdout();
+    let _ = std::io::Write::flush(&mut stdout);
+
+    let mut input = String::new();
+    match std::io::stdin().read_line(&mut input) {
+        Ok(0) => {
+            app_println!(mode, "");
+            warn!("Input closed, aborting");
+            emit(mode, "input_closed", serde_json::json!({}));
+            None
+        }
+        Ok(_) => {
+            mode.answers.set(mode.answers.get() + 1);
+            Some(input)
+        }
+        Err(err) => {
+            app_println!(mode, "");
+            warn!("Failed to read input, aborting: {}", err);
+            emit(
+                mode,
+                "input_closed",
+                serde_json::json!({ "error": err.to_string() }),
+            );
+            None
+        }
+    }
+}
+
+// Helper to add a finished sync to the history
+//
+// The backups it made are added to the backup registry, with the id of the run.
+//
+// @param report: &SyncReport - the finished sync
+pub fn record_history(report: &SyncReport) {
+    let items = report
+        .executed
+        .iter()
+        .map(|ExecutedItem { item, .. }| history::Item {
+            from: item.from.clone(),
+            to: item.to.clone(),
+            action: item.action.as_str().to_string(),
+            reason: item.reason.as_ref().map(ToString::to_string),
+        })
+        .collect();
+    let outcome = if report.aborted { "aborted" } else { "done" };
+    let run = history::record(report.started_at, "sync", outcome, items)
+        .inspect_err(|err| warn!("Could not record the sync in the history: {}", err))
+        .ok();
+
+    let made: Vec<backups::Record> = report
+        .executed
+        .iter()
+        .filter_map(|ExecutedItem { item, backup }| {
+            Some(backups::Record {
+                original: item.to.clone(),
+                backup: backup.clone()?,
+                time: report.started_at,
+                run,
+            })
+        })
+        .collect();
+    if let Err(err) = backups::record(&made) {
+        warn!("Could not record the backups in the registry: {}", err);
+    }
+}
+
+fn is_empty_path(path: &Path, meta: &FileStat, naming: &BackupNaming) -> std::io::Result<bool> {
+    if meta.is_file() {
+        return Ok(meta.len == 0);
+    }
+
+    if meta.is_dir() {
+        return is_empty_dir_recursive(path, naming);
+    }
+
+    if meta.is_symlink() {
+        return Ok(false);
+    }
+
+    Ok(false)
+}
+
+fn is_empty_dir_recursive(path: &Path, naming: &BackupNaming) -> std::io::Result<bool> {
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        if is_backup_name(&entry.file_name(), naming) {
+            continue;
+        }
+        let entry_path = entry.path();
+        let meta = std::fs::symlink_metadata(&entry_path)?;
+
+        if meta.is_file() {
+            if meta.len() > 0 {
+                return Ok(false);
+            }
+            continue;
+        }
+
+        if meta.is_dir() {
+            if !is_empty_dir_recursive(&entry_path, naming)? {
+                return Ok(false);
+            }
+            continue;
+        }
+
+        return Ok(false);
+    }
+
+    Ok(true)
+}
@@ -1,172 +1,1932 @@
-use regex::Regex;
+use crate::expand::{VarMap, expand};
+use crate::{BackupLocation, ConflictPolicy, canonicalize_or_fallback, resolve_link_destination};
+use std::borrow::Cow;
+use std::collections::HashSet;
 use std::env;
+use std::ffi::{OsStr, OsString};
 use std::fs;
-use std::path::PathBuf;
-use std::sync::LazyLock;
-
-static PARAMS_REGEXP: LazyLock<Regex> = LazyLock::new(|| {
-    Regex::new(r"^(?P<from>/?\S+/?)[ \t]+(?P<to>/?\S+/?)[ \t]*$")
-        .map_err(|err| format!("Regex init error: {}", err))
-        .unwrap()
-});
-static HOME_DIR: LazyLock<String> = LazyLock::new(|| env::var("HOME").expect("Can't read $HOME"));
-static XDG_CONFIG_HOME: LazyLock<String> = LazyLock::new(|| {
-    env::var("XDG_CONFIG_HOME").unwrap_or_else(|_| format!("{}/.config", HOME_DIR.as_str()))
-});
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
 
 #[derive(Debug, PartialEq)]
 pub struct Link {
     pub from: PathBuf,
     pub to: PathBuf,
+    pub kind: LinkKind,
+    pub options: LinkOptions,
+    // The `[module <name>]` block the link is in, None outside of one
+    pub module: Option<String>,
 }
 
-#[derive(Debug)]
+impl Link {
+    pub fn new(from: PathBuf, to: PathBuf) -> Link {
+        Link {
+            from,
+            to,
+            kind: LinkKind::Link,
+            options: LinkOptions::default(),
+            module: None,
+        }
+    }
+
+    // The path the link ends up at, used to tell if two links clash
+    //
+    // @return PathBuf - the canonical destination, a file source linked into a directory ends up inside it
+    pub fn destination(&self) -> PathBuf {
+        let to = resolve_link_destination(&self.from, &self.to).unwrap_or_else(|_| self.to.clone());
+        canonicalize_or_fallback(&to)
+    }
+
+    // Helper to write the link back as a config line, with its paths expanded
+    //
+    // @return Option<OsString> - the line, None if a path can't be written on this platform
+    pub fn config_line(&self) -> Option<OsString> {
+        let mut line = OsString::from(self.kind.as_str());
+        line.push(" = ");
+        line.push(crate::import::quote(self.from.as_os_str())?);
+        line.push(" ");
+        line.push(crate::import::quote(self.to.as_os_str())?);
+        for option in self.options.to_args() {
+            line.push(" ");
+            line.push(crate::import::quote(OsStr::new(&option))?);
+        }
+        Some(line)
+    }
+}
+
+// The kind of a config line, the part before `=`
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LinkKind {
+    // `link = ...`, a user config
+    Link,
+    // `system = ...`, a destination outside $HOME such as /etc, handled with extra care
+    System,
+    // `encrypted = ...`, an .age or .gpg source decrypted into a file at the destination
+    Encrypted,
+}
+
+impl LinkKind {
+    // Helper to get the kind as it is written in the config
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            LinkKind::Link => "link",
+            LinkKind::System => "system",
+            LinkKind::Encrypted => "encrypted",
+        }
+    }
+}
+
+// The keys used to decrypt `encrypted` sources, from the `[secrets]` section
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Secrets {
+    // The age identity file, from `identity = <path>`
+    pub identity: Option<PathBuf>,
+    // The gpg key to try for decryption, from `gpg_key = <id>`
+    pub gpg_key: Option<String>,
+}
+
+// How long backups of replaced destinations are kept, from the `[backups]` section
+//
+// A backup is removed once it is past either limit. Without any limit
+// backups are kept forever.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Retention {
+    // How many backups of each destination are kept, newest first, from `keep = <n>`
+    pub keep: Option<usize>,
+    // How many days a backup is kept, from `keep_days = <n>`
+    pub keep_days: Option<u64>,
+}
+
+impl Retention {
+    // If any limit is set
+    pub fn is_set(&self) -> bool {
+        self.keep.is_some() || self.keep_days.is_some()
+    }
+}
+
+// The `key=value` options following the paths of a link
+#[derive(Debug, Default, PartialEq)]
+pub struct LinkOptions {
+    // Permission bits the source is kept at, from `mode=0600`
+    pub mode: Option<u32>,
+    // Owner the source is kept at, from `owner=user` or `owner=user:group`
+    pub owner: Option<String>,
+    // Changes to the destination always go through sudo, from `root=true`
+    pub root: bool,
+    // Destinations of links this one is applied after, from `after=<dest>`
+    pub after: Vec<PathBuf>,
+    // How a conflict at the destination is resolved, from `on_conflict=backup`, before `--on-conflict`
+    pub on_conflict: Option<ConflictPolicy>,
+    // A conflict is never replaced without asking, whatever the flags, from `protect=true`
+    pub protect: bool,
+    // The files of the source are kept read-only, from `readonly=true`
+    pub readonly: bool,
+}
+
+impl LinkOptions {
+    // Helper to write the options back the way they go into a config line
+    //
+    // @return Vec<String> - one `key=value` per option that is set, in the order of the struct
+    pub fn to_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        if let Some(mode) = self.mode {
+            args.push(format!("mode={:04o}", mode));
+        }
+        if let Some(owner) = &self.owner {
+            args.push(format!("owner={}", owner));
+        }
+        if self.root {
+            args.push(String::from("root=true"));
+        }
+        for after in &self.after {
+            args.push(format!("after={}", after.display()));
+        }
+        if let Some(policy) = self.on_conflict {
+            args.push(format!("on_conflict={}", policy.as_str()));
+        }
+        if self.protect {
+            args.push(String::from("protect=true"));
+        }
+        if self.readonly {
+            args.push(String::from("readonly=true"));
+        }
+        args
+    }
+}
+
+#[derive(Debug, Default)]
 pub struct Config {
     pub links: Vec<Link>,
+    pub secrets: Secrets,
+    pub retention: Retention,
+    // Where backups go, from `location =` in the `[backups]` section, None if unset
+    pub backup_location: Option<BackupLocation>,
+}
+
+// What to do when configs being merged have a link to the same destination
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MergeStrategy {
+    // The merge fails
+    Error,
+    // The link merged in replaces the one already there, in its place
+    PreferLast,
+    // The link already there is kept, the one merged in is dropped
+    PreferFirst,
+}
+
+impl Config {
+    // Starts a config built in code instead of read from a file
+    pub fn builder() -> ConfigBuilder {
+        ConfigBuilder::default()
+    }
+
+    // Merges another config into this one, matching links by destination
+    //
+    // Keys from `[secrets]` and `[backups]` follow the same strategy. With
+    // `MergeStrategy::Error` nothing is merged if there is a clash.
+    //
+    // @param other: Config - the config to merge in, its links go after the existing ones
+    // @param strategy: MergeStrategy - what to do with a destination declared by both
+    // @return Result<(), String> - an error naming the first clash, with `MergeStrategy::Error`
+    pub fn merge(&mut self, other: Config, strategy: MergeStrategy) -> Result<(), String> {
+        if strategy == MergeStrategy::Error {
+            let mut taken: HashSet<PathBuf> = self.links.iter().map(Link::destination).collect();
+            if let Some(link) = other
+                .links
+                .iter()
+                .find(|link| !taken.insert(link.destination()))
+            {
+                return Err(format!(
+                    "Destination {} is already declared",
+                    link.to.display()
+                ));
+            }
+            if clashes(&self.secrets.identity, &other.secrets.identity)
+                || clashes(&self.secrets.gpg_key, &other.secrets.gpg_key)
+            {
+                return Err(String::from("The [secrets] keys are already declared"));
+            }
+            if clashes(&self.retention.keep, &other.retention.keep)
+                || clashes(&self.retention.keep_days, &other.retention.keep_days)
+                || clashes(&self.backup_location, &other.backup_location)
+            {
+                return Err(String::from("The [backups] keys are already declared"));
+            }
+        }
+
+        merge_by_destination(&mut self.links, other.links, strategy, |link| link);
+        merge_secrets(&mut self.secrets, other.secrets, strategy);
+        merge_retention(&mut self.retention, other.retention, strategy);
+        self.backup_location = match strategy {
+            MergeStrategy::PreferFirst => self.backup_location.or(other.backup_location),
+            _ => other.backup_location.or(self.backup_location),
+        };
+        Ok(())
+    }
+}
+
+// Helper to merge items holding links into a list, matching them by destination
+//
+// @param existing: &mut Vec<T> - the items to merge into
+// @param incoming: Vec<T> - the items to merge, in order
+// @param strategy: MergeStrategy - what to do with a destination that is already taken
+// @param link: fn(&T) -> &Link - how to get the link of an item
+// @return Vec<(T, usize)> - the items refused with `MergeStrategy::Error`, with the index of the item holding the destination
+fn merge_by_destination<T>(
+    existing: &mut Vec<T>,
+    incoming: Vec<T>,
+    strategy: MergeStrategy,
+    link: fn(&T) -> &Link,
+) -> Vec<(T, usize)> {
+    let mut taken: Vec<PathBuf> = existing
+        .iter()
+        .map(|item| link(item).destination())
+        .collect();
+    let mut refused = Vec::new();
+
+    for item in incoming {
+        let destination = link(&item).destination();
+        match taken.iter().position(|taken| *taken == destination) {
+            Some(pos) => match strategy {
+                MergeStrategy::Error => refused.push((item, pos)),
+                MergeStrategy::PreferLast => existing[pos] = item,
+                MergeStrategy::PreferFirst => {}
+            },
+            None => {
+                existing.push(item);
+                taken.push(destination);
+            }
+        }
+    }
+
+    refused
+}
+
+// Helper to merge the `[secrets]` keys of two configs
+//
+// @param secrets: &mut Secrets - the keys to merge into
+// @param other: Secrets - the keys to merge in
+// @param strategy: MergeStrategy - which keys win when both are set, the new ones unless `PreferFirst`
+fn merge_secrets(secrets: &mut Secrets, other: Secrets, strategy: MergeStrategy) {
+    let prefer_first = strategy == MergeStrategy::PreferFirst;
+    if other.identity.is_some() && !(prefer_first && secrets.identity.is_some()) {
+        secrets.identity = other.identity;
+    }
+    if other.gpg_key.is_some() && !(prefer_first && secrets.gpg_key.is_some()) {
+        secrets.gpg_key = other.gpg_key;
+    }
+}
+
+// Helper to merge the `[backups]` limits of two configs
+//
+// @param retention: &mut Retention - the limits to merge into
+// @param other: Retention - the limits to merge in
+// @param strategy: MergeStrategy - which limits win when both are set, the new ones unless `PreferFirst`
+fn merge_retention(retention: &mut Retention, other: Retention, strategy: MergeStrategy) {
+    let prefer_first = strategy == MergeStrategy::PreferFirst;
+    if other.keep.is_some() && !(prefer_first && retention.keep.is_some()) {
+        retention.keep = other.keep;
+    }
+    if other.keep_days.is_some() && !(prefer_first && retention.keep_days.is_some()) {
+        retention.keep_days = other.keep_days;
+    }
+}
+
+// Helper to tell if two optional keys are both set to different values
+fn clashes<T: PartialEq>(a: &Option<T>, b: &Option<T>) -> bool {
+    matches!((a, b), (Some(a), Some(b)) if a != b)
+}
+
+// Builds a `Config` link by link, checking every link like the parser does
+//
+// Paths are taken as they are, use `dbdm::expand` first for `!keyword` paths.
+#[derive(Debug, Default)]
+pub struct ConfigBuilder {
+    links: Vec<Link>,
+    secrets: Secrets,
+    retention: Retention,
+    backup_location: Option<BackupLocation>,
+}
+
+impl ConfigBuilder {
+    // Adds a `link = <from> <to>` entry
+    pub fn link(self, from: impl Into<PathBuf>, to: impl Into<PathBuf>) -> ConfigBuilder {
+        self.entry(Link::new(from.into(), to.into()))
+    }
+
+    // Adds a `system = <from> <to>` entry
+    pub fn system(self, from: impl Into<PathBuf>, to: impl Into<PathBuf>) -> ConfigBuilder {
+        self.entry(Link {
+            kind: LinkKind::System,
+            ..Link::new(from.into(), to.into())
+        })
+    }
+
+    // Adds an `encrypted = <from> <to>` entry
+    pub fn encrypted(self, from: impl Into<PathBuf>, to: impl Into<PathBuf>) -> ConfigBuilder {
+        self.entry(Link {
+            kind: LinkKind::Encrypted,
+            ..Link::new(from.into(), to.into())
+        })
+    }
+
+    // Adds a link with its kind and options already set
+    pub fn entry(mut self, link: Link) -> ConfigBuilder {
+        self.links.push(link);
+        self
+    }
+
+    // Sets the keys used to decrypt `encrypted` sources
+    pub fn secrets(mut self, secrets: Secrets) -> ConfigBuilder {
+        self.secrets = secrets;
+        self
+    }
+
+    // Sets how long backups are kept
+    pub fn retention(mut self, retention: Retention) -> ConfigBuilder {
+        self.retention = retention;
+        self
+    }
+
+    // Sets where backups go
+    pub fn backup_location(mut self, location: BackupLocation) -> ConfigBuilder {
+        self.backup_location = Some(location);
+        self
+    }
+
+    // Checks the links and returns the config
+    //
+    // @return Result<Config, String> - the config, or an error naming the first invalid link
+    pub fn build(self) -> Result<Config, String> {
+        for link in &self.links {
+            check_link(link).map_err(|err| {
+                format!(
+                    "Invalid link {} -> {}: {}",
+                    link.from.display(),
+                    link.to.display(),
+                    err
+                )
+            })?;
+        }
+
+        Ok(Config {
+            links: self.links,
+            secrets: self.secrets,
+            retention: self.retention,
+            backup_location: self.backup_location,
+        })
+    }
+}
+
+// A successfully parsed link together with the file and line it was declared on
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    pub file: PathBuf,
+    // Counted from 1
+    pub line: usize,
+    pub link: Link,
+}
+
+// An error attached to the file and line of the config it was found on
+#[derive(Debug, PartialEq)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    // Counted from 1
+    pub line: usize,
+    pub message: String,
+    // Something this version of dbdm doesn't know, like a kind or an option a
+    // newer one added. Outside of strict mode its line is skipped with a warning.
+    pub unknown: bool,
+}
+
+// The result of parsing every line of a config, without stopping at the first error
+#[derive(Debug, Default)]
+pub struct ParsedConfig {
+    pub entries: Vec<Entry>,
+    pub errors: Vec<Diagnostic>,
+    // Lines that were skipped for something unknown, outside of strict mode
+    pub warnings: Vec<Diagnostic>,
+    pub secrets: Secrets,
+    pub retention: Retention,
+    // The `location = source|state` key of the `[backups]` section, None if unset
+    pub backup_location: Option<BackupLocation>,
+    // The `strict = true|false` setting, None if the config doesn't have one
+    pub strict: Option<bool>,
 }
 
 pub fn read_config(path: &PathBuf) -> Result<Config, String> {
-    let content = match fs::read_to_string(path) {
-        Ok(res) => res,
-        Err(err) => {
-            return Err(err.to_string());
+    read_config_with(path, false)
+}
+
+// Same as `read_config`, with a choice of strict mode
+//
+// @param path: &PathBuf - the path to the config file
+// @param strict: bool - if unknown kinds, options, keywords and sections are errors, whatever the config says
+// @return Result<Config, String> - the config, or the first error in it
+pub fn read_config_with(path: &PathBuf, strict: bool) -> Result<Config, String> {
+    let parsed = parse_config_with(path, strict)?;
+    if let Some(err) = parsed.errors.into_iter().next() {
+        // Errors from included files need to say which file they are from
+        if err.file != *path {
+            return Err(format!("{}: {}", err.file.display(), err.message));
         }
-    };
+        return Err(err.message);
+    }
+
+    Ok(Config {
+        links: parsed.entries.into_iter().map(|entry| entry.link).collect(),
+        secrets: parsed.secrets,
+        retention: parsed.retention,
+        backup_location: parsed.backup_location,
+    })
+}
+
+// Parses the whole config, collecting every line error instead of bailing out
+//
+// Files pulled in with `include` are parsed in place, so the entries keep the
+// order in which they appear once all includes are expanded.
+//
+// @param path: &Path - the path to the config file
+// @return Result<ParsedConfig, String> - the parsed entries and errors, or an error if the file can't be read
+pub fn parse_config(path: &Path) -> Result<ParsedConfig, String> {
+    parse_config_with(path, false)
+}
+
+// Same as `parse_config`, with a choice of strict mode
+//
+// Outside of strict mode, lines with something unknown are moved from the
+// errors to the warnings, and logged. A config turns strict mode on with a
+// `strict = true` line.
+//
+// @param path: &Path - the path to the config file
+// @param strict: bool - if unknown kinds, options, keywords and sections are errors, whatever the config says
+// @return Result<ParsedConfig, String> - the parsed entries and errors, or an error if the file can't be read
+pub fn parse_config_with(path: &Path, strict: bool) -> Result<ParsedConfig, String> {
+    let mut parsed = parse_all(path)?;
+    if !strict && parsed.strict != Some(true) {
+        let (unknown, errors) = parsed.errors.into_iter().partition(|err| err.unknown);
+        parsed.errors = errors;
+        parsed.warnings = unknown;
+    }
+    for skipped in &parsed.warnings {
+        warn!("{}: {}, skipped", skipped.file.display(), skipped.message);
+    }
+    Ok(parsed)
+}
+
+// Helper to parse the config and its local overrides, with every error
+fn parse_all(path: &Path) -> Result<ParsedConfig, String> {
+    let mut parsed = ParsedConfig::default();
+    let mut stack: Vec<PathBuf> = Vec::new();
+    parse_file(path, &mut stack, &mut parsed)?;
+
+    // Merge the machine-local overrides on top, if there are any
+    let local_path = local_config_path(path);
+    if local_path.exists() {
+        let mut local = ParsedConfig::default();
+        let mut stack = vec![canonicalize_or_fallback(path)];
+        parse_file(&local_path, &mut stack, &mut local)?;
+        merge_local(&mut parsed, local);
+    }
+
+    Ok(parsed)
+}
+
+// Helper to get the path of the machine-local overrides for a config
+//
+// `dbdm.conf` gets `dbdm.local.conf` from the same directory.
+//
+// @param path: &Path - the path to the shared config file
+// @return PathBuf - the path to the local overrides file
+pub fn local_config_path(path: &Path) -> PathBuf {
+    let mut name = path
+        .file_stem()
+        .map(OsString::from)
+        .unwrap_or_else(|| OsString::from("dbdm"));
+    name.push(".local.conf");
+    path.with_file_name(name)
+}
+
+// Helper to merge the local overrides into the shared config
+//
+// A local link with the same source as shared links replaces them in place,
+// so a machine can point a source somewhere else. Other local links replace
+// a shared link to the same destination, or are added at the end.
+//
+// @param parsed: &mut ParsedConfig - the shared config to merge into
+// @param local: ParsedConfig - the parsed local overrides
+fn merge_local(parsed: &mut ParsedConfig, local: ParsedConfig) {
+    parsed.errors.extend(local.errors);
+    parsed.strict = local.strict.or(parsed.strict);
+
+    // Keys are usually different per machine, so local ones win
+    merge_secrets(
+        &mut parsed.secrets,
+        local.secrets,
+        MergeStrategy::PreferLast,
+    );
+    merge_retention(
+        &mut parsed.retention,
+        local.retention,
+        MergeStrategy::PreferLast,
+    );
+    parsed.backup_location = local.backup_location.or(parsed.backup_location);
+
+    let mut other_sources = Vec::new();
+    for entry in local.entries {
+        let from = canonicalize_or_fallback(&entry.link.from);
+        let same_source = |existing: &Entry| canonicalize_or_fallback(&existing.link.from) == from;
+
+        match parsed.entries.iter().position(same_source) {
+            Some(pos) => {
+                parsed.entries[pos] = entry;
+                let rest = parsed.entries.split_off(pos + 1);
+                parsed
+                    .entries
+                    .extend(rest.into_iter().filter(|existing| !same_source(existing)));
+            }
+            None => other_sources.push(entry),
+        }
+    }
+
+    merge_by_destination(
+        &mut parsed.entries,
+        other_sources,
+        MergeStrategy::PreferLast,
+        |entry| &entry.link,
+    );
+}
+
+// Helper to parse a single config file into the shared result
+//
+// @param path: &Path - the path to the config file
+// @param stack: &mut Vec<PathBuf> - the files currently being included, to detect cycles
+// @param parsed: &mut ParsedConfig - the result to collect entries and errors into
+// @return Result<(), String> - an error if the file can't be read
+fn parse_file(
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+    parsed: &mut ParsedConfig,
+) -> Result<(), String> {
+    // Paths in the config don't have to be valid UTF-8, so it's read as bytes
+    let content = fs::read(path).map_err(|err| err.to_string())?;
+    debug!(file = %path.display(), "parsing config");
+    stack.push(canonicalize_or_fallback(path));
+    // Lines after a `[section]` header belong to it, until the next one or the end of the file
+    let mut section = Section::Links;
+
+    // Lines are counted from 1, like editors and `<file>:<line>` tools do
+    for (idx, line) in (1..).zip(lines(&content)) {
+        if line.is_empty() || is_comment(line) {
+            continue;
+        };
+
+        let error = |message: String| Diagnostic {
+            file: path.to_path_buf(),
+            line: idx,
+            message,
+            unknown: false,
+        };
+        let unknown = |message: String| Diagnostic {
+            unknown: true,
+            ..error(message)
+        };
+
+        if let Some(name) = line
+            .trim_ascii()
+            .strip_prefix(b"[")
+            .and_then(|rest| rest.strip_suffix(b"]"))
+        {
+            let name = String::from_utf8_lossy(name);
+            match name.trim() {
+                "secrets" => section = Section::Secrets,
+                "backups" => section = Section::Backups,
+                header if header.starts_with("module ") => match parse_module(header, idx) {
+                    Ok(module) => section = Section::Module(module),
+                    Err(message) => parsed.errors.push(error(message)),
+                },
+                other => {
+                    // Its lines can't be read without knowing the section
+                    section = Section::Unknown;
+                    parsed.errors.push(unknown(format!(
+                        "Unknown section [{}] on line {}",
+                        other, idx
+                    )));
+                }
+            }
+            continue;
+        }
+
+        let parsed_setting = match section {
+            Section::Links | Section::Module(_) => None,
+            Section::Unknown => continue,
+            Section::Secrets => Some(parse_secret(line, idx, path, &mut parsed.secrets)),
+            Section::Backups => Some(parse_backups(
+                line,
+                idx,
+                &mut parsed.retention,
+                &mut parsed.backup_location,
+            )),
+        };
+        if let Some(parsed_setting) = parsed_setting {
+            if let Err(message) = parsed_setting {
+                parsed.errors.push(error(message));
+            }
+            continue;
+        }
+
+        if let Some((key, params)) = split_key(line)
+            && key.trim() == "version"
+        {
+            if let Err(message) = parse_version(params, idx) {
+                parsed.errors.push(error(message));
+            }
+            continue;
+        }
+
+        if let Some((key, params)) = split_key(line)
+            && key.trim() == "strict"
+        {
+            match String::from_utf8_lossy(params).trim() {
+                "true" => parsed.strict = Some(true),
+                "false" => parsed.strict = Some(false),
+                value => parsed.errors.push(error(format!(
+                    "Invalid strict value {} on line {}",
+                    value, idx
+                ))),
+            }
+            continue;
+        }
+
+        if let Some((key, params)) = split_key(line)
+            && key.trim() == "include"
+        {
+            let files = match resolve_include(params, idx, path) {
+                Ok(files) => files,
+                Err(message) => {
+                    parsed.errors.push(error(message));
+                    continue;
+                }
+            };
+
+            for file in files {
+                if stack.contains(&canonicalize_or_fallback(&file)) {
+                    parsed.errors.push(error(format!(
+                        "Include cycle on line {}: {} is already being included",
+                        idx,
+                        file.display()
+                    )));
+                    continue;
+                }
+
+                let mut included = ParsedConfig::default();
+                if let Err(err) = parse_file(&file, stack, &mut included) {
+                    parsed.errors.push(error(format!(
+                        "Failed to include {} on line {}: {}",
+                        file.display(),
+                        idx,
+                        err
+                    )));
+                }
+                // Files included in a module are part of it, unless they have modules of their own
+                if let Section::Module(module) = &section {
+                    for entry in &mut included.entries {
+                        entry.link.module.get_or_insert_with(|| module.clone());
+                    }
+                }
+                merge_included(parsed, included);
+            }
+            continue;
+        }
+
+        match parse_line(line, idx) {
+            Ok(Some(link)) => parsed.entries.push(Entry {
+                file: path.to_path_buf(),
+                line: idx,
+                link: Link {
+                    module: match &section {
+                        Section::Module(module) => Some(module.clone()),
+                        _ => None,
+                    },
+                    ..link
+                },
+            }),
+            // The link's conditions don't hold on this machine
+            Ok(None) => debug!(file = %path.display(), line = idx, "conditions don't hold"),
+            Err(LineError::Invalid(message)) => parsed.errors.push(error(message)),
+            Err(LineError::Unknown(message)) => parsed.errors.push(unknown(message)),
+        }
+    }
+
+    stack.pop();
+    Ok(())
+}
+
+// The part of a config file a line belongs to
+enum Section {
+    // Before any `[section]` header
+    Links,
+    Secrets,
+    Backups,
+    // Links grouped under `[module <name>]`
+    Module(String),
+    // A section this version doesn't know, its lines are skipped
+    Unknown,
+}
+
+// Why a line of a config was refused
+enum LineError {
+    Invalid(String),
+    // It has something this version doesn't know, a newer one may
+    Unknown(String),
+}
+
+impl From<String> for LineError {
+    fn from(message: String) -> LineError {
+        LineError::Invalid(message)
+    }
+}
+
+// The version of the config syntax this dbdm reads
+//
+// Configs without a `version = <n>` line are version 1.
+pub const CONFIG_VERSION: u32 = 1;
+
+// Helper to read the value of a `version = <n>` line
+//
+// A config for a newer version may mean something else by the same lines, so
+// it's refused instead of skipping what isn't known.
+//
+// @param params: &[u8] - everything after the `=`
+// @param idx: usize - the line number, for error reporting
+// @return Result<u32, String> - the version, an error if it isn't one this dbdm reads
+fn parse_version(params: &[u8], idx: usize) -> Result<u32, String> {
+    let value = String::from_utf8_lossy(params);
+    let version = value
+        .trim()
+        .parse::<u32>()
+        .ok()
+        .filter(|version| *version > 0)
+        .ok_or_else(|| format!("Invalid version {} on line {}", value.trim(), idx))?;
+    if version > CONFIG_VERSION {
+        return Err(format!(
+            "The config is written for version {} on line {}, but this dbdm only reads up to version {}. Upgrade dbdm to use it",
+            version, idx, CONFIG_VERSION
+        ));
+    }
+    Ok(version)
+}
+
+// Rewrites a config in the syntax of the current version
+//
+// Every config without a `version` line is written in the syntax of version 1,
+// the current one, so for now it only gets the line added at the top. Rewrites
+// of older syntaxes go here once the syntax changes.
+//
+// @param content: &[u8] - the contents of the config file
+// @return Result<Option<Vec<u8>>, String> - the new contents, None if the config is current already
+pub fn migrate(content: &[u8]) -> Result<Option<Vec<u8>>, String> {
+    let version = (1..)
+        .zip(lines(content))
+        .filter_map(|(idx, line)| {
+            split_key(line)
+                .filter(|(key, _)| key.trim() == "version")
+                .map(|(_, params)| parse_version(params, idx))
+        })
+        .next()
+        .transpose()?;
+    if version == Some(CONFIG_VERSION) {
+        return Ok(None);
+    }
+
+    let mut migrated = format!("version = {}\n", CONFIG_VERSION).into_bytes();
+    migrated.extend_from_slice(content);
+    Ok(Some(migrated))
+}
+
+// A link line taken apart by `format`, its arguments kept as written
+struct FormattedLink<'a> {
+    kind: String,
+    from: &'a [u8],
+    to: &'a [u8],
+    options: Vec<&'a [u8]>,
+    // `when` and the conditions after it
+    conditions: Vec<&'a [u8]>,
+    // The paths with their quotes and escapes removed, to sort by
+    source: OsString,
+    destination: OsString,
+}
+
+impl FormattedLink<'_> {
+    // Helper to get what makes two links the same, whatever their spacing and option order
+    fn key(&self) -> Vec<u8> {
+        let mut options = self.options.clone();
+        options.sort();
+        let mut key = self.kind.clone().into_bytes();
+        for arg in [self.from, self.to]
+            .into_iter()
+            .chain(options)
+            .chain(self.conditions.iter().copied())
+        {
+            key.push(0);
+            key.extend_from_slice(arg);
+        }
+        key
+    }
+}
+
+// How `config fmt` orders a run of link lines
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum SortBy {
+    #[default]
+    Source,
+    Destination,
+}
+
+impl SortBy {
+    // Helper to parse the value of the `--sort` flag
+    //
+    // @param value: &str - the flag value
+    // @return Option<SortBy> - the order, None if the value is unknown
+    pub fn parse(value: &str) -> Option<SortBy> {
+        match value {
+            "by-source" => Some(SortBy::Source),
+            "by-dest" => Some(SortBy::Destination),
+            _ => None,
+        }
+    }
+}
+
+// What `format_with` does besides the canonical style
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FormatOptions {
+    pub sort: SortBy,
+    // Lines that repeat an earlier line of the same section are left out
+    pub dedupe: bool,
+}
 
-    let mut links: Vec<Link> = Vec::new();
-    for (idx, line) in content.lines().enumerate() {
+// A config formatted by `format_with`
+#[derive(Debug)]
+pub struct Formatted {
+    pub content: Vec<u8>,
+    // Every line that repeats an earlier one of the same section, with the line it
+    // repeats, both counted from 1
+    pub duplicates: Vec<(usize, usize)>,
+}
+
+// A line of the config once formatted
+enum FormattedLine<'a> {
+    Blank,
+    Text(Vec<u8>),
+    Link(FormattedLink<'a>),
+}
+
+// Rewrites a config in the canonical style of `config fmt`
+//
+// Lines are trimmed and `key = value` lines get single spaces between their
+// values. Runs of link lines not broken up by anything else are sorted by
+// source and aligned in columns, with the options after the paths and the
+// conditions last. Runs of blank lines become one. Quotes and escapes are kept
+// as written, and comments and lines that can't be read, like those of unknown
+// sections, are only trimmed.
+//
+// @param content: &[u8] - the contents of the config file
+// @return Vec<u8> - the formatted contents, ending with a newline
+pub fn format(content: &[u8]) -> Vec<u8> {
+    format_with(content, &FormatOptions::default()).content
+}
+
+// Same as `format`, with a choice of order and of leaving out duplicates
+//
+// A line is a duplicate when it reads the same as an earlier line of the same
+// section once formatted, links also when only their options are in another order.
+// Duplicates are always found, and only left out with `dedupe`.
+//
+// @param content: &[u8] - the contents of the config file
+// @param options: &FormatOptions - how to sort, and if duplicates are left out
+// @return Formatted - the formatted contents and the duplicates found
+pub fn format_with(content: &[u8], options: &FormatOptions) -> Formatted {
+    let mut formatted: Vec<FormattedLine> = Vec::new();
+    let mut in_links = true;
+    let mut known_section = true;
+    let mut section = String::new();
+    // The section, key and line number of every line seen so far
+    let mut seen: Vec<(String, Vec<u8>, usize)> = Vec::new();
+    let mut duplicates = Vec::new();
+
+    for (idx, line) in (1..).zip(lines(content)) {
+        let line = line.trim_ascii();
         if line.is_empty() {
+            if !matches!(formatted.last(), None | Some(FormattedLine::Blank)) {
+                formatted.push(FormattedLine::Blank);
+            }
+            continue;
+        }
+        // Comments stay where they are, and the same comment twice isn't a duplicate
+        if is_comment(line) {
+            formatted.push(FormattedLine::Text(line.to_vec()));
             continue;
+        }
+
+        if let Some(name) = line
+            .strip_prefix(b"[")
+            .and_then(|rest| rest.strip_suffix(b"]"))
+        {
+            let name = String::from_utf8_lossy(name);
+            let name = name.split_whitespace().collect::<Vec<_>>().join(" ");
+            in_links = name.starts_with("module ");
+            known_section = in_links || name == "secrets" || name == "backups";
+            formatted.push(FormattedLine::Text(format!("[{}]", name).into_bytes()));
+            section = name;
+            continue;
+        }
+
+        let parts = split_key(line)
+            .filter(|_| known_section)
+            .and_then(|(key, params)| Some((key.trim().to_string(), split_raw(params)?)));
+        let next = match parts {
+            None => FormattedLine::Text(line.to_vec()),
+            Some((key, args)) => {
+                let is_link = in_links && ["link", "system", "encrypted"].contains(&key.as_str());
+                match is_link.then(|| link_parts(key.clone(), &args)).flatten() {
+                    Some(link) => FormattedLine::Link(link),
+                    None => {
+                        let mut text = format!("{} =", key).into_bytes();
+                        for arg in args {
+                            text.push(b' ');
+                            text.extend_from_slice(arg);
+                        }
+                        FormattedLine::Text(text)
+                    }
+                }
+            }
         };
 
-        let link: Link = match parse_line(line, idx) {
-            Ok(res) => res,
-            Err(err) => return Err(err),
+        let key = match &next {
+            FormattedLine::Link(link) => link.key(),
+            FormattedLine::Text(text) => text.clone(),
+            FormattedLine::Blank => Vec::new(),
         };
-        links.push(link);
+        if let Some((_, _, first)) = seen
+            .iter()
+            .find(|(seen_section, seen_key, _)| *seen_section == section && *seen_key == key)
+        {
+            duplicates.push((idx, *first));
+            if options.dedupe {
+                continue;
+            }
+        } else {
+            seen.push((section.clone(), key, idx));
+        }
+        formatted.push(next);
+    }
+    while let Some(FormattedLine::Blank) = formatted.last() {
+        formatted.pop();
     }
 
-    return Ok(Config { links: links });
+    let mut output = Vec::new();
+    let mut rest = formatted.as_slice();
+    while let Some(pos) = rest
+        .iter()
+        .position(|line| matches!(line, FormattedLine::Link(_)))
+    {
+        for line in &rest[..pos] {
+            push_line(&mut output, line);
+        }
+        let end = rest[pos..]
+            .iter()
+            .position(|line| !matches!(line, FormattedLine::Link(_)))
+            .map_or(rest.len(), |end| pos + end);
+        write_links(&mut output, &rest[pos..end], options.sort);
+        rest = &rest[end..];
+    }
+    for line in rest {
+        push_line(&mut output, line);
+    }
+    Formatted {
+        content: output,
+        duplicates,
+    }
 }
 
-fn parse_line(line: &str, idx: usize) -> Result<Link, String> {
-    // Read split out the line
-    let (text_kind, mut text_params) = match line.split_once('=') {
-        Some((a, b)) => (a, b),
-        None => return Err(format!("Invalid syntax on line {}", idx)),
+// Helper to take a link line apart, None if it doesn't have exactly two paths
+//
+// @param kind: String - the kind, trimmed
+// @param args: &[&[u8]] - the arguments as written
+// @return Option<FormattedLink> - the parts of the line
+fn link_parts<'a>(kind: String, args: &[&'a [u8]]) -> Option<FormattedLink<'a>> {
+    // Every argument has to read back as exactly one, or the line is kept as is
+    let values = args
+        .iter()
+        .map(|arg| match split_args(arg, 0).ok()?.as_slice() {
+            [value] => Some(value.clone()),
+            _ => None,
+        })
+        .collect::<Option<Vec<OsString>>>()?;
+    let when = values
+        .iter()
+        .position(|value| value == "when")
+        .unwrap_or(values.len());
+
+    let mut paths = Vec::new();
+    let mut options = Vec::new();
+    for (arg, value) in args[..when].iter().zip(&values) {
+        if value.to_str().is_some_and(is_option) {
+            options.push(*arg);
+        } else {
+            paths.push((*arg, value));
+        }
+    }
+    let [(from, source), (to, destination)] = paths.as_slice() else {
+        return None;
     };
-    text_params = text_params.trim();
+    Some(FormattedLink {
+        kind,
+        from,
+        to,
+        options,
+        conditions: args[when..].to_vec(),
+        source: (*source).clone(),
+        destination: (*destination).clone(),
+    })
+}
+
+// Helper to write a run of link lines, sorted and in aligned columns
+fn write_links(output: &mut Vec<u8>, block: &[FormattedLine], sort: SortBy) {
+    let mut links: Vec<&FormattedLink> = block
+        .iter()
+        .filter_map(|line| match line {
+            FormattedLine::Link(link) => Some(link),
+            _ => None,
+        })
+        .collect();
+    match sort {
+        SortBy::Source => links.sort_by(|a, b| a.source.cmp(&b.source)),
+        SortBy::Destination => links.sort_by(|a, b| a.destination.cmp(&b.destination)),
+    }
+
+    let width = |arg: &[u8]| String::from_utf8_lossy(arg).chars().count();
+    let kind_width = links.iter().map(|link| link.kind.len()).max().unwrap_or(0);
+    let from_width = links.iter().map(|link| width(link.from)).max().unwrap_or(0);
+    let to_width = links.iter().map(|link| width(link.to)).max().unwrap_or(0);
+
+    for link in links {
+        let mut line = format!("{:<kind_width$} = ", link.kind).into_bytes();
+        line.extend_from_slice(link.from);
+        line.extend(std::iter::repeat_n(b' ', from_width - width(link.from) + 1));
+        line.extend_from_slice(link.to);
+        line.extend(std::iter::repeat_n(b' ', to_width - width(link.to)));
+        for arg in link.options.iter().chain(&link.conditions) {
+            line.push(b' ');
+            line.extend_from_slice(arg);
+        }
+        let len = line.trim_ascii_end().len();
+        line.truncate(len);
+        output.extend_from_slice(&line);
+        output.push(b'\n');
+    }
+}
+
+// Helper to write a line of the formatted config that isn't a link
+fn push_line(output: &mut Vec<u8>, line: &FormattedLine) {
+    if let FormattedLine::Text(text) = line {
+        output.extend_from_slice(text);
+    }
+    output.push(b'\n');
+}
+
+// Helper to read the name of a `[module <name>]` header
+//
+// Names are used on the command line, so they are limited to letters, digits,
+// `-`, `_` and `.`.
+//
+// @param header: &str - the text between the brackets
+// @param idx: usize - the line number, for error reporting
+// @return Result<String, String> - the module name
+fn parse_module(header: &str, idx: usize) -> Result<String, String> {
+    let name = header.trim_start_matches("module").trim();
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(format!("Invalid module name {} on line {}", name, idx));
+    }
+    Ok(name.to_string())
+}
+
+// Helper to merge an included file into the config including it
+//
+// The entries are added in place of the `include` line. A link to a
+// destination that is already declared is refused, as syncing both would
+// give conflicting plans.
+//
+// @param parsed: &mut ParsedConfig - the config with the `include` line
+// @param included: ParsedConfig - the parsed included file
+fn merge_included(parsed: &mut ParsedConfig, included: ParsedConfig) {
+    parsed.errors.extend(included.errors);
+    parsed.strict = included.strict.or(parsed.strict);
+    merge_secrets(
+        &mut parsed.secrets,
+        included.secrets,
+        MergeStrategy::PreferLast,
+    );
+    merge_retention(
+        &mut parsed.retention,
+        included.retention,
+        MergeStrategy::PreferLast,
+    );
+    parsed.backup_location = included.backup_location.or(parsed.backup_location);
+
+    let refused = merge_by_destination(
+        &mut parsed.entries,
+        included.entries,
+        MergeStrategy::Error,
+        |entry| &entry.link,
+    );
+    for (entry, pos) in refused {
+        let first = &parsed.entries[pos];
+        parsed.errors.push(Diagnostic {
+            message: format!(
+                "Destination {} on line {} is already declared at {}:{}",
+                entry.link.to.display(),
+                entry.line,
+                first.file.display(),
+                first.line
+            ),
+            file: entry.file,
+            line: entry.line,
+            unknown: false,
+        });
+    }
+}
 
-    // Before applying regex, check if there is a need to match
-    if text_params.is_empty() {
+// Helper to parse a single `key = value` line of the `[secrets]` section
+//
+// Supported keys:
+// - `identity = <path>` - the age identity file, relative to the config file
+// - `gpg_key = <id>` - the gpg key to try for decryption
+//
+// @param line: &[u8] - the line to parse
+// @param idx: usize - the line number, for error reporting
+// @param file: &Path - the file containing the line
+// @param secrets: &mut Secrets - the settings to update
+// @return Result<(), String> - an error if the line is invalid
+fn parse_secret(line: &[u8], idx: usize, file: &Path, secrets: &mut Secrets) -> Result<(), String> {
+    let Some((key, value)) = split_key(line) else {
+        return Err(format!("Invalid syntax on line {}", idx));
+    };
+    let args = split_args(value, idx)?;
+    let [value] = args.as_slice() else {
         return Err(format!(
-            "Invalid number of values on line {}. The supported syntax is '<kind> = <from> <to>'. Found 0 args",
-            idx
+            "Invalid number of values on line {}. The supported syntax is '<key> = <value>'. Found {} args",
+            idx,
+            args.len()
         ));
+    };
+
+    match key.trim() {
+        "identity" => {
+            let expanded = expand(value, &VarMap::default())
+                .map_err(|err| format!("{} on line {}", err, idx))?;
+            let mut identity = PathBuf::from(expanded);
+            if identity.is_relative() {
+                identity = file
+                    .parent()
+                    .unwrap_or_else(|| Path::new("."))
+                    .join(identity);
+            }
+            secrets.identity = Some(identity);
+        }
+        "gpg_key" => secrets.gpg_key = Some(utf8(value.clone(), idx)?),
+        other => {
+            return Err(format!(
+                "Unknown secrets key {} on line {}, expected identity or gpg_key",
+                other, idx
+            ));
+        }
     }
+    Ok(())
+}
 
-    // Verify its only two arguments
-    let arg_count = text_params.split_whitespace().count();
-    if arg_count != 2 {
+// Helper to parse a single `key = value` line of the `[backups]` section
+//
+// Supported keys:
+// - `keep = <n>` - how many backups of each destination are kept
+// - `keep_days = <n>` - how many days a backup is kept
+// - `location = source|state` - where backups go
+//
+// @param line: &[u8] - the line to parse
+// @param idx: usize - the line number, for error reporting
+// @param retention: &mut Retention - the limits to update
+// @param location: &mut Option<BackupLocation> - the location to update
+// @return Result<(), String> - an error if the line is invalid
+fn parse_backups(
+    line: &[u8],
+    idx: usize,
+    retention: &mut Retention,
+    location: &mut Option<BackupLocation>,
+) -> Result<(), String> {
+    let Some((key, value)) = split_key(line) else {
+        return Err(format!("Invalid syntax on line {}", idx));
+    };
+    let value = String::from_utf8_lossy(value);
+    let value = value.trim();
+    let number = || {
+        value
+            .parse::<u64>()
+            .ok()
+            .filter(|number| *number > 0)
+            .ok_or_else(|| {
+                format!(
+                    "Invalid {} value {} on line {}, expected a number above 0",
+                    key.trim(),
+                    value,
+                    idx
+                )
+            })
+    };
+
+    match key.trim() {
+        "keep" => retention.keep = Some(number()? as usize),
+        "keep_days" => retention.keep_days = Some(number()?),
+        "location" => {
+            *location = Some(BackupLocation::parse(value).ok_or_else(|| {
+                format!(
+                    "Invalid location value {} on line {}, expected source or state",
+                    value, idx
+                )
+            })?)
+        }
+        other => {
+            return Err(format!(
+                "Unknown backups key {} on line {}, expected keep, keep_days or location",
+                other, idx
+            ));
+        }
+    }
+    Ok(())
+}
+
+// Helper to resolve the files an `include = <path>` line points to
+//
+// Keywords are expanded, relative paths are taken from the directory of the
+// including file, and glob patterns are expanded in sorted order.
+//
+// @param params: &[u8] - the part of the line after `=`
+// @param idx: usize - the line number, for error reporting
+// @param file: &Path - the file containing the include
+// @return Result<Vec<PathBuf>, String> - the files to include
+fn resolve_include(params: &[u8], idx: usize, file: &Path) -> Result<Vec<PathBuf>, String> {
+    let args = split_args(params, idx)?;
+    if args.len() != 1 {
         return Err(format!(
-            "Invalid number of values on line {}. The supported syntax is '<kind> = <from> <to>'. Found {} args",
-            idx, arg_count
+            "Invalid number of values on line {}. The supported syntax is 'include = <path>'. Found {} args",
+            idx,
+            args.len()
         ));
     }
 
-    if text_kind.trim() != "link" {
+    let expanded =
+        expand(&args[0], &VarMap::default()).map_err(|err| format!("{} on line {}", err, idx))?;
+    let mut pattern = PathBuf::from(expanded);
+    if pattern.is_relative() {
+        let base = file.parent().unwrap_or_else(|| Path::new("."));
+        pattern = base.join(pattern);
+    }
+
+    let is_glob = pattern
+        .as_os_str()
+        .as_encoded_bytes()
+        .iter()
+        .any(|byte| matches!(byte, b'*' | b'?' | b'['));
+    if !is_glob {
+        return Ok(vec![pattern]);
+    }
+    let Some(pattern) = pattern.to_str() else {
         return Err(format!(
-            "Invalid path syntax on line {}. The supported syntax is '<kind> = <from> <to>'",
+            "Invalid include pattern on line {}: glob patterns have to be valid UTF-8",
             idx
         ));
+    };
+
+    let paths = glob::glob(pattern)
+        .map_err(|err| format!("Invalid include pattern on line {}: {}", idx, err))?;
+    let mut files: Vec<PathBuf> = paths.filter_map(Result::ok).collect();
+    files.sort();
+    Ok(files)
+}
+
+// How a single line of a config is read, for `config explain`
+#[derive(Debug)]
+pub struct Explanation {
+    // The line as written
+    pub text: String,
+    // The `[section]` header the line is under, None before any header
+    pub section: Option<String>,
+    // The paths of a link line, empty for any other line
+    pub paths: Vec<ExplainedPath>,
+    // The `when` conditions of a link line, each with if it holds or why it can't be told
+    pub conditions: Vec<(String, Result<bool, String>)>,
+    // The link the line is read as, None if it isn't a link line, has an error or its conditions don't hold
+    pub link: Option<Link>,
+    // Why the line was refused
+    pub error: Option<String>,
+}
+
+// A path of a link line with every keyword expanded in it
+#[derive(Debug)]
+pub struct ExplainedPath {
+    // The path as written, without its quotes
+    pub written: OsString,
+    // Every keyword in it, with what it expanded to
+    pub steps: Vec<crate::expand::Step>,
+    // The expanded path, or why it couldn't be expanded
+    pub expanded: Result<PathBuf, String>,
+}
+
+// Explains how one line of a config file is read, step by step
+//
+// Only the line itself and the `[section]` header above it are looked at, so
+// includes and local overrides don't change the answer.
+//
+// @param path: &Path - the config file
+// @param idx: usize - the index of the line, counted from 0 unlike in error messages
+// @return Result<Explanation, String> - the explanation, an error if the file can't be read or is shorter
+pub fn explain_line(path: &Path, idx: usize) -> Result<Explanation, String> {
+    let content = fs::read(path).map_err(|err| err.to_string())?;
+    let line_no = idx + 1;
+    let Some(line) = lines(&content).nth(idx) else {
+        return Err(format!("{} has no line {}", path.display(), line_no));
+    };
+    let section = lines(&content)
+        .take(idx)
+        .filter_map(|line| {
+            line.trim_ascii()
+                .strip_prefix(b"[")
+                .and_then(|rest| rest.strip_suffix(b"]"))
+        })
+        .last()
+        .map(|name| String::from_utf8_lossy(name).trim().to_string());
+
+    let mut explanation = Explanation {
+        text: String::from_utf8_lossy(line).to_string(),
+        section,
+        paths: Vec::new(),
+        conditions: Vec::new(),
+        link: None,
+        error: None,
+    };
+    let in_links = match explanation.section.as_deref() {
+        None => true,
+        Some(name) => name.starts_with("module "),
+    };
+    let is_link_line = split_key(line)
+        .is_some_and(|(key, _)| !["version", "strict", "include"].contains(&key.trim()));
+    if !in_links || !is_link_line || is_comment(line) || line.trim_ascii().starts_with(b"[") {
+        return Ok(explanation);
     }
 
-    if let Some(caps) = PARAMS_REGEXP.captures(text_params) {
-        let from = caps.name("from").unwrap().as_str();
-        let to = caps.name("to").unwrap().as_str();
+    // The same steps as `parse_line`, kept apart so each can be shown
+    if let Some((_, params)) = split_key(line)
+        && let Ok(mut args) = split_args(params, line_no)
+    {
+        if let Some(pos) = args.iter().position(|arg| arg == "when") {
+            explanation.conditions = args
+                .split_off(pos)
+                .into_iter()
+                .skip(1)
+                .map(|condition| {
+                    let condition = condition.to_string_lossy().to_string();
+                    let holds = condition_holds(&condition, line_no);
+                    (condition, holds)
+                })
+                .collect();
+        }
+        let vars = VarMap::default();
+        explanation.paths = args
+            .into_iter()
+            .filter(|arg| !arg.to_str().is_some_and(is_option))
+            .map(|written| {
+                let (expanded, steps) = match crate::expand::explain(&written, &vars) {
+                    Ok((expanded, steps)) => (Ok(PathBuf::from(expanded)), steps),
+                    Err(err) => (Err(err), Vec::new()),
+                };
+                ExplainedPath {
+                    written,
+                    steps,
+                    expanded,
+                }
+            })
+            .collect();
+    }
 
-        let from = expand_keywords(from).map_err(|err| format!("{} on line {}", err, idx))?;
-        let to = expand_keywords(to).map_err(|err| format!("{} on line {}", err, idx))?;
+    match parse_line(line, line_no) {
+        Ok(link) => {
+            explanation.link = link.map(|link| Link {
+                module: explanation
+                    .section
+                    .as_deref()
+                    .and_then(|name| name.strip_prefix("module "))
+                    .map(|name| name.trim().to_string()),
+                ..link
+            })
+        }
+        Err(LineError::Invalid(message) | LineError::Unknown(message)) => {
+            explanation.error = Some(message)
+        }
+    }
+    Ok(explanation)
+}
 
-        let from_path = PathBuf::from(&from);
-        let to_path = PathBuf::from(&to);
+// Parses a single `link` line
+//
+// @param line: &[u8] - the line to parse
+// @param idx: usize - the line number, for error reporting
+// @return Result<Option<Link>, LineError> - the link, None if its `when` conditions don't hold
+fn parse_line(line: &[u8], idx: usize) -> Result<Option<Link>, LineError> {
+    // Read split out the line
+    let Some((text_kind, text_params)) = split_key(line) else {
+        return Err(format!("Invalid syntax on line {}", idx).into());
+    };
 
-        if !from_path.exists() {
-            return Err(format!(
-                "<from> path specified at line {} doest contain any object",
+    // A newer kind may take other values, so it's checked first
+    let kind = match text_kind.trim() {
+        "link" => LinkKind::Link,
+        "system" => LinkKind::System,
+        "encrypted" => LinkKind::Encrypted,
+        _ => {
+            return Err(LineError::Unknown(format!(
+                "Invalid path syntax on line {}. The supported syntax is '<kind> = <from> <to>'",
                 idx
-            ));
+            )));
         }
+    };
 
-        let from_meta = std::fs::metadata(&from_path)
-            .map_err(|err| format!("Failed to read <from> metadata on line {}: {}", idx, err))?;
-        let to_meta = std::fs::symlink_metadata(&to_path).ok();
-        let to_ends_with_slash = to.ends_with('/');
+    // Split the params into arguments, respecting quotes and escapes
+    let mut args = split_args(text_params, idx)?;
 
-        if from_meta.is_dir() {
-            if let Some(ref meta) = to_meta {
-                if meta.is_file() {
-                    return Err(format!(
-                        "Invalid destination on line {}: <to> is a file for directory source",
-                        idx
-                    ));
-                }
+    // Everything after `when` are conditions for the link
+    let conditions = match args.iter().position(|arg| arg == "when") {
+        Some(pos) => {
+            let conditions = args.split_off(pos).split_off(1);
+            if conditions.is_empty() {
+                return Err(format!("Missing conditions after 'when' on line {}", idx).into());
             }
+            conditions
+                .into_iter()
+                .map(|condition| utf8(condition, idx))
+                .collect::<Result<Vec<_>, _>>()?
         }
+        None => Vec::new(),
+    };
+
+    // Anything that looks like `key=value` is an option, the rest are the paths
+    let (options, args): (Vec<OsString>, Vec<OsString>) = args
+        .into_iter()
+        .partition(|arg| arg.to_str().is_some_and(is_option));
+
+    // Verify its only two arguments
+    if args.len() != 2 {
+        return Err(format!(
+            "Invalid number of values on line {}. The supported syntax is '<kind> = <from> <to>'. Found {} args",
+            idx,
+            args.len()
+        )
+        .into());
+    }
+
+    // Conditions are checked before the paths, as they may not exist on other machines
+    if !conditions_hold(&conditions, idx)? {
+        return Ok(None);
+    }
+
+    let options = options
+        .into_iter()
+        .map(|option| utf8(option, idx))
+        .collect::<Result<Vec<_>, _>>()?;
+    let options = parse_options(&options, idx)?;
+    let from = expand_arg(&args[0], idx)?;
+    let to = expand_arg(&args[1], idx)?;
+
+    let link = Link {
+        from: PathBuf::from(from),
+        to: PathBuf::from(to),
+        kind,
+        options,
+        module: None,
+    };
+    check_link(&link).map_err(|err| format!("{} on line {}", err, idx))?;
+
+    Ok(Some(link))
+}
+
+// Helper to expand the keywords of a value on a link line
+//
+// @param arg: &OsStr - the value
+// @param idx: usize - the line number, for error reporting
+// @return Result<OsString, LineError> - the expanded value, an unknown error if a keyword isn't known
+fn expand_arg(arg: &OsStr, idx: usize) -> Result<OsString, LineError> {
+    let vars = VarMap::default();
+    expand(arg, &vars).map_err(|err| {
+        let message = format!("{} on line {}", err, idx);
+        if vars.knows_all(arg) {
+            LineError::Invalid(message)
+        } else {
+            LineError::Unknown(message)
+        }
+    })
+}
+
+// Helper to check that a link can be synced, shared by the parser and the builder
+//
+// @param link: &Link - the link with its paths already expanded
+// @return Result<(), String> - an error describing the first problem found
+fn check_link(link: &Link) -> Result<(), String> {
+    if link.kind == LinkKind::Encrypted && link.options.root {
+        return Err(String::from(
+            "root=true is not supported for encrypted links",
+        ));
+    }
 
-        if from_meta.is_file() {
-            if to_meta.is_none() && to_ends_with_slash {
+    if !link.from.exists() {
+        return Err(String::from("<from> path doesn't contain any object"));
+    }
+
+    let from_meta = std::fs::metadata(&link.from)
+        .map_err(|err| format!("Failed to read <from> metadata: {}", err))?;
+    let to_meta = std::fs::symlink_metadata(&link.to).ok();
+    let to_ends_with_slash = crate::platform::ends_with_separator(link.to.as_os_str());
+
+    // The destination gets the decrypted file, so it has to be named by the config
+    if link.kind == LinkKind::Encrypted {
+        let encrypted_file = from_meta.is_file()
+            && link
+                .from
+                .extension()
+                .is_some_and(|ext| ext == "age" || ext == "gpg");
+        if !encrypted_file {
+            return Err(String::from(
+                "Encrypted sources have to be .age or .gpg files",
+            ));
+        }
+        if to_ends_with_slash || to_meta.as_ref().is_some_and(|meta| meta.is_dir()) {
+            return Err(String::from(
+                "The destination of an encrypted link has to be a file",
+            ));
+        }
+    }
+
+    if from_meta.is_dir()
+        && let Some(ref meta) = to_meta
+        && meta.is_file()
+    {
+        return Err(String::from(
+            "Invalid destination, <to> is a file for directory source",
+        ));
+    }
+
+    if from_meta.is_file() && to_meta.is_none() && to_ends_with_slash {
+        return Err(format!(
+            "Destination directory {} does not exist",
+            link.to.display()
+        ));
+    }
+
+    if !link.to.exists() {
+        match link.to.parent() {
+            // A link it's applied after can create the parent
+            Some(parent)
+                if !parent.exists()
+                    && !link
+                        .options
+                        .after
+                        .iter()
+                        .any(|after| parent.starts_with(after)) =>
+            {
                 return Err(format!(
-                    "Destination directory does not exist on line {}: {}",
-                    idx,
-                    to_path.display()
+                    "Parent directory {} does not exist",
+                    parent.display()
                 ));
             }
+            Some(_) => {}
+            None => return Err(format!("Path {} has no parent", link.to.display())),
         }
+    }
 
-        if !to_path.exists() {
-            if let Some(parent) = to_path.parent() {
-                if !parent.exists() {
-                    return Err(format!(
-                        "Parent directory does not exist: {}",
-                        parent.display()
-                    ));
+    Ok(())
+}
+
+// Helper to tell link options apart from paths
+//
+// @param arg: &str - a single argument of a link line
+// @return bool - if the argument has the `key=value` form with a lowercase key
+fn is_option(arg: &str) -> bool {
+    arg.split_once('=').is_some_and(|(key, _)| {
+        !key.is_empty() && key.chars().all(|c| c.is_ascii_lowercase() || c == '_')
+    })
+}
+
+// Helper to parse the options of a link
+//
+// Supported options:
+// - `mode=<octal>` - permission bits for the source, e.g. `mode=0600`
+// - `owner=<user>[:<group>]` - owner of the source
+// - `root=true|false` - if the destination is changed through sudo
+// - `after=<dest>` - the link is applied after the link to <dest>, can be repeated
+// - `on_conflict=ask|replace|backup|skip` - how a conflict at the destination is resolved
+// - `protect=true|false` - if a conflict at the destination is only replaced when asked
+// - `readonly=true|false` - if the files of the source are kept read-only
+//
+// @param options: &[String] - the `key=value` arguments of the line
+// @param idx: usize - the line number, for error reporting
+// @return Result<LinkOptions, LineError> - the parsed options, an unknown error for an option that isn't supported
+fn parse_options(options: &[String], idx: usize) -> Result<LinkOptions, LineError> {
+    let mut parsed = LinkOptions::default();
+    for option in options {
+        let (key, value) = option.split_once('=').unwrap_or((option, ""));
+        match key {
+            "mode" => {
+                let mode = u32::from_str_radix(value, 8)
+                    .ok()
+                    .filter(|mode| *mode <= 0o7777)
+                    .ok_or_else(|| format!("Invalid mode {} on line {}", value, idx))?;
+                parsed.mode = Some(mode);
+            }
+            "owner" => {
+                if value.is_empty() || value.starts_with(':') || value.ends_with(':') {
+                    return Err(format!("Invalid owner {} on line {}", value, idx).into());
                 }
-            } else {
-                return Err(format!("Path has no parent: {}", to_path.display()));
+                parsed.owner = Some(value.to_string());
+            }
+            "root" => {
+                parsed.root = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => {
+                        return Err(format!("Invalid root value {} on line {}", value, idx).into());
+                    }
+                };
+            }
+            "after" => {
+                if value.is_empty() {
+                    return Err(format!("Missing destination for after on line {}", idx).into());
+                }
+                let after = expand_arg(OsStr::new(value), idx)?;
+                parsed.after.push(PathBuf::from(after));
+            }
+            "protect" => {
+                parsed.protect = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => {
+                        return Err(
+                            format!("Invalid protect value {} on line {}", value, idx).into()
+                        );
+                    }
+                };
+            }
+            "readonly" => {
+                parsed.readonly = match value {
+                    "true" => true,
+                    "false" => false,
+                    _ => {
+                        return Err(
+                            format!("Invalid readonly value {} on line {}", value, idx).into()
+                        );
+                    }
+                };
+            }
+            "on_conflict" => {
+                let policy = ConflictPolicy::parse(value).ok_or_else(|| {
+                    format!("Invalid on_conflict value {} on line {}", value, idx)
+                })?;
+                parsed.on_conflict = Some(policy);
+            }
+            _ => {
+                return Err(LineError::Unknown(format!(
+                    "Unknown option {} on line {}",
+                    option, idx
+                )));
             }
         }
+    }
+    Ok(parsed)
+}
 
-        return Ok(Link {
-            from: PathBuf::from(&from),
-            to: PathBuf::from(&to),
-        });
+// Helper to evaluate the `when` conditions of a line
+//
+// All conditions have to hold for the link to be used. Supported conditions:
+// - `os=<name>` - the current OS is <name> (`linux`, `macos`, `windows`, ...)
+// - `os!=<name>` - the current OS is not <name>
+// - `command(<name>)` - an executable <name> is found in $PATH
+//
+// @param conditions: &[String] - the conditions following `when`
+// @param idx: usize - the line number, for error reporting
+// @return Result<bool, String> - if all conditions hold
+fn conditions_hold(conditions: &[String], idx: usize) -> Result<bool, String> {
+    for condition in conditions {
+        if !condition_holds(condition, idx)? {
+            return Ok(false);
+        }
     }
 
-    // TODO: Not sure if I am missing a case in which the state can occur here
-    Err(format!(
-        "Unknown error encountered while parsing line {}",
-        idx,
-    ))
+    Ok(true)
 }
 
-fn expand_keywords(line: &str) -> Result<String, String> {
-    if line.contains('!')
-        && !line.contains("!here")
-        && !line.contains("!home")
-        && !line.contains("!xdg_conf")
+// Helper to evaluate a single `when` condition
+//
+// @param condition: &str - the condition, like `os=linux`
+// @param idx: usize - the line number, for error reporting
+// @return Result<bool, String> - if it holds, an error if the condition isn't known
+fn condition_holds(condition: &str, idx: usize) -> Result<bool, String> {
+    if let Some(os) = condition.strip_prefix("os!=") {
+        Ok(os != env::consts::OS)
+    } else if let Some(os) = condition.strip_prefix("os=") {
+        Ok(os == env::consts::OS)
+    } else if let Some(name) = condition
+        .strip_prefix("command(")
+        .and_then(|rest| rest.strip_suffix(')'))
     {
-        return Err(format!("Invalid keyword in {}", line));
+        Ok(command_exists(name))
+    } else {
+        Err(format!("Unknown condition {} on line {}", condition, idx))
+    }
+}
+
+// Helper to check if an executable with the given name is in $PATH
+//
+// @param name: &str - the name of the command
+// @return bool - if an executable file with that name was found
+fn command_exists(name: &str) -> bool {
+    let Some(path) = env::var_os("PATH") else {
+        return false;
+    };
+
+    env::split_paths(&path).any(|dir| crate::platform::is_command(&dir.join(name)))
+}
+
+// Helper to split a config into lines like `str::lines`, without requiring UTF-8
+fn lines(content: &[u8]) -> impl Iterator<Item = &[u8]> {
+    content
+        .split(|byte| *byte == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+}
+
+// Helper to tell if a line is a comment
+//
+// Only whole lines are comments, as `#` is a valid character in paths.
+//
+// @param line: &[u8] - the line to check
+// @return bool - true if the line starts with `#`, after any whitespace
+fn is_comment(line: &[u8]) -> bool {
+    line.trim_ascii_start().starts_with(b"#")
+}
+
+// Helper to split a line at its first `=`
+//
+// @param line: &[u8] - the line to split
+// @return Option<(Cow<str>, &[u8])> - the key, lossy if it isn't UTF-8, and everything after `=`
+fn split_key(line: &[u8]) -> Option<(Cow<'_, str>, &[u8])> {
+    let pos = line.iter().position(|byte| *byte == b'=')?;
+    Some((String::from_utf8_lossy(&line[..pos]), &line[pos + 1..]))
+}
+
+// Helper to get an argument that isn't a path as a string
+//
+// @param arg: OsString - the argument
+// @param idx: usize - the line number, for error reporting
+// @return Result<String, String> - the argument, or an error if it isn't valid UTF-8
+fn utf8(arg: OsString, idx: usize) -> Result<String, String> {
+    arg.into_string()
+        .map_err(|arg| format!("Invalid UTF-8 in {} on line {}", arg.display(), idx))
+}
+
+// Helper to split the params of a line like `split_args`, keeping the arguments as written
+//
+// @param text: &[u8] - the params part of the line
+// @return Option<Vec<&[u8]>> - the arguments with their quotes and escapes, None if a quote or escape is left open
+fn split_raw(text: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut args = Vec::new();
+    let mut start = None;
+    let mut quote = None;
+    let mut escaped = false;
+    for (pos, byte) in text.iter().enumerate() {
+        if escaped {
+            escaped = false;
+            continue;
+        }
+        match quote {
+            // Single quotes are taken literally
+            Some(b'\'') if *byte == b'\'' => quote = None,
+            Some(b'\'') => {}
+            Some(_) if *byte == b'\\' => escaped = true,
+            Some(open) if *byte == open => quote = None,
+            Some(_) => {}
+            None if byte.is_ascii_whitespace() => {
+                if let Some(start) = start.take() {
+                    args.push(&text[start..pos]);
+                }
+            }
+            None => {
+                start.get_or_insert(pos);
+                match byte {
+                    b'\\' => escaped = true,
+                    b'"' | b'\'' => quote = Some(*byte),
+                    _ => {}
+                }
+            }
+        }
+    }
+    if quote.is_some() || escaped {
+        return None;
+    }
+    if let Some(start) = start {
+        args.push(&text[start..]);
+    }
+    Some(args)
+}
+
+// Helper to split the params of a line into separate arguments
+//
+// Arguments are separated by whitespace. Whitespace can be kept inside an
+// argument by wrapping it in double or single quotes, or by escaping it with
+// a backslash. Inside double quotes a backslash only escapes `"` and `\`,
+// single quotes are taken literally. Bytes that aren't valid UTF-8 are kept
+// as they are, so such file names can still be linked on Unix.
+//
+// @param text: &[u8] - the params part of the line
+// @param idx: usize - the line number, for error reporting
+// @return Result<Vec<OsString>, String> - the arguments with quotes and escapes removed
+fn split_args(text: &[u8], idx: usize) -> Result<Vec<OsString>, String> {
+    let mut args: Vec<Vec<u8>> = Vec::new();
+    let mut current: Vec<u8> = Vec::new();
+    // Tracks if an argument was started, so `""` still counts as one
+    let mut in_arg = false;
+    // Chars, with the bytes that aren't part of one as errors
+    let mut chars = text.utf8_chunks().flat_map(|chunk| {
+        let invalid = chunk.invalid().iter().map(|byte| Err(*byte));
+        chunk.valid().chars().map(Ok).chain(invalid)
+    });
+
+    while let Some(c) = chars.next() {
+        match c {
+            Ok(c) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            Ok('\\') => {
+                let escaped = chars
+                    .next()
+                    .ok_or_else(|| format!("Dangling escape at the end of line {}", idx))?;
+                push_piece(&mut current, escaped);
+                in_arg = true;
+            }
+            Ok(quote @ ('"' | '\'')) => {
+                in_arg = true;
+                loop {
+                    match chars.next() {
+                        Some(Ok(c)) if c == quote => break,
+                        Some(Ok('\\')) if quote == '"' => match chars.next() {
+                            Some(Ok(escaped @ ('"' | '\\'))) => {
+                                push_piece(&mut current, Ok(escaped))
+                            }
+                            Some(other) => {
+                                current.push(b'\\');
+                                push_piece(&mut current, other);
+                            }
+                            None => return Err(format!("Unterminated quote on line {}", idx)),
+                        },
+                        Some(c) => push_piece(&mut current, c),
+                        None => return Err(format!("Unterminated quote on line {}", idx)),
+                    }
+                }
+            }
+            c => {
+                push_piece(&mut current, c);
+                in_arg = true;
+            }
+        }
     }
 
-    let mut expanded = line.to_string();
-    if expanded.contains("!here") {
-        let here =
-            std::env::current_dir().map_err(|err| format!("Failed to resolve !here: {}", err))?;
-        expanded = expanded.replace("!here", &here.to_string_lossy());
+    if in_arg {
+        args.push(current);
     }
 
-    expanded = expanded.replace("!home", HOME_DIR.as_str());
-    expanded = expanded.replace("!xdg_conf", XDG_CONFIG_HOME.as_str());
-    Ok(expanded)
+    args.into_iter()
+        .map(|arg| {
+            crate::platform::os_string_from_bytes(arg)
+                .ok_or_else(|| format!("Invalid UTF-8 on line {}", idx))
+        })
+        .collect()
+}
+
+// Helper to add a char, or a byte that isn't part of one, to an argument
+fn push_piece(arg: &mut Vec<u8>, piece: Result<char, u8>) {
+    match piece {
+        Ok(c) => arg.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        Err(byte) => arg.push(byte),
+    }
 }
@@ -0,0 +1,202 @@
+use crate::git::{self, Merge};
+use crate::manifest::{Manifest, Record, content_hash};
+use crate::output::plural;
+use crate::{
+    BackupNaming, BackupOutcome, canonicalize_or_fallback, copy_preserving, dirs, hash, move_path,
+    remove_existing, same_content, unique_backup_path,
+};
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+pub use crate::manifest::State;
+
+// What dbdm copied where, because a symlink couldn't be created there
+const MANIFEST: Manifest = Manifest::new("copies.json");
+
+// The errno for a missing write permission, the same on Linux and macOS
+const EACCES: i32 = 13;
+
+// Helper to tell if symlinks can be created next to a destination
+//
+// Some filesystems, like FAT on USB drives or some network shares, reject
+// symlinks with EPERM or ENOTSUP. A throwaway link is made to find out.
+//
+// @param dest: &Path - the destination a link would be created at
+// @return bool - false only if the filesystem refused the link
+pub fn symlinks_supported(dest: &Path) -> bool {
+    let Some(dir) = dest.parent() else {
+        return true;
+    };
+    let probe = dir.join(format!(".dbdm-probe-{}", std::process::id()));
+    match crate::platform::symlink(Path::new("dbdm-probe"), &probe) {
+        Ok(()) => {
+            let _ = std::fs::remove_file(&probe);
+            true
+        }
+        Err(err) => {
+            debug!(dir = %dir.display(), %err, "symlink probe failed");
+            // A directory that can't be written to says nothing about symlinks
+            if cfg!(unix) && err.raw_os_error() == Some(EACCES) {
+                return true;
+            }
+            !matches!(
+                err.kind(),
+                std::io::ErrorKind::PermissionDenied | std::io::ErrorKind::Unsupported
+            )
+        }
+    }
+}
+
+// Helper to compare a destination with what dbdm last copied into it
+//
+// @param from: &Path - the source of the link
+// @param dest: &Path - the resolved destination
+// @return Result<Option<State>> - where the copy stands, None if dbdm never copied there
+pub fn state(from: &Path, dest: &Path) -> std::io::Result<Option<State>> {
+    if !MANIFEST.contains(dest)? {
+        return Ok(None);
+    }
+    MANIFEST.state(from, dest).map(Some)
+}
+
+// Copies a source to its destination instead of linking it
+//
+// Whatever was at the destination is replaced.
+//
+// @param from: &Path - the source of the link
+// @param dest: &Path - the resolved destination
+// @return Result<()> - an error if copying failed
+pub fn install(from: &Path, dest: &Path) -> std::io::Result<()> {
+    if std::fs::symlink_metadata(dest).is_ok() {
+        remove_existing(dest)?;
+    }
+    write_copy(from, dest)
+}
+
+// Same as `install`, with whatever is at the destination backed up first
+//
+// A destination with the same content as the source isn't backed up.
+//
+// @param from: &Path - the source of the link
+// @param dest: &Path - the resolved destination
+// @param backup_dir: &Path - the directory to put the backup in, created if missing
+// @param naming: &BackupNaming - how the backup is named
+// @return Result<BackupOutcome> - what happened to the old destination
+pub fn backup_and_install(
+    from: &Path,
+    dest: &Path,
+    backup_dir: &Path,
+    naming: &BackupNaming,
+) -> std::io::Result<BackupOutcome> {
+    // Not the content, the source itself would be removed
+    if crate::is_source_itself(from, dest) {
+        return Err(crate::source_itself_error(dest));
+    }
+    if same_content(from, dest)? {
+        install(from, dest)?;
+        return Ok(BackupOutcome::Identical);
+    }
+
+    std::fs::create_dir_all(backup_dir)?;
+    let base_name = dest.file_name().unwrap_or(OsStr::new("backup"));
+    let backup_path = unique_backup_path(backup_dir, base_name, naming);
+    debug!(to = %dest.display(), backup = %backup_path.display(), "backing up");
+    move_path(dest, &backup_path)?;
+
+    write_copy(from, dest)?;
+    Ok(BackupOutcome::BackedUp(backup_path))
+}
+
+// Helper to tell if a destination edited since it was copied can be merged with its source
+//
+// Only files are merged, and only if dbdm kept what it last copied there.
+//
+// @param dest: &Path - the resolved destination
+// @return bool - if `merge` has a base to merge from
+pub fn can_merge(dest: &Path) -> bool {
+    dest.is_file() && base_path(dest).is_ok_and(|base| base.is_file())
+}
+
+// Helper to preview the merge of a source into its edited destination
+//
+// Nothing is written, see `merge` for that.
+//
+// @param from: &Path - the source of the link
+// @param dest: &Path - the resolved destination
+// @return Result<Option<Merge>> - the merge, None if there is no base to merge from
+pub fn merge_preview(from: &Path, dest: &Path) -> std::io::Result<Option<Merge>> {
+    if !can_merge(dest) {
+        return Ok(None);
+    }
+    git::merge_file(
+        dest,
+        &base_path(dest)?,
+        from,
+        ["destination", "last synced", "source"],
+    )
+    .map(Some)
+}
+
+// Merges the changes made to a source since it was last copied into its edited destination
+//
+// The edits made at the destination are kept. If both sides changed the same
+// lines, nothing is written.
+//
+// @param from: &Path - the source of the link
+// @param dest: &Path - the resolved destination
+// @return Result<()> - an error if there is nothing to merge from, or the changes conflict
+pub fn merge(from: &Path, dest: &Path) -> std::io::Result<()> {
+    let Some(merge) = merge_preview(from, dest)? else {
+        return Err(std::io::Error::other(
+            "can't merge, dbdm didn't keep what it last copied there",
+        ));
+    };
+    if merge.conflicts > 0 {
+        return Err(std::io::Error::other(format!(
+            "can't merge, {} with the source",
+            plural(merge.conflicts, "conflict")
+        )));
+    }
+    debug!(from = %from.display(), to = %dest.display(), "merging into the copy");
+    std::fs::write(dest, &merge.content)?;
+    record(from, dest)
+}
+
+// Helper to copy a source and remember what was copied
+fn write_copy(from: &Path, dest: &Path) -> std::io::Result<()> {
+    debug!(from = %from.display(), to = %dest.display(), "copying instead of linking");
+    copy_preserving(from, dest)?;
+    record(from, dest)
+}
+
+// Helper to remember what was written to a destination, and the source it came from
+//
+// The source is kept as the base of a later merge, for files only.
+fn record(from: &Path, dest: &Path) -> std::io::Result<()> {
+    MANIFEST.insert(
+        dest,
+        Record {
+            source: content_hash(from)?,
+            written: content_hash(dest)?,
+        },
+    )?;
+    let base = base_path(dest)?;
+    if !std::fs::metadata(from)?.is_file() {
+        return match std::fs::remove_file(&base) {
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            outcome => outcome,
+        };
+    }
+    if let Some(parent) = base.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    // Read and written, so a read-only source doesn't make the base read-only
+    std::fs::write(&base, std::fs::read(from)?)
+}
+
+// Helper to get where the source last copied to a destination is kept
+fn base_path(dest: &Path) -> std::io::Result<PathBuf> {
+    let dest = canonicalize_or_fallback(dest);
+    Ok(dirs::copy_bases_dir()?.join(hash(dest.as_os_str().as_encoded_bytes())))
+}
@@ -0,0 +1,133 @@
+use crate::dirs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+// Creates a directory and the missing ones above it
+//
+// Without a mode the directories get the default one, which honors the umask.
+// With a mode it's set on every directory that was created, whatever the umask.
+//
+// @param path: &Path - the directory to create
+// @param mode: Option<u32> - the permission bits for the new directories
+// @return Result<Vec<PathBuf>> - the directories that were created, outermost first
+pub fn create(path: &Path, mode: Option<u32>) -> std::io::Result<Vec<PathBuf>> {
+    let missing: Vec<&Path> = path
+        .ancestors()
+        .take_while(|dir| !dir.as_os_str().is_empty() && std::fs::symlink_metadata(dir).is_err())
+        .collect();
+
+    let mut created = Vec::new();
+    for dir in missing.into_iter().rev() {
+        match std::fs::create_dir(dir) {
+            Ok(()) => created.push(dir.to_path_buf()),
+            // Made by someone else in the meantime, so it isn't ours
+            Err(err) if err.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(err),
+        }
+        if let Some(mode) = mode {
+            crate::platform::set_mode(dir, mode)?;
+        }
+    }
+    Ok(created)
+}
+
+// Helper to get the file the created directories are kept in
+//
+// @return Result<PathBuf> - `created_dirs.jsonl` in the state directory
+pub fn registry_path() -> std::io::Result<PathBuf> {
+    dirs::created_dirs_file()
+}
+
+// Remembers directories dbdm created, so they can be removed once empty
+//
+// @param dirs: &[PathBuf] - the directories that were created
+// @return Result<()> - an error if the registry can't be written
+pub fn record(dirs: &[PathBuf]) -> std::io::Result<()> {
+    if dirs.is_empty() {
+        return Ok(());
+    }
+    let path = registry_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)?;
+    let lines: String = dirs
+        .iter()
+        .map(|dir| format!("{}\n", to_json(dir)))
+        .collect();
+    file.write_all(lines.as_bytes())
+}
+
+// Reads every directory dbdm created
+//
+// @return Result<Vec<PathBuf>> - the directories, in the order they were created
+pub fn load() -> std::io::Result<Vec<PathBuf>> {
+    let contents = match std::fs::read_to_string(registry_path()?) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(line).ok())
+        .filter_map(|json| Some(PathBuf::from(json.get("path")?.as_str()?)))
+        .collect())
+}
+
+// Drops directories from the registry, once they were removed
+//
+// @param dirs: &[PathBuf] - the directories to drop
+// @return Result<()> - an error if the registry can't be rewritten
+pub fn forget(dirs: &[PathBuf]) -> std::io::Result<()> {
+    let recorded = load()?;
+    let kept: Vec<&PathBuf> = recorded.iter().filter(|dir| !dirs.contains(dir)).collect();
+    if kept.len() == recorded.len() {
+        return Ok(());
+    }
+    let lines: String = kept
+        .iter()
+        .map(|dir| format!("{}\n", to_json(dir)))
+        .collect();
+    std::fs::write(registry_path()?, lines)
+}
+
+fn to_json(dir: &Path) -> serde_json::Value {
+    serde_json::json!({ "path": dir.display().to_string() })
+}
+
+// Removes the directories dbdm created above a removed path, once they are empty
+//
+// Directories are removed from the innermost one up, stopping at the first one
+// that isn't empty or that dbdm didn't create. The removed ones are dropped
+// from the registry.
+//
+// @param removed: &Path - the path that was removed
+// @return Result<Vec<PathBuf>> - the directories that were removed, innermost first
+pub fn remove_empty_parents(removed: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let recorded = load()?;
+    let mut gone = Vec::new();
+    for dir in removed.ancestors().skip(1) {
+        if !recorded.iter().any(|created| created == dir) {
+            break;
+        }
+        match std::fs::read_dir(dir) {
+            Ok(mut entries) => {
+                if entries.next().is_some() {
+                    break;
+                }
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {}
+            Err(err) => return Err(err),
+        }
+        match std::fs::remove_dir(dir) {
+            Ok(()) => gone.push(dir.to_path_buf()),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => gone.push(dir.to_path_buf()),
+            Err(err) => return Err(err),
+        }
+    }
+    forget(&gone)?;
+    Ok(gone)
+}
@@ -0,0 +1,137 @@
+use dbdm::commands::sync::{ExecutedItem, PlanItem, SyncAction};
+use dbdm::output::plural;
+use std::process::Command;
+use tracing::{debug, warn};
+
+// How many destinations are listed in a notification before the rest are counted
+const MAX_LISTED: usize = 5;
+
+// Shows a desktop notification summarizing a background sync
+//
+// Nothing is shown when the sync didn't change anything and nothing was
+// skipped, so a watch that keeps finding everything in place stays quiet.
+//
+// @param outcome: &[ExecutedItem] - the executed plan
+pub fn notify_outcome(outcome: &[ExecutedItem]) {
+    let items = outcome.iter().map(|executed| &executed.item);
+    let linked: Vec<&PlanItem> = items
+        .clone()
+        .filter(|item| item.action.changes_destination())
+        .collect();
+    let skipped: Vec<&PlanItem> = items
+        .filter(|item| item.action == SyncAction::Skip)
+        .collect();
+    if linked.is_empty() && skipped.is_empty() {
+        return;
+    }
+
+    let summary = match (linked.len(), skipped.len()) {
+        (linked, 0) => format!("dbdm linked {}", plural(linked, "destination")),
+        (0, skipped) => format!("dbdm skipped {}", plural(skipped, "link")),
+        (linked, skipped) => format!(
+            "dbdm linked {} and skipped {}",
+            plural(linked, "destination"),
+            plural(skipped, "link")
+        ),
+    };
+
+    let mut body = String::new();
+    if !linked.is_empty() {
+        body.push_str(&format!("Linked:\n{}", list(&linked)));
+    }
+    if !skipped.is_empty() {
+        if !body.is_empty() {
+            body.push('\n');
+        }
+        body.push_str(&format!(
+            "Skipped, run dbdm sync to resolve:\n{}",
+            list(&skipped)
+        ));
+    }
+
+    show(&summary, &body);
+}
+
+// Helper to hand a notification to the notifier of the platform
+//
+// That is `notify-send` on Linux and the BSDs, `osascript` on macOS and
+// Windows PowerShell on Windows. A missing notifier shouldn't stop the
+// sync, so failures are only logged.
+//
+// @param summary: &str - the title of the notification
+// @param body: &str - the text of the notification
+fn show(summary: &str, body: &str) {
+    let mut command = notifier(summary, body);
+    let program = command.get_program().to_string_lossy().into_owned();
+    debug!(summary, program, "showing desktop notification");
+    match command.status() {
+        Ok(status) if status.success() => {}
+        Ok(status) => warn!("{} exited with {}", program, status),
+        Err(err) => warn!("Could not run {}: {}", program, err),
+    }
+}
+
+// Helper to build the notify-send command of a notification
+#[cfg(all(unix, not(target_os = "macos")))]
+fn notifier(summary: &str, body: &str) -> Command {
+    let mut command = Command::new("notify-send");
+    command.arg("--app-name=dbdm").arg(summary).arg(body);
+    command
+}
+
+// Helper to build the osascript command of a notification
+//
+// The texts are passed as arguments of the script, so they need no quoting.
+#[cfg(target_os = "macos")]
+fn notifier(summary: &str, body: &str) -> Command {
+    let mut command = Command::new("osascript");
+    command
+        .args(["-e", "on run argv"])
+        .args([
+            "-e",
+            "display notification (item 2 of argv) with title (item 1 of argv)",
+        ])
+        .args(["-e", "end run"])
+        .arg(summary)
+        .arg(body);
+    command
+}
+
+// Helper to build the PowerShell command of a notification
+//
+// The texts are passed through the environment, so they need no quoting.
+// The toast is shown as PowerShell, as Windows drops toasts of apps that
+// aren't registered.
+#[cfg(windows)]
+fn notifier(summary: &str, body: &str) -> Command {
+    const SCRIPT: &str = "\
+[Windows.UI.Notifications.ToastNotificationManager, Windows.UI.Notifications, ContentType = WindowsRuntime] > $null; \
+$template = [Windows.UI.Notifications.ToastNotificationManager]::GetTemplateContent([Windows.UI.Notifications.ToastTemplateType]::ToastText02); \
+$text = $template.GetElementsByTagName('text'); \
+$text.Item(0).AppendChild($template.CreateTextNode($env:DBDM_NOTIFY_SUMMARY)) > $null; \
+$text.Item(1).AppendChild($template.CreateTextNode($env:DBDM_NOTIFY_BODY)) > $null; \
+$app = '{1AC14E77-02E7-4E5D-B744-2EB1AE5198B7}\\WindowsPowerShell\\v1.0\\powershell.exe'; \
+[Windows.UI.Notifications.ToastNotificationManager]::CreateToastNotifier($app).Show([Windows.UI.Notifications.ToastNotification]::new($template))";
+    let mut command = Command::new("powershell");
+    command
+        .args(["-NoProfile", "-NonInteractive", "-Command", SCRIPT])
+        .env("DBDM_NOTIFY_SUMMARY", summary)
+        .env("DBDM_NOTIFY_BODY", body);
+    command
+}
+
+// Helper to list the destinations of some items, one per line
+fn list(items: &[&PlanItem]) -> String {
+    let mut lines: Vec<String> = items
+        .iter()
+        .take(MAX_LISTED)
+        .map(|item| match &item.reason {
+            Some(reason) => format!("{} ({})", item.to.display(), reason),
+            None => item.to.display().to_string(),
+        })
+        .collect();
+    if items.len() > MAX_LISTED {
+        lines.push(format!("and {} more", items.len() - MAX_LISTED));
+    }
+    lines.join("\n")
+}
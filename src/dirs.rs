@@ -0,0 +1,128 @@
+use crate::platform::home_dir;
+use std::path::{Component, Path, PathBuf};
+
+// Helper to get the directory dbdm keeps its state in
+//
+// History, backup registry, manifests and the rollback journal all live
+// here, so nothing dbdm needs for itself is written next to the dotfiles.
+//
+// @return Result<PathBuf> - `$XDG_STATE_HOME/dbdm`, or `~/.local/state/dbdm` if unset
+pub fn state_dir() -> std::io::Result<PathBuf> {
+    base_dir("XDG_STATE_HOME", ".local/state")
+}
+
+// Helper to get the directory dbdm keeps files it can do without in
+//
+// @return Result<PathBuf> - `$XDG_CACHE_HOME/dbdm`, or `~/.cache/dbdm` if unset
+pub fn cache_dir() -> std::io::Result<PathBuf> {
+    base_dir("XDG_CACHE_HOME", ".cache")
+}
+
+// Helper to get the directory for files that only live as long as the session, like locks
+//
+// @return Result<PathBuf> - `$XDG_RUNTIME_DIR/dbdm`, or the cache directory if unset
+pub fn runtime_dir() -> std::io::Result<PathBuf> {
+    match xdg_var("XDG_RUNTIME_DIR") {
+        Some(dir) => Ok(dir.join("dbdm")),
+        None => cache_dir(),
+    }
+}
+
+// Helper to get the file the history of runs is kept in
+//
+// @return Result<PathBuf> - `history.jsonl` in the state directory
+pub fn history_file() -> std::io::Result<PathBuf> {
+    Ok(state_dir()?.join("history.jsonl"))
+}
+
+// Helper to get the directory backups go to with `location = state`
+//
+// @return Result<PathBuf> - `backups` in the state directory
+pub fn backup_dir() -> std::io::Result<PathBuf> {
+    Ok(state_dir()?.join("backups"))
+}
+
+// Helper to get the directory in `backup_dir` the backups of a destination go to
+//
+// The directory of the destination is mirrored in it, so destinations with
+// the same name in different directories keep their backups apart.
+//
+// @param dest: &Path - the resolved destination
+// @return Result<PathBuf> - e.g. `backups/home/user/.config` for `/home/user/.config/nvim`
+pub fn backup_dir_for(dest: &Path) -> std::io::Result<PathBuf> {
+    let mut dir = backup_dir()?;
+    if let Some(parent) = dest.parent() {
+        // Only the names, as the root and a Windows drive can't be joined
+        dir.extend(parent.components().filter_map(|component| match component {
+            Component::Normal(name) => Some(name),
+            _ => None,
+        }));
+    }
+    Ok(dir)
+}
+
+// Helper to get the file the backups made by `sync` are recorded in
+//
+// @return Result<PathBuf> - `backups.jsonl` in the state directory
+pub fn backup_registry() -> std::io::Result<PathBuf> {
+    Ok(state_dir()?.join("backups.jsonl"))
+}
+
+// Helper to get the file the directories created by `sync` are recorded in
+//
+// @return Result<PathBuf> - `created_dirs.jsonl` in the state directory
+pub fn created_dirs_file() -> std::io::Result<PathBuf> {
+    Ok(state_dir()?.join("created_dirs.jsonl"))
+}
+
+// Helper to get the file a manifest is kept in
+//
+// @param name: &str - the file name of the manifest, e.g. `copies.json`
+// @return Result<PathBuf> - the file in the state directory
+pub fn manifest_file(name: &str) -> std::io::Result<PathBuf> {
+    Ok(state_dir()?.join(name))
+}
+
+// Helper to get the directory the journals of `sync --atomic` are kept in
+//
+// @return Result<PathBuf> - `rollback` in the state directory
+pub fn journal_dir() -> std::io::Result<PathBuf> {
+    Ok(state_dir()?.join("rollback"))
+}
+
+// Helper to get the directory the sources last copied to destinations are kept in
+//
+// @return Result<PathBuf> - `copy_bases` in the state directory
+pub fn copy_bases_dir() -> std::io::Result<PathBuf> {
+    Ok(state_dir()?.join("copy_bases"))
+}
+
+// Helper to resolve a base directory of the XDG spec for dbdm
+//
+// @param var: &str - the variable naming the base directory
+// @param fallback: &str - the base directory under the home directory if the variable is unset
+// @return Result<PathBuf> - the `dbdm` directory inside it, an error if no home is known either
+fn base_dir(var: &str, fallback: &str) -> std::io::Result<PathBuf> {
+    let base = xdg_var(var)
+        .or_else(|| home_dir().map(|home| PathBuf::from(home).join(fallback)))
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("neither {} nor HOME is set", var),
+            )
+        })?;
+    Ok(base.join("dbdm"))
+}
+
+// Helper to read a variable of the XDG spec
+//
+// The spec says relative paths are invalid and must be ignored, same as
+// empty ones.
+//
+// @param var: &str - the name of the variable
+// @return Option<PathBuf> - the path, None if it is unset or not absolute
+fn xdg_var(var: &str) -> Option<PathBuf> {
+    std::env::var_os(var)
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+}
@@ -0,0 +1,93 @@
+use crate::config_parser::{Config, Link};
+use crate::{canonicalize_or_fallback, normalize_lexically, resolve_link_destination};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+// Finds the source a destination comes from, following the config
+//
+// A path inside a linked directory maps to the same path inside its source.
+// The config is looked at first, so destinations that aren't linked yet or
+// were copied are found too, then where the symlinks on the way lead. With
+// links nested in each other, the innermost destination wins.
+//
+// @param config: &Config - the parsed config state
+// @param dest: &Path - the destination, absolute
+// @return Option<(&Link, PathBuf)> - the link and the source to edit, None if no link goes there
+pub fn source_of<'a>(config: &'a Config, dest: &Path) -> Option<(&'a Link, PathBuf)> {
+    let dest = normalize_lexically(dest);
+    let by_config = innermost(
+        config,
+        |link| {
+            let to =
+                resolve_link_destination(&link.from, &link.to).unwrap_or_else(|_| link.to.clone());
+            Some(normalize_lexically(&to))
+        },
+        &dest,
+    );
+    by_config.or_else(|| {
+        let resolved = canonicalize_or_fallback(&dest);
+        innermost(
+            config,
+            |link| Some(canonicalize_or_fallback(&link.from)),
+            &resolved,
+        )
+    })
+}
+
+// Helper to find the link whose root is the longest prefix of a path
+//
+// @param root: impl Fn(&Link) -> Option<PathBuf> - the path of a link to match against
+// @param path: &Path - the path to look up
+// @return Option<(&Link, PathBuf)> - the link and the path mapped into its source
+fn innermost<'a>(
+    config: &'a Config,
+    root: impl Fn(&Link) -> Option<PathBuf>,
+    path: &Path,
+) -> Option<(&'a Link, PathBuf)> {
+    config
+        .links
+        .iter()
+        .filter_map(|link| {
+            let root = root(link)?;
+            let rest = path.strip_prefix(&root).ok()?;
+            if rest.as_os_str().is_empty() {
+                return Some((root.components().count(), link, link.from.clone()));
+            }
+            // Only a directory has anything inside it
+            link.from
+                .is_dir()
+                .then(|| (root.components().count(), link, link.from.join(rest)))
+        })
+        .max_by_key(|(depth, _, _)| *depth)
+        .map(|(_, link, source)| (link, source))
+}
+
+// Opens a file in the editor of the user and waits for it to be closed
+//
+// The editor is `$VISUAL`, then `$EDITOR`, then `vi`. It may have arguments,
+// like `code --wait`.
+//
+// @param path: &Path - the file to edit
+// @return Result<()> - an error if the editor couldn't run or failed
+pub fn open_in_editor(path: &Path) -> std::io::Result<()> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|name| std::env::var(name).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| String::from("vi"));
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+    debug!(editor, path = %path.display(), "opening editor");
+
+    let status = std::process::Command::new(program)
+        .args(words)
+        .arg(path)
+        .status()?;
+    if status.success() {
+        return Ok(());
+    }
+    Err(std::io::Error::other(format!(
+        "{} exited with {}",
+        program, status
+    )))
+}
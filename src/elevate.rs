@@ -0,0 +1,96 @@
+use crate::{BackupNaming, BackupOutcome, resolve_link_destination, unique_backup_path};
+use std::ffi::OsStr;
+use std::path::Path;
+use tracing::debug;
+
+// The command used to run file operations with elevated permissions
+const SUDO: &str = "sudo";
+
+// Same as `replace_link`, with every change to the filesystem run through sudo
+//
+// @param from: &Path - the source path for the symlink
+// @param to: &Path - the destination path for the symlink
+// @return Result<()> - if replacement was successful
+pub fn replace_link(from: &Path, to: &Path) -> std::io::Result<()> {
+    let dest = resolve_link_destination(from, to)?;
+    if std::fs::symlink_metadata(&dest).is_ok() {
+        sudo(&[
+            OsStr::new("rm"),
+            OsStr::new("-rf"),
+            OsStr::new("--"),
+            dest.as_os_str(),
+        ])?;
+    }
+    sudo(&[
+        OsStr::new("ln"),
+        OsStr::new("-s"),
+        OsStr::new("--"),
+        from.as_os_str(),
+        dest.as_os_str(),
+    ])
+}
+
+// Same as `backup_and_replace_in`, with every change to the filesystem run through sudo
+//
+// @param from: &Path - the source path for the symlink
+// @param to: &Path - the destination path to backup and replace
+// @param backup_dir: &Path - the directory to put the backup in, created if missing
+// @param naming: &BackupNaming - how the backup is named
+// @return Result<BackupOutcome> - what happened to the old destination
+pub fn backup_and_replace_in(
+    from: &Path,
+    to: &Path,
+    backup_dir: &Path,
+    naming: &BackupNaming,
+) -> std::io::Result<BackupOutcome> {
+    let dest = resolve_link_destination(from, to)?;
+
+    // Protected destinations may not be readable, in which case they are backed up
+    if crate::same_content(from, &dest).unwrap_or(false) {
+        replace_link(from, &dest)?;
+        return Ok(BackupOutcome::Identical);
+    }
+
+    sudo(&[
+        OsStr::new("mkdir"),
+        OsStr::new("-p"),
+        OsStr::new("--"),
+        backup_dir.as_os_str(),
+    ])?;
+    let base_name = dest.file_name().unwrap_or(OsStr::new("backup"));
+    let backup_path = unique_backup_path(backup_dir, base_name, naming);
+    sudo(&[
+        OsStr::new("mv"),
+        OsStr::new("--"),
+        dest.as_os_str(),
+        backup_path.as_os_str(),
+    ])?;
+    sudo(&[
+        OsStr::new("ln"),
+        OsStr::new("-s"),
+        OsStr::new("--"),
+        from.as_os_str(),
+        dest.as_os_str(),
+    ])?;
+    Ok(BackupOutcome::BackedUp(backup_path))
+}
+
+// Helper to run a single command through sudo
+//
+// The terminal is inherited, so sudo can ask for a password.
+//
+// @param args: &[&OsStr] - the command and its arguments
+// @return Result<()> - an error if the command couldn't run or failed
+fn sudo(args: &[&OsStr]) -> std::io::Result<()> {
+    debug!(?args, "running with sudo");
+    let status = std::process::Command::new(SUDO).args(args).status()?;
+    if status.success() {
+        return Ok(());
+    }
+    Err(std::io::Error::other(format!(
+        "{} {} exited with {}",
+        SUDO,
+        args[0].to_string_lossy(),
+        status
+    )))
+}
@@ -0,0 +1,309 @@
+use std::env;
+use std::ffi::{OsStr, OsString};
+use std::fs;
+
+// A function resolving the value of a built-in
+type Resolver = fn() -> Result<OsString, String>;
+// A function telling where a built-in gets its value from, like `$HOME`
+type Origin = fn() -> String;
+
+// How a variable gets its value
+#[derive(Clone)]
+enum Value {
+    // A value set by the caller
+    Fixed(OsString),
+    // A built-in, resolved only when a path uses it, so a missing
+    // environment variable doesn't break unrelated lines
+    Resolve(Resolver, Origin),
+}
+
+// The `!keyword` variables that can be used in config paths
+//
+// `VarMap::default()` has the built-ins (`!home`, `!here`, `!xdg_conf`, ...).
+// Callers can add their own variables or override the built-ins with `set`.
+#[derive(Clone)]
+pub struct VarMap {
+    vars: Vec<(String, Value)>,
+}
+
+impl Default for VarMap {
+    fn default() -> Self {
+        let builtins: [(&str, Resolver, Origin); 10] = [
+            ("!here", here_dir, || {
+                String::from("the directory of the config")
+            }),
+            ("!home", home_dir, || String::from("$HOME")),
+            (
+                "!xdg_conf",
+                || xdg_dir("XDG_CONFIG_HOME", ".config"),
+                || xdg_origin("XDG_CONFIG_HOME", ".config"),
+            ),
+            (
+                "!xdg_data",
+                || xdg_dir("XDG_DATA_HOME", ".local/share"),
+                || xdg_origin("XDG_DATA_HOME", ".local/share"),
+            ),
+            (
+                "!xdg_cache",
+                || xdg_dir("XDG_CACHE_HOME", ".cache"),
+                || xdg_origin("XDG_CACHE_HOME", ".cache"),
+            ),
+            (
+                "!xdg_state",
+                || xdg_dir("XDG_STATE_HOME", ".local/state"),
+                || xdg_origin("XDG_STATE_HOME", ".local/state"),
+            ),
+            (
+                "!app_support",
+                || macos_dir("Library/Application Support", "XDG_CONFIG_HOME", ".config"),
+                || macos_origin("Library/Application Support", "XDG_CONFIG_HOME", ".config"),
+            ),
+            (
+                "!library",
+                || macos_dir("Library", "XDG_DATA_HOME", ".local/share"),
+                || macos_origin("Library", "XDG_DATA_HOME", ".local/share"),
+            ),
+            ("!hostname", hostname, || match env::var_os("HOSTNAME") {
+                Some(name) if !name.is_empty() => String::from("$HOSTNAME"),
+                _ => String::from("the hostname of the machine"),
+            }),
+            ("!user", user, || match env::var_os("USER") {
+                Some(name) if !name.is_empty() => String::from("$USER"),
+                _ => String::from("$LOGNAME"),
+            }),
+        ];
+        VarMap {
+            vars: builtins
+                .into_iter()
+                .map(|(name, resolve, origin)| (name.to_string(), Value::Resolve(resolve, origin)))
+                .collect(),
+        }
+    }
+}
+
+impl VarMap {
+    // A map without any variables, not even the built-ins
+    pub fn empty() -> VarMap {
+        VarMap { vars: Vec::new() }
+    }
+
+    // Adds a variable, or replaces the one with the same name
+    //
+    // @param name: &str - the variable as written in paths, with its `!`
+    // @param value: impl Into<OsString> - what it expands to
+    // @return &mut VarMap - the map, to chain more calls
+    pub fn set(&mut self, name: &str, value: impl Into<OsString>) -> &mut VarMap {
+        let value = Value::Fixed(value.into());
+        match self.vars.iter_mut().find(|(var, _)| var == name) {
+            Some((_, existing)) => *existing = value,
+            None => self.vars.push((name.to_string(), value)),
+        }
+        self
+    }
+
+    // Removes a variable, so paths using it are rejected
+    pub fn remove(&mut self, name: &str) -> &mut VarMap {
+        self.vars.retain(|(var, _)| var != name);
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.vars.iter().any(|(var, _)| var == name)
+    }
+
+    // Checks that every `!` in a string starts one of the variables
+    //
+    // @param input: impl AsRef<OsStr> - the string, like a path from the config
+    // @return bool - false if the string has a keyword the map doesn't know
+    pub fn knows_all(&self, input: impl AsRef<OsStr>) -> bool {
+        let mut rest = input.as_ref().as_encoded_bytes();
+        while let Some(pos) = rest.iter().position(|byte| *byte == b'!') {
+            rest = &rest[pos..];
+            match self.longest_match(rest) {
+                Some((name, _)) => rest = &rest[name.len()..],
+                None => return false,
+            }
+        }
+        true
+    }
+
+    // Helper to find the longest variable at the start of a string
+    fn longest_match(&self, rest: &[u8]) -> Option<&(String, Value)> {
+        self.vars
+            .iter()
+            .filter(|(var, _)| rest.starts_with(var.as_bytes()))
+            .max_by_key(|(var, _)| var.len())
+    }
+}
+
+// Expands the `!keyword` variables in a string
+//
+// Every `!` has to start one of the variables, the longest one that matches
+// is used. Values are put in as they are, a `!` inside a value isn't expanded again.
+// Paths that aren't valid UTF-8 are expanded byte for byte, as are values.
+//
+// @param input: impl AsRef<OsStr> - the string to expand, like a path from the config
+// @param vars: &VarMap - the variables that can be used
+// @return Result<OsString, String> - the expanded string, or an error for an unknown or unresolvable variable
+pub fn expand(input: impl AsRef<OsStr>, vars: &VarMap) -> Result<OsString, String> {
+    explain(input, vars).map(|(expanded, _)| expanded)
+}
+
+// A keyword replaced while expanding a string
+#[derive(Debug, PartialEq)]
+pub struct Step {
+    // The keyword as written, with its `!`
+    pub keyword: String,
+    // Where the value comes from, like `$XDG_CONFIG_HOME`
+    pub origin: String,
+    pub value: OsString,
+}
+
+// Same as `expand`, also telling what every keyword was replaced with
+//
+// @param input: impl AsRef<OsStr> - the string to expand, like a path from the config
+// @param vars: &VarMap - the variables that can be used
+// @return Result<(OsString, Vec<Step>), String> - the expanded string and one step per keyword, in order
+pub fn explain(input: impl AsRef<OsStr>, vars: &VarMap) -> Result<(OsString, Vec<Step>), String> {
+    let input = input.as_ref();
+    let mut expanded: Vec<u8> = Vec::with_capacity(input.len());
+    let mut steps = Vec::new();
+    // Every variable is resolved once per input
+    let mut resolved: Vec<(&str, OsString)> = Vec::new();
+    let mut rest = input.as_encoded_bytes();
+    while let Some(pos) = rest.iter().position(|byte| *byte == b'!') {
+        expanded.extend_from_slice(&rest[..pos]);
+        rest = &rest[pos..];
+        let Some((name, value)) = vars.longest_match(rest) else {
+            return Err(format!("Invalid keyword in {}", input.display()));
+        };
+        let (value, origin) = match (resolved.iter().find(|(var, _)| var == name), value) {
+            (Some((_, resolved)), Value::Fixed(_)) => {
+                (resolved.clone(), String::from("set by dbdm"))
+            }
+            (Some((_, resolved)), Value::Resolve(_, origin)) => (resolved.clone(), origin()),
+            (None, Value::Fixed(value)) => {
+                resolved.push((name, value.clone()));
+                (value.clone(), String::from("set by dbdm"))
+            }
+            (None, Value::Resolve(resolve, origin)) => {
+                let value = resolve()?;
+                resolved.push((name, value.clone()));
+                (value, origin())
+            }
+        };
+        expanded.extend_from_slice(value.as_encoded_bytes());
+        steps.push(Step {
+            keyword: name.clone(),
+            origin,
+            value,
+        });
+        rest = &rest[name.len()..];
+    }
+    expanded.extend_from_slice(rest);
+    let expanded = crate::platform::os_string_from_bytes(expanded)
+        .ok_or_else(|| format!("Invalid path after expanding {}", input.display()))?;
+    Ok((expanded, steps))
+}
+
+fn here_dir() -> Result<OsString, String> {
+    let here = env::current_dir().map_err(|err| format!("Failed to resolve !here: {}", err))?;
+    Ok(here.into_os_string())
+}
+
+fn home_dir() -> Result<OsString, String> {
+    crate::platform::home_dir().ok_or_else(|| String::from("Can't read $HOME"))
+}
+
+// Helper to resolve an XDG base directory
+//
+// @param var: &str - the XDG variable to read
+// @param fallback: &str - the path relative to $HOME used when the variable is unset or empty
+// @return Result<OsString, String> - the resolved directory
+fn xdg_dir(var: &str, fallback: &str) -> Result<OsString, String> {
+    match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => Ok(dir),
+        _ => under_home(fallback),
+    }
+}
+
+// Helper to tell where an XDG base directory comes from
+//
+// @param var: &str - the XDG variable
+// @param fallback: &str - the path relative to $HOME used when the variable is unset or empty
+// @return String - the variable, or the fallback and why it is used
+fn xdg_origin(var: &str, fallback: &str) -> String {
+    match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => format!("${}", var),
+        _ => format!("$HOME/{}, as ${} is unset", fallback, var),
+    }
+}
+
+// Helper to join a path to $HOME, keeping `/` as the separator like the config does
+fn under_home(path: &str) -> Result<OsString, String> {
+    let mut dir = home_dir()?;
+    dir.push("/");
+    dir.push(path);
+    Ok(dir)
+}
+
+// Helper to resolve a directory that macOS keeps under ~/Library
+//
+// Other systems get the matching XDG base directory, so one config line covers both.
+//
+// @param library: &str - the path relative to $HOME on macOS
+// @param var: &str - the XDG variable to read elsewhere
+// @param fallback: &str - the path relative to $HOME used when the variable is unset or empty
+// @return Result<OsString, String> - the resolved directory
+fn macos_dir(library: &str, var: &str, fallback: &str) -> Result<OsString, String> {
+    if cfg!(target_os = "macos") {
+        return under_home(library);
+    }
+    xdg_dir(var, fallback)
+}
+
+// Helper to tell where a directory that macOS keeps under ~/Library comes from
+fn macos_origin(library: &str, var: &str, fallback: &str) -> String {
+    if cfg!(target_os = "macos") {
+        return format!("$HOME/{}", library);
+    }
+    xdg_origin(var, fallback)
+}
+
+// Helper to resolve the machine hostname
+//
+// Tries $HOSTNAME first, then the kernel and /etc files, then the `hostname` binary
+fn hostname() -> Result<OsString, String> {
+    if let Some(name) = env::var_os("HOSTNAME")
+        && !name.is_empty()
+    {
+        return Ok(name);
+    }
+
+    for file in ["/proc/sys/kernel/hostname", "/etc/hostname"] {
+        if let Ok(name) = fs::read_to_string(file) {
+            let name = name.trim();
+            if !name.is_empty() {
+                return Ok(name.into());
+            }
+        }
+    }
+
+    let output = std::process::Command::new("hostname")
+        .output()
+        .map_err(|err| format!("Failed to resolve !hostname: {}", err))?;
+    let name = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if name.is_empty() {
+        return Err(String::from("Failed to resolve !hostname"));
+    }
+    Ok(name.into())
+}
+
+// Helper to resolve the name of the user running the util
+fn user() -> Result<OsString, String> {
+    ["USER", "LOGNAME"]
+        .iter()
+        .filter_map(env::var_os)
+        .find(|name| !name.is_empty())
+        .ok_or_else(|| String::from("Failed to resolve !user: $USER and $LOGNAME are unset"))
+}
@@ -0,0 +1,211 @@
+use crate::config_parser::{Config, LinkKind};
+use crate::resolve_link_destination;
+use std::path::{Path, PathBuf};
+
+mod chezmoi;
+mod stow;
+
+// The tools a config can be exported to
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    // GNU Stow, one package directory per top-level source directory
+    Stow,
+    // The chezmoi source state, with its `dot_` and `executable_` names
+    Chezmoi,
+}
+
+impl Format {
+    // Helper to parse the value of the `--format` flag of `export`
+    //
+    // @param value: &str - the flag value
+    // @return Option<Format> - the format, None if the value is unknown
+    pub fn parse(value: &str) -> Option<Format> {
+        match value {
+            "stow" => Some(Format::Stow),
+            "chezmoi" => Some(Format::Chezmoi),
+            _ => None,
+        }
+    }
+
+    fn backend(&self) -> &'static dyn Backend {
+        match self {
+            Format::Stow => &stow::Stow,
+            Format::Chezmoi => &chezmoi::Chezmoi,
+        }
+    }
+}
+
+// How a format lays out the files of a link
+trait Backend {
+    // Where a single file of a link goes in the exported layout
+    //
+    // @param source: &Path - the file in the dotfiles, relative to the repository
+    // @param target: &Path - where the file ends up, relative to the home directory
+    // @param symlink: bool - if the file is a symlink
+    // @param executable: bool - if the file is executable
+    // @return PathBuf - the path in the layout
+    fn place(&self, source: &Path, target: &Path, symlink: bool, executable: bool) -> PathBuf;
+
+    // If the layout stores symlinks as files with their target as content
+    fn symlinks_as_files(&self) -> bool {
+        false
+    }
+}
+
+// What is written at one path of the layout
+#[derive(Debug, PartialEq)]
+pub enum Content {
+    // A copy of a file from the dotfiles
+    Copy(PathBuf),
+    // A symlink with this target
+    Symlink(PathBuf),
+    // A file with this content, not always valid UTF-8
+    Text(Vec<u8>),
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Entry {
+    // The path in the layout, relative to its top
+    pub path: PathBuf,
+    pub content: Content,
+}
+
+#[derive(Debug, Default, PartialEq)]
+pub struct Export {
+    pub entries: Vec<Entry>,
+    // Links that can't be expressed in the format, with the reason
+    pub skipped: Vec<(PathBuf, String)>,
+}
+
+// Converts a config to the layout of another tool
+//
+// Both tools link into a single directory, so only links into the home
+// directory are exported. `system` and `encrypted` links are left out.
+//
+// @param config: &Config - the parsed config
+// @param repo: &Path - the dotfiles repository, where dbdm.conf is
+// @param home: &Path - the home directory
+// @param format: Format - the tool to export to
+// @return Export - the layout, and the links that were left out
+pub fn export(config: &Config, repo: &Path, home: &Path, format: Format) -> Export {
+    let backend = format.backend();
+    let mut export = Export::default();
+
+    for link in &config.links {
+        if link.kind == LinkKind::System {
+            export
+                .skipped
+                .push((link.to.clone(), "system links are not exported".to_string()));
+            continue;
+        }
+        if link.kind == LinkKind::Encrypted {
+            export.skipped.push((
+                link.to.clone(),
+                "encrypted links are not exported".to_string(),
+            ));
+            continue;
+        }
+        let dest =
+            resolve_link_destination(&link.from, &link.to).unwrap_or_else(|_| link.to.clone());
+        let Ok(target) = dest.strip_prefix(home) else {
+            export
+                .skipped
+                .push((link.to.clone(), "not in the home directory".to_string()));
+            continue;
+        };
+        let source = link.from.strip_prefix(repo).unwrap_or(&link.from);
+
+        let mut files = Vec::new();
+        if let Err(err) = collect(&link.from, Path::new(""), &mut files) {
+            export.skipped.push((link.to.clone(), err.to_string()));
+            continue;
+        }
+        for (rel, meta) in files {
+            let from = join(&link.from, &rel);
+            let symlink = meta.file_type().is_symlink();
+            let executable = crate::platform::is_executable(&meta);
+            let path = backend.place(
+                &join(source, &rel),
+                &join(target, &rel),
+                symlink,
+                executable,
+            );
+            let content = if symlink {
+                match std::fs::read_link(&from) {
+                    Ok(target) if backend.symlinks_as_files() => {
+                        Content::Text(target.into_os_string().into_encoded_bytes())
+                    }
+                    Ok(target) => Content::Symlink(target),
+                    Err(err) => {
+                        export.skipped.push((from, err.to_string()));
+                        continue;
+                    }
+                }
+            } else {
+                Content::Copy(from)
+            };
+            export.entries.push(Entry { path, content });
+        }
+    }
+
+    export
+}
+
+// Writes an exported layout to a directory
+//
+// @param export: &Export - the layout
+// @param out: &Path - the directory to write to, created if missing
+// @return Result<()> - an error if anything couldn't be written
+pub fn write(export: &Export, out: &Path) -> std::io::Result<()> {
+    for entry in &export.entries {
+        let path = out.join(&entry.path);
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        match &entry.content {
+            Content::Copy(from) => {
+                std::fs::copy(from, &path)?;
+            }
+            Content::Symlink(target) => crate::platform::symlink(target, &path)?,
+            Content::Text(text) => std::fs::write(&path, text)?,
+        }
+    }
+    Ok(())
+}
+
+// Helper to list every file and symlink of a source, directories are walked
+//
+// @param root: &Path - the source of the link
+// @param rel: &Path - the path below the source that is being listed
+// @param files: &mut Vec<(PathBuf, std::fs::Metadata)> - where the files are collected, relative to the source
+// @return Result<()> - an error if something can't be read
+fn collect(
+    root: &Path,
+    rel: &Path,
+    files: &mut Vec<(PathBuf, std::fs::Metadata)>,
+) -> std::io::Result<()> {
+    let path = join(root, rel);
+    let meta = std::fs::symlink_metadata(&path)?;
+    if !meta.is_dir() {
+        files.push((rel.to_path_buf(), meta));
+        return Ok(());
+    }
+
+    let mut names = std::fs::read_dir(&path)?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    names.sort();
+    for name in names {
+        collect(root, &rel.join(name), files)?;
+    }
+    Ok(())
+}
+
+// Helper to join a path below a base, without the trailing `/` an empty path would add
+fn join(base: &Path, rel: &Path) -> PathBuf {
+    if rel.as_os_str().is_empty() {
+        base.to_path_buf()
+    } else {
+        base.join(rel)
+    }
+}
@@ -0,0 +1,42 @@
+use super::Backend;
+use std::path::{Path, PathBuf};
+
+// chezmoi keeps the home directory in its source state, with the attributes
+// of every entry in its name: `.zshrc` is `dot_zshrc`, an executable script
+// is `executable_name`, and a symlink is a `symlink_` file with its target
+// as content.
+pub struct Chezmoi;
+
+impl Backend for Chezmoi {
+    fn place(&self, _source: &Path, target: &Path, symlink: bool, executable: bool) -> PathBuf {
+        let count = target.components().count();
+        target
+            .components()
+            .enumerate()
+            .map(|(idx, component)| {
+                let name = component.as_os_str();
+                // Names are built as bytes, they don't have to be valid UTF-8
+                let mut placed: Vec<u8> = Vec::new();
+                if idx + 1 == count {
+                    if symlink {
+                        placed.extend_from_slice(b"symlink_");
+                    } else if executable {
+                        placed.extend_from_slice(b"executable_");
+                    }
+                }
+                match name.as_encoded_bytes().strip_prefix(b".") {
+                    Some(rest) => {
+                        placed.extend_from_slice(b"dot_");
+                        placed.extend_from_slice(rest);
+                    }
+                    None => placed.extend_from_slice(name.as_encoded_bytes()),
+                }
+                crate::platform::os_string_from_bytes(placed).unwrap_or_else(|| name.to_os_string())
+            })
+            .collect()
+    }
+
+    fn symlinks_as_files(&self) -> bool {
+        true
+    }
+}
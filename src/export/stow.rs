@@ -0,0 +1,23 @@
+use super::Backend;
+use std::ffi::OsStr;
+use std::path::{Component, Path, PathBuf};
+
+// The package for sources right at the top of the repository
+const DEFAULT_PACKAGE: &str = "dotfiles";
+
+// GNU Stow links every file of a package directory into the target directory,
+// at the same path. Files in a directory of the repository, like `nvim/init.lua`,
+// go into the package of that directory, `nvim`. Files right at the top of the
+// repository share one package.
+pub struct Stow;
+
+impl Backend for Stow {
+    fn place(&self, source: &Path, target: &Path, _symlink: bool, _executable: bool) -> PathBuf {
+        let mut components = source.components();
+        let package = match (components.next(), components.next()) {
+            (Some(Component::Normal(dir)), Some(_)) => dir,
+            _ => OsStr::new(DEFAULT_PACKAGE),
+        };
+        Path::new(package).join(target)
+    }
+}
@@ -0,0 +1,558 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::path::{Component, Path, PathBuf};
+
+// How many symlinks are followed before a path is treated as a loop
+const MAX_SYMLINK_HOPS: usize = 40;
+
+// The filesystem calls the library makes to look at and change destinations
+//
+// `RealFs` goes to the disk. `MemoryFs` keeps everything in memory, so the
+// library can be tested without temp directories, and `FailingFs` makes
+// chosen calls fail, so error paths can be tested too.
+pub trait Fs {
+    // Looks at a path without following a symlink at the end of it
+    fn stat(&self, path: &Path) -> std::io::Result<FileStat>;
+
+    fn read_link(&self, path: &Path) -> std::io::Result<PathBuf>;
+
+    // @param target: &Path - where the symlink points
+    // @param link: &Path - where the symlink is made, must not exist
+    fn symlink(&self, target: &Path, link: &Path) -> std::io::Result<()>;
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()>;
+
+    // Removes a file or a symlink, or a directory with everything in it
+    fn remove(&self, path: &Path) -> std::io::Result<()>;
+
+    // @return Result<Vec<OsString>> - the names of the entries, sorted
+    fn read_dir(&self, path: &Path) -> std::io::Result<Vec<OsString>>;
+
+    fn create_dir_all(&self, path: &Path) -> std::io::Result<()>;
+
+    // Reads a whole file, following symlinks
+    fn read(&self, path: &Path) -> std::io::Result<Vec<u8>>;
+
+    fn canonicalize(&self, path: &Path) -> std::io::Result<PathBuf>;
+
+    // Compares the content of two regular files of the same length
+    fn same_file(&self, a: &Path, b: &Path) -> std::io::Result<bool> {
+        Ok(self.read(a)? == self.read(b)?)
+    }
+}
+
+// The kinds of entries the library tells apart
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    File,
+    Dir,
+    Symlink,
+    // Sockets, devices and the like
+    Other,
+}
+
+// The parts of a `std::fs::Metadata` the library looks at
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FileStat {
+    pub kind: FileKind,
+    pub len: u64,
+}
+
+impl FileStat {
+    pub fn is_file(&self) -> bool {
+        self.kind == FileKind::File
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.kind == FileKind::Dir
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        self.kind == FileKind::Symlink
+    }
+}
+
+impl From<&std::fs::Metadata> for FileStat {
+    fn from(meta: &std::fs::Metadata) -> FileStat {
+        let file_type = meta.file_type();
+        let kind = if file_type.is_symlink() {
+            FileKind::Symlink
+        } else if file_type.is_dir() {
+            FileKind::Dir
+        } else if file_type.is_file() {
+            FileKind::File
+        } else {
+            FileKind::Other
+        };
+        FileStat {
+            kind,
+            len: meta.len(),
+        }
+    }
+}
+
+// The real filesystem
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RealFs;
+
+impl Fs for RealFs {
+    fn stat(&self, path: &Path) -> std::io::Result<FileStat> {
+        std::fs::symlink_metadata(path).map(|meta| FileStat::from(&meta))
+    }
+
+    fn read_link(&self, path: &Path) -> std::io::Result<PathBuf> {
+        std::fs::read_link(path)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> std::io::Result<()> {
+        crate::platform::symlink(target, link)
+    }
+
+    // Falls back to copying when the paths are on different filesystems
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        crate::move_path(from, to)
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        let meta = std::fs::symlink_metadata(path)?;
+        if meta.is_dir() {
+            std::fs::remove_dir_all(path)
+        } else {
+            std::fs::remove_file(path)
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> std::io::Result<Vec<OsString>> {
+        let mut names = std::fs::read_dir(path)?
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        names.sort();
+        Ok(names)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        std::fs::create_dir_all(path)
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        std::fs::read(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> std::io::Result<PathBuf> {
+        std::fs::canonicalize(path)
+    }
+
+    // Compares in chunks, so large files aren't loaded whole
+    fn same_file(&self, a: &Path, b: &Path) -> std::io::Result<bool> {
+        use std::io::Read;
+
+        let mut a_file = std::fs::File::open(a)?;
+        let mut b_file = std::fs::File::open(b)?;
+        let mut a_buf = [0u8; 8192];
+        let mut b_buf = [0u8; 8192];
+        loop {
+            let read = a_file.read(&mut a_buf)?;
+            if read == 0 {
+                return Ok(true);
+            }
+            b_file.read_exact(&mut b_buf[..read])?;
+            if a_buf[..read] != b_buf[..read] {
+                return Ok(false);
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    File(Vec<u8>),
+    Dir,
+    Symlink(PathBuf),
+}
+
+// A filesystem kept in memory, for tests
+//
+// Paths have to be absolute, and `/` always exists. Symlinks are only
+// followed by `read` and `canonicalize`, a symlink in the middle of any other
+// path is not resolved.
+#[derive(Debug, Default)]
+pub struct MemoryFs {
+    nodes: std::sync::Mutex<BTreeMap<PathBuf, Node>>,
+}
+
+impl MemoryFs {
+    pub fn new() -> MemoryFs {
+        MemoryFs::default()
+    }
+
+    // Adds a file, with any missing parent directories
+    pub fn add_file(&self, path: &Path, contents: &[u8]) {
+        self.add(path, Node::File(contents.to_vec()));
+    }
+
+    // Adds a directory, with any missing parent directories
+    pub fn add_dir(&self, path: &Path) {
+        self.add(path, Node::Dir);
+    }
+
+    // Adds a symlink, with any missing parent directories
+    pub fn add_symlink(&self, path: &Path, target: &Path) {
+        self.add(path, Node::Symlink(target.to_path_buf()));
+    }
+
+    // Lists every path in the filesystem, sorted
+    pub fn paths(&self) -> Vec<PathBuf> {
+        self.nodes().keys().cloned().collect()
+    }
+
+    fn add(&self, path: &Path, node: Node) {
+        if let Some(parent) = path.parent() {
+            let _ = self.create_dir_all(parent);
+        }
+        self.nodes().insert(path.to_path_buf(), node);
+    }
+
+    fn nodes(&self) -> std::sync::MutexGuard<'_, BTreeMap<PathBuf, Node>> {
+        self.nodes
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn get(&self, path: &Path) -> std::io::Result<Node> {
+        if path == Path::new("/") {
+            return Ok(Node::Dir);
+        }
+        self.nodes()
+            .get(path)
+            .cloned()
+            .ok_or_else(|| not_found(path))
+    }
+
+    // Helper to check that a path can be created
+    fn check_new(&self, path: &Path) -> std::io::Result<()> {
+        if self.get(path).is_ok() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} already exists", path.display()),
+            ));
+        }
+        match path.parent().map(|parent| self.get(parent)) {
+            Some(Ok(Node::Dir)) => Ok(()),
+            Some(Ok(_)) => Err(std::io::Error::new(
+                std::io::ErrorKind::NotADirectory,
+                format!("{} is not a directory", path.display()),
+            )),
+            _ => Err(not_found(path)),
+        }
+    }
+}
+
+impl Fs for MemoryFs {
+    fn stat(&self, path: &Path) -> std::io::Result<FileStat> {
+        Ok(match self.get(path)? {
+            Node::File(contents) => FileStat {
+                kind: FileKind::File,
+                len: contents.len() as u64,
+            },
+            Node::Dir => FileStat {
+                kind: FileKind::Dir,
+                len: 0,
+            },
+            Node::Symlink(target) => FileStat {
+                kind: FileKind::Symlink,
+                len: target.as_os_str().len() as u64,
+            },
+        })
+    }
+
+    fn read_link(&self, path: &Path) -> std::io::Result<PathBuf> {
+        match self.get(path)? {
+            Node::Symlink(target) => Ok(target),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is not a symlink", path.display()),
+            )),
+        }
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> std::io::Result<()> {
+        self.check_new(link)?;
+        self.nodes()
+            .insert(link.to_path_buf(), Node::Symlink(target.to_path_buf()));
+        Ok(())
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        let node = self.get(from)?;
+        match self.get(to) {
+            Ok(Node::Dir) => {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::IsADirectory,
+                    format!("{} is a directory", to.display()),
+                ));
+            }
+            // Like rename(2), files and symlinks are replaced
+            Ok(_) => self.remove(to)?,
+            Err(_) => self.check_new(to)?,
+        }
+
+        let mut nodes = self.nodes();
+        let moved: Vec<PathBuf> = nodes
+            .keys()
+            .filter(|path| path.starts_with(from) && path.as_path() != from)
+            .cloned()
+            .collect();
+        for path in moved {
+            if let Some(child) = nodes.remove(&path) {
+                let rel = path.strip_prefix(from).unwrap_or(&path);
+                nodes.insert(to.join(rel), child);
+            }
+        }
+        nodes.remove(from);
+        nodes.insert(to.to_path_buf(), node);
+        Ok(())
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        self.get(path)?;
+        self.nodes().retain(|other, _| !other.starts_with(path));
+        Ok(())
+    }
+
+    fn read_dir(&self, path: &Path) -> std::io::Result<Vec<OsString>> {
+        if self.get(path)? != Node::Dir {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::NotADirectory,
+                format!("{} is not a directory", path.display()),
+            ));
+        }
+        Ok(self
+            .nodes()
+            .keys()
+            .filter(|other| other.parent() == Some(path))
+            .filter_map(|other| other.file_name().map(|name| name.to_os_string()))
+            .collect())
+    }
+
+    fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        let mut current = PathBuf::new();
+        for component in path.components() {
+            current.push(component);
+            match self.get(&current) {
+                Ok(Node::Dir) => {}
+                Ok(_) => {
+                    return Err(std::io::Error::new(
+                        std::io::ErrorKind::AlreadyExists,
+                        format!("{} is not a directory", current.display()),
+                    ));
+                }
+                Err(_) => {
+                    self.nodes().insert(current.clone(), Node::Dir);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        match self.get(&self.canonicalize(path)?)? {
+            Node::File(contents) => Ok(contents),
+            _ => Err(std::io::Error::new(
+                std::io::ErrorKind::IsADirectory,
+                format!("{} is not a file", path.display()),
+            )),
+        }
+    }
+
+    fn canonicalize(&self, path: &Path) -> std::io::Result<PathBuf> {
+        let mut resolved = PathBuf::from("/");
+        // Steps left to walk, the next one last
+        let mut pending = steps(path);
+        pending.reverse();
+        let mut hops = 0;
+        while let Some(step) = pending.pop() {
+            match step {
+                Step::Root => resolved = PathBuf::from("/"),
+                Step::Parent => {
+                    resolved.pop();
+                }
+                Step::Name(name) => {
+                    let next = resolved.join(name);
+                    let Node::Symlink(target) = self.get(&next)? else {
+                        resolved = next;
+                        continue;
+                    };
+                    hops += 1;
+                    if hops > MAX_SYMLINK_HOPS {
+                        return Err(std::io::Error::new(
+                            std::io::ErrorKind::InvalidInput,
+                            format!("too many symlinks in {}", path.display()),
+                        ));
+                    }
+                    // A relative target is walked from the directory of the link
+                    pending.extend(steps(&target).into_iter().rev());
+                }
+            }
+        }
+        Ok(resolved)
+    }
+}
+
+// A filesystem that fails on purpose, for tests
+//
+// Every call is passed on to another filesystem, unless it is the one picked
+// to fail. The failed call changes nothing.
+#[derive(Debug)]
+pub struct FailingFs<F: Fs> {
+    inner: F,
+    // The call that fails, counted from 1
+    fail_nth: Option<usize>,
+    // Calls on this path, or anything below it, fail
+    fail_on: Option<PathBuf>,
+    kind: std::io::ErrorKind,
+    calls: std::sync::atomic::AtomicUsize,
+}
+
+impl<F: Fs> FailingFs<F> {
+    // Wraps a filesystem, nothing fails until told to
+    //
+    // @param inner: F - the filesystem calls are passed on to
+    // @return FailingFs<F> - the wrapper
+    pub fn new(inner: F) -> FailingFs<F> {
+        FailingFs {
+            inner,
+            fail_nth: None,
+            fail_on: None,
+            kind: std::io::ErrorKind::PermissionDenied,
+            calls: std::sync::atomic::AtomicUsize::new(0),
+        }
+    }
+
+    // Fails the nth call, counted from 1
+    pub fn fail_nth(mut self, n: usize) -> FailingFs<F> {
+        self.fail_nth = Some(n);
+        self
+    }
+
+    // Fails every call on a path or anything below it
+    pub fn fail_on(mut self, path: &Path) -> FailingFs<F> {
+        self.fail_on = Some(path.to_path_buf());
+        self
+    }
+
+    // Sets the kind of the injected errors, `PermissionDenied` by default
+    pub fn with_error(mut self, kind: std::io::ErrorKind) -> FailingFs<F> {
+        self.kind = kind;
+        self
+    }
+
+    // How many calls were made so far, failed ones included
+    pub fn calls(&self) -> usize {
+        self.calls.load(std::sync::atomic::Ordering::SeqCst)
+    }
+
+    pub fn inner(&self) -> &F {
+        &self.inner
+    }
+
+    // Helper to count a call and decide if it fails
+    //
+    // @param call: &str - the name of the call, for the error message
+    // @param paths: &[&Path] - the paths the call touches
+    // @return Result<()> - the injected error, if the call is the one picked
+    fn check(&self, call: &str, paths: &[&Path]) -> std::io::Result<()> {
+        let n = self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+        let matched = paths.iter().find(|path| {
+            self.fail_on
+                .as_ref()
+                .is_some_and(|fail_on| path.starts_with(fail_on))
+        });
+        if self.fail_nth != Some(n) && matched.is_none() {
+            return Ok(());
+        }
+        let path = matched.or(paths.first()).copied().unwrap_or(Path::new(""));
+        Err(std::io::Error::new(
+            self.kind,
+            format!("injected failure: {} {} (call {})", call, path.display(), n),
+        ))
+    }
+}
+
+impl<F: Fs> Fs for FailingFs<F> {
+    fn stat(&self, path: &Path) -> std::io::Result<FileStat> {
+        self.check("stat", &[path])?;
+        self.inner.stat(path)
+    }
+
+    fn read_link(&self, path: &Path) -> std::io::Result<PathBuf> {
+        self.check("read_link", &[path])?;
+        self.inner.read_link(path)
+    }
+
+    fn symlink(&self, target: &Path, link: &Path) -> std::io::Result<()> {
+        // Only where the link is made is touched
+        self.check("symlink", &[link])?;
+        self.inner.symlink(target, link)
+    }
+
+    fn rename(&self, from: &Path, to: &Path) -> std::io::Result<()> {
+        self.check("rename", &[from, to])?;
+        self.inner.rename(from, to)
+    }
+
+    fn remove(&self, path: &Path) -> std::io::Result<()> {
+        self.check("remove", &[path])?;
+        self.inner.remove(path)
+    }
+
+    fn read_dir(&self, path: &Path) -> std::io::Result<Vec<OsString>> {
+        self.check("read_dir", &[path])?;
+        self.inner.read_dir(path)
+    }
+
+    fn create_dir_all(&self, path: &Path) -> std::io::Result<()> {
+        self.check("create_dir_all", &[path])?;
+        self.inner.create_dir_all(path)
+    }
+
+    fn read(&self, path: &Path) -> std::io::Result<Vec<u8>> {
+        self.check("read", &[path])?;
+        self.inner.read(path)
+    }
+
+    fn canonicalize(&self, path: &Path) -> std::io::Result<PathBuf> {
+        self.check("canonicalize", &[path])?;
+        self.inner.canonicalize(path)
+    }
+
+    fn same_file(&self, a: &Path, b: &Path) -> std::io::Result<bool> {
+        self.check("same_file", &[a, b])?;
+        self.inner.same_file(a, b)
+    }
+}
+
+// One step of walking a path
+enum Step {
+    Root,
+    Parent,
+    Name(OsString),
+}
+
+// Helper to split a path into the steps to walk it, `.` is left out
+fn steps(path: &Path) -> Vec<Step> {
+    path.components()
+        .filter_map(|component| match component {
+            Component::RootDir | Component::Prefix(_) => Some(Step::Root),
+            Component::ParentDir => Some(Step::Parent),
+            Component::Normal(name) => Some(Step::Name(name.to_os_string())),
+            Component::CurDir => None,
+        })
+        .collect()
+}
+
+fn not_found(path: &Path) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::NotFound,
+        format!("{} doesn't exist", path.display()),
+    )
+}
@@ -0,0 +1,208 @@
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+// The command used for everything git
+const GIT: &str = "git";
+
+// Clones a repository
+//
+// The terminal is inherited, so git can show progress and ask for credentials.
+//
+// @param url: &str - anything `git clone` accepts, a URL or a local path
+// @param dest: &Path - the directory to clone into, must not exist or be empty
+// @return Result<()> - an error if git couldn't run or the clone failed
+pub fn clone(url: &str, dest: &Path) -> std::io::Result<()> {
+    git(&[
+        OsStr::new("clone"),
+        OsStr::new("--"),
+        OsStr::new(url),
+        dest.as_os_str(),
+    ])
+}
+
+// Helper to guess the directory `git clone` would pick for a URL
+//
+// @param url: &str - the URL of the repository
+// @return Option<String> - the last path segment without `.git`, None if there is none
+pub fn default_clone_dir(url: &str) -> Option<String> {
+    let url = url.trim_end_matches('/');
+    let name = url.rsplit(['/', ':']).next()?;
+    let name = name.strip_suffix(".git").unwrap_or(name);
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct RepoStatus {
+    pub root: PathBuf,
+    // Files with uncommitted changes, untracked ones included
+    pub changed: usize,
+    // Commits the upstream branch has that the checkout doesn't, as of the last fetch
+    pub behind: usize,
+}
+
+impl RepoStatus {
+    pub fn is_clean(&self) -> bool {
+        self.changed == 0 && self.behind == 0
+    }
+}
+
+// Helper to find the repository a path is in
+//
+// @param path: &Path - a file or directory, it has to exist
+// @return Option<PathBuf> - the top of the work tree, None if the path isn't in one
+pub fn repo_root(path: &Path) -> Option<PathBuf> {
+    let dir = if path.is_dir() { path } else { path.parent()? };
+    let root = output(dir, &["rev-parse", "--show-toplevel"]).ok()?;
+    Some(PathBuf::from(root.trim_end()))
+}
+
+// Looks at uncommitted changes and how far the checkout is behind its upstream
+//
+// Nothing is fetched, so `behind` is only as fresh as the last fetch or pull.
+// A branch without an upstream is never behind.
+//
+// @param root: &Path - the top of the work tree
+// @return Result<RepoStatus> - an error if git couldn't run or failed
+pub fn status(root: &Path) -> std::io::Result<RepoStatus> {
+    let changed = output(root, &["status", "--porcelain"])?.lines().count();
+    let behind = output(root, &["rev-list", "--count", "HEAD..@{upstream}"])
+        .ok()
+        .and_then(|count| count.trim().parse().ok())
+        .unwrap_or(0);
+    Ok(RepoStatus {
+        root: root.to_path_buf(),
+        changed,
+        behind,
+    })
+}
+
+// The hooks that run after the work tree changed from a pull or a checkout
+pub const SYNC_HOOKS: [&str; 2] = ["post-merge", "post-checkout"];
+
+// The line that marks a hook as written by dbdm, so it can be replaced safely
+pub const HOOK_MARKER: &str = "# Installed by dbdm git-hooks install";
+
+// Helper to find where the hooks of a repository go
+//
+// This honors `core.hooksPath` and works in linked work trees.
+//
+// @param root: &Path - the top of the work tree
+// @return Result<PathBuf> - the hooks directory, it may not exist yet
+pub fn hooks_dir(root: &Path) -> std::io::Result<PathBuf> {
+    let dir = output(root, &["rev-parse", "--git-path", "hooks"])?;
+    Ok(root.join(dir.trim_end()))
+}
+
+// Generates a hook that syncs without asking anything
+//
+// @param binary: &Path - the dbdm binary to run
+// @param config_dir: &Path - the directory with dbdm.conf
+// @return String - the contents of the hook script
+pub fn sync_hook(binary: &Path, config_dir: &Path) -> String {
+    format!(
+        "#!/bin/sh\n{}\ncd {} && exec {} sync --non-interactive --on-conflict skip\n",
+        HOOK_MARKER,
+        shell_quote(&config_dir.to_string_lossy()),
+        shell_quote(&binary.to_string_lossy())
+    )
+}
+
+// The result of a three-way merge of a file
+#[derive(Debug)]
+pub struct Merge {
+    // The merged content, with conflict markers where both sides changed the same lines
+    pub content: Vec<u8>,
+    // How many conflicts are marked in the content
+    pub conflicts: usize,
+}
+
+// Merges the changes made to two versions of a file since a common base, with `git merge-file`
+//
+// Nothing is written, the merged content is returned. It works outside of a repository too.
+//
+// @param current: &Path - the version the other changes are merged into
+// @param base: &Path - the version both started from
+// @param other: &Path - the version whose changes are merged in
+// @param labels: [&str; 3] - the names of the three versions in conflict markers
+// @return Result<Merge> - the merge, an error if git couldn't run or failed
+pub fn merge_file(
+    current: &Path,
+    base: &Path,
+    other: &Path,
+    labels: [&str; 3],
+) -> std::io::Result<Merge> {
+    debug!(current = %current.display(), base = %base.display(), other = %other.display(), "running git merge-file");
+    let output = std::process::Command::new(GIT)
+        .args(["merge-file", "-p"])
+        .args(labels.iter().flat_map(|label| ["-L", label]))
+        .arg(current)
+        .arg(base)
+        .arg(other)
+        .stderr(std::process::Stdio::null())
+        .output()?;
+    // The exit code is the number of conflicts, up to 127, and negative on errors
+    match output.status.code() {
+        Some(conflicts @ 0..=127) => Ok(Merge {
+            content: output.stdout,
+            conflicts: conflicts as usize,
+        }),
+        _ => Err(std::io::Error::other(format!(
+            "{} merge-file exited with {}",
+            GIT, output.status
+        ))),
+    }
+}
+
+// Helper to quote a single shell word
+fn shell_quote(word: &str) -> String {
+    format!("'{}'", word.replace('\'', "'\\''"))
+}
+
+// Helper to run a git command in a directory and capture what it prints
+//
+// @param dir: &Path - the directory to run git in
+// @param args: &[&str] - the git subcommand and its arguments
+// @return Result<String> - the standard output, an error if git couldn't run or failed
+fn output(dir: &Path, args: &[&str]) -> std::io::Result<String> {
+    debug!(?args, dir = %dir.display(), "running git");
+    let output = std::process::Command::new(GIT)
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .stderr(std::process::Stdio::null())
+        .output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "{} {} exited with {}",
+            GIT, args[0], output.status
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+// Helper to run a single git command
+//
+// @param args: &[&OsStr] - the git subcommand and its arguments
+// @return Result<()> - an error if git couldn't run or failed
+fn git(args: &[&OsStr]) -> std::io::Result<()> {
+    debug!(?args, "running git");
+    // Stdout can carry NDJSON events, so whatever git prints goes to stderr
+    let status = std::process::Command::new(GIT)
+        .args(args)
+        .stdout(std::io::stderr())
+        .status()?;
+    if status.success() {
+        return Ok(());
+    }
+    Err(std::io::Error::other(format!(
+        "{} {} exited with {}",
+        GIT,
+        args[0].to_string_lossy(),
+        status
+    )))
+}
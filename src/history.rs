@@ -0,0 +1,139 @@
+use crate::dirs;
+use std::io::Write;
+use std::path::PathBuf;
+
+// How many runs are kept, older ones are dropped
+const MAX_RUNS: usize = 200;
+
+// A single run of a command that changes destinations
+#[derive(Debug, PartialEq)]
+pub struct Run {
+    // Counts up from 1, never reused
+    pub id: u64,
+    // When the run started, in seconds since the epoch
+    pub time: u64,
+    pub command: String,
+    // `done` or `aborted`
+    pub outcome: String,
+    pub items: Vec<Item>,
+}
+
+// A single executed item of a run
+#[derive(Debug, PartialEq)]
+pub struct Item {
+    pub from: PathBuf,
+    pub to: PathBuf,
+    pub action: String,
+    pub reason: Option<String>,
+}
+
+// Helper to get the file the history is kept in
+//
+// @return Result<PathBuf> - `history.jsonl` in the state directory
+pub fn history_path() -> std::io::Result<PathBuf> {
+    dirs::history_file()
+}
+
+// Adds a run to the history, dropping the oldest ones past the limit
+//
+// @param time: u64 - when the run started, in seconds since the epoch
+// @param command: &str - the command that ran
+// @param outcome: &str - how it ended
+// @param items: Vec<Item> - what was executed
+// @return Result<u64> - the id the run got
+pub fn record(time: u64, command: &str, outcome: &str, items: Vec<Item>) -> std::io::Result<u64> {
+    let mut runs = load()?;
+    let run = Run {
+        id: runs.last().map_or(1, |last| last.id + 1),
+        time,
+        command: command.to_string(),
+        outcome: outcome.to_string(),
+        items,
+    };
+    let id = run.id;
+    let path = history_path()?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    if runs.len() < MAX_RUNS {
+        let mut file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)?;
+        return writeln!(file, "{}", to_json(&run)).map(|_| id);
+    }
+
+    runs.drain(..=runs.len() - MAX_RUNS);
+    runs.push(run);
+    let lines: String = runs
+        .iter()
+        .map(|run| format!("{}\n", to_json(run)))
+        .collect();
+    std::fs::write(&path, lines)?;
+    Ok(id)
+}
+
+// Reads every run in the history
+//
+// Lines that can't be read, e.g. from an interrupted write, are left out.
+//
+// @return Result<Vec<Run>> - the runs, oldest first, empty if nothing was recorded yet
+pub fn load() -> std::io::Result<Vec<Run>> {
+    let contents = match std::fs::read_to_string(history_path()?) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err),
+    };
+    Ok(contents
+        .lines()
+        .filter_map(|line| serde_json::from_str(line).ok())
+        .filter_map(|json| from_json(&json))
+        .collect())
+}
+
+fn to_json(run: &Run) -> serde_json::Value {
+    let items: Vec<serde_json::Value> = run
+        .items
+        .iter()
+        .map(|item| {
+            serde_json::json!({
+                "from": item.from.display().to_string(),
+                "to": item.to.display().to_string(),
+                "action": item.action,
+                "reason": item.reason,
+            })
+        })
+        .collect();
+    serde_json::json!({
+        "id": run.id,
+        "time": run.time,
+        "command": run.command,
+        "outcome": run.outcome,
+        "items": items,
+    })
+}
+
+fn from_json(json: &serde_json::Value) -> Option<Run> {
+    let text = |value: &serde_json::Value, name: &str| Some(value.get(name)?.as_str()?.to_string());
+    let items = json
+        .get("items")?
+        .as_array()?
+        .iter()
+        .map(|item| {
+            Some(Item {
+                from: PathBuf::from(text(item, "from")?),
+                to: PathBuf::from(text(item, "to")?),
+                action: text(item, "action")?,
+                reason: text(item, "reason"),
+            })
+        })
+        .collect::<Option<Vec<Item>>>()?;
+    Some(Run {
+        id: json.get("id")?.as_u64()?,
+        time: json.get("time")?.as_u64()?,
+        command: text(json, "command")?,
+        outcome: text(json, "outcome")?,
+        items,
+    })
+}
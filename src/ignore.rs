@@ -0,0 +1,140 @@
+use std::path::{Component, Path};
+use tracing::debug;
+
+// The name of the file that lists what to leave out of a source directory
+pub const FILE_NAME: &str = ".dbdmignore";
+
+// One line of a `.dbdmignore`
+struct Rule {
+    pattern: glob::Pattern,
+    // `!pattern`, brings back what an earlier line left out
+    negated: bool,
+    // `pattern/`, only matches directories
+    dir_only: bool,
+    // Has a `/` before its end, so it matches from the top of the source
+    anchored: bool,
+}
+
+// The rules of a `.dbdmignore`, with gitignore syntax
+//
+// Blank lines and lines starting with `#` are skipped. A pattern without a `/`
+// matches a name at any depth, one with a `/` matches from the directory the
+// file is in, and `**` matches any number of directories. A trailing `/` only
+// matches directories, and a leading `!` brings back what an earlier line left
+// out. The last matching line wins, and anything inside an ignored directory is
+// ignored too.
+#[derive(Default)]
+pub struct Ignore {
+    rules: Vec<Rule>,
+}
+
+impl Ignore {
+    // Reads the `.dbdmignore` at the top of a source directory
+    //
+    // A missing or unreadable file ignores nothing.
+    //
+    // @param dir: &Path - the source directory
+    // @return Ignore - the rules of its `.dbdmignore`
+    pub fn load(dir: &Path) -> Ignore {
+        match std::fs::read_to_string(dir.join(FILE_NAME)) {
+            Ok(contents) => Ignore::parse(&contents),
+            Err(_) => Ignore::default(),
+        }
+    }
+
+    // Parses the contents of a `.dbdmignore`
+    //
+    // Lines that aren't valid patterns are left out.
+    //
+    // @param contents: &str - the file contents
+    // @return Ignore - the rules
+    pub fn parse(contents: &str) -> Ignore {
+        let rules = contents
+            .lines()
+            .filter_map(|line| {
+                let rule = parse_rule(line);
+                if rule.is_none() && !is_blank_or_comment(line) {
+                    debug!(line, "not a valid ignore pattern, skipping");
+                }
+                rule
+            })
+            .collect();
+        Ignore { rules }
+    }
+
+    // Checks whether a path inside the source directory is left out
+    //
+    // @param rel: &Path - the path, relative to the source directory
+    // @param is_dir: bool - if the path is a directory
+    // @return bool - true if it or a directory above it is ignored
+    pub fn is_ignored(&self, rel: &Path, is_dir: bool) -> bool {
+        if self.rules.is_empty() {
+            return false;
+        }
+        let names: Vec<String> = rel
+            .components()
+            .filter_map(|component| match component {
+                Component::Normal(name) => Some(name.to_string_lossy().to_string()),
+                _ => None,
+            })
+            .collect();
+
+        (1..=names.len()).any(|len| {
+            let path = names[..len].join("/");
+            let name = &names[len - 1];
+            self.matches(&path, name, len < names.len() || is_dir)
+        })
+    }
+
+    // Helper to apply the rules to a single path, without looking at its parents
+    fn matches(&self, path: &str, name: &str, is_dir: bool) -> bool {
+        let options = glob::MatchOptions {
+            case_sensitive: true,
+            require_literal_separator: true,
+            require_literal_leading_dot: false,
+        };
+        self.rules
+            .iter()
+            .rev()
+            .find(|rule| {
+                (is_dir || !rule.dir_only)
+                    && if rule.anchored {
+                        rule.pattern.matches_with(path, options)
+                    } else {
+                        rule.pattern.matches_with(name, options)
+                    }
+            })
+            .is_some_and(|rule| !rule.negated)
+    }
+}
+
+fn is_blank_or_comment(line: &str) -> bool {
+    let line = line.trim();
+    line.is_empty() || line.starts_with('#')
+}
+
+fn parse_rule(line: &str) -> Option<Rule> {
+    if is_blank_or_comment(line) {
+        return None;
+    }
+    let line = line.trim();
+    let (negated, line) = match line.strip_prefix('!') {
+        Some(rest) => (true, rest),
+        None => (false, line.strip_prefix('\\').unwrap_or(line)),
+    };
+    let (dir_only, line) = match line.strip_suffix('/') {
+        Some(rest) => (true, rest),
+        None => (false, line),
+    };
+    let anchored = line.contains('/');
+    let line = line.strip_prefix('/').unwrap_or(line);
+    if line.is_empty() {
+        return None;
+    }
+    Some(Rule {
+        pattern: glob::Pattern::new(line).ok()?,
+        negated,
+        dir_only,
+        anchored,
+    })
+}
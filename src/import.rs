@@ -0,0 +1,194 @@
+use crate::ignore::Ignore;
+use crate::{canonicalize_or_fallback, catalog, resolve_symlink_target};
+use std::ffi::{OsStr, OsString};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+// A symlink that points into the dotfiles repository
+#[derive(Debug, PartialEq)]
+pub struct Found {
+    // Where the symlink is
+    pub link: PathBuf,
+    // What it points to, canonicalized, inside the repository
+    pub target: PathBuf,
+}
+
+// Walks a directory for symlinks that point into the dotfiles repository
+//
+// Symlinked directories are not followed, and the repository itself is not
+// walked. Directories that can't be read are left out.
+//
+// @param dir: &Path - the directory to walk, like $HOME
+// @param repo: &Path - the dotfiles repository
+// @param max_depth: usize - how many levels below `dir` are looked at, 1 is only its entries
+// @return Result<Vec<Found>> - the symlinks sorted by path, an error if `dir` can't be read
+pub fn scan(dir: &Path, repo: &Path, max_depth: usize) -> std::io::Result<Vec<Found>> {
+    let repo = canonicalize_or_fallback(repo);
+    let mut found = Vec::new();
+    // The top directory has to be readable, anything below is best effort
+    std::fs::read_dir(dir)?;
+    walk(dir, &repo, max_depth, &mut found);
+    found.sort_by(|a, b| a.link.cmp(&b.link));
+    Ok(found)
+}
+
+// Helper to turn a found symlink into a config line
+//
+// Paths are written relative to `!here` and `!home` where possible, so the
+// line works on other machines too. Paths that aren't valid UTF-8 are kept
+// byte for byte, which the config parser reads back on Unix.
+//
+// @param found: &Found - the symlink
+// @param repo: &Path - the dotfiles repository, where dbdm.conf is
+// @param home: Option<&Path> - the home directory
+// @return Option<OsString> - the line, None if a path has a `!` that would be read as a keyword
+pub fn config_line(found: &Found, repo: &Path, home: Option<&Path>) -> Option<OsString> {
+    if [&found.target, &found.link]
+        .iter()
+        .any(|path| path.as_os_str().as_encoded_bytes().contains(&b'!'))
+    {
+        return None;
+    }
+
+    let from = keyword_path(&found.target, &canonicalize_or_fallback(repo), "!here")?;
+    let to = match home {
+        Some(home) => keyword_path(&found.link, &canonicalize_or_fallback(home), "!home")?,
+        None => quote(found.link.as_os_str())?,
+    };
+    let mut line = OsString::from("link = ");
+    line.push(from);
+    line.push(" ");
+    line.push(to);
+    Some(line)
+}
+
+// An entry of the dotfiles repository the catalog knows a destination for
+#[derive(Debug, PartialEq)]
+pub struct Suggestion {
+    // The file or directory in the repository
+    pub source: PathBuf,
+    // Where the catalog says it goes, with keywords
+    pub destination: &'static str,
+}
+
+// Looks through the top of the dotfiles repository for configs of known applications
+//
+// Entries the repository's `.dbdmignore` leaves out aren't suggested.
+//
+// @param repo: &Path - the dotfiles repository
+// @param os: &str - the OS to suggest destinations for, as in `std::env::consts::OS`
+// @return Result<Vec<Suggestion>> - the suggestions sorted by source, an error if `repo` can't be read
+pub fn suggest(repo: &Path, os: &str) -> std::io::Result<Vec<Suggestion>> {
+    let ignore = Ignore::load(repo);
+    let mut suggestions = Vec::new();
+    for entry in std::fs::read_dir(repo)? {
+        let entry = entry?;
+        let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+        if ignore.is_ignored(Path::new(&entry.file_name()), is_dir) {
+            continue;
+        }
+        if let Some(destination) = catalog::destination(&entry.file_name(), os) {
+            suggestions.push(Suggestion {
+                source: entry.path(),
+                destination,
+            });
+        }
+    }
+    suggestions.sort_by(|a, b| a.source.cmp(&b.source));
+    Ok(suggestions)
+}
+
+// Helper to turn a suggestion into a config line
+//
+// @param suggestion: &Suggestion - the suggestion
+// @param repo: &Path - the dotfiles repository, where dbdm.conf is
+// @return Option<OsString> - the line, None if the source has a `!` that would be read as a keyword
+pub fn suggestion_line(suggestion: &Suggestion, repo: &Path) -> Option<OsString> {
+    if suggestion
+        .source
+        .as_os_str()
+        .as_encoded_bytes()
+        .contains(&b'!')
+    {
+        return None;
+    }
+    let from = keyword_path(&suggestion.source, repo, "!here")?;
+    let mut line = OsString::from("link = ");
+    line.push(from);
+    line.push(" ");
+    line.push(suggestion.destination);
+    Some(line)
+}
+
+fn walk(dir: &Path, repo: &Path, depth_left: usize, found: &mut Vec<Found>) {
+    if depth_left == 0 || canonicalize_or_fallback(dir) == repo {
+        return;
+    }
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        debug!(dir = %dir.display(), "can't be read, skipping");
+        return;
+    };
+
+    for entry in entries.flatten() {
+        let path = entry.path();
+        let Ok(file_type) = entry.file_type() else {
+            continue;
+        };
+        if file_type.is_dir() {
+            walk(&path, repo, depth_left - 1, found);
+            continue;
+        }
+        if !file_type.is_symlink() {
+            continue;
+        }
+
+        let Ok(target) = std::fs::read_link(&path) else {
+            continue;
+        };
+        let target = canonicalize_or_fallback(&resolve_symlink_target(&path, &target));
+        if target.starts_with(repo) && target != repo {
+            found.push(Found { link: path, target });
+        }
+    }
+}
+
+// Helper to write a path relative to a keyword, if it is below the keyword's directory
+//
+// @param path: &Path - the path to write
+// @param base: &Path - the directory the keyword stands for
+// @param keyword: &str - the keyword
+// @return Option<OsString> - the path, quoted if needed
+fn keyword_path(path: &Path, base: &Path, keyword: &str) -> Option<OsString> {
+    match path.strip_prefix(base) {
+        Ok(rel) if rel.as_os_str().is_empty() => Some(OsString::from(keyword)),
+        Ok(rel) => {
+            let mut arg = OsString::from(format!("{}/", keyword));
+            arg.push(rel);
+            quote(&arg)
+        }
+        Err(_) => quote(path.as_os_str()),
+    }
+}
+
+// Helper to quote an argument the way the config parser reads it back
+//
+// @param arg: &OsStr - the argument
+// @return Option<OsString> - the argument, None if it can't be written on this platform
+pub(crate) fn quote(arg: &OsStr) -> Option<OsString> {
+    let needs_quotes = arg
+        .to_string_lossy()
+        .contains(|c: char| c.is_whitespace() || c == '"' || c == '\'' || c == '\\');
+    if !needs_quotes {
+        return Some(arg.to_os_string());
+    }
+
+    let mut quoted = vec![b'"'];
+    for byte in arg.as_encoded_bytes() {
+        if matches!(byte, b'\\' | b'"') {
+            quoted.push(b'\\');
+        }
+        quoted.push(*byte);
+    }
+    quoted.push(b'"');
+    crate::platform::os_string_from_bytes(quoted)
+}
@@ -1,12 +1,217 @@
+use fs::{FileStat, Fs, RealFs};
+use std::ffi::OsStr;
 use std::path::{Path, PathBuf};
+use tracing::debug;
+pub mod backups;
+pub mod catalog;
+pub mod commands;
 pub mod config_parser;
+pub mod copies;
+pub mod created_dirs;
+pub mod dirs;
+pub mod edit;
+pub mod elevate;
+pub mod expand;
+pub mod export;
+pub mod fs;
+pub mod git;
+pub mod history;
+pub mod ignore;
+pub mod import;
+pub mod lock;
+pub mod manifest;
+pub mod output;
+pub mod permissions;
+pub mod platform;
+pub mod rollback;
+pub mod secrets;
+pub mod service;
+pub mod state;
+pub mod validate;
+pub mod verify;
 
-// Helper to make an absolute path out of a Path
+// Helper to make an absolute path out of a Path, for comparing it with others
+//
+// The path is canonicalized as far as it exists, and the missing rest is
+// appended with `.` and `..` resolved lexically. So a destination that doesn't
+// exist yet still compares equal to the same path written another way.
 //
 // @param path: &Path - the path to canonicalize
-// @return PathBuf - the canonicalized path or the initial Path converted to PathBuf
+// @return PathBuf - the canonicalized path, the initial Path if it can't even be made absolute
 pub fn canonicalize_or_fallback(path: &Path) -> PathBuf {
-    std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf())
+    resolve_existing_prefix(path, |path: &Path| std::fs::canonicalize(path))
+}
+
+// Makes a path absolute and resolves `.`, `..` and repeated separators, without
+// looking at the filesystem
+//
+// A `..` after a symlink is resolved as if the symlink were a directory, so
+// prefer `canonicalize_or_fallback` for paths that can exist.
+//
+// @param path: &Path - the path to normalize
+// @return PathBuf - the normalized path, the initial Path if it can't be made absolute
+pub fn normalize_lexically(path: &Path) -> PathBuf {
+    match std::path::absolute(path) {
+        Ok(absolute) => append_lexically(PathBuf::new(), &absolute),
+        Err(_) => path.to_path_buf(),
+    }
+}
+
+// Helper to canonicalize a path one component at a time, as far as it exists
+//
+// Past a missing component the rest is pushed lexically, until enough `..`
+// lead back to a directory that exists.
+fn resolve_existing_prefix(
+    path: &Path,
+    canonicalize: impl Fn(&Path) -> std::io::Result<PathBuf>,
+) -> PathBuf {
+    if let Ok(full) = canonicalize(path) {
+        return full;
+    }
+    let Ok(absolute) = std::path::absolute(path) else {
+        return path.to_path_buf();
+    };
+    let mut resolved = PathBuf::new();
+    // How many components were pushed past the part that exists
+    let mut missing: usize = 0;
+    for component in absolute.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                // The parent of a canonical path is canonical too
+                resolved.pop();
+                missing = missing.saturating_sub(1);
+            }
+            std::path::Component::Normal(name) if missing == 0 => {
+                match canonicalize(&resolved.join(name)) {
+                    Ok(full) => resolved = full,
+                    Err(_) => {
+                        resolved.push(name);
+                        missing = 1;
+                    }
+                }
+            }
+            other => {
+                resolved.push(other);
+                if matches!(other, std::path::Component::Normal(_)) {
+                    missing += 1;
+                }
+            }
+        }
+    }
+    resolved
+}
+
+// Helper to push the components of a path one by one, `..` dropping the last one
+fn append_lexically(mut base: PathBuf, rest: &Path) -> PathBuf {
+    for component in rest.components() {
+        match component {
+            std::path::Component::CurDir => {}
+            std::path::Component::ParentDir => {
+                base.pop();
+            }
+            other => base.push(other),
+        }
+    }
+    base
+}
+
+// Canonicalizes paths, remembering the directories it resolved
+//
+// Links tend to share parent directories, like ~/.config or the dotfiles
+// repository, so each of them is only resolved once. It can be shared between
+// threads.
+#[derive(Debug, Default)]
+pub struct CanonicalCache {
+    // None for directories that could not be resolved
+    dirs: std::sync::Mutex<std::collections::HashMap<PathBuf, Option<PathBuf>>>,
+}
+
+impl CanonicalCache {
+    // Same as `std::fs::canonicalize`, with the parent directory looked up in the cache
+    //
+    // @param path: &Path - the path to canonicalize
+    // @return Result<PathBuf> - the canonical path, an error if it doesn't exist
+    pub fn canonicalize(&self, path: &Path) -> std::io::Result<PathBuf> {
+        // `.` and `..` have to be resolved in order, so those paths skip the cache
+        let plain = path.components().all(|component| {
+            matches!(
+                component,
+                std::path::Component::Normal(_) | std::path::Component::RootDir
+            )
+        });
+        let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+            return std::fs::canonicalize(path);
+        };
+        if !plain || parent.as_os_str().is_empty() {
+            return std::fs::canonicalize(path);
+        }
+        // The last component can be a symlink, which its parent doesn't tell about
+        if std::fs::symlink_metadata(path)?.file_type().is_symlink() {
+            return std::fs::canonicalize(path);
+        }
+        Ok(self.dir(parent)?.join(name))
+    }
+
+    // Same as `canonicalize_or_fallback`, through the cache
+    //
+    // @param path: &Path - the path to canonicalize
+    // @return PathBuf - the canonicalized path or the initial Path converted to PathBuf
+    pub fn canonicalize_or_fallback(&self, path: &Path) -> PathBuf {
+        resolve_existing_prefix(path, |path: &Path| self.canonicalize(path))
+    }
+
+    fn dir(&self, dir: &Path) -> std::io::Result<PathBuf> {
+        let cached = self
+            .dirs
+            .lock()
+            .ok()
+            .and_then(|dirs| dirs.get(dir).cloned());
+        let resolved = match cached {
+            Some(resolved) => resolved,
+            None => {
+                // Resolved without holding the lock, a race only costs a second lookup
+                let resolved = std::fs::canonicalize(dir).ok();
+                if let Ok(mut dirs) = self.dirs.lock() {
+                    dirs.insert(dir.to_path_buf(), resolved.clone());
+                }
+                resolved
+            }
+        };
+        resolved.ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("{} can't be resolved", dir.display()),
+            )
+        })
+    }
+}
+
+// Helper to run a function over every item on a few threads
+//
+// @param items: &[T] - the items
+// @param f: impl Fn(&T) -> R - the function, called once per item
+// @return Vec<R> - the results, in the order of the items
+pub fn parallel_map<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    let threads = std::thread::available_parallelism()
+        .map(|count| count.get())
+        .unwrap_or(1)
+        .min(items.len());
+    if threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let chunk_size = items.len().div_ceil(threads);
+    std::thread::scope(|scope| {
+        let workers: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(&f).collect::<Vec<R>>()))
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|worker| worker.join().expect("worker thread panicked"))
+            .collect()
+    })
 }
 
 // Helper to resolve a symlink target into an absolute path
@@ -35,35 +240,287 @@ pub fn resolve_symlink_target(link_path: &Path, target: &Path) -> PathBuf {
 // @param to: &Path - the destination path for the symlink
 // @return Result<()> - if replacement was successful
 pub fn replace_link(from: &Path, to: &Path) -> std::io::Result<()> {
-    let dest = resolve_link_destination(from, to)?;
-    remove_existing(&dest)?;
-    std::os::unix::fs::symlink(from, &dest)
+    replace_snapshot(from, &snapshot_link_destination(from, to)?)
+}
+
+// Same as `replace_link`, for a destination that was already looked at
+//
+// What is removed is decided from the snapshot. If the destination changed
+// since, the removal or the symlink fails instead of removing something else.
+//
+// @param from: &Path - the source path for the symlink
+// @param snapshot: &FsSnapshot - the resolved destination
+// @return Result<()> - if replacement was successful
+pub fn replace_snapshot(from: &Path, snapshot: &FsSnapshot) -> std::io::Result<()> {
+    replace_snapshot_with(&RealFs, from, snapshot)
+}
+
+// Same as `replace_snapshot`, on any filesystem
+//
+// @param fs: &dyn Fs - the filesystem to change
+// @param from: &Path - the source path for the symlink
+// @param snapshot: &FsSnapshot - the resolved destination
+// @return Result<()> - if replacement was successful
+pub fn replace_snapshot_with(
+    fs: &dyn Fs,
+    from: &Path,
+    snapshot: &FsSnapshot,
+) -> std::io::Result<()> {
+    let dest = &snapshot.path;
+    debug!(from = %from.display(), to = %dest.display(), "replacing with link");
+    if let Some(meta) = &snapshot.meta {
+        if fs.stat(dest)?.kind != meta.kind {
+            return Err(std::io::Error::other(format!(
+                "{} changed since it was planned",
+                dest.display()
+            )));
+        }
+        fs.remove(dest)?;
+    }
+    fs.symlink(from, dest)
 }
 
 // Helper to backup an existing target and create a symlink
 //
+// The backup is placed next to the source, so it never ends up inside a
+// linked directory tree.
+//
 // @param from: &Path - the source path for the symlink
 // @param to: &Path - the destination path to backup and replace
-// @return Result<()> - if backup and replacement were successful
-pub fn backup_and_replace(from: &Path, to: &Path) -> std::io::Result<()> {
+// @param naming: &BackupNaming - how the backup is named
+// @return Result<BackupOutcome> - what happened to the old destination
+pub fn backup_and_replace(
+    from: &Path,
+    to: &Path,
+    naming: &BackupNaming,
+) -> std::io::Result<BackupOutcome> {
+    backup_and_replace_in(from, to, &default_backup_dir(from), naming)
+}
+
+// Helper to get the directory backups of a link go to by default
+//
+// @param from: &Path - the source path of the link
+// @return PathBuf - the directory next to the source
+pub fn default_backup_dir(from: &Path) -> PathBuf {
+    from.parent()
+        .map(|p| p.to_path_buf())
+        .unwrap_or_else(|| from.to_path_buf())
+}
+
+// Helper to get the directory backups of a link go to
+//
+// @param location: BackupLocation - where backups go, from the config
+// @param from: &Path - the source path of the link
+// @param dest: &Path - the resolved destination
+// @return Result<PathBuf> - the directory, an error if the backup directory of dbdm isn't known
+pub fn backup_dir_of(
+    location: BackupLocation,
+    from: &Path,
+    dest: &Path,
+) -> std::io::Result<PathBuf> {
+    match location {
+        BackupLocation::Source => Ok(default_backup_dir(from)),
+        BackupLocation::State => dirs::backup_dir_for(dest),
+    }
+}
+
+// Helper to backup an existing target into a given directory and create a symlink
+//
+// A destination with the same content as the source isn't backed up, it is
+// just replaced.
+//
+// @param from: &Path - the source path for the symlink
+// @param to: &Path - the destination path to backup and replace
+// @param backup_dir: &Path - the directory to put the backup in, created if missing
+// @param naming: &BackupNaming - how the backup is named
+// @return Result<BackupOutcome> - what happened to the old destination
+pub fn backup_and_replace_in(
+    from: &Path,
+    to: &Path,
+    backup_dir: &Path,
+    naming: &BackupNaming,
+) -> std::io::Result<BackupOutcome> {
     let dest = resolve_link_destination(from, to)?;
-    let backup_dir = match std::fs::metadata(from) {
-        Ok(meta) if meta.is_dir() => from.to_path_buf(),
-        _ => from
-            .parent()
-            .map(|p| p.to_path_buf())
-            .unwrap_or_else(|| from.to_path_buf()),
+    backup_and_replace_resolved(from, &dest, backup_dir, naming)
+}
+
+// Same as `backup_and_replace_in`, for a destination that was already resolved
+//
+// @param from: &Path - the source path for the symlink
+// @param dest: &Path - the resolved destination to backup and replace
+// @param backup_dir: &Path - the directory to put the backup in, created if missing
+// @param naming: &BackupNaming - how the backup is named
+// @return Result<BackupOutcome> - what happened to the old destination
+pub fn backup_and_replace_resolved(
+    from: &Path,
+    dest: &Path,
+    backup_dir: &Path,
+    naming: &BackupNaming,
+) -> std::io::Result<BackupOutcome> {
+    backup_and_replace_resolved_with(&RealFs, from, dest, backup_dir, naming)
+}
+
+// Same as `backup_and_replace_resolved`, on any filesystem
+//
+// @param fs: &dyn Fs - the filesystem to change
+// @param from: &Path - the source path for the symlink
+// @param dest: &Path - the resolved destination to backup and replace
+// @param backup_dir: &Path - the directory to put the backup in, created if missing
+// @param naming: &BackupNaming - how the backup is named
+// @return Result<BackupOutcome> - what happened to the old destination
+pub fn backup_and_replace_resolved_with(
+    fs: &dyn Fs,
+    from: &Path,
+    dest: &Path,
+    backup_dir: &Path,
+    naming: &BackupNaming,
+) -> std::io::Result<BackupOutcome> {
+    // Not the content, the source itself would be removed
+    if is_source_itself_with(fs, from, dest) {
+        return Err(source_itself_error(dest));
+    }
+    if same_content_with(fs, from, dest)? {
+        debug!(to = %dest.display(), "identical to the source, not backing up");
+        remove_existing_with(fs, dest)?;
+        fs.symlink(from, dest)?;
+        return Ok(BackupOutcome::Identical);
+    }
+
+    fs.create_dir_all(backup_dir)?;
+    let base_name = dest.file_name().unwrap_or(OsStr::new("backup"));
+    let backup_path = unique_backup_path_with(fs, backup_dir, base_name, naming);
+    debug!(to = %dest.display(), backup = %backup_path.display(), "backing up");
+
+    fs.rename(dest, &backup_path)?;
+    fs.symlink(from, dest)?;
+    Ok(BackupOutcome::BackedUp(backup_path))
+}
+
+// What `backup_and_replace` did with the old destination
+#[derive(Debug, PartialEq, Eq)]
+pub enum BackupOutcome {
+    // Moved to the given backup path
+    BackedUp(PathBuf),
+    // Removed without a backup, since it had the same content as the source
+    Identical,
+}
+
+// Helper to check if two paths have the same content
+//
+// Files are compared byte by byte, directories by their entry names and the
+// content of each entry, and symlinks by where they point. Paths of different
+// kinds, or paths that don't exist, are never the same.
+//
+// @param a: &Path - the first path
+// @param b: &Path - the second path
+// @return Result<bool> - if both paths have the same content
+pub fn same_content(a: &Path, b: &Path) -> std::io::Result<bool> {
+    same_content_with(&RealFs, a, b)
+}
+
+// Same as `same_content`, on any filesystem
+//
+// @param fs: &dyn Fs - the filesystem to look at
+// @param a: &Path - the first path
+// @param b: &Path - the second path
+// @return Result<bool> - if both paths have the same content
+pub fn same_content_with(fs: &dyn Fs, a: &Path, b: &Path) -> std::io::Result<bool> {
+    let (Ok(a_meta), Ok(b_meta)) = (fs.stat(a), fs.stat(b)) else {
+        return Ok(false);
     };
 
-    std::fs::create_dir_all(&backup_dir)?;
-    let base_name = dest
-        .file_name()
-        .map(|name| name.to_string_lossy().to_string())
-        .unwrap_or_else(|| "backup".to_string());
-    let backup_path = unique_backup_path(&backup_dir, &base_name);
+    if a_meta.is_symlink() || b_meta.is_symlink() {
+        return Ok(a_meta.is_symlink()
+            && b_meta.is_symlink()
+            && fs.read_link(a)? == fs.read_link(b)?);
+    }
+
+    if a_meta.is_file() && b_meta.is_file() {
+        return Ok(a_meta.len == b_meta.len && fs.same_file(a, b)?);
+    }
+
+    if a_meta.is_dir() && b_meta.is_dir() {
+        let a_names = fs.read_dir(a)?;
+        if a_names != fs.read_dir(b)? {
+            return Ok(false);
+        }
+        for name in a_names {
+            if !same_content_with(fs, &a.join(&name), &b.join(&name))? {
+                return Ok(false);
+            }
+        }
+        return Ok(true);
+    }
+
+    Ok(false)
+}
+
+// What is at a destination, looked at once while planning
+//
+// The snapshot is reused while executing the plan, so every link costs as
+// few filesystem calls as possible.
+#[derive(Clone, Debug)]
+pub struct FsSnapshot {
+    // The resolved destination
+    pub path: PathBuf,
+    // What is there without following a symlink, None if nothing is
+    pub meta: Option<FileStat>,
+    // Where it points as read from the link, if it is a symlink
+    pub link_target: Option<PathBuf>,
+    // The link target canonicalized, None if it's broken or not a symlink
+    pub target_full: Option<PathBuf>,
+}
+
+impl FsSnapshot {
+    // Looks at a destination
+    //
+    // @param path: &Path - the resolved destination
+    // @return FsSnapshot - what is there
+    pub fn capture(path: &Path) -> FsSnapshot {
+        FsSnapshot::capture_with(&RealFs, path)
+    }
+
+    // Same as `capture`, on any filesystem
+    //
+    // @param fs: &dyn Fs - the filesystem to look at
+    // @param path: &Path - the resolved destination
+    // @return FsSnapshot - what is there
+    pub fn capture_with(fs: &dyn Fs, path: &Path) -> FsSnapshot {
+        FsSnapshot::with_meta(fs, path, fs.stat(path).ok())
+    }
+
+    fn with_meta(fs: &dyn Fs, path: &Path, meta: Option<FileStat>) -> FsSnapshot {
+        let link_target = meta
+            .filter(FileStat::is_symlink)
+            .and_then(|_| fs.read_link(path).ok());
+        let target_full = link_target
+            .as_ref()
+            .and_then(|target| fs.canonicalize(&resolve_symlink_target(path, target)).ok());
+        FsSnapshot {
+            path: path.to_path_buf(),
+            meta,
+            link_target,
+            target_full,
+        }
+    }
 
-    std::fs::rename(&dest, &backup_path)?;
-    std::os::unix::fs::symlink(from, &dest)
+    pub fn exists(&self) -> bool {
+        self.meta.is_some()
+    }
+}
+
+// Same as `resolve_link_destination`, capturing the resolved destination on the way
+//
+// @param from: &Path - the source path for the symlink
+// @param to: &Path - the destination from the config
+// @return Result<FsSnapshot> - the resolved destination and what is there
+pub fn snapshot_link_destination(from: &Path, to: &Path) -> std::io::Result<FsSnapshot> {
+    let (dest, meta) = resolve_with_meta(from, to)?;
+    Ok(FsSnapshot::with_meta(
+        &RealFs,
+        &dest,
+        meta.as_ref().map(FileStat::from),
+    ))
 }
 
 // Helper to resolve the actual destination path for a symlink
@@ -77,67 +534,515 @@ pub fn backup_and_replace(from: &Path, to: &Path) -> std::io::Result<()> {
 // - If <from> is a file and <to> exists as dir -> link at <to>/<from basename>
 // - If <from> is a file and <to> is file or missing -> link at <to>
 pub fn resolve_link_destination(from: &Path, to: &Path) -> std::io::Result<PathBuf> {
+    resolve_with_meta(from, to).map(|(dest, _)| dest)
+}
+
+// Helper to look at a destination without following it
+//
+// Only a destination that doesn't exist is None. Any other error, like one
+// that can't be looked at for lack of permissions or a symlink loop above it,
+// is returned, as nothing can be created there either.
+//
+// @param path: &Path - the destination
+// @return Result<Option<Metadata>> - its `symlink_metadata`, None if nothing is there
+fn metadata_if_exists(path: &Path) -> std::io::Result<Option<std::fs::Metadata>> {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) => Ok(Some(meta)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+// Helper to resolve a destination, keeping the metadata looked at along the way
+//
+// @param from: &Path - the source path for the symlink
+// @param to: &Path - the destination from the config
+// @return Result<(PathBuf, Option<Metadata>)> - the destination and its `symlink_metadata`, None if missing
+fn resolve_with_meta(
+    from: &Path,
+    to: &Path,
+) -> std::io::Result<(PathBuf, Option<std::fs::Metadata>)> {
     let from_meta = std::fs::metadata(from)?;
-    let to_meta = std::fs::symlink_metadata(to).ok();
+    let to_meta = metadata_if_exists(to)?;
 
     if from_meta.is_dir() {
-        if let Some(meta) = to_meta {
-            if meta.is_file() {
-                return Err(std::io::Error::new(
-                    std::io::ErrorKind::InvalidInput,
-                    format!("destination is file for directory source: {}", to.display()),
-                ));
-            }
+        if let Some(meta) = &to_meta
+            && meta.is_file()
+        {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("destination is file for directory source: {}", to.display()),
+            ));
+        }
+
+        check_containment(from, to)?;
+        return Ok((to.to_path_buf(), to_meta));
+    }
+
+    if let Some(meta) = &to_meta
+        && meta.is_dir()
+    {
+        let name = from.file_name().ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("source has no basename: {}", from.display()),
+            )
+        })?;
+        let dest = to.join(name);
+        check_containment(from, &dest)?;
+        let dest_meta = metadata_if_exists(&dest)?;
+        return Ok((dest, dest_meta));
+    }
+
+    check_containment(from, to)?;
+    Ok((to.to_path_buf(), to_meta))
+}
+
+// Helper to reject links where one side lies inside the other
+//
+// Replacing a destination that contains the source removes the source, and
+// backing up a destination into a source it lives in recurses into itself.
+// The destination itself isn't followed, so an existing link to the source
+// doesn't count as being inside it. A destination that is the source, e.g.
+// through a linked parent directory, is rejected too, as replacing it would
+// remove the source.
+//
+// @param from: &Path - the source path for the symlink
+// @param dest: &Path - the resolved destination path
+// @return Result<()> - an InvalidInput error if one path contains the other
+pub fn check_containment(from: &Path, dest: &Path) -> std::io::Result<()> {
+    let from_full = canonicalize_or_fallback(from);
+    let dest_full = match (dest.parent(), dest.file_name()) {
+        (Some(parent), Some(name)) => canonicalize_or_fallback(parent).join(name),
+        _ => canonicalize_or_fallback(dest),
+    };
+    if from_full == dest_full {
+        // Only a link is safe to replace, as the source is somewhere else
+        if std::fs::symlink_metadata(dest).is_ok_and(|meta| meta.file_type().is_symlink()) {
+            return Ok(());
         }
+        return Err(source_itself_error(dest));
+    }
+
+    let message = if dest_full.starts_with(&from_full) {
+        "destination is inside the source"
+    } else if from_full.starts_with(&dest_full) {
+        "source is inside the destination"
+    } else {
+        return Ok(());
+    };
+    Err(std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("{}: {}", message, dest.display()),
+    ))
+}
 
-        return Ok(to.to_path_buf());
+// Helper to tell if a destination is the source itself, not a link to it
+//
+// Comparing the content of both is trivially equal then, and replacing the
+// destination removes the source.
+//
+// @param from: &Path - the source path for the symlink
+// @param dest: &Path - the resolved destination path
+// @return bool - if both are the same file or directory
+pub fn is_source_itself(from: &Path, dest: &Path) -> bool {
+    is_source_itself_with(&RealFs, from, dest)
+}
+
+// Same as `is_source_itself`, on any filesystem
+//
+// @param fs: &dyn Fs - the filesystem to look at
+// @param from: &Path - the source path for the symlink
+// @param dest: &Path - the resolved destination path
+// @return bool - if both are the same file or directory
+pub fn is_source_itself_with(fs: &dyn Fs, from: &Path, dest: &Path) -> bool {
+    if fs.stat(dest).is_ok_and(|stat| stat.is_symlink()) {
+        return false;
+    }
+    match (fs.canonicalize(from), fs.canonicalize(dest)) {
+        (Ok(from), Ok(dest)) => from == dest,
+        _ => false,
+    }
+}
+
+// Helper to build the error for a destination that is the source itself
+pub(crate) fn source_itself_error(dest: &Path) -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        format!("destination is the source: {}", dest.display()),
+    )
+}
+
+// Helper to check if replacing a destination could wipe something important
+//
+// A destination is dangerous if it is `/` or `$HOME` itself, since replacing
+// it removes everything below it. Parents of the source are already rejected
+// by `check_containment`.
+//
+// @param dest: &Path - the resolved destination path
+// @return Option<String> - why the destination is dangerous, None if it's fine
+pub fn dangerous_destination(dest: &Path) -> Option<String> {
+    let dest_full = canonicalize_or_fallback(dest);
+    if dest_full.parent().is_none() {
+        return Some("destination is the filesystem root".to_string());
     }
 
-    if let Some(meta) = to_meta {
-        if meta.is_dir() {
-            let name = from.file_name().ok_or_else(|| {
-                std::io::Error::new(
-                    std::io::ErrorKind::InvalidInput,
-                    format!("source has no basename: {}", from.display()),
-                )
-            })?;
-            return Ok(to.join(name));
+    if let Some(home) = platform::home_dir()
+        && dest_full == canonicalize_or_fallback(Path::new(&home))
+    {
+        return Some("destination is the home directory".to_string());
+    }
+
+    None
+}
+
+// What to do with destinations that conflict with their source
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConflictPolicy {
+    // Prompt for every conflict
+    Ask,
+    Replace,
+    Backup,
+    Skip,
+}
+
+impl ConflictPolicy {
+    // Helper to parse the value of the `--on-conflict` flag
+    //
+    // @param value: &str - the flag value
+    // @return Option<ConflictPolicy> - the policy, None if the value is unknown
+    pub fn parse(value: &str) -> Option<ConflictPolicy> {
+        match value {
+            "ask" => Some(ConflictPolicy::Ask),
+            "replace" => Some(ConflictPolicy::Replace),
+            "backup" => Some(ConflictPolicy::Backup),
+            "skip" => Some(ConflictPolicy::Skip),
+            _ => None,
+        }
+    }
+
+    // Helper to get the policy as it is written on the command line and in the config
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConflictPolicy::Ask => "ask",
+            ConflictPolicy::Replace => "replace",
+            ConflictPolicy::Backup => "backup",
+            ConflictPolicy::Skip => "skip",
+        }
+    }
+}
+
+// Where backups of replaced destinations go, from `location =` in the `[backups]` section
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum BackupLocation {
+    // Next to the source of the link
+    #[default]
+    Source,
+    // In the backup directory of dbdm, see `dirs::backup_dir`
+    State,
+}
+
+impl BackupLocation {
+    // Helper to parse the value of the `location` key
+    //
+    // @param value: &str - the key value
+    // @return Option<BackupLocation> - the location, None if the value is unknown
+    pub fn parse(value: &str) -> Option<BackupLocation> {
+        match value {
+            "source" => Some(BackupLocation::Source),
+            "state" => Some(BackupLocation::State),
+            _ => None,
         }
     }
 
-    Ok(to.to_path_buf())
+    // Helper to get the location as it is written in the config
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BackupLocation::Source => "source",
+            BackupLocation::State => "state",
+        }
+    }
 }
 
-// Helper to create a unique backup path with a numeric suffix
+// How the numbers that keep backups apart are picked
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BackupNumbering {
+    // `name.bak.dbdm`, then `name.bak.dbdm.1`, `name.bak.dbdm.2`, ...
+    Counter,
+    // `name.20240312T1012.bak.dbdm`, with a counter if that is taken too
+    Timestamp,
+}
+
+impl BackupNumbering {
+    // Helper to parse the value of the `--backup-naming` flag
+    //
+    // @param value: &str - the flag value
+    // @return Option<BackupNumbering> - the scheme, None if the value is unknown
+    pub fn parse(value: &str) -> Option<BackupNumbering> {
+        match value {
+            "counter" => Some(BackupNumbering::Counter),
+            "timestamp" => Some(BackupNumbering::Timestamp),
+            _ => None,
+        }
+    }
+}
+
+// How backups of replaced destinations are named
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackupNaming {
+    pub suffix: String,
+    pub numbering: BackupNumbering,
+}
+
+impl Default for BackupNaming {
+    fn default() -> Self {
+        BackupNaming {
+            suffix: String::from(".bak.dbdm"),
+            numbering: BackupNumbering::Counter,
+        }
+    }
+}
+
+// Helper to create a unique backup path
+//
+// The base name is `<name><suffix>`, or `<name>.<timestamp><suffix>` with
+// timestamp numbering. A numeric suffix is added if that path is taken.
 //
 // @param dir: &Path - the directory where backup should be created
-// @param name: &str - the base name of the file being backed up
+// @param name: impl AsRef<OsStr> - the base name of the file being backed up
+// @param naming: &BackupNaming - how the backup is named
 // @return PathBuf - the unique backup path
-pub fn unique_backup_path(dir: &Path, name: &str) -> PathBuf {
-    let base = format!("{}.bak.dbdm", name);
+pub fn unique_backup_path(dir: &Path, name: impl AsRef<OsStr>, naming: &BackupNaming) -> PathBuf {
+    unique_backup_path_with(&RealFs, dir, name, naming)
+}
+
+// Same as `unique_backup_path`, on any filesystem
+//
+// @param fs: &dyn Fs - the filesystem to look at
+// @param dir: &Path - the directory where backup should be created
+// @param name: impl AsRef<OsStr> - the base name of the file being backed up
+// @param naming: &BackupNaming - how the backup is named
+// @return PathBuf - the unique backup path
+pub fn unique_backup_path_with(
+    fs: &dyn Fs,
+    dir: &Path,
+    name: impl AsRef<OsStr>,
+    naming: &BackupNaming,
+) -> PathBuf {
+    let mut base = name.as_ref().to_os_string();
+    if naming.numbering == BackupNumbering::Timestamp {
+        base.push(format!(".{}", timestamp()));
+    }
+    base.push(&naming.suffix);
     let mut path = dir.join(&base);
     let mut counter = 1;
-    while path.exists() {
-        let candidate = format!("{}.{}", base, counter);
+    while fs.stat(&path).is_ok() {
+        let mut candidate = base.clone();
+        candidate.push(format!(".{}", counter));
         path = dir.join(candidate);
         counter += 1;
     }
     path
 }
 
+// Helper to format the current UTC time for backup names
+//
+// @return String - the time as `YYYYMMDDTHHMM`
+fn timestamp() -> String {
+    let (year, month, day, minutes) = civil_time(unix_time());
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+// Helper to format a UTC time for people
+//
+// @param secs: u64 - seconds since the epoch
+// @return String - the time as `YYYY-MM-DD HH:MM`
+pub fn format_time(secs: u64) -> String {
+    let (year, month, day, minutes) = civil_time(secs);
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}",
+        year,
+        month,
+        day,
+        minutes / 60,
+        minutes % 60
+    )
+}
+
+// Helper to get the current time
+//
+// @return u64 - seconds since the epoch
+pub fn unix_time() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
+
+// Helper to split a time into its UTC date and minute of the day
+//
+// @param secs: u64 - seconds since the epoch
+// @return (i64, i64, i64, u64) - the year, month, day and minutes since midnight
+fn civil_time(secs: u64) -> (i64, i64, i64, u64) {
+    let days = (secs / 86_400) as i64;
+    let minutes = (secs % 86_400) / 60;
+
+    // Days since the epoch to a civil date, see Howard Hinnant's `civil_from_days`
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    (year, month, day, minutes)
+}
+
+// Helper to hash content with 64-bit FNV-1a
+//
+// This only tells if something changed, it is not meant to protect anything.
+//
+// @param bytes: &[u8] - the content
+// @return String - the hash in hex
+pub(crate) fn hash(bytes: &[u8]) -> String {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in bytes {
+        hash ^= u64::from(*byte);
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    format!("{:016x}", hash)
+}
+
+// Helper to move a path, copying it when it can't be renamed
+//
+// Renames don't work across filesystems, e.g. when backups go to another
+// mount. The copy keeps the metadata, so the backup is a faithful restore point.
+//
+// @param from: &Path - the path to move
+// @param to: &Path - where to move it, must not exist
+// @return Result<()> - if the move was successful
+pub fn move_path(from: &Path, to: &Path) -> std::io::Result<()> {
+    match std::fs::rename(from, to) {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == std::io::ErrorKind::CrossesDevices => {
+            debug!(from = %from.display(), to = %to.display(), "copying across filesystems");
+            copy_preserving(from, to)?;
+            remove_existing(from)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+// Helper to copy a file, symlink or directory tree with its metadata
+//
+// Mode bits, access and modification times, and extended attributes are kept.
+// Symlinks are copied as links, not followed.
+//
+// @param from: &Path - the path to copy
+// @param to: &Path - where to copy it, must not exist
+// @return Result<()> - if the copy was successful
+pub fn copy_preserving(from: &Path, to: &Path) -> std::io::Result<()> {
+    let meta = std::fs::symlink_metadata(from)?;
+
+    if meta.file_type().is_symlink() {
+        return platform::symlink(&std::fs::read_link(from)?, to);
+    }
+
+    if meta.is_dir() {
+        std::fs::create_dir(to)?;
+        for entry in std::fs::read_dir(from)? {
+            let entry = entry?;
+            copy_preserving(&entry.path(), &to.join(entry.file_name()))?;
+        }
+    } else {
+        std::fs::copy(from, to)?;
+    }
+
+    copy_metadata(from, to, &meta)
+}
+
+// Helper to carry the metadata of a copied path over
+//
+// Done after the content is in place, as filling a directory changes its
+// times and a read-only mode would block it.
+fn copy_metadata(from: &Path, to: &Path, meta: &std::fs::Metadata) -> std::io::Result<()> {
+    copy_xattrs(from, to)?;
+
+    let times = std::fs::FileTimes::new()
+        .set_accessed(meta.accessed()?)
+        .set_modified(meta.modified()?);
+    std::fs::File::open(to)?.set_times(times)?;
+
+    std::fs::set_permissions(to, meta.permissions())
+}
+
+// Helper to carry the extended attributes of a copied path over
+#[cfg(unix)]
+fn copy_xattrs(from: &Path, to: &Path) -> std::io::Result<()> {
+    for name in xattr::list(from)? {
+        if let Some(value) = xattr::get(from, &name)?
+            && let Err(err) = xattr::set(to, &name, &value)
+        {
+            // Some namespaces need privileges, losing those shouldn't fail the backup
+            debug!(path = %to.display(), name = ?name, %err, "failed to copy xattr");
+        }
+    }
+    Ok(())
+}
+
+// Extended attributes are only copied on Unix
+#[cfg(not(unix))]
+fn copy_xattrs(_from: &Path, _to: &Path) -> std::io::Result<()> {
+    Ok(())
+}
+
+// Helper to check if a file name is one of the backups made by dbdm
+//
+// @param name: &OsStr - the file name to check
+// @param naming: &BackupNaming - how backups are named
+// @return bool - if the name ends with the backup suffix, optionally followed by `.N`
+pub fn is_backup_name(name: &OsStr, naming: &BackupNaming) -> bool {
+    let name = name.as_encoded_bytes();
+    let suffix = naming.suffix.as_bytes();
+    let Some(pos) = (0..=name.len().saturating_sub(suffix.len()))
+        .rev()
+        .find(|pos| name[*pos..].starts_with(suffix))
+    else {
+        return false;
+    };
+    let rest = &name[pos + suffix.len()..];
+    rest.is_empty()
+        || rest
+            .strip_prefix(b".")
+            .is_some_and(|n| !n.is_empty() && n.iter().all(u8::is_ascii_digit))
+}
+
 // Helper to remove existing path whether file, directory, or symlink
 //
 // @param path: &Path - the path to remove
 // @return Result<()> - if removal was successful
 pub fn remove_existing(path: &Path) -> std::io::Result<()> {
-    let meta = match std::fs::symlink_metadata(path) {
-        Ok(meta) => meta,
-        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(()),
-        Err(err) => return Err(err),
-    };
-    if meta.file_type().is_symlink() || meta.is_file() {
-        std::fs::remove_file(path)
-    } else {
-        std::fs::remove_dir_all(path)
+    remove_existing_with(&RealFs, path)
+}
+
+// Same as `remove_existing`, on any filesystem
+//
+// @param fs: &dyn Fs - the filesystem to change
+// @param path: &Path - the path to remove
+// @return Result<()> - if removal was successful
+pub fn remove_existing_with(fs: &dyn Fs, path: &Path) -> std::io::Result<()> {
+    match fs.remove(path) {
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        result => result,
     }
 }
@@ -0,0 +1,69 @@
+use crate::{canonicalize_or_fallback, hash};
+use std::fs::{File, TryLockError};
+use std::io::{Read, Seek, Write};
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+// Held while a command changes destinations, released when dropped
+#[derive(Debug)]
+pub struct Lock {
+    // The lock lives as long as the file is open
+    _file: File,
+}
+
+// Helper to get the file used as the lock of a config
+//
+// Each config gets its own lock, named after a hash of its directory.
+//
+// @param config_dir: &Path - the directory with dbdm.conf
+// @return Result<PathBuf> - a file in the runtime directory of dbdm
+pub fn lock_path(config_dir: &Path) -> std::io::Result<PathBuf> {
+    let dir = crate::dirs::runtime_dir()?;
+    let key = canonicalize_or_fallback(config_dir);
+    let name = format!("{}.lock", hash(key.as_os_str().as_encoded_bytes()));
+    Ok(dir.join(name))
+}
+
+// Takes the lock of a config without waiting for it
+//
+// The lock is advisory, it only keeps other dbdm processes out. It is
+// released by the OS when the process exits, so a crash never leaves it held.
+//
+// @param config_dir: &Path - the directory with dbdm.conf
+// @return Result<Lock> - the lock, an error naming the holder if another process has it
+pub fn acquire(config_dir: &Path) -> std::io::Result<Lock> {
+    let path = lock_path(config_dir)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let mut file = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(&path)?;
+
+    match file.try_lock() {
+        Ok(()) => {}
+        Err(TryLockError::WouldBlock) => {
+            let mut holder = String::new();
+            let _ = file.read_to_string(&mut holder);
+            let holder = match holder.trim() {
+                "" => String::new(),
+                pid => format!(" (pid {})", pid),
+            };
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::WouldBlock,
+                format!("another dbdm process is running{}", holder),
+            ));
+        }
+        Err(TryLockError::Error(err)) => return Err(err),
+    }
+
+    debug!(path = %path.display(), "locked");
+    // The pid is only there to tell the user who holds the lock
+    file.set_len(0)?;
+    file.rewind()?;
+    write!(file, "{}", std::process::id())?;
+    Ok(Lock { _file: file })
+}
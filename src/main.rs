@@ -1,518 +1,1965 @@
-use crate::config_parser::Config;
+use dbdm::commands::check::check;
+use dbdm::commands::clean::{self, clean};
+use dbdm::commands::present::print_report;
+use dbdm::commands::sync::{ExecutedItem, PlanItem, SyncOptions, record_history, sync};
+use dbdm::commands::{Detail, RunMode};
+use dbdm::config_parser::{self, Config, Link, LinkKind, Retention};
+use dbdm::output::{self, Cell, Color, ColorChoice, paint, table};
+use dbdm::service::{self, ServiceCommand};
+use dbdm::validate::validate;
+use dbdm::verify::verify_link;
 use dbdm::{
-    backup_and_replace, canonicalize_or_fallback, replace_link, resolve_link_destination,
-    resolve_symlink_target,
+    BackupNaming, BackupNumbering, ConflictPolicy, app_println, canonicalize_or_fallback,
+    format_time, resolve_link_destination,
 };
-use std::io::Read;
+use dbdm::{backups, created_dirs, export, git, history, import, permissions, platform, secrets};
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use tracing::{debug, error, info, warn};
+
+#[cfg(feature = "desktop-notify")]
+mod desktop;
+#[cfg(feature = "tui")]
+mod tui;
+#[cfg(feature = "notify")]
+mod watch;
+
+// Exit code for a command that failed or found problems
+const EXIT_FAILURE: u8 = 1;
+// Exit code for a command line that can't be run as given, `EX_USAGE` of sysexits.h
+const EXIT_USAGE: u8 = 64;
+// Exit code for a sync that was aborted before changing anything
+const EXIT_ABORTED: u8 = 2;
+// Exit code for a sync or `config validate` that couldn't start because of the config
+const EXIT_CONFIG: u8 = 3;
+
+// Why dbdm stopped early
+//
+// Handlers return it instead of exiting, so `main` is the only place that
+// reports failures and picks the exit code.
+struct Failure {
+    code: u8,
+    // What went wrong, None if the handler already reported it
+    message: Option<String>,
+}
 
-mod config_parser;
+impl Failure {
+    // A command line that can't be run as given
+    fn usage(message: impl Into<String>) -> Failure {
+        Failure {
+            code: EXIT_USAGE,
+            message: Some(message.into()),
+        }
+    }
 
-struct RunMode {
-    test_mode: bool,
-}
+    // A command that couldn't be done
+    fn error(message: impl Into<String>) -> Failure {
+        Failure {
+            code: EXIT_FAILURE,
+            message: Some(message.into()),
+        }
+    }
 
-macro_rules! app_println {
-    ($mode:expr, $($arg:tt)*) => {
-        if !$mode.test_mode {
-            println!($($arg)*);
+    // A command that printed its problems itself, like `verify`
+    fn reported() -> Failure {
+        Failure {
+            code: EXIT_FAILURE,
+            message: None,
         }
-    };
+    }
 }
 
-macro_rules! app_print {
-    ($mode:expr, $($arg:tt)*) => {
-        if !$mode.test_mode {
-            print!($($arg)*);
+fn main() -> ExitCode {
+    let args = with_env_defaults(std::env::args().skip(1).collect());
+    match run(&args) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(failure) => {
+            if let Some(message) = failure.message {
+                error!("{}", message);
+            }
+            ExitCode::from(failure.code)
         }
-    };
+    }
 }
 
-fn main() {
-    let args: Vec<String> = std::env::args().skip(1).collect();
+// Runs the command from the command line
+//
+// @param args: &[String] - the command line arguments, without the binary
+// @return Result<(), Failure> - why the command failed, if it did
+fn run(args: &[String]) -> Result<(), Failure> {
+    // Logging comes first, so even a wrong --color can be reported
+    let color = match flag_value(args, "--color") {
+        Some(value) => ColorChoice::parse(&value).ok_or_else(|| {
+            Failure::usage(format!(
+                "Invalid --color value {}, expected auto, always or never",
+                value
+            ))
+        }),
+        None => Ok(ColorChoice::Auto),
+    };
+    let resolved_color = *color.as_ref().unwrap_or(&ColorChoice::Auto);
+    output::init(resolved_color);
+    init_logging(args, resolved_color);
+    color?;
+
+    let format = flag_value(args, "--format").unwrap_or_else(|| String::from("text"));
+    let preview_lines = match flag_value(args, "--preview-lines") {
+        Some(value) => value.parse().map_err(|_| {
+            Failure::usage(format!(
+                "Invalid --preview-lines value {}, expected a number",
+                value
+            ))
+        })?,
+        None => DEFAULT_PREVIEW_LINES,
+    };
     let mode = RunMode {
-        test_mode: args.iter().any(|arg| arg == "--test-mode"),
+        interactive: !args.iter().any(|arg| arg == "--non-interactive"),
+        ndjson: format == "ndjson",
+        preview_lines,
+        answers: std::cell::Cell::new(0),
+        detail: if args.iter().any(|arg| arg == "--quiet" || arg == "-q") {
+            Detail::Quiet
+        } else if args.iter().any(|arg| arg == "--summary") {
+            Detail::Summary
+        } else {
+            Detail::Full
+        },
     };
     let force = args.iter().any(|arg| arg == "--force");
-    let command = args
-        .iter()
-        .find(|arg| !arg.starts_with("--"))
+    let positionals = positional_args(args);
+    let command = positionals
+        .first()
         .cloned()
         .unwrap_or_else(|| String::from("help"));
 
-    if command != "check" && command != "sync" {
-        println!("\x1b[31mInvalid argument {}\x1b[0m\n", command);
+    if command == "help" {
         help(&mode);
-        return;
+        return Ok(());
+    }
+    if ![
+        "check",
+        "sync",
+        "verify",
+        "clean",
+        "watch",
+        "config",
+        "service",
+        "bootstrap",
+        "git-hooks",
+        "import",
+        "export",
+        "history",
+        "edit",
+        "which",
+    ]
+    .contains(&command.as_str())
+    {
+        help(&mode);
+        return Err(Failure::usage(format!("Invalid argument {}", command)));
     }
 
-    // Grab current dir
-    let mut pwd = match std::env::current_dir() {
-        Ok(path) => path,
-        Err(err) => {
-            app_println!(&mode, "Could not parse the {}", err.to_string());
-            return;
+    // Bootstrap clones the dotfiles and then runs a first sync from the config inside
+    let command = if command == "bootstrap" {
+        let config_dir = bootstrap(&positionals[1..])?;
+        std::env::set_current_dir(&config_dir).map_err(|err| {
+            Failure::error(format!("Could not enter {}: {}", config_dir.display(), err))
+        })?;
+        String::from("sync")
+    } else {
+        command
+    };
+
+    // Resolved before moving into the directory of the config, so relative paths work
+    let target = match (command.as_str(), positionals.get(1)) {
+        ("edit" | "which", Some(path)) => Some(
+            std::path::absolute(path)
+                .map_err(|err| Failure::error(format!("Could not resolve {}: {}", path, err)))?,
+        ),
+        _ => None,
+    };
+
+    // A config elsewhere is used from its own directory, so `!here` means the same.
+    // Bootstrap uses the config of the repository it cloned.
+    let config_name = match flag_value(args, "--config") {
+        Some(config) if !config.is_empty() && positionals[0] != "bootstrap" => {
+            enter_config_dir(Path::new(&config))?
         }
+        _ => PathBuf::from("dbdm.conf"),
+    };
+
+    // Grab current dir
+    let mut pwd = current_dir()?;
+
+    // Import is for making a config, so it runs without one
+    if command == "import" {
+        let subcommand = positionals.get(1).map(String::as_str).unwrap_or("");
+        return match (subcommand, positionals.get(2)) {
+            ("scan", Some(dir)) => import_scan(Path::new(dir), args),
+            ("suggest", None) => import_suggest(),
+            _ => {
+                help(&mode);
+                Err(Failure::usage(
+                    "Invalid import command, expected import scan <dir> or import suggest",
+                ))
+            }
+        };
+    }
+
+    // The history is kept per user, not per config
+    if command == "history" {
+        return match (positionals.get(1).map(String::as_str), positionals.get(2)) {
+            (None, _) => history_list(&mode),
+            (Some("show"), Some(id)) => history_show(&mode, id),
+            _ => {
+                help(&mode);
+                Err(Failure::usage(
+                    "Invalid history command, expected history or history show <id>",
+                ))
+            }
+        };
+    }
+
+    // Sync tells config errors apart from the items that failed
+    let config_error = |message: String| Failure {
+        code: if command == "sync" {
+            EXIT_CONFIG
+        } else {
+            EXIT_FAILURE
+        },
+        message: Some(message),
     };
 
     // Check for presence of dbdm.conf
-    pwd.push("dbdm.conf");
+    pwd.push(&config_name);
     if !pwd.exists() {
-        let mut path_str = pwd.clone();
-        path_str.pop();
-        app_println!(
-            &mode,
-            "dbdm.conf doesn exist in {}",
-            path_str.to_str().expect("Can't parse dir path")
-        );
-        return;
+        let mut dir = pwd.clone();
+        dir.pop();
+        return Err(config_error(format!(
+            "{} doesn't exist in {}",
+            config_name.display(),
+            dir.display()
+        )));
     }
 
-    // Parse the config
-    let config = match config_parser::read_config(&pwd) {
-        Ok(res) => res,
-        Err(err) => {
-            app_println!(&mode, "Error in config:\n\n{}", err);
-            return;
-        }
+    // Unknown kinds, options, keywords and sections are errors instead of skipped lines
+    let strict = args.iter().any(|arg| arg == "--strict");
+
+    // The config command works on the raw file, so it has to run before parsing
+    if command == "config" {
+        let subcommand = positionals.get(1).map(String::as_str).unwrap_or("");
+        return match subcommand {
+            "validate" => config_validate(&pwd, &format, strict),
+            "show" => config_show(&pwd, &format, strict),
+            "explain" => config_explain(&pwd, positionals.get(2).map(String::as_str)),
+            "migrate" => config_migrate(&pwd),
+            "fmt" => config_fmt(&pwd, args),
+            _ => {
+                help(&mode);
+                Err(Failure::usage(format!(
+                    "Invalid config command {}",
+                    subcommand
+                )))
+            }
+        };
+    }
+
+    if command == "git-hooks" {
+        let subcommand = positionals.get(1).map(String::as_str).unwrap_or("");
+        return match subcommand {
+            "install" => git_hooks_install(&pwd, force),
+            _ => {
+                help(&mode);
+                Err(Failure::usage(format!(
+                    "Invalid git-hooks command {}",
+                    subcommand
+                )))
+            }
+        };
+    }
+
+    if command == "service" {
+        let subcommand = positionals.get(1).map(String::as_str).unwrap_or("");
+        return match subcommand {
+            "install" => service_install(&pwd, args, force),
+            _ => {
+                help(&mode);
+                Err(Failure::usage(format!(
+                    "Invalid service command {}",
+                    subcommand
+                )))
+            }
+        };
+    }
+
+    let sync_options = SyncOptions {
+        force,
+        allow_dangerous: args.iter().any(|arg| arg == "--allow-dangerous"),
+        // The TUI needs someone at the keyboard too
+        tui: (mode.interactive && args.iter().any(|arg| arg == "--tui"))
+            .then_some(run_tui as fn(&mut [PlanItem], &BackupNaming) -> bool),
+        backup_naming: backup_naming(args)?,
+        sudo: args.iter().any(|arg| arg == "--sudo"),
+        // Nobody is there to answer, so conflicts are left for an interactive sync
+        skip_conflicts: !mode.interactive,
+        on_conflict: conflict_policy(args)?,
+        require_clean: args.iter().any(|arg| arg == "--require-clean"),
+        // `dbdm sync <module>...` syncs only those modules
+        modules: match positionals[0].as_str() {
+            "sync" => positionals[1..].to_vec(),
+            _ => Vec::new(),
+        },
+        dir_mode: dir_mode(args)?,
+        fail_fast: args.iter().any(|arg| arg == "--fail-fast"),
+        atomic: args.iter().any(|arg| arg == "--atomic"),
+        confirm_always: args.iter().any(|arg| arg == "--confirm-always"),
+        yes: args.iter().any(|arg| arg == "--yes" || arg == "-y"),
     };
 
+    let desktop_notify = args.iter().any(|arg| arg == "--desktop-notify");
+
+    // Watch re-reads the config on every change, so it does its own parsing
+    if command == "watch" {
+        let options = SyncOptions {
+            tui: None,
+            skip_conflicts: true,
+            ..sync_options
+        };
+        return run_watch(&pwd, &mode, &options, desktop_notify, strict);
+    }
+
+    // Parse the config
+    let config = config_parser::read_config_with(&pwd, strict)
+        .map_err(|err| config_error(format!("Error in config:\n\n{}", err)))?;
+
     // Handle the command
     match command.as_str() {
-        "check" => check(&config, &mode),
-        "sync" => sync(&config, &mode, force),
+        "check" if args.iter().any(|arg| arg == "--watch") => check_watch(&config, &mode)?,
+        "check" => check(
+            &config,
+            &mode,
+            args.iter().any(|arg| arg == "--fix"),
+            args.iter().any(|arg| arg == "--strict-target"),
+        ),
+        "verify" => verify(&config, &mode)?,
+        "clean" => clean_backups(&config, &mode, args)?,
+        "export" => export_config(&config, &format, args)?,
+        "edit" => {
+            let Some(dest) = target else {
+                return Err(Failure::usage(
+                    "Missing destination, usage: dbdm edit <dest>",
+                ));
+            };
+            edit(&config, &dest)?;
+            if args.iter().any(|arg| arg == "--sync") {
+                run_sync(&config, &mode, &sync_options, desktop_notify)?;
+            }
+        }
+        "which" => {
+            let Some(path) = target else {
+                return Err(Failure::usage("Missing path, usage: dbdm which <path>"));
+            };
+            which(&config, &pwd, strict, &mode, &path)?
+        }
+        "sync" => run_sync(&config, &mode, &sync_options, desktop_notify)?,
         _ => help(&mode),
     }
+    Ok(())
 }
 
-// One of the command handlers
-// Allows to check if the current state of the system matches
-// the desired state that is specified in the provided config
+// Helper to run a sync and report it
 //
 // @param config: &Config - the parsed config state
-fn check(config: &Config, mode: &RunMode) {
-    for link in &config.links {
-        let from_full = std::fs::canonicalize(&link.from).unwrap_or_else(|_| link.from.clone());
-        let resolved_to = match resolve_link_destination(&link.from, &link.to) {
-            Ok(path) => path,
-            Err(_) => link.to.clone(),
-        };
-        let to_full = std::fs::canonicalize(&resolved_to).unwrap_or_else(|_| resolved_to.clone());
+// @param mode: &RunMode - how to prompt and print
+// @param options: &SyncOptions - the options of the sync
+// @param desktop_notify: bool - if a desktop notification is sent afterwards
+// @return Result<(), Failure> - a failure if the sync was aborted or an item failed
+fn run_sync(
+    config: &Config,
+    mode: &RunMode,
+    options: &SyncOptions,
+    desktop_notify: bool,
+) -> Result<(), Failure> {
+    check_modules(config, &options.modules)?;
+    let report = sync(config, mode, options);
+    print_report(mode, &report);
+    record_history(&report);
+    prune_backups(config, &report.executed, &options.backup_naming);
+    if desktop_notify {
+        notify_desktop(&report.executed);
+    }
+    if report.aborted {
+        return Err(Failure {
+            code: EXIT_ABORTED,
+            message: None,
+        });
+    }
+    if report.failed() {
+        return Err(Failure::reported());
+    }
+    Ok(())
+}
 
-        let is_match = match std::fs::read_link(&resolved_to) {
-            Ok(target) => {
-                let target_full = std::fs::canonicalize(&target).unwrap_or(target);
-                target_full == from_full
-            }
-            Err(_) => false,
+// Helper to move into the directory of the config given with `--config`
+//
+// @param config: &Path - the config file, or the directory its `dbdm.conf` is in
+// @return Result<PathBuf, Failure> - the file name of the config
+fn enter_config_dir(config: &Path) -> Result<PathBuf, Failure> {
+    let (dir, name) = if config.is_dir() {
+        (config, PathBuf::from("dbdm.conf"))
+    } else {
+        let Some(name) = config.file_name() else {
+            return Err(Failure::usage(format!(
+                "Invalid --config value {}, expected a config file",
+                config.display()
+            )));
         };
+        (
+            config.parent().unwrap_or(Path::new("")),
+            PathBuf::from(name),
+        )
+    };
+    if !dir.as_os_str().is_empty() {
+        std::env::set_current_dir(dir)
+            .map_err(|err| Failure::error(format!("Could not enter {}: {}", dir.display(), err)))?;
+    }
+    Ok(name)
+}
 
-        if is_match {
-            app_println!(
-                mode,
-                "\x1b[32m{} -> {}\x1b[0m",
-                from_full.display(),
-                to_full.display()
-            );
-        } else {
-            app_println!(
-                mode,
-                "\x1b[31m{} -> {}\x1b[0m",
-                from_full.display(),
-                to_full.display()
-            );
+// Flags whose value can come from the environment instead, for wrapper scripts and CI
+const ENV_FLAGS: &[(&str, &str)] = &[
+    ("DBDM_CONFIG", "--config"),
+    ("DBDM_ON_CONFLICT", "--on-conflict"),
+    ("DBDM_COLOR", "--color"),
+];
+
+// Helper to add the flags set through the environment to the command line
+//
+// Flags on the command line win over the environment. `DBDM_NON_INTERACTIVE`
+// is a switch, any value but an empty one, `0` or `false` turns it on. Values
+// are checked like those of the flags.
+//
+// @param args: Vec<String> - the command line arguments, without the binary
+// @return Vec<String> - the arguments with the flags from the environment added at the end
+fn with_env_defaults(mut args: Vec<String>) -> Vec<String> {
+    for (var, flag) in ENV_FLAGS {
+        if flag_value(&args, flag).is_some() {
+            continue;
+        }
+        if let Some(value) = std::env::var(var).ok().filter(|value| !value.is_empty()) {
+            args.push(flag.to_string());
+            args.push(value);
         }
     }
+
+    let non_interactive = std::env::var("DBDM_NON_INTERACTIVE")
+        .is_ok_and(|value| !matches!(value.as_str(), "" | "0" | "false"));
+    if non_interactive && !args.iter().any(|arg| arg == "--non-interactive") {
+        args.push(String::from("--non-interactive"));
+    }
+    args
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
-enum SyncAction {
-    Ignore,
-    Replace,
-    BackupReplace,
-    Skip,
-    Pending, // Temp state to mark files that need to be acted upon
+// Helper to read the current directory, where dbdm.conf is looked for
+//
+// @return Result<PathBuf, Failure> - the directory, or why it can't be read
+fn current_dir() -> Result<PathBuf, Failure> {
+    std::env::current_dir()
+        .map_err(|err| Failure::error(format!("Could not read the current directory: {}", err)))
 }
 
-#[derive(Debug)]
-struct PlanItem {
-    from: PathBuf,
-    to: PathBuf,
-    action: SyncAction,
-    reason: Option<String>,
+// Helper to read the `--on-conflict` flag
+//
+// @param args: &[String] - the command line arguments
+// @return Result<ConflictPolicy, Failure> - the policy from the flag or asking by default, an error for an invalid value
+fn conflict_policy(args: &[String]) -> Result<ConflictPolicy, Failure> {
+    let Some(value) = flag_value(args, "--on-conflict") else {
+        return Ok(ConflictPolicy::Ask);
+    };
+    ConflictPolicy::parse(&value).ok_or_else(|| {
+        Failure::usage(format!(
+            "Invalid --on-conflict value {}, expected ask, replace, backup or skip",
+            value
+        ))
+    })
 }
 
-// One of the command handlers
-// Allows to perform a sync of system state to the desired state specified in the config.
+// Helper to read the `--dir-mode` flag
 //
-// Accepts a `--force` flag if a non-interactive execution is preferred.
+// @param args: &[String] - the command line arguments
+// @return Result<Option<u32>, Failure> - the octal mode from the flag, None without it, an error for an invalid value
+fn dir_mode(args: &[String]) -> Result<Option<u32>, Failure> {
+    let Some(value) = flag_value(args, "--dir-mode") else {
+        return Ok(None);
+    };
+    u32::from_str_radix(&value, 8)
+        .ok()
+        .filter(|mode| *mode <= 0o7777)
+        .map(Some)
+        .ok_or_else(|| {
+            Failure::usage(format!(
+                "Invalid --dir-mode value {}, expected octal permission bits like 0700",
+                value
+            ))
+        })
+}
+
+// Helper to read the backup naming flags
 //
-// Otherwise tires to sync the state described in the config with the system state
+// @param args: &[String] - the command line arguments
+// @return Result<BackupNaming, Failure> - the naming from the flags or the default one, an error for an invalid value
+fn backup_naming(args: &[String]) -> Result<BackupNaming, Failure> {
+    let mut naming = BackupNaming::default();
+    if let Some(suffix) = flag_value(args, "--backup-suffix") {
+        if suffix.is_empty() || suffix.contains('/') {
+            return Err(Failure::usage(format!(
+                "Invalid --backup-suffix value {:?}, expected a file name suffix",
+                suffix
+            )));
+        }
+        naming.suffix = suffix;
+    }
+    if let Some(value) = flag_value(args, "--backup-naming") {
+        naming.numbering = BackupNumbering::parse(&value).ok_or_else(|| {
+            Failure::usage(format!(
+                "Invalid --backup-naming value {}, expected counter or timestamp",
+                value
+            ))
+        })?;
+    }
+    Ok(naming)
+}
+
+// Sets up logging to stderr based on the verbosity flags
+//
+// Errors, warnings and status messages are shown by default, `-v` adds debug
+// output, `-vv` adds traces and `--quiet` leaves only errors.
+//
+// @param args: &[String] - the command line arguments
+// @param color: ColorChoice - the color choice, applied to stderr
+fn init_logging(args: &[String], color: ColorChoice) {
+    let verbosity: usize = args
+        .iter()
+        .map(|arg| match arg.as_str() {
+            "--verbose" => 1,
+            arg if arg.starts_with('-')
+                && !arg.starts_with("--")
+                && arg[1..].chars().all(|c| c == 'v') =>
+            {
+                arg.len() - 1
+            }
+            _ => 0,
+        })
+        .sum();
+
+    let level = if args.iter().any(|arg| arg == "--quiet" || arg == "-q") {
+        tracing::Level::ERROR
+    } else {
+        match verbosity {
+            0 => tracing::Level::INFO,
+            1 => tracing::Level::DEBUG,
+            _ => tracing::Level::TRACE,
+        }
+    };
+
+    tracing_subscriber::fmt()
+        .with_writer(std::io::stderr)
+        .with_max_level(level)
+        .with_ansi(color.resolve(std::io::stderr().is_terminal()))
+        .with_target(false)
+        .without_time()
+        .init();
+}
+
+// Flags that take a value as the next argument
+const VALUE_FLAGS: &[&str] = &[
+    "--config",
+    "--format",
+    "--color",
+    "--preview-lines",
+    "--backup-suffix",
+    "--backup-naming",
+    "--timer",
+    "--on-conflict",
+    "--depth",
+    "--out",
+    "--keep",
+    "--keep-days",
+    "--dir-mode",
+    "--sort",
+];
+// How many directory levels `import scan` looks at by default
+const DEFAULT_IMPORT_DEPTH: usize = 4;
+// How many lines of a file are shown in conflict previews by default
+const DEFAULT_PREVIEW_LINES: usize = 20;
+
+// Helper to read the value of a flag passed as `--flag value` or `--flag=value`
+//
+// @param args: &[String] - the command line arguments
+// @param flag: &str - the flag to look for
+// @return Option<String> - the value of the flag, if present
+fn flag_value(args: &[String], flag: &str) -> Option<String> {
+    let prefix = format!("{}=", flag);
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if arg == flag {
+            return iter.next().cloned();
+        }
+        if let Some(value) = arg.strip_prefix(&prefix) {
+            return Some(value.to_string());
+        }
+    }
+    None
+}
+
+// Helper to collect the arguments that are not flags or flag values
+//
+// @param args: &[String] - the command line arguments
+// @return Vec<String> - the positional arguments in order
+fn positional_args(args: &[String]) -> Vec<String> {
+    let mut positionals = Vec::new();
+    let mut iter = args.iter();
+    while let Some(arg) = iter.next() {
+        if VALUE_FLAGS.contains(&arg.as_str()) {
+            iter.next();
+            continue;
+        }
+        if !arg.starts_with('-') {
+            positionals.push(arg.clone());
+        }
+    }
+    positionals
+}
+
+// One of the command handlers
+// Converts the config to the layout of GNU Stow or chezmoi. Without `--out`
+// it only prints where every file would go, with it the layout is written to
+// that directory, which has to be empty or missing.
 //
 // @param config: &Config - the parsed config state
-fn sync(config: &Config, mode: &RunMode, force: bool) {
-    // The plan to be previewed and then executed
-    let mut plan: Vec<PlanItem> = Vec::new();
-    // To have a quicker lookup for which plan items require care
-    let mut pending_indices: Vec<usize> = Vec::new();
+// @param format: &str - the `--format` flag, `stow` or `chezmoi`
+// @param args: &[String] - the command line arguments, for `--out`
+fn export_config(config: &Config, format: &str, args: &[String]) -> Result<(), Failure> {
+    let Some(format) = export::Format::parse(format) else {
+        return Err(Failure::usage(format!(
+            "Invalid --format value {} for export, expected stow or chezmoi",
+            format
+        )));
+    };
+    let repo = current_dir()?;
+    let Some(home) = platform::home_dir().map(PathBuf::from) else {
+        return Err(Failure::error(
+            "Can't find the home directory, links are exported relative to it",
+        ));
+    };
 
-    for link in &config.links {
-        let from = link.from.clone();
-        let to = link.to.clone();
-        let resolved_to = match resolve_link_destination(&from, &to) {
-            Ok(path) => path,
-            Err(err) => {
-                plan.push(PlanItem {
-                    from,
-                    to,
-                    action: SyncAction::Skip,
-                    reason: Some(err.to_string()),
-                });
-                continue;
-            }
-        };
+    let exported = export::export(config, &repo, &home, format);
+    for (path, reason) in &exported.skipped {
+        warn!("Left out {}: {}", path.display(), reason);
+    }
 
-        // Check if the path is valid and we have permission to modify it
-        match std::fs::symlink_metadata(&resolved_to) {
-            Ok(meta) => {
-                if meta.file_type().is_symlink() {
-                    // Try grab the file the link points to
-                    let target =
-                        std::fs::read_link(&resolved_to).unwrap_or_else(|_| resolved_to.clone());
-
-                    let from_full = canonicalize_or_fallback(&from);
-                    let target_full =
-                        canonicalize_or_fallback(&resolve_symlink_target(&resolved_to, &target));
-
-                    // Update the plan with an IGNORE
-                    if target_full == from_full {
-                        plan.push(PlanItem {
-                            from,
-                            to: resolved_to,
-                            action: SyncAction::Ignore,
-                            reason: None,
-                        });
-                        continue;
-                    }
+    let Some(out) = flag_value(args, "--out").map(PathBuf::from) else {
+        for entry in &exported.entries {
+            let from = match &entry.content {
+                export::Content::Copy(from) => from.display().to_string(),
+                export::Content::Symlink(target) => format!("symlink to {}", target.display()),
+                export::Content::Text(target) => {
+                    format!("symlink to {}", String::from_utf8_lossy(target))
                 }
+            };
+            println!("{} -> {}", from, entry.path.display());
+        }
+        return Ok(());
+    };
 
-                let is_empty = is_empty_path(&resolved_to, &meta).unwrap_or(false);
-                let is_conflict = !is_empty;
-
-                // Account for the flag
-                let action = if force || !is_conflict {
-                    SyncAction::Replace
-                } else {
-                    SyncAction::Pending
-                };
-
-                // Add to pending for later decision
-                let idx = plan.len();
-                plan.push(PlanItem {
-                    from,
-                    to: resolved_to,
-                    action,
-                    reason: None,
-                });
-
-                if !force && is_conflict {
-                    pending_indices.push(idx);
-                }
-            }
+    if std::fs::read_dir(&out).is_ok_and(|mut entries| entries.next().is_some()) {
+        return Err(Failure::error(format!(
+            "{} is not empty, export into a new directory",
+            out.display()
+        )));
+    }
+    export::write(&exported, &out).map_err(|err| {
+        Failure::error(format!(
+            "Could not write the export to {}: {}",
+            out.display(),
+            err
+        ))
+    })?;
+    info!(
+        "Exported {} files to {}",
+        exported.entries.len(),
+        out.display()
+    );
+    Ok(())
+}
 
-            // Missing target: safe to replace without prompt
-            Err(_) => {
-                plan.push(PlanItem {
-                    from,
-                    to: resolved_to,
-                    action: SyncAction::Replace,
-                    reason: None,
-                });
+// One of the command handlers
+// Prints a config line for every symlink in a directory that points into the
+// dotfiles repository, the current directory. Destinations that an existing
+// dbdm.conf already links are left out.
+//
+// @param dir: &Path - the directory to scan, like $HOME
+// @param args: &[String] - the command line arguments, for `--depth`
+fn import_scan(dir: &Path, args: &[String]) -> Result<(), Failure> {
+    let depth = match flag_value(args, "--depth") {
+        Some(value) => value.parse().map_err(|_| {
+            Failure::usage(format!(
+                "Invalid --depth value {}, expected a number",
+                value
+            ))
+        })?,
+        None => DEFAULT_IMPORT_DEPTH,
+    };
+    let repo = current_dir()?;
+
+    let found = import::scan(&canonicalize_or_fallback(dir), &repo, depth)
+        .map_err(|err| Failure::error(format!("Could not scan {}: {}", dir.display(), err)))?;
+
+    // Only a config that parses can tell what is linked already
+    let config_path = repo.join("dbdm.conf");
+    let linked: Vec<PathBuf> = config_path
+        .exists()
+        .then(|| config_parser::read_config(&config_path).ok())
+        .flatten()
+        .map(|config| {
+            config
+                .links
+                .iter()
+                .filter_map(|link| resolve_link_destination(&link.from, &link.to).ok())
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let home = platform::home_dir().map(PathBuf::from);
+    let mut new = 0;
+    for item in &found {
+        if linked.contains(&item.link) {
+            debug!(link = %item.link.display(), "already in the config");
+            continue;
+        }
+        match import::config_line(item, &repo, home.as_deref()) {
+            Some(mut line) => {
+                // Written as bytes, paths in it don't have to be valid UTF-8
+                line.push("\n");
+                let _ = std::io::stdout().write_all(line.as_encoded_bytes());
+                new += 1;
             }
+            None => warn!(
+                "{} can't be written in a config line, it has a `!` in its path",
+                item.link.display()
+            ),
         }
     }
+    info!(
+        "Found {} symlinks into {}, {} not in the config yet",
+        found.len(),
+        repo.display(),
+        new
+    );
+    Ok(())
+}
 
-    for &idx in pending_indices.iter() {
-        let item = &plan[idx];
-        app_println!(mode, "\nConflict at: {}", item.to.display());
-        if let Err(err) = print_preview(mode, &item.to) {
-            app_println!(mode, "Preview error: {}", err);
+// One of the command handlers
+// Prints a config line for every file or directory at the top of the dotfiles
+// repository, the current directory, that the catalog knows a destination
+// for on this OS. Sources that an existing dbdm.conf already links are left out.
+fn import_suggest() -> Result<(), Failure> {
+    let repo = current_dir()?;
+    let suggestions = import::suggest(&repo, std::env::consts::OS)
+        .map_err(|err| Failure::error(format!("Could not read {}: {}", repo.display(), err)))?;
+
+    // Only a config that parses can tell what is linked already
+    let config_path = repo.join("dbdm.conf");
+    let linked: Vec<PathBuf> = config_path
+        .exists()
+        .then(|| config_parser::read_config(&config_path).ok())
+        .flatten()
+        .map(|config| {
+            config
+                .links
+                .iter()
+                .map(|link| canonicalize_or_fallback(&link.from))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    let mut new = 0;
+    for suggestion in &suggestions {
+        if linked.contains(&canonicalize_or_fallback(&suggestion.source)) {
+            debug!(source = %suggestion.source.display(), "already in the config");
+            continue;
         }
+        match import::suggestion_line(suggestion, &repo) {
+            Some(mut line) => {
+                // Written as bytes, paths in it don't have to be valid UTF-8
+                line.push("\n");
+                let _ = std::io::stdout().write_all(line.as_encoded_bytes());
+                new += 1;
+            }
+            None => warn!(
+                "{} can't be written in a config line, it has a `!` in its path",
+                suggestion.source.display()
+            ),
+        }
+    }
+    info!(
+        "Found {} known configs in {}, {} not in the config yet",
+        suggestions.len(),
+        repo.display(),
+        new
+    );
+    Ok(())
+}
 
-        let action = prompt_action(mode);
-        plan[idx].action = action;
+// One of the command handlers
+// Lists the recorded runs, oldest first, with a count per action
+fn history_list(mode: &RunMode) -> Result<(), Failure> {
+    let runs = load_history()?;
+    if runs.is_empty() {
+        info!("Nothing was recorded yet");
+        return Ok(());
     }
 
-    print_plan(mode, "Planned actions", &plan);
-    if !force && !pending_indices.is_empty() {
-        if !confirm_proceed(mode) {
-            app_println!(mode, "Aborted.");
-            return;
-        }
+    let rows: Vec<Vec<Cell>> = runs
+        .iter()
+        .map(|run| {
+            vec![
+                Cell::plain(run.id.to_string()),
+                Cell::plain(format_time(run.time)),
+                Cell::plain(run.command.clone()),
+                Cell::plain(run.outcome.clone()),
+                Cell::plain(history_counts(&run.items)),
+            ]
+        })
+        .collect();
+    for line in table(&["ID", "TIME (UTC)", "COMMAND", "OUTCOME", "ITEMS"], &rows) {
+        app_println!(mode, "{}", line);
     }
+    Ok(())
+}
 
-    let mut executed: Vec<PlanItem> = Vec::new();
-    let mut errors: Vec<String> = Vec::new();
+// One of the command handlers
+// Prints everything a recorded run executed
+//
+// @param id: &str - the id of the run, as listed by `history`
+fn history_show(mode: &RunMode, id: &str) -> Result<(), Failure> {
+    let runs = load_history()?;
+    let Some(run) = runs.iter().find(|run| run.id.to_string() == id) else {
+        return Err(Failure::error(format!(
+            "No run with id {} in the history",
+            id
+        )));
+    };
 
-    for mut item in plan {
-        match item.action {
-            SyncAction::Ignore | SyncAction::Skip => {
-                executed.push(item);
-            }
-            SyncAction::Replace => {
-                if let Err(err) = replace_link(&item.from, &item.to) {
-                    errors.push(format!("{}: {}", item.to.display(), err));
-                    item.action = SyncAction::Skip;
-                    item.reason = Some("replace failed".to_string());
-                }
-                executed.push(item);
-            }
-            SyncAction::BackupReplace => {
-                if let Err(err) = backup_and_replace(&item.from, &item.to) {
-                    errors.push(format!("{}: {}", item.to.display(), err));
-                    item.action = SyncAction::Skip;
-                    item.reason = Some("backup+replace failed".to_string());
-                }
-                executed.push(item);
+    app_println!(
+        mode,
+        "{} #{} at {} UTC, {}",
+        run.command,
+        run.id,
+        format_time(run.time),
+        run.outcome
+    );
+    let registry = backups::load().unwrap_or_else(|err| {
+        warn!("Could not read the backup registry: {}", err);
+        Vec::new()
+    });
+    let rows: Vec<Vec<Cell>> = run
+        .items
+        .iter()
+        .map(|item| {
+            let backup = backups::of(&registry, &item.to)
+                .find(|record| record.run == Some(run.id))
+                .map(|record| record.backup.display().to_string());
+            vec![
+                Cell::plain(item.from.display().to_string()),
+                Cell::plain(item.to.display().to_string()),
+                Cell::plain(item.action.clone()),
+                Cell::plain(item.reason.clone().unwrap_or_default()),
+                Cell::plain(backup.unwrap_or_default()),
+            ]
+        })
+        .collect();
+    app_println!(mode, "");
+    for line in table(
+        &["SOURCE", "DESTINATION", "ACTION", "REASON", "BACKUP"],
+        &rows,
+    ) {
+        app_println!(mode, "{}", line);
+    }
+    Ok(())
+}
+
+// Helper to load the recorded runs for the history commands
+fn load_history() -> Result<Vec<history::Run>, Failure> {
+    history::load().map_err(|err| Failure::error(format!("Could not read the history: {}", err)))
+}
+
+// Helper to summarize the items of a recorded run
+//
+// @param items: &[history::Item] - the executed items
+// @return String - the count per action, in order of first appearance
+fn history_counts(items: &[history::Item]) -> String {
+    let mut counts: Vec<(&str, usize)> = Vec::new();
+    for item in items {
+        match counts.iter_mut().find(|(action, _)| *action == item.action) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((&item.action, 1)),
+        }
+    }
+    counts
+        .iter()
+        .map(|(action, count)| format!("{} {}", count, action))
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+// One of the command handlers
+// Clones a dotfiles repository and finds the dbdm.conf inside, so the first
+// sync can run from there. The config is looked for at the top of the
+// repository first, then one directory down.
+//
+// @param args: &[String] - the positional arguments after `bootstrap`, the URL and an optional destination
+// @return Result<PathBuf, Failure> - the directory with dbdm.conf, or why it wasn't found
+fn bootstrap(args: &[String]) -> Result<PathBuf, Failure> {
+    let Some(url) = args.first() else {
+        return Err(Failure::usage(
+            "bootstrap needs the URL of the dotfiles repository",
+        ));
+    };
+    let dest = match args.get(1) {
+        Some(dest) => PathBuf::from(dest),
+        None => match git::default_clone_dir(url) {
+            Some(name) => PathBuf::from(name),
+            None => {
+                return Err(Failure::usage(format!(
+                    "Could not tell where to clone {}, pass a destination",
+                    url
+                )));
             }
-            SyncAction::Pending => {
-                // TODO: I don't even know how to handle the ones that are still pending.
-                // This technically shouldn't even happen, so yea
-                continue;
+        },
+    };
+
+    info!("Cloning {} into {}", url, dest.display());
+    git::clone(url, &dest)
+        .map_err(|err| Failure::error(format!("Could not clone {}: {}", url, err)))?;
+
+    let mut found = Vec::new();
+    if dest.join("dbdm.conf").is_file() {
+        found.push(dest.clone());
+    } else if let Ok(entries) = std::fs::read_dir(&dest) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let hidden = entry.file_name().to_string_lossy().starts_with('.');
+            if !hidden && path.join("dbdm.conf").is_file() {
+                found.push(path);
             }
         }
+        found.sort();
     }
 
-    print_plan(mode, "Outcome", &executed);
-    if !errors.is_empty() {
-        app_println!(mode, "\nErrors:");
-        for err in errors {
-            app_println!(mode, "- {}", err);
+    match found.as_slice() {
+        [] => Err(Failure::error(format!(
+            "Cloned {}, but there is no dbdm.conf in it",
+            dest.display()
+        ))),
+        [config_dir] => {
+            info!("Found {}", config_dir.join("dbdm.conf").display());
+            Ok(canonicalize_or_fallback(config_dir))
+        }
+        _ => {
+            let dirs: Vec<String> = found.iter().map(|dir| dir.display().to_string()).collect();
+            Err(Failure::error(format!(
+                "Cloned {}, but found more than one dbdm.conf, run dbdm sync in one of: {}",
+                dest.display(),
+                dirs.join(", ")
+            )))
         }
     }
 }
 
-// Helper to print out a preview of what the utility is going to do
+// One of the command handlers
+// Writes git hooks into the repository the config is in, so pulls and
+// checkouts sync the links without asking anything. Hooks that dbdm didn't
+// write are only replaced with `--force`.
 //
-// @param path: &Path - the path to the symlink
-// @return Result<()> - if print was successful
-fn print_preview(mode: &RunMode, path: &Path) -> std::io::Result<()> {
-    let meta = std::fs::symlink_metadata(path)?;
+// @param config_path: &Path - the path to dbdm.conf
+// @param force: bool - if hooks that weren't written by dbdm can be replaced
+fn git_hooks_install(config_path: &Path, force: bool) -> Result<(), Failure> {
+    let config_dir = canonicalize_or_fallback(config_path.parent().unwrap_or(Path::new(".")));
+    let Some(root) = git::repo_root(&config_dir) else {
+        return Err(Failure::error(format!(
+            "{} is not in a git repository",
+            config_dir.display()
+        )));
+    };
+    let hooks_dir = git::hooks_dir(&root).map_err(|err| {
+        Failure::error(format!(
+            "Could not find the hooks of {}: {}",
+            root.display(),
+            err
+        ))
+    })?;
+    let binary = current_exe()?;
+
+    let script = git::sync_hook(&binary, &config_dir);
+    for hook in git::SYNC_HOOKS {
+        let path = hooks_dir.join(hook);
+        if let Ok(existing) = std::fs::read_to_string(&path) {
+            if existing == script {
+                info!("{} is up to date", path.display());
+                continue;
+            }
+            if !existing.contains(git::HOOK_MARKER) && !force {
+                return Err(Failure::error(format!(
+                    "{} already exists and wasn't written by dbdm, use --force to replace it",
+                    path.display()
+                )));
+            }
+        }
 
-    if meta.file_type().is_symlink() {
-        let target = std::fs::read_link(path)?;
-        app_println!(mode, "SYMLINK: {} -> {}", path.display(), target.display());
-        return Ok(());
+        std::fs::create_dir_all(&hooks_dir)
+            .and_then(|_| std::fs::write(&path, &script))
+            .and_then(|_| dbdm::platform::set_mode(&path, 0o755))
+            .map_err(|err| {
+                Failure::error(format!("Could not write {}: {}", path.display(), err))
+            })?;
+        info!("Wrote {}", path.display());
     }
+    Ok(())
+}
 
-    if meta.is_file() {
-        print_file_preview(mode, path)?;
-        return Ok(());
+// Helper to find the running binary, for hooks and units that run it again
+//
+// @return Result<PathBuf, Failure> - the canonical path of the binary
+fn current_exe() -> Result<PathBuf, Failure> {
+    std::env::current_exe()
+        .map(|path| canonicalize_or_fallback(&path))
+        .map_err(|err| Failure::error(format!("Could not find the dbdm binary: {}", err)))
+}
+
+// One of the command handlers
+// Writes systemd user units that run dbdm for the config in the current
+// directory, with the binary that is running now. Existing units are only
+// replaced with `--force`.
+//
+// @param config_path: &Path - the path to dbdm.conf
+// @param args: &[String] - the command line arguments, for the unit options
+// @param force: bool - if existing units with other contents can be replaced
+fn service_install(config_path: &Path, args: &[String], force: bool) -> Result<(), Failure> {
+    let command = if args.iter().any(|arg| arg == "--watch") {
+        ServiceCommand::Watch
+    } else {
+        ServiceCommand::Sync
+    };
+    let timer = flag_value(args, "--timer");
+    let on_path = args.iter().any(|arg| arg == "--path-unit");
+    if command == ServiceCommand::Watch && (timer.is_some() || on_path) {
+        return Err(Failure::usage(
+            "--timer and --path-unit only work with sync services, watch is always running",
+        ));
+    }
+    if timer
+        .as_deref()
+        .is_some_and(|span| span.trim().is_empty() || span.contains('\n'))
+    {
+        return Err(Failure::usage(
+            "Invalid --timer value, expected a systemd time span like 1h or 30min",
+        ));
     }
 
-    if meta.is_dir() {
-        print_dir_preview(mode, path)?;
+    let binary = current_exe()?;
+    let config_dir = canonicalize_or_fallback(config_path.parent().unwrap_or(Path::new(".")));
+    let Some(unit_dir) = service::user_unit_dir() else {
+        return Err(Failure::error(
+            "Could not find the systemd user unit directory, neither XDG_CONFIG_HOME nor HOME is set",
+        ));
+    };
+
+    let units = service::units(&binary, &config_dir, command, timer.as_deref(), on_path);
+    for unit in &units {
+        let path = unit_dir.join(unit.name);
+        let existing = std::fs::read_to_string(&path).ok();
+        if existing.as_deref() == Some(unit.contents.as_str()) {
+            info!("{} is up to date", path.display());
+            continue;
+        }
+        if existing.is_some() && !force {
+            return Err(Failure::error(format!(
+                "{} already exists with other contents, use --force to replace it",
+                path.display()
+            )));
+        }
+        std::fs::create_dir_all(&unit_dir)
+            .and_then(|_| std::fs::write(&path, &unit.contents))
+            .map_err(|err| {
+                Failure::error(format!("Could not write {}: {}", path.display(), err))
+            })?;
+        info!("Wrote {}", path.display());
     }
 
+    let enable: Vec<&str> = units.iter().map(|unit| unit.name).collect();
+    info!(
+        "Enable it with: systemctl --user daemon-reload && systemctl --user enable --now {}",
+        enable.join(" ")
+    );
     Ok(())
 }
 
-// Helper to print preview for all files in a directory recursively
+// One of the command handlers
+// Removes the backups past the retention limits of the `[backups]` section,
+// or of `--keep` and `--keep-days`, which override it
 //
-// @param path: &Path - the directory path to traverse
-// @return Result<()> - if print was successful
-fn print_dir_preview(mode: &RunMode, path: &Path) -> std::io::Result<()> {
-    app_println!(mode, "\nDIRECTORY: {}", path.display());
-    for entry in std::fs::read_dir(path)? {
-        let entry = entry?;
-        let entry_path = entry.path();
-        let meta = std::fs::symlink_metadata(&entry_path)?;
+// @param config: &Config - the parsed config state
+// @param args: &[String] - the command line arguments, for the limits, `--dry-run` and `--remove-empty-dirs`
+fn clean_backups(config: &Config, mode: &RunMode, args: &[String]) -> Result<(), Failure> {
+    let number = |flag: &str| -> Result<Option<u64>, Failure> {
+        flag_value(args, flag)
+            .map(|value| {
+                value
+                    .parse()
+                    .ok()
+                    .filter(|number| *number > 0)
+                    .ok_or_else(|| {
+                        Failure::usage(format!(
+                            "Invalid {} value {}, expected a number above 0",
+                            flag, value
+                        ))
+                    })
+            })
+            .transpose()
+    };
+    let retention = Retention {
+        keep: number("--keep")?
+            .map(|keep| keep as usize)
+            .or(config.retention.keep),
+        keep_days: number("--keep-days")?.or(config.retention.keep_days),
+    };
+    if !retention.is_set() {
+        return Err(Failure::usage(
+            "No retention limits, set keep or keep_days in [backups] or pass --keep or --keep-days",
+        ));
+    }
 
-        if meta.is_dir() {
-            print_dir_preview(mode, &entry_path)?;
-            continue;
+    let dry_run = args.iter().any(|arg| arg == "--dry-run");
+    let removed = clean(config, mode, &retention, &backup_naming(args)?, dry_run);
+    if !dry_run && args.iter().any(|arg| arg == "--remove-empty-dirs") {
+        for path in &removed {
+            match created_dirs::remove_empty_parents(path) {
+                Ok(dirs) => {
+                    for dir in dirs {
+                        app_println!(mode, "Removed {}", dir.display());
+                    }
+                }
+                Err(err) => warn!(
+                    "Could not remove the empty directories above {}: {}",
+                    path.display(),
+                    err
+                ),
+            }
         }
+    }
+    if dry_run {
+        info!("{} backups would be removed", removed.len());
+    } else {
+        info!("Removed {} backups", removed.len());
+    }
+    Ok(())
+}
 
-        if meta.file_type().is_symlink() {
-            let target = std::fs::read_link(&entry_path)?;
-            app_println!(
-                mode,
-                "\nSYMLINK: {} -> {}",
-                entry_path.display(),
-                target.display()
-            );
-            continue;
+// Helper to make sure the modules picked for a sync are in the config
+//
+// @param config: &Config - the parsed config state
+// @param modules: &[String] - the picked modules
+fn check_modules(config: &Config, modules: &[String]) -> Result<(), Failure> {
+    let known: Vec<&String> = config
+        .links
+        .iter()
+        .filter_map(|link| link.module.as_ref())
+        .collect();
+    match modules.iter().find(|module| !known.contains(module)) {
+        Some(module) => Err(Failure::usage(format!(
+            "No links of module {} in the config",
+            module
+        ))),
+        None => Ok(()),
+    }
+}
+
+// Helper to apply the `[backups]` limits to the destinations a sync backed up
+//
+// @param config: &Config - the parsed config state
+// @param executed: &[ExecutedItem] - the executed plan
+// @param naming: &BackupNaming - how backups are named
+fn prune_backups(config: &Config, executed: &[ExecutedItem], naming: &BackupNaming) {
+    if !config.retention.is_set() {
+        return;
+    }
+    let registry = backups::load().unwrap_or_else(|err| {
+        warn!("Could not read the backup registry: {}", err);
+        Vec::new()
+    });
+    for ExecutedItem { item, .. } in executed.iter().filter(|executed| executed.backup.is_some()) {
+        for path in clean::prune(
+            &item.from,
+            &item.to,
+            &registry,
+            &config.retention,
+            naming,
+            false,
+        ) {
+            info!("Removed old backup {}", path.display());
         }
+    }
+}
 
-        if meta.is_file() {
-            print_file_preview(mode, &entry_path)?;
+// One of the command handlers
+// Runs the parser and the semantic checks over the config and reports every
+// issue at once. Fails if anything was found, so it can be used as a
+// pre-commit hook. A config that can't be read exits with 3 instead of 1.
+//
+// @param path: &Path - the path to the config file
+// @param format: &str - the output format, `text` or `json`
+// @param strict: bool - if unknown kinds, options, keywords and sections are issues
+fn config_validate(path: &Path, format: &str, strict: bool) -> Result<(), Failure> {
+    let issues = validate(path, strict).map_err(|err| Failure {
+        code: EXIT_CONFIG,
+        message: Some(format!("Could not read {}: {}", path.display(), err)),
+    })?;
+
+    if format == "json" {
+        let items: Vec<serde_json::Value> = issues
+            .iter()
+            .map(|issue| {
+                serde_json::json!({
+                    "file": issue.file.display().to_string(),
+                    "line": issue.line,
+                    "kind": issue.kind.as_str(),
+                    "message": issue.message,
+                })
+            })
+            .collect();
+        let report = serde_json::json!({
+            "config": path.display().to_string(),
+            "valid": issues.is_empty(),
+            "issues": items,
+        });
+        println!("{}", report);
+    } else {
+        for issue in &issues {
+            println!(
+                "{}:{}: {}: {}",
+                issue.file.display(),
+                issue.line,
+                issue.kind.as_str(),
+                issue.message
+            );
         }
     }
 
+    if !issues.is_empty() {
+        return Err(Failure::reported());
+    }
     Ok(())
 }
 
-// Helper to print preview for a single file
+// One of the command handlers
+// Prints the config the way dbdm reads it, with keywords expanded, includes
+// and local overrides merged in, and the links whose conditions don't hold
+// left out. Every link comes with the file and line it was declared on.
 //
-// @param path: &Path - the file path to preview
-// @return Result<()> - if print was successful
-fn print_file_preview(mode: &RunMode, path: &Path) -> std::io::Result<()> {
-    const MAX_PREVIEW_SIZE: u64 = 32 * 1024;
-    let meta = std::fs::metadata(path)?;
-    app_println!(mode, "\nFILE: {}", path.display());
-
-    if meta.len() > MAX_PREVIEW_SIZE {
-        app_println!(mode, "TOO LARGE ({} bytes)", meta.len());
-        return Ok(());
+// @param path: &Path - the path to the config file
+// @param format: &str - the `--format` flag, `text` or `json`
+// @param strict: bool - if unknown kinds, options, keywords and sections are errors
+fn config_show(path: &Path, format: &str, strict: bool) -> Result<(), Failure> {
+    let parsed = config_parser::parse_config_with(path, strict).map_err(Failure::error)?;
+    if let Some(err) = parsed.errors.first() {
+        return Err(Failure::error(format!(
+            "Error in config:\n\n{}: {}",
+            err.file.display(),
+            err.message
+        )));
     }
 
-    let mut file = std::fs::File::open(path)?;
-    let mut buf = Vec::new();
-    file.read_to_end(&mut buf)?;
-
-    if buf.iter().any(|b| *b == 0) {
-        app_println!(mode, "BINARY FILE");
+    if format == "json" {
+        let links: Vec<serde_json::Value> = parsed
+            .entries
+            .iter()
+            .map(|entry| {
+                serde_json::json!({
+                    "file": entry.file.display().to_string(),
+                    "line": entry.line,
+                    "kind": entry.link.kind.as_str(),
+                    "from": entry.link.from.display().to_string(),
+                    "to": entry.link.to.display().to_string(),
+                    "options": entry.link.options.to_args(),
+                    "module": entry.link.module,
+                })
+            })
+            .collect();
+        let report = serde_json::json!({
+            "config": path.display().to_string(),
+            "links": links,
+            "secrets": {
+                "identity": parsed.secrets.identity.as_ref().map(|path| path.display().to_string()),
+                "gpg_key": parsed.secrets.gpg_key,
+            },
+            "backups": {
+                "keep": parsed.retention.keep,
+                "keep_days": parsed.retention.keep_days,
+                "location": parsed.backup_location.map(|location| location.as_str()),
+            },
+        });
+        println!("{}", report);
         return Ok(());
     }
 
-    match String::from_utf8(buf) {
-        Ok(text) => {
-            if text.is_empty() {
-                app_println!(mode, "(empty)");
-            } else {
-                app_print!(mode, "{}", text);
-                if !text.ends_with('\n') {
-                    app_println!(mode, "");
-                }
-            }
+    for entry in &parsed.entries {
+        let line = entry
+            .link
+            .config_line()
+            .map(|line| line.to_string_lossy().to_string())
+            .unwrap_or_default();
+        match &entry.link.module {
+            Some(module) => println!(
+                "{}:{}: {} (module {})",
+                entry.file.display(),
+                entry.line,
+                line,
+                module
+            ),
+            None => println!("{}:{}: {}", entry.file.display(), entry.line, line),
+        }
+    }
+    if parsed.secrets.identity.is_some() || parsed.secrets.gpg_key.is_some() {
+        println!("[secrets]");
+        if let Some(identity) = &parsed.secrets.identity {
+            println!("identity = {}", identity.display());
+        }
+        if let Some(gpg_key) = &parsed.secrets.gpg_key {
+            println!("gpg_key = {}", gpg_key);
+        }
+    }
+    if parsed.retention.is_set() || parsed.backup_location.is_some() {
+        println!("[backups]");
+        if let Some(keep) = parsed.retention.keep {
+            println!("keep = {}", keep);
+        }
+        if let Some(keep_days) = parsed.retention.keep_days {
+            println!("keep_days = {}", keep_days);
+        }
+        if let Some(location) = parsed.backup_location {
+            println!("location = {}", location.as_str());
         }
-        Err(_) => app_println!(mode, "BINARY FILE"),
     }
-
     Ok(())
 }
 
-// Helper to get user choice on how to resolve a conflict
+// One of the command handlers
+// Shows how a single line of the config is read: what every keyword in its
+// paths expands to and where that comes from, if its conditions hold, what
+// is at the expanded paths and the link it ends up as.
 //
-// @return SyncAction - the chosen action
-fn prompt_action(mode: &RunMode) -> SyncAction {
-    loop {
-        app_print!(mode, "Action [r]eplace, [b]ackup, [s]kip: ");
-        let mut stdout = std::io::stdout();
-        let _ = std::io::Write::flush(&mut stdout);
+// @param path: &Path - the path to the config file
+// @param target: Option<&str> - `<line>`, or `<file>:<line>` for another file like an included one
+fn config_explain(path: &Path, target: Option<&str>) -> Result<(), Failure> {
+    let usage = || Failure::usage("Missing line, usage: dbdm config explain [<file>:]<line>");
+    let target = target.ok_or_else(usage)?;
+    let (file, line) = match target.rsplit_once(':') {
+        Some((file, line)) if !file.is_empty() => (PathBuf::from(file), line),
+        _ => (path.to_path_buf(), target),
+    };
+    // Lines are counted from 1, like in error messages and `config show`
+    let line: usize = line
+        .parse()
+        .map_err(|_| Failure::usage(format!("Invalid line {}, expected a number", line)))?;
+    let idx = line
+        .checked_sub(1)
+        .ok_or_else(|| Failure::usage("Invalid line 0, lines are counted from 1"))?;
+
+    let explanation = config_parser::explain_line(&file, idx).map_err(Failure::error)?;
+    println!("{}:{}: {}", file.display(), line, explanation.text);
+    if let Some(section) = &explanation.section {
+        println!("  section  [{}]", section);
+    }
+    if explanation.paths.is_empty() && explanation.error.is_none() {
+        println!("  not a link line, nothing to expand");
+        return Ok(());
+    }
 
-        let mut input = String::new();
-        if std::io::stdin().read_line(&mut input).is_err() {
-            continue;
+    for (name, explained) in ["from", "to"].iter().zip(&explanation.paths) {
+        println!("  {:<8} {}", name, explained.written.display());
+        for step in &explained.steps {
+            println!(
+                "           {} -> {} ({})",
+                step.keyword,
+                step.value.display(),
+                step.origin
+            );
+        }
+        match &explained.expanded {
+            Ok(expanded) => println!(
+                "           = {} ({})",
+                expanded.display(),
+                describe_path(expanded)
+            ),
+            Err(err) => println!("           ! {}", err),
         }
+    }
+    for (condition, holds) in &explanation.conditions {
+        match holds {
+            Ok(true) => println!("  when     {} holds", condition),
+            Ok(false) => println!("  when     {} doesn't hold", condition),
+            Err(err) => println!("  when     {}: {}", condition, err),
+        }
+    }
 
-        let choice = input.trim().to_lowercase();
-        match choice.as_str() {
-            "r" | "replace" => return SyncAction::Replace,
-            "b" | "backup" => return SyncAction::BackupReplace,
-            "s" | "skip" => return SyncAction::Skip,
-            _ => app_println!(mode, "Invalid choice. Use r, b, or s."),
+    match (&explanation.link, &explanation.error) {
+        (_, Some(err)) => {
+            println!("  error    {}", err);
+            return Err(Failure::reported());
         }
+        (Some(link), None) => {
+            if let Ok(resolved) = resolve_link_destination(&link.from, &link.to)
+                && resolved != link.to
+            {
+                println!("  into     {}", resolved.display());
+            }
+            let line = link
+                .config_line()
+                .map(|line| line.to_string_lossy().to_string())
+                .unwrap_or_default();
+            println!("  read as  {}", line);
+        }
+        (None, None) => println!("  skipped, its conditions don't hold on this machine"),
     }
+    Ok(())
 }
 
-// Helper to ask for a final confirmation before executing actions
+// Helper to tell what is at a path, without following a symlink at it
 //
-// @return bool - true if confirmed, false otherwise
-fn confirm_proceed(mode: &RunMode) -> bool {
-    app_print!(mode, "\nProceed? [y/N]: ");
-    let mut stdout = std::io::stdout();
-    let _ = std::io::Write::flush(&mut stdout);
-    let mut input = String::new();
-    if std::io::stdin().read_line(&mut input).is_err() {
-        return false;
+// @param path: &Path - the path
+// @return String - `file`, `directory`, `symlink to <target>` or `missing`
+fn describe_path(path: &Path) -> String {
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_symlink() => match std::fs::read_link(path) {
+            Ok(target) => format!("symlink to {}", target.display()),
+            Err(_) => String::from("symlink"),
+        },
+        Ok(meta) if meta.is_dir() => String::from("directory"),
+        Ok(_) => String::from("file"),
+        Err(_) => String::from("missing"),
     }
-
-    matches!(input.trim().to_lowercase().as_str(), "y" | "yes")
 }
 
-// Helper to print a summary of planned or executed actions
+// One of the command handlers
+// Rewrites the config in the syntax of the current version, with a `version` line
 //
-// @param title: &str - the title of the summary section
-// @param plan: &[PlanItem] - items to print
-fn print_plan(mode: &RunMode, title: &str, plan: &[PlanItem]) {
-    app_println!(mode, "\n{}", title);
-    print_plan_section(mode, "ignored", plan, SyncAction::Ignore);
-    print_plan_section(mode, "skipped", plan, SyncAction::Skip);
-    print_plan_section(mode, "replaced", plan, SyncAction::Replace);
-    print_plan_section(mode, "backup+replaced", plan, SyncAction::BackupReplace);
+// @param path: &Path - the path to the config file
+fn config_migrate(path: &Path) -> Result<(), Failure> {
+    let content = std::fs::read(path)
+        .map_err(|err| Failure::error(format!("Could not read {}: {}", path.display(), err)))?;
+    let Some(migrated) = config_parser::migrate(&content).map_err(Failure::error)? else {
+        info!(
+            "{} is at version {} already",
+            path.display(),
+            config_parser::CONFIG_VERSION
+        );
+        return Ok(());
+    };
+    std::fs::write(path, migrated)
+        .map_err(|err| Failure::error(format!("Could not write {}: {}", path.display(), err)))?;
+    info!(
+        "Migrated {} to version {}",
+        path.display(),
+        config_parser::CONFIG_VERSION
+    );
+    Ok(())
 }
 
-// Helper to print a summary for a specific action group
+// One of the command handlers
+// Rewrites the config in the canonical style, with sorted and aligned links,
+// and warns about lines that repeat earlier ones. With `--check` nothing is
+// written, and it fails if the config isn't formatted.
 //
-// @param label: &str - the label for the action group
-// @param plan: &[PlanItem] - items to print
-// @param action: SyncAction - action type to filter by
-fn print_plan_section(mode: &RunMode, label: &str, plan: &[PlanItem], action: SyncAction) {
-    let mut items = plan.iter().filter(|item| item.action == action).peekable();
-    if items.peek().is_none() {
-        return;
-    }
+// @param path: &Path - the path to the config file
+// @param args: &[String] - the command line arguments, for `--check`, `--sort` and `--dedupe`
+fn config_fmt(path: &Path, args: &[String]) -> Result<(), Failure> {
+    let check = args.iter().any(|arg| arg == "--check");
+    let sort = match flag_value(args, "--sort") {
+        Some(value) => config_parser::SortBy::parse(&value).ok_or_else(|| {
+            Failure::usage(format!(
+                "Invalid --sort value {}, expected by-source or by-dest",
+                value
+            ))
+        })?,
+        None => config_parser::SortBy::Source,
+    };
+    let options = config_parser::FormatOptions {
+        sort,
+        dedupe: args.iter().any(|arg| arg == "--dedupe"),
+    };
 
-    app_println!(mode, "\n{}:", label);
-    for item in items {
-        match &item.reason {
-            Some(reason) => app_println!(mode, "- {} ({})", item.to.display(), reason),
-            None => app_println!(mode, "- {}", item.to.display()),
+    let content = std::fs::read(path)
+        .map_err(|err| Failure::error(format!("Could not read {}: {}", path.display(), err)))?;
+    let config_parser::Formatted {
+        content: formatted,
+        duplicates,
+    } = config_parser::format_with(&content, &options);
+    for (line, first) in duplicates {
+        if options.dedupe && !check {
+            info!("Removing line {}, it repeats line {}", line, first);
+        } else {
+            warn!(
+                "Line {} repeats line {}, remove it with --dedupe",
+                line, first
+            );
         }
     }
+    if formatted == content {
+        info!("{} is formatted already", path.display());
+        return Ok(());
+    }
+    if check {
+        return Err(Failure::error(format!(
+            "{} isn't formatted, run `dbdm config fmt`",
+            path.display()
+        )));
+    }
+    std::fs::write(path, formatted)
+        .map_err(|err| Failure::error(format!("Could not write {}: {}", path.display(), err)))?;
+    info!("Formatted {}", path.display());
+    Ok(())
 }
 
-fn is_empty_path(path: &Path, meta: &std::fs::Metadata) -> std::io::Result<bool> {
-    if meta.is_file() {
-        return Ok(meta.len() == 0);
+// One of the command handlers
+// Opens the source a destination comes from in $EDITOR, so symlinks don't
+// have to be followed by hand. The source of a `readonly=true` link is made
+// writable for the edit and read-only again afterwards.
+//
+// @param config: &Config - the parsed config state
+// @param dest: &Path - the destination, absolute
+fn edit(config: &Config, dest: &Path) -> Result<(), Failure> {
+    let Some((link, source)) = dbdm::edit::source_of(config, dest) else {
+        return Err(Failure::error(format!(
+            "No link in the config goes to {}",
+            dest.display()
+        )));
+    };
+    if link.kind == LinkKind::Encrypted {
+        return Err(Failure::error(format!(
+            "{} is encrypted, decrypt it to edit it",
+            source.display()
+        )));
     }
+    info!("Editing {}", source.display());
 
-    if meta.is_dir() {
-        return is_empty_dir_recursive(path);
+    let edit_error = |err: std::io::Error| {
+        Failure::error(format!("Could not edit {}: {}", source.display(), err))
+    };
+    if link.options.readonly && source.exists() {
+        permissions::make_writable(&source).map_err(edit_error)?;
     }
-
-    if meta.file_type().is_symlink() {
-        return Ok(false);
+    let edited = dbdm::edit::open_in_editor(&source).map_err(edit_error);
+    if link.options.readonly {
+        permissions::apply(&link.from, &link.options).map_err(edit_error)?;
     }
-
-    Ok(false)
+    edited
 }
 
-fn is_empty_dir_recursive(path: &Path) -> std::io::Result<bool> {
-    for entry in std::fs::read_dir(path)? {
-        let entry = entry?;
-        let entry_path = entry.path();
-        let meta = std::fs::symlink_metadata(&entry_path)?;
+// One of the command handlers
+// Tells if dbdm manages a path, and if so the config line of its link, the
+// source it comes from and where the link stands. Fails if no link goes there.
+//
+// @param config: &Config - the parsed config state
+// @param config_path: &Path - the config file, to find the line the link is on
+// @param strict: bool - if the config is parsed in strict mode
+// @param path: &Path - the path to look up, absolute
+fn which(
+    config: &Config,
+    config_path: &Path,
+    strict: bool,
+    mode: &RunMode,
+    path: &Path,
+) -> Result<(), Failure> {
+    use dbdm::state::LinkState;
+
+    let Some((link, source)) = dbdm::edit::source_of(config, path) else {
+        app_println!(mode, "{} is not managed by dbdm", path.display());
+        return Err(Failure::reported());
+    };
+    app_println!(mode, "{} is managed by dbdm", path.display());
+
+    // The config is parsed again, as only the entries know their lines
+    let entry = config_parser::parse_config_with(config_path, strict)
+        .ok()
+        .and_then(|parsed| parsed.entries.into_iter().find(|entry| entry.link == *link));
+    if let Some(entry) = entry {
+        let text = std::fs::read_to_string(&entry.file)
+            .ok()
+            .and_then(|content| {
+                content
+                    .lines()
+                    .nth(entry.line - 1)
+                    .map(str::trim)
+                    .map(String::from)
+            })
+            .unwrap_or_default();
+        app_println!(
+            mode,
+            "  config  {}:{}: {}",
+            entry.file.display(),
+            entry.line,
+            text
+        );
+    }
+    if let Some(module) = &link.module {
+        app_println!(mode, "  module  {}", module);
+    }
+    app_println!(
+        mode,
+        "  link    {} -> {}",
+        link.from.display(),
+        link.to.display()
+    );
+    if source.exists() {
+        app_println!(mode, "  source  {}", source.display());
+    } else {
+        app_println!(mode, "  source  {} (missing)", source.display());
+    }
 
-        if meta.is_file() {
-            if meta.len() > 0 {
-                return Ok(false);
+    let (state, color) = if link.kind == LinkKind::Encrypted {
+        match secrets::state(&link.from, &link.to) {
+            Ok(secrets::State::UpToDate) => ("decrypted".to_string(), Color::Green),
+            Ok(secrets::State::Stale) => ("stale".to_string(), Color::Yellow),
+            Ok(secrets::State::Missing) => ("not synced yet".to_string(), Color::Red),
+            Ok(secrets::State::Modified | secrets::State::Diverged) => {
+                ("modified".to_string(), Color::Red)
             }
-            continue;
+            Err(err) => (format!("unknown, {}", err), Color::Red),
         }
+    } else {
+        let finding =
+            dbdm::commands::check::inspect_link(link, &dbdm::CanonicalCache::default(), false);
+        let state = match (&finding.label, &finding.state) {
+            (Some(label), _) => label.to_string(),
+            (None, LinkState::LinkedCorrectly) => "linked".to_string(),
+            (None, LinkState::WrongTarget(target)) => format!("points to {}", target.display()),
+            (None, LinkState::RegularFileConflict) => "a file is in the way".to_string(),
+            (None, LinkState::DirectoryConflict) => "a directory is in the way".to_string(),
+            (None, _) => "not synced yet".to_string(),
+        };
+        (state, finding.color)
+    };
+    app_println!(mode, "  state   {}", paint(&state, color));
+    Ok(())
+}
 
-        if meta.is_dir() {
-            if !is_empty_dir_recursive(&entry_path)? {
-                return Ok(false);
-            }
+// One of the command handlers
+// Allows to verify every link all the way down, not just the top-level symlink
+//
+// Fails if any link has a problem.
+//
+// @param config: &Config - the parsed config state
+fn verify(config: &Config, mode: &RunMode) -> Result<(), Failure> {
+    let mut failed = false;
+    for link in &config.links {
+        let problems = if link.kind == LinkKind::Encrypted {
+            verify_encrypted(link)
+        } else {
+            verify_link(&link.from, &link.to)
+        };
+        let line = format!("{} -> {}", link.from.display(), link.to.display());
+        if problems.is_empty() {
+            app_println!(mode, "{}", paint(&line, Color::Green));
             continue;
         }
 
-        return Ok(false);
+        failed = true;
+        app_println!(mode, "{}", paint(&line, Color::Red));
+        for problem in problems {
+            app_println!(mode, "  {}: {}", problem.path.display(), problem.message);
+        }
+    }
+
+    if failed {
+        return Err(Failure::reported());
+    }
+    Ok(())
+}
+
+// Helper to verify an encrypted link, which is a decrypted copy instead of a symlink
+//
+// @param link: &Link - the encrypted link
+// @return Vec<Problem> - the problem with the destination, empty if it's up to date
+fn verify_encrypted(link: &Link) -> Vec<dbdm::verify::Problem> {
+    let message = match secrets::state(&link.from, &link.to) {
+        Ok(secrets::State::UpToDate) => return Vec::new(),
+        Ok(secrets::State::Missing) => "missing".to_string(),
+        Ok(secrets::State::Stale) => "decrypted from an older version of the source".to_string(),
+        Ok(secrets::State::Modified | secrets::State::Diverged) => {
+            "changed since it was decrypted".to_string()
+        }
+        Err(err) => err.to_string(),
+    };
+    vec![dbdm::verify::Problem {
+        path: link.to.clone(),
+        message,
+    }]
+}
+
+// Helper to keep the system in sync while the dotfiles are edited
+//
+// @param config_path: &Path - the path to dbdm.conf
+// @param options: &SyncOptions - the flags for each sync
+// @param strict: bool - if the config is read in strict mode
+#[cfg(feature = "notify")]
+fn run_watch(
+    config_path: &Path,
+    mode: &RunMode,
+    options: &SyncOptions,
+    desktop_notify: bool,
+    strict: bool,
+) -> Result<(), Failure> {
+    let on_change = |config: &Config| {
+        let report = sync(config, mode, options);
+        print_report(mode, &report);
+        record_history(&report);
+        if desktop_notify {
+            notify_desktop(&report.executed);
+        }
+    };
+    watch::run(config_path, strict, on_change)
+        .map_err(|err| Failure::error(format!("Failed to watch for changes: {}", err)))
+}
+
+#[cfg(not(feature = "notify"))]
+fn run_watch(
+    _config_path: &Path,
+    _mode: &RunMode,
+    _options: &SyncOptions,
+    _desktop_notify: bool,
+    _strict: bool,
+) -> Result<(), Failure> {
+    Err(Failure::error(
+        "dbdm was built without the notify feature, rebuild it with `--features notify`",
+    ))
+}
+
+// One of the command handlers
+// Stays running and reports every change other programs make to the
+// destinations, with where the link stands afterwards.
+//
+// @param config: &Config - the parsed config state
+#[cfg(feature = "notify")]
+fn check_watch(config: &Config, mode: &RunMode) -> Result<(), Failure> {
+    use dbdm::state::LinkState;
+
+    let on_change = |link: &Link, change: watch::Change| {
+        let finding =
+            dbdm::commands::check::inspect_link(link, &dbdm::CanonicalCache::default(), false);
+        let now = match (&finding.label, &finding.state) {
+            (Some(label), _) => label.to_string(),
+            (None, LinkState::LinkedCorrectly) => "still linked".to_string(),
+            (None, LinkState::WrongTarget(target)) => format!("now points to {}", target.display()),
+            (None, LinkState::RegularFileConflict) => "now a file".to_string(),
+            (None, LinkState::DirectoryConflict) => "now a directory".to_string(),
+            (None, _) => "gone".to_string(),
+        };
+        app_println!(
+            mode,
+            "{}",
+            paint(
+                &format!(
+                    "{} {}, {}",
+                    finding.resolved_to.display(),
+                    change.as_str(),
+                    now
+                ),
+                finding.color
+            )
+        );
+    };
+    watch::watch_destinations(config, on_change)
+        .map_err(|err| Failure::error(format!("Failed to watch for changes: {}", err)))
+}
+
+#[cfg(not(feature = "notify"))]
+fn check_watch(_config: &Config, _mode: &RunMode) -> Result<(), Failure> {
+    Err(Failure::error(
+        "dbdm was built without the notify feature, rebuild it with `--features notify`",
+    ))
+}
+
+// Helper to summarize a sync in a desktop notification
+//
+// @param outcome: &[ExecutedItem] - the executed plan
+#[cfg(feature = "desktop-notify")]
+fn notify_desktop(outcome: &[ExecutedItem]) {
+    desktop::notify_outcome(outcome);
+}
+
+#[cfg(not(feature = "desktop-notify"))]
+fn notify_desktop(_outcome: &[ExecutedItem]) {
+    error!(
+        "dbdm was built without the desktop-notify feature, rebuild it with `--features desktop-notify`"
+    );
+}
+
+// Helper to let the user resolve the plan in the interactive TUI
+//
+// @param plan: &mut [PlanItem] - the plan to edit in place
+// @param naming: &BackupNaming - how backups are named, to leave them out of previews
+// @return bool - true if the plan should be applied
+#[cfg(feature = "tui")]
+fn run_tui(plan: &mut [PlanItem], naming: &BackupNaming) -> bool {
+    match tui::run(plan, naming) {
+        Ok(apply) => apply,
+        Err(err) => {
+            error!("Failed to run the TUI: {}", err);
+            false
+        }
     }
+}
 
-    Ok(true)
+#[cfg(not(feature = "tui"))]
+fn run_tui(_plan: &mut [PlanItem], _naming: &BackupNaming) -> bool {
+    error!("dbdm was built without the tui feature, rebuild it with `--features tui`");
+    false
 }
 
 fn help(mode: &RunMode) {
     app_println!(mode, "dbdm - dotfile link manager");
     app_println!(mode, "\nUsage:");
-    app_println!(mode, "  dbdm <command> [--force]");
+    app_println!(
+        mode,
+        "  dbdm <command> [--force] [--format text|json|ndjson] [-v|-vv|--quiet]"
+    );
+    app_println!(
+        mode,
+        "               [--color auto|always|never] [--preview-lines N] [--tui]"
+    );
+    app_println!(
+        mode,
+        "               [--allow-dangerous] [--sudo] [--backup-suffix SUFFIX]"
+    );
+    app_println!(
+        mode,
+        "               [--backup-naming counter|timestamp] [--non-interactive]"
+    );
+    app_println!(
+        mode,
+        "               [--desktop-notify] [--require-clean] [--strict] [--config FILE]"
+    );
+    app_println!(
+        mode,
+        "               [--on-conflict ask|replace|backup|skip] [--dir-mode MODE]"
+    );
+    app_println!(mode, "               [--fail-fast] [--atomic] [--summary]");
+    app_println!(mode, "               [--confirm-always] [-y|--yes]");
     app_println!(mode, "\nCommands:");
-    app_println!(mode, "  check   Validate config and planned links");
-    app_println!(mode, "  sync    Apply config links to the filesystem");
-    app_println!(mode, "  help    Show this help message");
+    app_println!(mode, "  check [--fix] [--strict-target]");
+    app_println!(mode, "                   Validate config and planned links");
+    app_println!(
+        mode,
+        "  check --watch    Report changes other programs make to destinations"
+    );
+    app_println!(
+        mode,
+        "  verify           Check that linked trees resolve to their sources"
+    );
+    app_println!(
+        mode,
+        "  clean [--keep N] [--keep-days N] [--dry-run] [--remove-empty-dirs]"
+    );
+    app_println!(
+        mode,
+        "                   Remove backups past the limits of the [backups] section"
+    );
+    app_println!(
+        mode,
+        "  sync             Apply config links to the filesystem"
+    );
+    app_println!(
+        mode,
+        "                   With module names, only the links of those modules"
+    );
+    app_println!(
+        mode,
+        "  watch            Sync again whenever the config or a source changes"
+    );
+    app_println!(mode, "  edit <dest> [--sync]");
+    app_println!(
+        mode,
+        "                   Open the source of a destination in $EDITOR"
+    );
+    app_println!(
+        mode,
+        "  which <path>     Tell if dbdm manages a path, and where it comes from"
+    );
+    app_println!(
+        mode,
+        "  config validate  Report every config error and semantic issue"
+    );
+    app_println!(mode, "  config show [--format json]");
+    app_println!(
+        mode,
+        "                   Print the config as read, with the file and line of each link"
+    );
+    app_println!(mode, "  config explain [<file>:]<line>");
+    app_println!(
+        mode,
+        "                   Show how a config line is expanded, step by step"
+    );
+    app_println!(
+        mode,
+        "  config migrate   Rewrite the config in the current syntax version"
+    );
+    app_println!(
+        mode,
+        "  config fmt [--check] [--sort by-source|by-dest] [--dedupe]"
+    );
+    app_println!(
+        mode,
+        "                   Rewrite the config with sorted links in aligned columns"
+    );
+    app_println!(
+        mode,
+        "  service install [--watch] [--timer SPAN] [--path-unit]"
+    );
+    app_println!(
+        mode,
+        "                   Write a systemd user service that syncs at login"
+    );
+    app_println!(
+        mode,
+        "  git-hooks install  Sync after every pull and checkout of the dotfiles repo"
+    );
+    app_println!(mode, "  export --format stow|chezmoi [--out DIR]");
+    app_println!(
+        mode,
+        "                   Convert the config to another dotfile manager's layout"
+    );
+    app_println!(mode, "  import scan <dir> [--depth N]");
+    app_println!(
+        mode,
+        "                   Print config lines for symlinks in <dir> into this repo"
+    );
+    app_println!(mode, "  import suggest");
+    app_println!(
+        mode,
+        "                   Print config lines for known app configs in this repo"
+    );
+    app_println!(mode, "  bootstrap <git-url> [dest]");
+    app_println!(
+        mode,
+        "                   Clone a dotfiles repository and sync its dbdm.conf"
+    );
+    app_println!(mode, "  history [show <id>]");
+    app_println!(
+        mode,
+        "                   List past syncs, or show everything one of them did"
+    );
+    app_println!(mode, "  help             Show this help message");
     app_println!(mode, "\nConfig:");
     app_println!(mode, "  Looks for dbdm.conf in the current directory.");
     app_println!(mode, "  Each line: 'link = <from> <to>'");
@@ -0,0 +1,190 @@
+use crate::hash;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Where a destination that dbdm wrote stands, compared to what it last wrote there
+#[derive(Debug, PartialEq, Eq)]
+pub enum State {
+    // Nothing at the destination
+    Missing,
+    // Written from the current source and untouched since
+    UpToDate,
+    // Untouched since it was written, but the source changed
+    Stale,
+    // Changed after it was written, or not written by dbdm at all
+    Modified,
+    // Changed after it was written, and the source changed too
+    Diverged,
+}
+
+// What dbdm wrote to a destination, by hash
+pub struct Record {
+    // The source it was written from
+    pub source: String,
+    // What was written
+    pub written: String,
+}
+
+// A file in the state directory remembering what dbdm wrote where
+//
+// Decrypted secrets and copies made in place of symlinks are both checked this
+// way, as there is no link to follow back to the source.
+pub struct Manifest {
+    // The name of the file in the state directory
+    file: &'static str,
+}
+
+impl Manifest {
+    pub const fn new(file: &'static str) -> Manifest {
+        Manifest { file }
+    }
+
+    // Helper to compare a destination with what was last written into it
+    //
+    // @param from: &Path - the source
+    // @param dest: &Path - the destination
+    // @return Result<State> - where the destination stands
+    pub fn state(&self, from: &Path, dest: &Path) -> std::io::Result<State> {
+        if std::fs::symlink_metadata(dest).is_err() {
+            return Ok(State::Missing);
+        }
+        let Some(record) = self.load()?.remove(&key(dest)) else {
+            return Ok(State::Modified);
+        };
+        let source_changed = content_hash(from)? != record.source;
+        Ok(
+            match (content_hash(dest)? != record.written, source_changed) {
+                (true, true) => State::Diverged,
+                (true, false) => State::Modified,
+                (false, true) => State::Stale,
+                (false, false) => State::UpToDate,
+            },
+        )
+    }
+
+    // If anything was recorded for a destination
+    pub fn contains(&self, dest: &Path) -> std::io::Result<bool> {
+        Ok(self.load()?.contains_key(&key(dest)))
+    }
+
+    // Remembers what was written to a destination
+    //
+    // @param dest: &Path - the destination
+    // @param record: Record - the hashes of the source and of what was written
+    // @return Result<()> - an error if the manifest can't be written
+    pub fn insert(&self, dest: &Path, record: Record) -> std::io::Result<()> {
+        let mut records = self.load()?;
+        records.insert(key(dest), record);
+        self.save(&records)
+    }
+
+    // Helper to get the file the records are kept in
+    fn path(&self) -> std::io::Result<PathBuf> {
+        crate::dirs::manifest_file(self.file)
+    }
+
+    fn load(&self) -> std::io::Result<HashMap<String, Record>> {
+        let path = self.path()?;
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                return Ok(Default::default());
+            }
+            Err(err) => return Err(err),
+        };
+        let json: serde_json::Value = serde_json::from_str(&contents).map_err(|err| {
+            std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("{}: {}", path.display(), err),
+            )
+        })?;
+
+        let field = |value: &serde_json::Value, name: &str| {
+            value
+                .get(name)
+                .and_then(|field| field.as_str())
+                .unwrap_or_default()
+                .to_string()
+        };
+        Ok(json
+            .as_object()
+            .map(|records| {
+                records
+                    .iter()
+                    .map(|(dest, value)| {
+                        let record = Record {
+                            source: field(value, "source"),
+                            written: field(value, "written"),
+                        };
+                        (dest.clone(), record)
+                    })
+                    .collect()
+            })
+            .unwrap_or_default())
+    }
+
+    fn save(&self, records: &HashMap<String, Record>) -> std::io::Result<()> {
+        let path = self.path()?;
+        let json: serde_json::Map<String, serde_json::Value> = records
+            .iter()
+            .map(|(dest, record)| {
+                let value = serde_json::json!({
+                    "source": record.source,
+                    "written": record.written,
+                });
+                (dest.clone(), value)
+            })
+            .collect();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(&path, serde_json::Value::Object(json).to_string())
+    }
+}
+
+// Helper to hash what is at a path
+//
+// Files are hashed by content, symlinks by where they point and directories
+// by the names and hashes of everything below them.
+//
+// @param path: &Path - the path to hash, not followed if it's a symlink
+// @return Result<String> - the hash in hex
+pub fn content_hash(path: &Path) -> std::io::Result<String> {
+    let meta = std::fs::symlink_metadata(path)?;
+    if meta.file_type().is_symlink() {
+        return Ok(hash(
+            std::fs::read_link(path)?.as_os_str().as_encoded_bytes(),
+        ));
+    }
+    if !meta.is_dir() {
+        return Ok(hash(&std::fs::read(path)?));
+    }
+
+    let mut entries = std::fs::read_dir(path)?
+        .map(|entry| entry.map(|entry| entry.file_name()))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    entries.sort();
+    let mut listing: Vec<u8> = Vec::new();
+    for name in entries {
+        let entry_hash = content_hash(&path.join(&name))?;
+        listing.extend_from_slice(name.as_encoded_bytes());
+        listing.extend_from_slice(format!("\0{}\n", entry_hash).as_bytes());
+    }
+    Ok(hash(&listing))
+}
+
+// Helper to get the key a destination is recorded under
+//
+// JSON keys have to be strings, so a path that isn't valid UTF-8 gets the
+// hash of its bytes appended to keep it apart from similar ones.
+fn key(dest: &Path) -> String {
+    let dest = crate::canonicalize_or_fallback(dest);
+    match dest.to_str() {
+        Some(dest) => dest.to_string(),
+        None => format!(
+            "{}#{}",
+            dest.display(),
+            hash(dest.as_os_str().as_encoded_bytes())
+        ),
+    }
+}
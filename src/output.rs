@@ -0,0 +1,203 @@
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+// If styled output is written to stdout, decided once at startup by `init`
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    // Helper to parse the value of the `--color` flag
+    //
+    // @param value: &str - the flag value
+    // @return Option<ColorChoice> - the choice, None if the value is unknown
+    pub fn parse(value: &str) -> Option<ColorChoice> {
+        match value {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    // Helper to decide if colors should be used for a stream
+    //
+    // `auto` disables colors when NO_COLOR is set to a non-empty value, when
+    // TERM is `dumb`, or when the stream is not a terminal.
+    //
+    // @param is_terminal: bool - if the stream is attached to a terminal
+    // @return bool - if colors should be used
+    pub fn resolve(&self, is_terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+                let dumb = std::env::var("TERM").is_ok_and(|term| term == "dumb");
+                !no_color && !dumb && is_terminal
+            }
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Color {
+    Red,
+    Green,
+    Yellow,
+    Blue,
+    Dim,
+}
+
+impl Color {
+    fn code(&self) -> &'static str {
+        match self {
+            Color::Red => "31",
+            Color::Green => "32",
+            Color::Yellow => "33",
+            Color::Blue => "34",
+            Color::Dim => "2",
+        }
+    }
+}
+
+// Sets up styling of stdout for the rest of the run
+//
+// @param choice: ColorChoice - the color choice from the command line
+pub fn init(choice: ColorChoice) {
+    COLOR.store(
+        choice.resolve(std::io::stdout().is_terminal()),
+        Ordering::Relaxed,
+    );
+}
+
+// Helper to check if styled output is enabled
+pub fn colors_enabled() -> bool {
+    COLOR.load(Ordering::Relaxed)
+}
+
+// Helper to style a piece of text for stdout
+//
+// @param text: &str - the text to style
+// @param color: Color - the color to use
+// @return String - the styled text, or the text as is when colors are disabled
+pub fn paint(text: &str, color: Color) -> String {
+    if !colors_enabled() {
+        return text.to_string();
+    }
+    format!("\x1b[{}m{}\x1b[0m", color.code(), text)
+}
+
+// A single table cell with an optional color
+pub struct Cell {
+    pub text: String,
+    pub color: Option<Color>,
+}
+
+impl Cell {
+    pub fn plain(text: impl Into<String>) -> Cell {
+        Cell {
+            text: text.into(),
+            color: None,
+        }
+    }
+
+    pub fn colored(text: impl Into<String>, color: Color) -> Cell {
+        Cell {
+            text: text.into(),
+            color: Some(color),
+        }
+    }
+}
+
+// Helper to render rows into aligned columns
+//
+// Widths are computed on the plain text, colors are applied after padding so
+// the escape codes don't break the alignment. The last column isn't padded.
+//
+// @param headers: &[&str] - the column titles
+// @param rows: &[Vec<Cell>] - the rows, each with a cell per column
+// @return Vec<String> - the rendered lines, header first
+pub fn table(headers: &[&str], rows: &[Vec<Cell>]) -> Vec<String> {
+    let mut widths: Vec<usize> = headers
+        .iter()
+        .map(|header| header.chars().count())
+        .collect();
+    for row in rows {
+        for (idx, cell) in row.iter().enumerate() {
+            widths[idx] = widths[idx].max(cell.text.chars().count());
+        }
+    }
+
+    let render = |cells: Vec<(String, Option<Color>)>| {
+        let last = cells.len().saturating_sub(1);
+        let columns: Vec<String> = cells
+            .into_iter()
+            .enumerate()
+            .map(|(idx, (text, color))| {
+                let padded = if idx == last {
+                    text
+                } else {
+                    format!("{:<width$}", text, width = widths[idx])
+                };
+                match color {
+                    Some(color) => paint(&padded, color),
+                    None => padded,
+                }
+            })
+            .collect();
+        columns.join("  ").trim_end().to_string()
+    };
+
+    let mut lines = vec![render(
+        headers
+            .iter()
+            .map(|header| (header.to_string(), Some(Color::Dim)))
+            .collect(),
+    )];
+    for row in rows {
+        lines.push(render(
+            row.iter()
+                .map(|cell| (cell.text.clone(), cell.color))
+                .collect(),
+        ));
+    }
+    lines
+}
+
+// Helper to format a byte count in human readable units
+//
+// @param bytes: u64 - the size in bytes
+// @return String - the size, e.g. `512 B` or `1.5 KiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", size, UNITS[unit])
+}
+
+// Helper to count something in words
+//
+// @param count: usize - how many there are
+// @param noun: &str - the singular noun, plurals just add an `s`
+// @return String - e.g. `1 link` or `3 links`
+pub fn plural(count: usize, noun: &str) -> String {
+    if count == 1 {
+        format!("1 {}", noun)
+    } else {
+        format!("{} {}s", count, noun)
+    }
+}
@@ -0,0 +1,223 @@
+use crate::config_parser::LinkOptions;
+use crate::output::plural;
+#[cfg(unix)]
+use std::os::unix::fs::{MetadataExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+
+// Helper to list how a path differs from the `mode=`, `owner=` and `readonly=` of its link
+//
+// @param path: &Path - the path to inspect, usually the source of the link
+// @param options: &LinkOptions - the options of the link
+// @return Result<Vec<String>> - one description per mismatch, empty if it all matches
+#[cfg(unix)]
+pub fn mismatches(path: &Path, options: &LinkOptions) -> std::io::Result<Vec<String>> {
+    let meta = std::fs::metadata(path)?;
+    let mut found = Vec::new();
+
+    if let Some(mode) = expected_mode(&meta, options) {
+        let current = meta.mode() & 0o7777;
+        if current != mode {
+            found.push(format!("mode is {:04o}, expected {:04o}", current, mode));
+        }
+    }
+
+    if let Some(owner) = &options.owner {
+        let (uid, gid) = resolve_owner(owner)?;
+        if meta.uid() != uid || gid.is_some_and(|gid| meta.gid() != gid) {
+            found.push(format!(
+                "owner is {}:{}, expected {}",
+                meta.uid(),
+                meta.gid(),
+                owner
+            ));
+        }
+    }
+
+    found.extend(readonly_mismatch(path, options)?);
+    Ok(found)
+}
+
+// Helper to bring a path to the `mode=`, `owner=` and `readonly=` of its link
+//
+// @param path: &Path - the path to change, usually the source of the link
+// @param options: &LinkOptions - the options of the link
+// @return Result<bool> - if anything had to be changed
+#[cfg(unix)]
+pub fn apply(path: &Path, options: &LinkOptions) -> std::io::Result<bool> {
+    if mismatches(path, options)?.is_empty() {
+        return Ok(false);
+    }
+
+    if let Some(owner) = &options.owner {
+        let (uid, gid) = resolve_owner(owner)?;
+        std::os::unix::fs::chown(path, Some(uid), gid)?;
+    }
+    if let Some(mode) = expected_mode(&std::fs::metadata(path)?, options) {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))?;
+    }
+    make_readonly(path, options)?;
+    Ok(true)
+}
+
+// Helper to get the permission bits a source is kept at
+//
+// A read-only file loses the write bits of its `mode=`, while a directory keeps
+// them, as only the files in it are made read-only.
+#[cfg(unix)]
+fn expected_mode(meta: &std::fs::Metadata, options: &LinkOptions) -> Option<u32> {
+    options.mode.map(|mode| {
+        if options.readonly && !meta.is_dir() {
+            mode & !0o222
+        } else {
+            mode
+        }
+    })
+}
+
+// Unix permission bits and owners don't exist on other platforms
+#[cfg(not(unix))]
+pub fn mismatches(path: &Path, options: &LinkOptions) -> std::io::Result<Vec<String>> {
+    unsupported(options)?;
+    Ok(readonly_mismatch(path, options)?.into_iter().collect())
+}
+
+#[cfg(not(unix))]
+pub fn apply(path: &Path, options: &LinkOptions) -> std::io::Result<bool> {
+    unsupported(options)?;
+    Ok(make_readonly(path, options)? > 0)
+}
+
+// Helper to describe the files of a `readonly=true` source that can still be written
+//
+// @param path: &Path - the source, a file or a directory
+// @param options: &LinkOptions - the options of the link
+// @return Result<Option<String>> - the mismatch, None if nothing can be written
+fn readonly_mismatch(path: &Path, options: &LinkOptions) -> std::io::Result<Option<String>> {
+    let writable = writable_files(path, options)?;
+    Ok((!writable.is_empty()).then(|| {
+        format!(
+            "{} writable, expected read-only",
+            plural(writable.len(), "file")
+        )
+    }))
+}
+
+// Helper to take the write permission away from a `readonly=true` source, like `chmod a-w`
+//
+// A directory stays writable, so files can still be added to it, while every
+// file in it is made read-only.
+//
+// @param path: &Path - the source, a file or a directory
+// @param options: &LinkOptions - the options of the link
+// @return Result<usize> - how many files were made read-only
+fn make_readonly(path: &Path, options: &LinkOptions) -> std::io::Result<usize> {
+    let writable = writable_files(path, options)?;
+    for file in &writable {
+        let mut permissions = std::fs::metadata(file)?.permissions();
+        permissions.set_readonly(true);
+        std::fs::set_permissions(file, permissions)?;
+    }
+    Ok(writable.len())
+}
+
+// Gives the owner write permission to a file again, like `chmod u+w`
+//
+// Used to edit the source of a `readonly=true` link, `apply` takes it away again.
+//
+// @param path: &Path - the file
+// @return Result<()> - an error if the permissions couldn't be changed
+#[cfg(unix)]
+pub fn make_writable(path: &Path) -> std::io::Result<()> {
+    let mode = std::fs::metadata(path)?.permissions().mode();
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode | 0o200))
+}
+
+#[cfg(not(unix))]
+#[allow(clippy::permissions_set_readonly_false)]
+pub fn make_writable(path: &Path) -> std::io::Result<()> {
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_readonly(false);
+    std::fs::set_permissions(path, permissions)
+}
+
+// Helper to list the files of a `readonly=true` source that can be written
+//
+// Symlinks inside a source directory are not followed.
+fn writable_files(path: &Path, options: &LinkOptions) -> std::io::Result<Vec<PathBuf>> {
+    if !options.readonly {
+        return Ok(Vec::new());
+    }
+    let meta = std::fs::metadata(path)?;
+    if !meta.is_dir() {
+        return Ok(if meta.permissions().readonly() {
+            Vec::new()
+        } else {
+            vec![path.to_path_buf()]
+        });
+    }
+
+    let mut writable = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() || file_type.is_file() {
+            writable.extend(writable_files(&entry.path(), options)?);
+        }
+    }
+    Ok(writable)
+}
+
+#[cfg(not(unix))]
+fn unsupported(options: &LinkOptions) -> std::io::Result<()> {
+    if options.mode.is_some() || options.owner.is_some() {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::Unsupported,
+            "mode= and owner= are only supported on Unix",
+        ));
+    }
+    Ok(())
+}
+
+// Helper to turn `user[:group]` into ids
+//
+// Names are looked up in /etc/passwd and /etc/group, numeric ids are used as is.
+//
+// @param owner: &str - the owner from the link options
+// @return Result<(u32, Option<u32>)> - the user id and the group id, if a group was given
+#[cfg(unix)]
+fn resolve_owner(owner: &str) -> std::io::Result<(u32, Option<u32>)> {
+    let (user, group) = match owner.split_once(':') {
+        Some((user, group)) => (user, Some(group)),
+        None => (owner, None),
+    };
+    let uid = lookup_id("/etc/passwd", user)?;
+    let gid = group
+        .map(|group| lookup_id("/etc/group", group))
+        .transpose()?;
+    Ok((uid, gid))
+}
+
+// Helper to find the id of a name in a passwd-style database
+//
+// @param database: &str - the path of the database, /etc/passwd or /etc/group
+// @param name: &str - the user or group name, or a numeric id
+// @return Result<u32> - the id, NotFound if there is no such name
+#[cfg(unix)]
+fn lookup_id(database: &str, name: &str) -> std::io::Result<u32> {
+    if let Ok(id) = name.parse() {
+        return Ok(id);
+    }
+
+    let contents = std::fs::read_to_string(database)?;
+    contents
+        .lines()
+        .map(|line| line.split(':').collect::<Vec<_>>())
+        .find(|fields| fields.first() == Some(&name))
+        .and_then(|fields| fields.get(2).and_then(|id| id.parse().ok()))
+        .ok_or_else(|| {
+            std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                format!("no {} in {}", name, database),
+            )
+        })
+}
@@ -0,0 +1,201 @@
+// The parts of the filesystem that differ between Unix and Windows
+//
+// Code that needs the difference goes through these helpers, or is kept
+// behind `cfg(unix)` where Windows has nothing like it.
+use std::ffi::{OsStr, OsString};
+use std::path::Path;
+
+// Creates a symlink at `link` pointing to `target`
+//
+// On Windows creating symlinks needs Developer Mode or an elevated shell.
+// Without it directories are linked with a junction, and files are copied.
+//
+// @param target: &Path - where the link points, relative to the directory of the link if not absolute
+// @param link: &Path - the path of the link to create
+// @return Result<()> - if the link was created
+#[cfg(unix)]
+pub fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    std::os::unix::fs::symlink(target, link)
+}
+
+#[cfg(windows)]
+pub fn symlink(target: &Path, link: &Path) -> std::io::Result<()> {
+    // Windows needs to know up front if the target is a directory
+    let resolved = crate::resolve_symlink_target(link, target);
+    let is_dir = std::fs::metadata(&resolved).is_ok_and(|meta| meta.is_dir());
+    let created = if is_dir {
+        std::os::windows::fs::symlink_dir(target, link)
+    } else {
+        std::os::windows::fs::symlink_file(target, link)
+    };
+
+    match created {
+        Err(err) if err.raw_os_error() == Some(ERROR_PRIVILEGE_NOT_HELD) => {
+            tracing::debug!(link = %link.display(), "no symlink privilege, falling back");
+            if is_dir {
+                junction(&resolved, link)
+            } else {
+                tracing::warn!(
+                    "Copying {} to {}, enable Developer Mode to link it instead",
+                    resolved.display(),
+                    link.display()
+                );
+                crate::copy_preserving(&resolved, link)
+            }
+        }
+        created => created,
+    }
+}
+
+// The error Windows returns when the user may not create symlinks
+#[cfg(windows)]
+const ERROR_PRIVILEGE_NOT_HELD: i32 = 1314;
+
+// Helper to create a directory junction, which needs no privileges
+//
+// @param target: &Path - the directory to point to, junctions can't be relative
+// @param link: &Path - the path of the junction to create
+// @return Result<()> - if the junction was created
+#[cfg(windows)]
+fn junction(target: &Path, link: &Path) -> std::io::Result<()> {
+    let target = std::path::absolute(target)?;
+    let output = std::process::Command::new("cmd")
+        .arg("/C")
+        .arg("mklink")
+        .arg("/J")
+        .arg(link)
+        .arg(&target)
+        .output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "mklink /J failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(())
+}
+
+// Helper to tell if a file can be run
+//
+// Windows has no executable bit, so nothing is executable there.
+//
+// @param meta: &std::fs::Metadata - the metadata of the file
+// @return bool - if any of the executable bits is set
+#[cfg(unix)]
+pub fn is_executable(meta: &std::fs::Metadata) -> bool {
+    std::os::unix::fs::PermissionsExt::mode(&meta.permissions()) & 0o111 != 0
+}
+
+#[cfg(windows)]
+pub fn is_executable(_meta: &std::fs::Metadata) -> bool {
+    false
+}
+
+// Helper to set the permission bits of a path
+//
+// Only the write bits mean anything on Windows, the file is made read-only
+// when the mode has no write bit for the owner.
+//
+// @param path: &Path - the path to change
+// @param mode: u32 - the permission bits, e.g. 0o755
+// @return Result<()> - if the permissions were changed
+#[cfg(unix)]
+pub fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    std::fs::set_permissions(path, std::os::unix::fs::PermissionsExt::from_mode(mode))
+}
+
+#[cfg(windows)]
+pub fn set_mode(path: &Path, mode: u32) -> std::io::Result<()> {
+    let mut permissions = std::fs::metadata(path)?.permissions();
+    permissions.set_readonly(mode & 0o200 == 0);
+    std::fs::set_permissions(path, permissions)
+}
+
+// Helper to tell when a path was last changed, its metadata included
+//
+// Moving a file counts as a change on Unix, so a backup moved into place is
+// dated by when it was made. Windows has no such time, the creation time is used.
+//
+// @param meta: &std::fs::Metadata - the metadata of the path, not followed if it's a symlink
+// @return SystemTime - when the path was changed
+#[cfg(unix)]
+pub fn changed_time(meta: &std::fs::Metadata) -> std::time::SystemTime {
+    use std::os::unix::fs::MetadataExt;
+    let secs = u64::try_from(meta.ctime()).unwrap_or(0);
+    let nanos = u32::try_from(meta.ctime_nsec()).unwrap_or(0);
+    std::time::UNIX_EPOCH + std::time::Duration::new(secs, nanos)
+}
+
+#[cfg(windows)]
+pub fn changed_time(meta: &std::fs::Metadata) -> std::time::SystemTime {
+    meta.created()
+        .or_else(|_| meta.modified())
+        .unwrap_or(std::time::UNIX_EPOCH)
+}
+
+// Helper to tell if a path is a command that can be run
+//
+// On Windows the extensions from %PATHEXT% are tried too, so `git` finds `git.exe`.
+//
+// @param path: &Path - the path to look at, without an extension on Windows
+// @return bool - if an executable file is there
+#[cfg(unix)]
+pub fn is_command(path: &Path) -> bool {
+    std::fs::metadata(path).is_ok_and(|meta| meta.is_file() && is_executable(&meta))
+}
+
+#[cfg(windows)]
+pub fn is_command(path: &Path) -> bool {
+    let extensions = std::env::var("PATHEXT").unwrap_or_else(|_| String::from(".EXE;.CMD;.BAT"));
+    std::iter::once(path.to_path_buf())
+        .chain(
+            extensions
+                .split(';')
+                .filter(|ext| !ext.is_empty())
+                .map(|ext| {
+                    let mut name = path.as_os_str().to_owned();
+                    name.push(ext);
+                    std::path::PathBuf::from(name)
+                }),
+        )
+        .any(|path| std::fs::metadata(path).is_ok_and(|meta| meta.is_file()))
+}
+
+// Helper to find the home directory of the user
+//
+// @return Option<OsString> - $HOME, or %USERPROFILE% on Windows
+pub fn home_dir() -> Option<OsString> {
+    let vars: &[&str] = if cfg!(windows) {
+        &["HOME", "USERPROFILE"]
+    } else {
+        &["HOME"]
+    };
+    vars.iter()
+        .filter_map(std::env::var_os)
+        .find(|home| !home.is_empty())
+}
+
+// Helper to tell if a path is written with a trailing separator
+//
+// @param path: &OsStr - the path as written
+// @return bool - if it ends with `/`, or `\` on Windows
+pub fn ends_with_separator(path: &OsStr) -> bool {
+    let path = path.as_encoded_bytes();
+    path.ends_with(b"/") || (cfg!(windows) && path.ends_with(b"\\"))
+}
+
+// Helper to turn bytes from `OsStr::as_encoded_bytes` back into an OS string
+//
+// Any bytes are a valid path on Unix, Windows paths have to be valid UTF-8 here.
+//
+// @param bytes: Vec<u8> - the bytes of the string
+// @return Option<OsString> - None if the bytes can't be a path on this platform
+#[cfg(unix)]
+pub fn os_string_from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    Some(std::os::unix::ffi::OsStringExt::from_vec(bytes))
+}
+
+#[cfg(windows)]
+pub fn os_string_from_bytes(bytes: Vec<u8>) -> Option<OsString> {
+    String::from_utf8(bytes).ok().map(OsString::from)
+}
@@ -0,0 +1,119 @@
+use crate::{copy_preserving, created_dirs, dirs, move_path, remove_existing};
+use std::path::{Path, PathBuf};
+use tracing::{debug, error};
+
+// What is needed to put one destination back
+struct Undo {
+    // The resolved destination
+    dest: PathBuf,
+    // A copy of what was there before, None if nothing was
+    saved: Option<PathBuf>,
+    // A backup the item made, removed again as the copy restores the destination
+    backup: Option<PathBuf>,
+}
+
+// Keeps what a sync changes, so the destinations can be put back if it fails
+//
+// Every destination is copied into the state directory before it is changed,
+// so items that replace without a backup can be undone too.
+pub struct Journal {
+    dir: PathBuf,
+    undos: Vec<Undo>,
+    // Kept when something couldn't be put back, as the copies are all that's left of it
+    keep: bool,
+}
+
+impl Journal {
+    // Starts an empty journal in `rollback/<pid>` in the state directory
+    //
+    // @return Result<Journal> - the journal, an error if the state directory isn't known
+    pub fn new() -> std::io::Result<Journal> {
+        Ok(Journal {
+            dir: dirs::journal_dir()?.join(std::process::id().to_string()),
+            undos: Vec::new(),
+            keep: false,
+        })
+    }
+
+    // Copies a destination into the journal before it is changed
+    //
+    // @param dest: &Path - the resolved destination
+    // @return Result<()> - an error if the copy couldn't be made
+    pub fn save(&mut self, dest: &Path) -> std::io::Result<()> {
+        let saved = match std::fs::symlink_metadata(dest) {
+            Ok(_) => {
+                std::fs::create_dir_all(&self.dir)?;
+                let saved = self.dir.join(self.undos.len().to_string());
+                copy_preserving(dest, &saved)?;
+                Some(saved)
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => None,
+            Err(err) => return Err(err),
+        };
+        self.undos.push(Undo {
+            dest: dest.to_path_buf(),
+            saved,
+            backup: None,
+        });
+        Ok(())
+    }
+
+    // Remembers the backup the last saved destination was moved to
+    //
+    // @param backup: &Path - the backup
+    pub fn backed_up(&mut self, backup: &Path) {
+        if let Some(undo) = self.undos.last_mut() {
+            undo.backup = Some(backup.to_path_buf());
+        }
+    }
+
+    // Puts every saved destination back, the last one changed first
+    //
+    // Backups made during the sync are removed, along with the directories
+    // made for them once empty, as the destinations are restored from the copies.
+    //
+    // @return Vec<(PathBuf, std::io::Error)> - the destinations that couldn't be put back
+    pub fn rollback(mut self) -> Vec<(PathBuf, std::io::Error)> {
+        let mut failed = Vec::new();
+        for undo in std::mem::take(&mut self.undos).into_iter().rev() {
+            debug!(dest = %undo.dest.display(), "rolling back");
+            if let Err(err) = restore(&undo) {
+                if let Some(saved) = &undo.saved {
+                    error!(
+                        "Could not put {} back, its old content is kept in {}",
+                        undo.dest.display(),
+                        saved.display()
+                    );
+                }
+                failed.push((undo.dest, err));
+            }
+        }
+        self.keep = !failed.is_empty();
+        failed
+    }
+}
+
+impl Drop for Journal {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = std::fs::remove_dir_all(&self.dir);
+            // Only removed once no other sync has a journal in it
+            if let Some(parent) = self.dir.parent() {
+                let _ = std::fs::remove_dir(parent);
+            }
+        }
+    }
+}
+
+// Helper to put a single destination back
+fn restore(undo: &Undo) -> std::io::Result<()> {
+    remove_existing(&undo.dest)?;
+    if let Some(saved) = &undo.saved {
+        move_path(saved, &undo.dest)?;
+    }
+    if let Some(backup) = &undo.backup {
+        remove_existing(backup)?;
+        created_dirs::remove_empty_parents(backup)?;
+    }
+    Ok(())
+}
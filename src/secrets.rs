@@ -0,0 +1,163 @@
+use crate::config_parser::Secrets;
+pub use crate::manifest::State;
+use crate::manifest::{Manifest, Record};
+use crate::{BackupNaming, BackupOutcome, hash, move_path, remove_existing, unique_backup_path};
+use std::ffi::{OsStr, OsString};
+use std::io::Write;
+use std::path::Path;
+use tracing::debug;
+
+// The commands used to decrypt sources
+const AGE: &str = "age";
+const GPG: &str = "gpg";
+
+// Decrypted files are only readable by their owner
+const DECRYPTED_MODE: u32 = 0o600;
+
+// What dbdm decrypted where
+const MANIFEST: Manifest = Manifest::new("encrypted.json");
+
+// Helper to decrypt a source with age or gpg, picked by its extension
+//
+// The terminal is inherited, so a passphrase can be asked for.
+//
+// @param from: &Path - the .age or .gpg file
+// @param secrets: &Secrets - the keys from the `[secrets]` section
+// @return Result<Vec<u8>> - the plaintext
+pub fn decrypt(from: &Path, secrets: &Secrets) -> std::io::Result<Vec<u8>> {
+    let extension = from.extension().and_then(|ext| ext.to_str());
+    let mut command = match extension {
+        Some("age") => {
+            let identity = secrets.identity.as_ref().ok_or_else(|| {
+                std::io::Error::new(
+                    std::io::ErrorKind::InvalidInput,
+                    "age needs an identity, set `identity = <path>` in [secrets]",
+                )
+            })?;
+            let mut command = std::process::Command::new(AGE);
+            command.arg("--decrypt").arg("--identity").arg(identity);
+            command
+        }
+        Some("gpg") => {
+            let mut command = std::process::Command::new(GPG);
+            command.args(["--decrypt", "--batch", "--quiet"]);
+            if let Some(key) = &secrets.gpg_key {
+                command.arg("--try-secret-key").arg(key);
+            }
+            command
+        }
+        _ => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("{} is not an .age or .gpg file", from.display()),
+            ));
+        }
+    };
+
+    let program = command.get_program().to_string_lossy().to_string();
+    debug!(from = %from.display(), program, "decrypting");
+    let output = command
+        .arg(from)
+        .stderr(std::process::Stdio::inherit())
+        .output()?;
+    if !output.status.success() {
+        return Err(std::io::Error::other(format!(
+            "{} exited with {}",
+            program, output.status
+        )));
+    }
+    Ok(output.stdout)
+}
+
+// Helper to compare a destination with what dbdm last decrypted into it
+//
+// @param from: &Path - the encrypted source
+// @param dest: &Path - the destination
+// @return Result<State> - where the destination stands
+pub fn state(from: &Path, dest: &Path) -> std::io::Result<State> {
+    MANIFEST.state(from, dest)
+}
+
+// Decrypts a source into its destination
+//
+// The source is decrypted before the destination is touched, so a failed
+// decryption leaves it as it was. Whatever was at the destination is replaced.
+//
+// @param from: &Path - the encrypted source
+// @param dest: &Path - the destination
+// @param secrets: &Secrets - the keys from the `[secrets]` section
+// @return Result<()> - an error if decrypting or writing failed
+pub fn install(from: &Path, dest: &Path, secrets: &Secrets) -> std::io::Result<()> {
+    let plaintext = decrypt(from, secrets)?;
+    if std::fs::symlink_metadata(dest).is_ok() {
+        remove_existing(dest)?;
+    }
+    write_decrypted(from, dest, &plaintext)
+}
+
+// Same as `install`, with whatever is at the destination backed up first
+//
+// @param from: &Path - the encrypted source
+// @param dest: &Path - the destination
+// @param backup_dir: &Path - the directory to put the backup in, created if missing
+// @param naming: &BackupNaming - how the backup is named
+// @param secrets: &Secrets - the keys from the `[secrets]` section
+// @return Result<BackupOutcome> - where the backup went
+pub fn backup_and_install(
+    from: &Path,
+    dest: &Path,
+    backup_dir: &Path,
+    naming: &BackupNaming,
+    secrets: &Secrets,
+) -> std::io::Result<BackupOutcome> {
+    let plaintext = decrypt(from, secrets)?;
+
+    std::fs::create_dir_all(backup_dir)?;
+    let base_name = dest.file_name().unwrap_or(OsStr::new("backup"));
+    let backup_path = unique_backup_path(backup_dir, base_name, naming);
+    debug!(to = %dest.display(), backup = %backup_path.display(), "backing up");
+    move_path(dest, &backup_path)?;
+
+    write_decrypted(from, dest, &plaintext)?;
+    Ok(BackupOutcome::BackedUp(backup_path))
+}
+
+// Helper to write a decrypted file and remember what was written
+//
+// The file is written next to the destination first and renamed over it, so
+// the destination is never half written or readable by others.
+//
+// @param from: &Path - the encrypted source
+// @param dest: &Path - the destination, which must not exist or be a file
+// @param plaintext: &[u8] - the decrypted content
+// @return Result<()> - an error if writing failed
+fn write_decrypted(from: &Path, dest: &Path, plaintext: &[u8]) -> std::io::Result<()> {
+    let mut name = OsString::from(".");
+    name.push(dest.file_name().unwrap_or_default());
+    name.push(".dbdm-tmp");
+    let temp = dest.with_file_name(name);
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, DECRYPTED_MODE);
+    let written = options
+        .open(&temp)
+        .and_then(|mut file| {
+            // The mode only applies to new files
+            crate::platform::set_mode(&temp, DECRYPTED_MODE)?;
+            file.write_all(plaintext)
+        })
+        .and_then(|_| std::fs::rename(&temp, dest));
+    if let Err(err) = written {
+        let _ = std::fs::remove_file(&temp);
+        return Err(err);
+    }
+
+    MANIFEST.insert(
+        dest,
+        Record {
+            source: hash(&std::fs::read(from)?),
+            written: hash(plaintext),
+        },
+    )
+}
@@ -0,0 +1,124 @@
+use std::path::{Path, PathBuf};
+
+// What the generated service runs
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServiceCommand {
+    // A single non-interactive sync, at login or from a timer or path unit
+    Sync,
+    // `dbdm watch`, kept running for the whole session
+    Watch,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct UnitFile {
+    pub name: &'static str,
+    pub contents: String,
+}
+
+// The name of the service unit, the timer and path units are named after it
+pub const SERVICE_NAME: &str = "dbdm.service";
+
+// Helper to find where systemd looks for user units
+//
+// @return Option<PathBuf> - $XDG_CONFIG_HOME/systemd/user, or ~/.config/systemd/user
+pub fn user_unit_dir() -> Option<PathBuf> {
+    let config_home = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|value| !value.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(config_home.join("systemd").join("user"))
+}
+
+// Generates the systemd user units for running dbdm in the background
+//
+// dbdm reads dbdm.conf from the current directory, so the service runs in the
+// directory of the config.
+//
+// @param binary: &Path - the dbdm binary to run
+// @param config_dir: &Path - the directory with dbdm.conf
+// @param command: ServiceCommand - what the service runs
+// @param timer: Option<&str> - a systemd time span to sync again after, sync only
+// @param on_path: bool - if a path unit should sync when dbdm.conf changes, sync only
+// @return Vec<UnitFile> - the service unit first, then the optional timer and path units
+pub fn units(
+    binary: &Path,
+    config_dir: &Path,
+    command: ServiceCommand,
+    timer: Option<&str>,
+    on_path: bool,
+) -> Vec<UnitFile> {
+    let mut service = String::from("[Unit]\n");
+    let exec = match command {
+        ServiceCommand::Sync => {
+            service.push_str("Description=Link dotfiles with dbdm\n");
+            format!(
+                "{} sync --non-interactive",
+                escape(&binary.to_string_lossy())
+            )
+        }
+        ServiceCommand::Watch => {
+            service.push_str("Description=Keep dotfiles linked with dbdm watch\n");
+            format!("{} watch", escape(&binary.to_string_lossy()))
+        }
+    };
+    service.push_str("\n[Service]\n");
+    match command {
+        ServiceCommand::Sync => service.push_str("Type=oneshot\n"),
+        ServiceCommand::Watch => service.push_str("Restart=on-failure\n"),
+    }
+    service.push_str(&format!(
+        "WorkingDirectory={}\n",
+        config_dir.to_string_lossy().replace('%', "%%")
+    ));
+    service.push_str(&format!("ExecStart={}\n", exec));
+    service.push_str("\n[Install]\nWantedBy=default.target\n");
+
+    let mut units = vec![UnitFile {
+        name: SERVICE_NAME,
+        contents: service,
+    }];
+
+    if command == ServiceCommand::Sync {
+        if let Some(span) = timer {
+            units.push(UnitFile {
+                name: "dbdm.timer",
+                contents: format!(
+                    "[Unit]\nDescription=Sync dotfiles with dbdm every {span}\n\n\
+                     [Timer]\nOnStartupSec={span}\nOnUnitActiveSec={span}\nUnit={SERVICE_NAME}\n\n\
+                     [Install]\nWantedBy=timers.target\n"
+                ),
+            });
+        }
+        if on_path {
+            units.push(UnitFile {
+                name: "dbdm.path",
+                contents: format!(
+                    "[Unit]\nDescription=Sync dotfiles with dbdm when dbdm.conf changes\n\n\
+                     [Path]\nPathChanged={}\nUnit={SERVICE_NAME}\n\n\
+                     [Install]\nWantedBy=default.target\n",
+                    config_dir
+                        .join("dbdm.conf")
+                        .to_string_lossy()
+                        .replace('%', "%%")
+                ),
+            });
+        }
+    }
+
+    units
+}
+
+// Helper to escape a single ExecStart argument
+//
+// Specifiers start with `%`, and arguments with whitespace or quotes have to be quoted.
+//
+// @param arg: &str - the argument
+// @return String - the argument as it has to be written in the unit
+fn escape(arg: &str) -> String {
+    let arg = arg.replace('%', "%%");
+    if arg.is_empty() || arg.contains(|c: char| c.is_whitespace() || c == '"' || c == '\\') {
+        format!("\"{}\"", arg.replace('\\', "\\\\").replace('"', "\\\""))
+    } else {
+        arg
+    }
+}
@@ -0,0 +1,100 @@
+use crate::config_parser::Link;
+use crate::{
+    CanonicalCache, FsSnapshot, normalize_lexically, resolve_link_destination,
+    resolve_symlink_target,
+};
+use std::path::{Path, PathBuf};
+
+// Where a link stands on disk, compared to what the config asks for
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LinkState {
+    // The destination is a symlink to the source
+    LinkedCorrectly,
+    // The destination is a symlink to something that doesn't exist
+    BrokenSymlink,
+    // The destination is a symlink to something else, canonicalized
+    WrongTarget(PathBuf),
+    // A file, or anything else that isn't a directory or a symlink, is at the destination
+    RegularFileConflict,
+    // A directory is at the destination
+    DirectoryConflict,
+    // Nothing is at the destination
+    Missing,
+    // The source doesn't exist, so there is nothing to link
+    SourceMissing,
+}
+
+impl LinkState {
+    // Helper to classify what was captured at a destination
+    //
+    // @param snapshot: &FsSnapshot - the resolved destination and what is there
+    // @param from_full: &Path - the canonicalized source
+    // @return LinkState - where the destination stands, never `SourceMissing`
+    pub fn of(snapshot: &FsSnapshot, from_full: &Path) -> LinkState {
+        let Some(meta) = snapshot.meta else {
+            return LinkState::Missing;
+        };
+
+        match (&snapshot.link_target, &snapshot.target_full) {
+            (Some(_), Some(target)) if target == from_full => LinkState::LinkedCorrectly,
+            (Some(_), Some(target)) => LinkState::WrongTarget(target.clone()),
+            (Some(_), None) => LinkState::BrokenSymlink,
+            // A symlink that couldn't be read is treated like any other file
+            (None, _) if meta.is_dir() => LinkState::DirectoryConflict,
+            (None, _) => LinkState::RegularFileConflict,
+        }
+    }
+
+    // If the destination can be replaced without losing anything
+    pub fn is_safe_to_replace(&self) -> bool {
+        matches!(
+            self,
+            LinkState::BrokenSymlink | LinkState::WrongTarget(_) | LinkState::Missing
+        )
+    }
+}
+
+// Helper to tell whether a symlink names the source itself
+//
+// A destination is linked correctly as long as its symlink ends up at the
+// source, even through other symlinks, like one to `~/.dotfiles-current/zshrc`
+// where `~/.dotfiles-current` links to the repository. This only holds for a
+// symlink whose own target is the source, compared as written.
+//
+// @param snapshot: &FsSnapshot - the resolved destination
+// @param from: &Path - the source, as written in the config
+// @return bool - true if the destination is a symlink straight to the source
+pub fn points_directly(snapshot: &FsSnapshot, from: &Path) -> bool {
+    let Some(target) = &snapshot.link_target else {
+        return false;
+    };
+    normalize_lexically(&resolve_symlink_target(&snapshot.path, target))
+        == normalize_lexically(from)
+}
+
+// Looks at where a single link stands, without changing anything
+//
+// @param link: &Link - the link to look at
+// @return LinkState - where the destination stands
+pub fn link_state(link: &Link) -> LinkState {
+    link_state_cached(link, &CanonicalCache::default())
+}
+
+// Same as `link_state`, sharing resolved directories between links
+//
+// @param link: &Link - the link to look at
+// @param cache: &CanonicalCache - shared between the links, they often have directories in common
+// @return LinkState - where the destination stands
+pub fn link_state_cached(link: &Link, cache: &CanonicalCache) -> LinkState {
+    if std::fs::metadata(&link.from).is_err() {
+        return LinkState::SourceMissing;
+    }
+
+    // A destination that can't be resolved is looked at as written in the config
+    let resolved_to =
+        resolve_link_destination(&link.from, &link.to).unwrap_or_else(|_| link.to.clone());
+    LinkState::of(
+        &FsSnapshot::capture(&resolved_to),
+        &cache.canonicalize_or_fallback(&link.from),
+    )
+}
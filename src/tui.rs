@@ -0,0 +1,411 @@
+use dbdm::BackupNaming;
+use dbdm::commands::sync::{PlanItem, SkipReason, SyncAction, summarize_dir};
+use dbdm::ignore::Ignore;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::path::Path;
+
+// Files longer than this are not diffed, only their sizes are shown
+const MAX_DIFF_LINES: usize = 2000;
+
+// A line of the plan list
+enum Row {
+    // The header of a module, selecting it acts on all of its items
+    Module(String),
+    // An item, by its index in the plan
+    Item(usize),
+}
+
+struct App<'a> {
+    plan: &'a mut [PlanItem],
+    // Modules whose items are hidden under their header
+    collapsed: Vec<String>,
+    // To leave backups out of directory summaries
+    naming: &'a BackupNaming,
+    list: ListState,
+    // Scroll offset of the side pane
+    scroll: u16,
+    // Message shown in the status line, e.g. why apply was refused
+    status: String,
+}
+
+// Runs the interactive plan editor
+//
+// Shows the plan as a list on the left and a diff of the selected item on the
+// right. Actions can be changed for any item that isn't already linked, and
+// the plan is only applied once no item is left pending. Items of a module are
+// listed under its header, which can be folded and changes all of them at once.
+//
+// @param plan: &mut [PlanItem] - the plan to edit in place
+// @param naming: &BackupNaming - how backups are named
+// @return std::io::Result<bool> - true if the plan should be applied, false to abort
+pub fn run(plan: &mut [PlanItem], naming: &BackupNaming) -> std::io::Result<bool> {
+    let mut app = App {
+        plan,
+        collapsed: Vec::new(),
+        naming,
+        list: ListState::default().with_selected(Some(0)),
+        scroll: 0,
+        status: String::from(
+            "↑/↓ move  r replace  b backup  s skip  space cycle  tab fold  PgUp/PgDn scroll  a apply  q quit",
+        ),
+    };
+
+    let mut terminal = ratatui::try_init()?;
+    let result = app.event_loop(&mut terminal);
+    ratatui::restore();
+    result
+}
+
+impl App<'_> {
+    fn event_loop(&mut self, terminal: &mut DefaultTerminal) -> std::io::Result<bool> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+                KeyCode::Up | KeyCode::Char('k') => self.select(-1),
+                KeyCode::Down | KeyCode::Char('j') => self.select(1),
+                KeyCode::PageUp => self.scroll = self.scroll.saturating_sub(10),
+                KeyCode::PageDown => self.scroll = self.scroll.saturating_add(10),
+                KeyCode::Char('r') => self.set_action(SyncAction::Replace),
+                KeyCode::Char('b') => self.set_action(SyncAction::BackupReplace),
+                KeyCode::Char('s') => self.set_action(SyncAction::Skip),
+                KeyCode::Char(' ') => self.cycle_action(),
+                KeyCode::Tab => self.toggle_fold(),
+                KeyCode::Char('a') | KeyCode::Enter => {
+                    let pending = self
+                        .plan
+                        .iter()
+                        .filter(|item| item.action == SyncAction::Pending)
+                        .count();
+                    if pending == 0 {
+                        return Ok(true);
+                    }
+                    self.status = format!("{} items are still pending a decision", pending);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Lists the items outside of modules first, then every module in the order it appears
+    fn rows(&self) -> Vec<Row> {
+        let mut rows: Vec<Row> = (0..self.plan.len())
+            .filter(|&idx| self.plan[idx].module.is_none())
+            .map(Row::Item)
+            .collect();
+        let mut modules: Vec<&String> = Vec::new();
+        for module in self.plan.iter().filter_map(|item| item.module.as_ref()) {
+            if !modules.contains(&module) {
+                modules.push(module);
+            }
+        }
+        for module in modules {
+            rows.push(Row::Module(module.clone()));
+            if !self.collapsed.contains(module) {
+                rows.extend(self.module_items(module).map(Row::Item));
+            }
+        }
+        rows
+    }
+
+    fn module_items<'b>(&'b self, module: &'b str) -> impl Iterator<Item = usize> + 'b {
+        (0..self.plan.len()).filter(move |&idx| self.plan[idx].module.as_deref() == Some(module))
+    }
+
+    fn selected(&self) -> usize {
+        self.list.selected().unwrap_or(0)
+    }
+
+    // The plan items the selected row stands for
+    fn selected_items(&self) -> Vec<usize> {
+        match self.rows().into_iter().nth(self.selected()) {
+            Some(Row::Item(idx)) => vec![idx],
+            Some(Row::Module(module)) => self.module_items(&module).collect(),
+            None => Vec::new(),
+        }
+    }
+
+    fn select(&mut self, delta: isize) {
+        let rows = self.rows().len();
+        if rows == 0 {
+            return;
+        }
+        let next = self.selected().saturating_add_signed(delta).min(rows - 1);
+        self.list.select(Some(next));
+        self.scroll = 0;
+    }
+
+    fn toggle_fold(&mut self) {
+        let module = match self.rows().into_iter().nth(self.selected()) {
+            Some(Row::Module(module)) => module,
+            Some(Row::Item(idx)) => match &self.plan[idx].module {
+                Some(module) => module.clone(),
+                None => return,
+            },
+            None => return,
+        };
+        match self.collapsed.iter().position(|folded| *folded == module) {
+            Some(pos) => {
+                self.collapsed.remove(pos);
+            }
+            None => self.collapsed.push(module.clone()),
+        }
+        // Keep the module selected, its items may be gone from under the cursor
+        let header = self
+            .rows()
+            .iter()
+            .position(|row| matches!(row, Row::Module(name) if *name == module));
+        self.list.select(header);
+    }
+
+    fn set_action(&mut self, action: SyncAction) {
+        for idx in self.selected_items() {
+            let item = &mut self.plan[idx];
+            // Already linked items have nothing to decide
+            if item.action == SyncAction::Ignore {
+                continue;
+            }
+            item.action = action;
+            item.reason = (action == SyncAction::Skip).then_some(SkipReason::UserSkipped);
+        }
+    }
+
+    fn cycle_action(&mut self) {
+        // A module cycles from the first of its items that can change
+        let Some(item) = self
+            .selected_items()
+            .into_iter()
+            .map(|idx| &self.plan[idx])
+            .find(|item| item.action != SyncAction::Ignore)
+        else {
+            return;
+        };
+        let next = match item.action {
+            SyncAction::Pending | SyncAction::Skip => SyncAction::Replace,
+            SyncAction::Create
+            | SyncAction::Replace
+            | SyncAction::Identical
+            | SyncAction::Retarget
+            | SyncAction::Merge => SyncAction::BackupReplace,
+            SyncAction::BackupReplace => SyncAction::Skip,
+            SyncAction::Ignore => return,
+        };
+        self.set_action(next);
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let [left, right] =
+            Layout::horizontal([Constraint::Percentage(45), Constraint::Percentage(55)])
+                .areas(main);
+
+        let rows = self.rows();
+        let items: Vec<ListItem> = rows
+            .iter()
+            .map(|row| match row {
+                Row::Module(module) => {
+                    let items: Vec<&PlanItem> = self
+                        .module_items(module)
+                        .map(|idx| &self.plan[idx])
+                        .collect();
+                    let pending = items
+                        .iter()
+                        .filter(|item| item.action == SyncAction::Pending)
+                        .count();
+                    let fold = if self.collapsed.contains(module) {
+                        '▸'
+                    } else {
+                        '▾'
+                    };
+                    ListItem::new(Line::styled(
+                        format!(
+                            "{} module {}, {} links, {} pending",
+                            fold,
+                            module,
+                            items.len(),
+                            pending
+                        ),
+                        Style::default().add_modifier(Modifier::BOLD),
+                    ))
+                }
+                Row::Item(idx) => {
+                    let item = &self.plan[*idx];
+                    let indent = if item.module.is_some() { "  " } else { "" };
+                    ListItem::new(Line::from(vec![
+                        Span::raw(indent),
+                        Span::styled(
+                            format!("{:<15}", item.action.label()),
+                            Style::default().fg(action_color(item.action)),
+                        ),
+                        Span::raw(item.to.display().to_string()),
+                    ]))
+                }
+            })
+            .collect();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(" Plan "))
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, left, &mut self.list);
+
+        let lines = match rows.get(self.selected()) {
+            Some(Row::Item(idx)) => preview_lines(&self.plan[*idx], self.naming),
+            Some(Row::Module(module)) => self
+                .module_items(module)
+                .map(|idx| {
+                    let item = &self.plan[idx];
+                    Line::from(vec![
+                        Span::styled(
+                            format!("{:<15}", item.action.label()),
+                            Style::default().fg(action_color(item.action)),
+                        ),
+                        Span::raw(item.to.display().to_string()),
+                    ])
+                })
+                .collect(),
+            None => vec![Line::raw("Nothing to do")],
+        };
+        let preview = Paragraph::new(lines)
+            .block(Block::default().borders(Borders::ALL).title(" Diff "))
+            .wrap(Wrap { trim: false })
+            .scroll((self.scroll, 0));
+        frame.render_widget(preview, right);
+
+        frame.render_widget(Paragraph::new(self.status.as_str()), status);
+    }
+}
+
+fn action_color(action: SyncAction) -> Color {
+    match action {
+        SyncAction::Ignore => Color::Green,
+        SyncAction::Create => Color::Green,
+        SyncAction::Replace => Color::Yellow,
+        SyncAction::Identical => Color::Green,
+        SyncAction::Retarget => Color::Yellow,
+        SyncAction::BackupReplace => Color::Blue,
+        SyncAction::Merge => Color::Yellow,
+        SyncAction::Skip => Color::DarkGray,
+        SyncAction::Pending => Color::Red,
+    }
+}
+
+// Helper to build the side pane content for a plan item
+//
+// Text files get a line diff from the destination to the source, everything
+// else gets a short description of what is at the destination.
+//
+// @param item: &PlanItem - the selected plan item
+// @param naming: &BackupNaming - how backups are named
+// @return Vec<Line> - the lines to show
+fn preview_lines(item: &PlanItem, naming: &BackupNaming) -> Vec<Line<'static>> {
+    let mut lines = vec![
+        Line::raw(format!("from: {}", item.from.display())),
+        Line::raw(format!("to:   {}", item.to.display())),
+    ];
+    if let Some(reason) = &item.reason {
+        lines.push(Line::raw(format!("reason: {}", reason)));
+    }
+    lines.push(Line::raw(""));
+
+    let Ok(meta) = std::fs::symlink_metadata(&item.to) else {
+        lines.push(Line::raw("Nothing exists at the destination yet"));
+        return lines;
+    };
+
+    if meta.file_type().is_symlink() {
+        let target = std::fs::read_link(&item.to).unwrap_or_default();
+        lines.push(Line::raw(format!("Symlink to {}", target.display())));
+    } else if meta.is_dir() {
+        match summarize_dir(&item.to, naming, &Ignore::load(&item.from)) {
+            Ok(summary) => lines.push(Line::raw(format!("Directory: {}", summary.describe()))),
+            Err(err) => lines.push(Line::raw(format!("Directory: {}", err))),
+        }
+    } else {
+        lines.extend(file_diff(&item.to, &item.from));
+    }
+
+    lines
+}
+
+// Helper to diff two text files line by line
+//
+// @param old: &Path - the file currently at the destination
+// @param new: &Path - the source file that will replace it
+// @return Vec<Line> - the diff, `-` for removed and `+` for added lines
+fn file_diff(old: &Path, new: &Path) -> Vec<Line<'static>> {
+    let (Ok(old_text), Ok(new_text)) = (std::fs::read_to_string(old), std::fs::read_to_string(new))
+    else {
+        return vec![Line::raw("Binary or unreadable file, no diff available")];
+    };
+
+    let old_lines: Vec<&str> = old_text.lines().collect();
+    let new_lines: Vec<&str> = new_text.lines().collect();
+    if old_lines.len() > MAX_DIFF_LINES || new_lines.len() > MAX_DIFF_LINES {
+        return vec![Line::raw(format!(
+            "Files too long to diff ({} vs {} lines)",
+            old_lines.len(),
+            new_lines.len()
+        ))];
+    }
+
+    diff_lines(&old_lines, &new_lines)
+        .into_iter()
+        .map(|(sign, text)| {
+            let style = match sign {
+                '-' => Style::default().fg(Color::Red),
+                '+' => Style::default().fg(Color::Green),
+                _ => Style::default(),
+            };
+            Line::styled(format!("{} {}", sign, text), style)
+        })
+        .collect()
+}
+
+// Helper to compute a line diff using the longest common subsequence
+//
+// @param old: &[&str] - the old lines
+// @param new: &[&str] - the new lines
+// @return Vec<(char, String)> - each line with ` `, `-` or `+`
+fn diff_lines(old: &[&str], new: &[&str]) -> Vec<(char, String)> {
+    // lcs[i][j] is the common subsequence length of old[i..] and new[j..]
+    let mut lcs = vec![vec![0usize; new.len() + 1]; old.len() + 1];
+    for i in (0..old.len()).rev() {
+        for j in (0..new.len()).rev() {
+            lcs[i][j] = if old[i] == new[j] {
+                lcs[i + 1][j + 1] + 1
+            } else {
+                lcs[i + 1][j].max(lcs[i][j + 1])
+            };
+        }
+    }
+
+    let mut result = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < old.len() || j < new.len() {
+        if i < old.len() && j < new.len() && old[i] == new[j] {
+            result.push((' ', old[i].to_string()));
+            i += 1;
+            j += 1;
+        } else if j < new.len() && (i == old.len() || lcs[i][j + 1] >= lcs[i + 1][j]) {
+            result.push(('+', new[j].to_string()));
+            j += 1;
+        } else {
+            result.push(('-', old[i].to_string()));
+            i += 1;
+        }
+    }
+    result
+}
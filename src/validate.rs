@@ -0,0 +1,259 @@
+use crate::canonicalize_or_fallback;
+use crate::commands::sync::link_order;
+use crate::config_parser::{Entry, Link, LinkKind, parse_config_with};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IssueKind {
+    Parse,
+    Duplicate,
+    Cycle,
+    Unreachable,
+    // A source is decrypted by one line and linked as is by another
+    MixedKinds,
+    // The links can't be put in an order to apply them in
+    Order,
+}
+
+impl IssueKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            IssueKind::Parse => "parse",
+            IssueKind::Duplicate => "duplicate",
+            IssueKind::Cycle => "cycle",
+            IssueKind::Unreachable => "unreachable",
+            IssueKind::MixedKinds => "mixed-kinds",
+            IssueKind::Order => "order",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Issue {
+    pub file: PathBuf,
+    pub line: usize,
+    pub kind: IssueKind,
+    pub message: String,
+}
+
+// Runs the full parse plus the semantic checks over a config file
+//
+// Unlike `read_config`, this doesn't stop at the first problem, so every
+// issue in the file is reported in one go.
+//
+// @param path: &Path - the path to the config file
+// @param strict: bool - if unknown kinds, options, keywords and sections are issues, whatever the config says
+// @return Result<Vec<Issue>, String> - all found issues ordered by line, or an error if the file can't be read
+pub fn validate(path: &Path, strict: bool) -> Result<Vec<Issue>, String> {
+    let parsed = parse_config_with(path, strict)?;
+
+    let mut issues: Vec<Issue> = parsed
+        .errors
+        .into_iter()
+        .map(|err| Issue {
+            file: err.file,
+            line: err.line,
+            kind: IssueKind::Parse,
+            message: err.message,
+        })
+        .collect();
+
+    issues.extend(check_duplicates(&parsed.entries));
+    issues.extend(check_cycles(&parsed.entries));
+    issues.extend(check_unreachable(&parsed.entries));
+    issues.extend(check_mixed_kinds(&parsed.entries));
+    issues.extend(check_order(&parsed.entries));
+    issues.sort_by(|a, b| (&a.file, a.line).cmp(&(&b.file, b.line)));
+
+    Ok(issues)
+}
+
+// Helper to find links that share the same destination
+//
+// @param entries: &[Entry] - the parsed config entries
+// @return Vec<Issue> - an issue for every repeated destination
+fn check_duplicates(entries: &[Entry]) -> Vec<Issue> {
+    let mut seen: HashMap<PathBuf, &Entry> = HashMap::new();
+    let mut issues = Vec::new();
+
+    for entry in entries {
+        let to = entry.link.destination();
+        match seen.get(&to) {
+            Some(first) => issues.push(Issue {
+                file: entry.file.clone(),
+                line: entry.line,
+                kind: IssueKind::Duplicate,
+                message: format!(
+                    "Destination {} on line {} is already declared at {}:{}",
+                    entry.link.to.display(),
+                    entry.line,
+                    first.file.display(),
+                    first.line
+                ),
+            }),
+            None => {
+                seen.insert(to, entry);
+            }
+        }
+    }
+
+    issues
+}
+
+// Helper to find links whose destinations lead back to their own source
+//
+// A link is followed into the next one when its destination is the source of
+// another link, so `a -> b` and `b -> a` form a cycle.
+//
+// @param entries: &[Entry] - the parsed config entries
+// @return Vec<Issue> - an issue for every link that is part of a cycle
+fn check_cycles(entries: &[Entry]) -> Vec<Issue> {
+    let sources: HashMap<PathBuf, usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(idx, entry)| (canonicalize_or_fallback(&entry.link.from), idx))
+        .collect();
+
+    let mut issues = Vec::new();
+    for (start, entry) in entries.iter().enumerate() {
+        let mut current = start;
+        let mut chain = vec![entry];
+
+        // A chain can't be longer than the amount of links without repeating
+        for _ in 0..entries.len() {
+            let to = canonicalize_or_fallback(&entries[current].link.to);
+            let Some(&next) = sources.get(&to) else {
+                break;
+            };
+
+            if next == start {
+                let lines: Vec<String> = chain
+                    .iter()
+                    .map(|entry| format!("{}:{}", entry.file.display(), entry.line))
+                    .collect();
+                issues.push(Issue {
+                    file: entry.file.clone(),
+                    line: entry.line,
+                    kind: IssueKind::Cycle,
+                    message: format!(
+                        "Link on line {} is part of a cycle through {}",
+                        entry.line,
+                        lines.join(" -> ")
+                    ),
+                });
+                break;
+            }
+
+            current = next;
+            chain.push(&entries[next]);
+        }
+    }
+
+    issues
+}
+
+// Helper to find destinations that can't be created
+//
+// The parser already makes sure the parent exists, this catches parents that
+// are not directories or that are read-only.
+//
+// @param entries: &[Entry] - the parsed config entries
+// @return Vec<Issue> - an issue for every unreachable destination
+fn check_unreachable(entries: &[Entry]) -> Vec<Issue> {
+    let mut issues = Vec::new();
+
+    for entry in entries {
+        let Some(parent) = entry.link.to.parent() else {
+            continue;
+        };
+        let Ok(meta) = std::fs::metadata(parent) else {
+            continue;
+        };
+
+        let problem = if !meta.is_dir() {
+            "is not a directory"
+        } else if meta.permissions().readonly() {
+            "is read-only"
+        } else {
+            continue;
+        };
+
+        issues.push(Issue {
+            file: entry.file.clone(),
+            line: entry.line,
+            kind: IssueKind::Unreachable,
+            message: format!(
+                "Destination on line {} is unreachable: {} {}",
+                entry.line,
+                parent.display(),
+                problem
+            ),
+        });
+    }
+
+    issues
+}
+
+// Helper to find sources used both by `encrypted` lines and by plain links
+//
+// Linking one source to several destinations is fine, even with `link` and
+// `system` lines mixed. But an `encrypted` source is decrypted, so a plain
+// link of it would put the encrypted file at its destination.
+//
+// @param entries: &[Entry] - the parsed config entries
+// @return Vec<Issue> - an issue for every line that mixes kinds with an earlier one
+fn check_mixed_kinds(entries: &[Entry]) -> Vec<Issue> {
+    let mut seen: HashMap<PathBuf, &Entry> = HashMap::new();
+    let mut issues = Vec::new();
+
+    for entry in entries {
+        let from = canonicalize_or_fallback(&entry.link.from);
+        let encrypted = entry.link.kind == LinkKind::Encrypted;
+        match seen.get(&from) {
+            Some(first) if (first.link.kind == LinkKind::Encrypted) != encrypted => {
+                issues.push(Issue {
+                    file: entry.file.clone(),
+                    line: entry.line,
+                    kind: IssueKind::MixedKinds,
+                    message: format!(
+                        "Source {} on line {} is an `{}` line, but `{}` at {}:{}",
+                        entry.link.from.display(),
+                        entry.line,
+                        entry.link.kind.as_str(),
+                        first.link.kind.as_str(),
+                        first.file.display(),
+                        first.line
+                    ),
+                })
+            }
+            Some(_) => {}
+            None => {
+                seen.insert(from, entry);
+            }
+        }
+    }
+
+    issues
+}
+
+// Helper to find `after=` options sync can't follow
+//
+// @param entries: &[Entry] - the parsed config entries
+// @return Vec<Issue> - an issue for the first unknown destination or cycle found
+fn check_order(entries: &[Entry]) -> Vec<Issue> {
+    let links: Vec<&Link> = entries.iter().map(|entry| &entry.link).collect();
+    let Err(err) = link_order(&links) else {
+        return Vec::new();
+    };
+    let entry = &entries[err.link];
+    vec![Issue {
+        file: entry.file.clone(),
+        line: entry.line,
+        kind: IssueKind::Order,
+        message: format!(
+            "Link on line {} can't be ordered: {}",
+            entry.line, err.message
+        ),
+    }]
+}
@@ -0,0 +1,145 @@
+use crate::ignore::Ignore;
+use crate::{
+    canonicalize_or_fallback, resolve_link_destination, resolve_symlink_target, same_content,
+};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, PartialEq)]
+pub struct Problem {
+    pub path: PathBuf,
+    pub message: String,
+}
+
+// Verifies a single link all the way down
+//
+// `check` only looks at the top-level symlink. This also walks the source and
+// the destination, through the symlink, and makes sure every entry resolves
+// to the same file. A destination that is a real file or directory, like a
+// copy, has to have the same content as the source instead. Whatever the
+// source's `.dbdmignore` leaves out isn't looked at.
+//
+// @param from: &Path - the source of the link
+// @param to: &Path - the destination of the link from the config
+// @return Vec<Problem> - everything that doesn't match, empty if the link is fine
+pub fn verify_link(from: &Path, to: &Path) -> Vec<Problem> {
+    let dest = match resolve_link_destination(from, to) {
+        Ok(path) => path,
+        Err(err) => return vec![problem(to, err.to_string())],
+    };
+
+    let meta = match std::fs::symlink_metadata(&dest) {
+        Ok(meta) => meta,
+        Err(_) => return vec![problem(&dest, "missing")],
+    };
+
+    if !meta.file_type().is_symlink() {
+        return match same_content(from, &dest) {
+            Ok(true) => Vec::new(),
+            Ok(false) => vec![problem(&dest, "not a link and differs from the source")],
+            Err(err) => vec![problem(&dest, err.to_string())],
+        };
+    }
+
+    let target = match std::fs::read_link(&dest) {
+        Ok(target) => resolve_symlink_target(&dest, &target),
+        Err(err) => return vec![problem(&dest, err.to_string())],
+    };
+    if !target.exists() {
+        return vec![problem(
+            &dest,
+            format!("broken link to {}", target.display()),
+        )];
+    }
+    if canonicalize_or_fallback(&target) != canonicalize_or_fallback(from) {
+        return vec![problem(&dest, format!("links to {}", target.display()))];
+    }
+
+    let mut problems = Vec::new();
+    if from.is_dir() {
+        let ignore = Ignore::load(from);
+        compare_trees(from, &dest, Path::new(""), &ignore, &mut problems);
+    }
+    problems
+}
+
+// Helper to walk a source tree and its linked destination side by side
+//
+// @param source: &Path - the directory inside the source
+// @param dest: &Path - the same directory reached through the destination
+// @param rel: &Path - the directory, relative to the top of the source
+// @param ignore: &Ignore - what the source's `.dbdmignore` leaves out
+// @param problems: &mut Vec<Problem> - where mismatches are collected
+fn compare_trees(
+    source: &Path,
+    dest: &Path,
+    rel: &Path,
+    ignore: &Ignore,
+    problems: &mut Vec<Problem>,
+) {
+    let names = |path: &Path| -> std::io::Result<Vec<std::ffi::OsString>> {
+        let mut names = std::fs::read_dir(path)?
+            .filter(|entry| {
+                entry.as_ref().map_or(true, |entry| {
+                    let is_dir = entry.file_type().is_ok_and(|kind| kind.is_dir());
+                    !ignore.is_ignored(&rel.join(entry.file_name()), is_dir)
+                })
+            })
+            .map(|entry| entry.map(|entry| entry.file_name()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        names.sort();
+        Ok(names)
+    };
+
+    let (source_names, dest_names) = match (names(source), names(dest)) {
+        (Ok(source_names), Ok(dest_names)) => (source_names, dest_names),
+        (Err(err), _) | (_, Err(err)) => {
+            problems.push(problem(dest, format!("can't be read: {}", err)));
+            return;
+        }
+    };
+
+    for name in &source_names {
+        if !dest_names.contains(name) {
+            problems.push(problem(&dest.join(name), "missing from the destination"));
+        }
+    }
+    for name in &dest_names {
+        if !source_names.contains(name) {
+            problems.push(problem(&dest.join(name), "not in the source"));
+        }
+    }
+
+    for name in source_names.iter().filter(|name| dest_names.contains(name)) {
+        let source_entry = source.join(name);
+        let dest_entry = dest.join(name);
+
+        let Ok(meta) = std::fs::symlink_metadata(&source_entry) else {
+            problems.push(problem(&dest_entry, "can't be read"));
+            continue;
+        };
+        if meta.file_type().is_symlink() && !source_entry.exists() {
+            problems.push(problem(&dest_entry, "broken link inside the source"));
+            continue;
+        }
+        if canonicalize_or_fallback(&source_entry) != canonicalize_or_fallback(&dest_entry) {
+            problems.push(problem(&dest_entry, "resolves to a different file"));
+            continue;
+        }
+        if meta.is_dir() {
+            compare_trees(
+                &source_entry,
+                &dest_entry,
+                &rel.join(name),
+                ignore,
+                problems,
+            );
+        }
+    }
+}
+
+fn problem(path: &Path, message: impl Into<String>) -> Problem {
+    Problem {
+        path: path.to_path_buf(),
+        message: message.into(),
+    }
+}
@@ -0,0 +1,215 @@
+use dbdm::config_parser::{Config, Link, read_config_with};
+use dbdm::resolve_link_destination;
+use notify::event::ModifyKind;
+use notify::{EventKind, RecursiveMode, Watcher};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc;
+use std::time::Duration;
+use tracing::{debug, error, info, warn};
+
+// How long to wait for more changes before syncing, so a burst of saves syncs once
+const DEBOUNCE: Duration = Duration::from_millis(500);
+
+// Runs a sync every time the config or one of the link sources changes
+//
+// The config is read again before every sync, so new links are picked up.
+// The watches are set up after each sync, so the changes the sync makes
+// itself don't trigger another one.
+//
+// @param config_path: &Path - the path to dbdm.conf
+// @param strict: bool - if the config is read in strict mode
+// @param on_change: impl FnMut(&Config) - the sync to run
+// @return notify::Result<()> - an error if watching isn't possible
+pub fn run(
+    config_path: &Path,
+    strict: bool,
+    mut on_change: impl FnMut(&Config),
+) -> notify::Result<()> {
+    loop {
+        let config = match read_config_with(&config_path.to_path_buf(), strict) {
+            Ok(config) => {
+                on_change(&config);
+                Some(config)
+            }
+            Err(err) => {
+                error!("Error in config, waiting for it to be fixed:\n\n{}", err);
+                None
+            }
+        };
+
+        let (tx, rx) = mpsc::channel();
+        let mut watcher = notify::recommended_watcher(tx)?;
+        for (path, mode) in watched_paths(config_path, config.as_ref()) {
+            if let Err(err) = watcher.watch(&path, mode) {
+                warn!("Can't watch {}: {}", path.display(), err);
+            }
+        }
+        info!("Watching for changes, press Ctrl+C to stop");
+
+        // Wait for a change that could matter
+        loop {
+            match rx.recv() {
+                Ok(Ok(event))
+                    if is_change(&event.kind)
+                        && event
+                            .paths
+                            .iter()
+                            .any(|path| is_relevant(path, config.as_ref())) =>
+                {
+                    debug!(paths = ?event.paths, "change detected");
+                    break;
+                }
+                Ok(Ok(_)) => {}
+                Ok(Err(err)) => warn!("Watch error: {}", err),
+                // The watcher is gone, nothing will come anymore
+                Err(_) => return Ok(()),
+            }
+        }
+        while rx.recv_timeout(DEBOUNCE).is_ok() {}
+    }
+}
+
+// What another program did to a destination
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Change {
+    // Written to in place
+    Modified,
+    // Something else was put there, like a file renamed over a symlink
+    Replaced,
+    Deleted,
+}
+
+impl Change {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Change::Modified => "modified",
+            Change::Replaced => "replaced",
+            Change::Deleted => "deleted",
+        }
+    }
+}
+
+// Reports every change made to the destinations of the config, until stopped
+//
+// The directories the destinations are in are watched instead of the
+// destinations, so a symlink that is replaced or removed is seen too, not only
+// writes through it.
+//
+// @param config: &Config - the parsed config state
+// @param on_change: impl FnMut(&Link, Change) - called with the link whose destination changed
+// @return notify::Result<()> - an error if watching isn't possible
+pub fn watch_destinations(
+    config: &Config,
+    mut on_change: impl FnMut(&Link, Change),
+) -> notify::Result<()> {
+    let destinations: Vec<(PathBuf, &Link)> = config
+        .links
+        .iter()
+        .map(|link| {
+            let dest =
+                resolve_link_destination(&link.from, &link.to).unwrap_or_else(|_| link.to.clone());
+            (dest, link)
+        })
+        .collect();
+
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    let mut dirs: Vec<&Path> = destinations
+        .iter()
+        .filter_map(|(dest, _)| dest.parent())
+        .collect();
+    dirs.sort();
+    dirs.dedup();
+    for dir in dirs {
+        if let Err(err) = watcher.watch(dir, RecursiveMode::NonRecursive) {
+            warn!("Can't watch {}: {}", dir.display(), err);
+        }
+    }
+    info!(
+        "Watching {} destinations for changes, press Ctrl+C to stop",
+        destinations.len()
+    );
+
+    loop {
+        match rx.recv() {
+            Ok(Ok(event)) if is_change(&event.kind) => {
+                for path in &event.paths {
+                    for (dest, link) in destinations.iter().filter(|(dest, _)| dest == path) {
+                        on_change(link, classify(dest, &event.kind));
+                    }
+                }
+            }
+            Ok(Ok(_)) => {}
+            Ok(Err(err)) => warn!("Watch error: {}", err),
+            // The watcher is gone, nothing will come anymore
+            Err(_) => return Ok(()),
+        }
+    }
+}
+
+// Helper to tell what happened to a destination from an event about it
+//
+// @param dest: &Path - the destination
+// @param kind: &EventKind - the kind of the event
+// @return Change - what happened, by what is there now
+fn classify(dest: &Path, kind: &EventKind) -> Change {
+    if std::fs::symlink_metadata(dest).is_err() {
+        return Change::Deleted;
+    }
+    match kind {
+        EventKind::Modify(ModifyKind::Data(_) | ModifyKind::Metadata(_) | ModifyKind::Any) => {
+            Change::Modified
+        }
+        _ => Change::Replaced,
+    }
+}
+
+// Helper to list everything that is watched
+//
+// @param config_path: &Path - the path to dbdm.conf
+// @param config: Option<&Config> - the parsed config, None if it has errors
+// @return Vec<(PathBuf, RecursiveMode)> - the paths and how deep to watch them
+fn watched_paths(config_path: &Path, config: Option<&Config>) -> Vec<(PathBuf, RecursiveMode)> {
+    // The directory is watched, so local overrides and includes next to it count too
+    let config_dir = config_path
+        .parent()
+        .map(Path::to_path_buf)
+        .unwrap_or_else(|| PathBuf::from("."));
+    let mut paths = vec![(config_dir, RecursiveMode::NonRecursive)];
+
+    for link in config
+        .map(|config| config.links.as_slice())
+        .unwrap_or_default()
+    {
+        let mode = if link.from.is_dir() {
+            RecursiveMode::Recursive
+        } else {
+            RecursiveMode::NonRecursive
+        };
+        paths.push((link.from.clone(), mode));
+    }
+    paths
+}
+
+// Helper to ignore changes to unrelated files next to the config
+//
+// The config directory is watched for config files only, like dbdm.conf,
+// dbdm.local.conf and included files. Anything else has to be in a source.
+//
+// @param path: &Path - the changed path
+// @param config: Option<&Config> - the parsed config, None if it has errors
+// @return bool - if the change should trigger a sync
+fn is_relevant(path: &Path, config: Option<&Config>) -> bool {
+    if path.extension().is_some_and(|ext| ext == "conf") {
+        return true;
+    }
+    config.is_some_and(|config| config.links.iter().any(|link| path.starts_with(&link.from)))
+}
+
+// Helper to tell changes apart from reads
+fn is_change(kind: &EventKind) -> bool {
+    matches!(
+        kind,
+        EventKind::Create(_) | EventKind::Modify(_) | EventKind::Remove(_)
+    )
+}
@@ -1,7 +1,7 @@
-use dbdm::backup_and_replace;
+use dbdm::{BackupNaming, BackupOutcome, backup_and_replace};
 
 #[test]
-fn backs_up_directory_target_into_source_dir() {
+fn backs_up_directory_target_next_to_source_dir() {
     let temp = tempfile::tempdir().expect("tempdir should be created");
     let from_dir = temp.path().join("dotfiles/nvim");
     let to_dir = temp.path().join("config/nvim");
@@ -12,9 +12,10 @@ fn backs_up_directory_target_into_source_dir() {
     let to_file = to_dir.join("init.lua");
     std::fs::write(&to_file, "old config").expect("write should succeed");
 
-    backup_and_replace(&from_dir, &to_dir).expect("backup should succeed");
+    backup_and_replace(&from_dir, &to_dir, &BackupNaming::default())
+        .expect("backup should succeed");
 
-    let backup_path = from_dir.join("nvim.bak.dbdm");
+    let backup_path = temp.path().join("dotfiles/nvim.bak.dbdm");
     let backup_file = backup_path.join("init.lua");
     let backup_contents = std::fs::read_to_string(&backup_file).expect("backup should exist");
     assert_eq!(backup_contents, "old config");
@@ -38,7 +39,8 @@ fn backs_up_file_target_into_source_parent() {
     std::fs::write(&from_file, "source").expect("write should succeed");
     std::fs::write(&to_file, "old").expect("write should succeed");
 
-    backup_and_replace(&from_file, &to_file).expect("backup should succeed");
+    backup_and_replace(&from_file, &to_file, &BackupNaming::default())
+        .expect("backup should succeed");
 
     let backup_path = from_file
         .parent()
@@ -53,3 +55,23 @@ fn backs_up_file_target_into_source_parent() {
     let target = std::fs::read_link(&to_file).expect("read_link should succeed");
     assert_eq!(target, from_file);
 }
+
+#[test]
+fn replaces_identical_target_without_backup() {
+    let temp = tempfile::tempdir().expect("tempdir should be created");
+    let from_dir = temp.path().join("dotfiles/nvim");
+    let to_dir = temp.path().join("config/nvim");
+
+    std::fs::create_dir_all(&from_dir).expect("from dir should be created");
+    std::fs::create_dir_all(&to_dir).expect("to dir should be created");
+    std::fs::write(from_dir.join("init.lua"), "same").expect("write should succeed");
+    std::fs::write(to_dir.join("init.lua"), "same").expect("write should succeed");
+
+    let outcome = backup_and_replace(&from_dir, &to_dir, &BackupNaming::default())
+        .expect("replace should succeed");
+    assert_eq!(outcome, BackupOutcome::Identical);
+    assert!(!temp.path().join("dotfiles/nvim.bak.dbdm").exists());
+
+    let target = std::fs::read_link(&to_dir).expect("read_link should succeed");
+    assert_eq!(target, from_dir);
+}
@@ -0,0 +1,110 @@
+use dbdm::BackupNaming;
+use dbdm::backups::{self, Record};
+use dbdm::commands::clean::backups_of;
+use std::fs;
+use std::path::PathBuf;
+use tempfile::tempdir;
+
+#[test]
+fn backups_are_recorded_and_forgotten() {
+    let workspace = tempdir().expect("create temp workspace");
+    let record = |name: &str, run| Record {
+        original: PathBuf::from("/home/user/.zshrc"),
+        backup: workspace.path().join(name),
+        time: 1_700_000_000,
+        run,
+    };
+
+    temp_env::with_var("XDG_STATE_HOME", Some(workspace.path()), || {
+        assert!(backups::load().expect("load empty registry").is_empty());
+
+        let made = vec![record("zshrc.bak.dbdm", Some(1)), record("old-zshrc", None)];
+        backups::record(&made).expect("record backups");
+        assert_eq!(backups::load().expect("load registry"), made);
+
+        backups::forget(&[workspace.path().join("zshrc.bak.dbdm")]).expect("forget backup");
+        assert_eq!(
+            backups::load().expect("load registry"),
+            vec![record("old-zshrc", None)]
+        );
+    });
+}
+
+#[test]
+fn relocated_backups_are_found_through_the_registry() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source = workspace.path().join("dotfiles/zshrc");
+    fs::create_dir_all(source.parent().expect("source parent")).expect("create dotfiles");
+    fs::write(&source, "# zsh").expect("write source");
+    let dest = workspace.path().join(".zshrc");
+
+    let elsewhere = workspace.path().join("archive/zshrc-before-dbdm");
+    fs::create_dir_all(elsewhere.parent().expect("archive parent")).expect("create archive");
+    fs::write(&elsewhere, "# old zsh").expect("write relocated backup");
+    let registry = vec![
+        Record {
+            original: dest.clone(),
+            backup: elsewhere.clone(),
+            time: 1_700_000_000,
+            run: Some(1),
+        },
+        Record {
+            original: dest.clone(),
+            backup: workspace.path().join("archive/removed"),
+            time: 1_700_000_100,
+            run: Some(2),
+        },
+    ];
+
+    let found: Vec<PathBuf> = backups_of(&source, &dest, &registry, &BackupNaming::default())
+        .expect("find backups")
+        .into_iter()
+        .map(|backup| backup.path)
+        .collect();
+    assert_eq!(found, vec![elsewhere]);
+}
+
+#[test]
+fn sync_backups_are_shown_in_history() {
+    let workspace = tempdir().expect("create temp workspace");
+    let state_home = workspace.path().join("state");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let dest_file = workspace.path().join("linked.txt");
+    fs::write(&dest_file, "existing").expect("write dest file");
+
+    fs::write(
+        workspace.path().join("dbdm.conf"),
+        format!("link = {} {}\n", source_file.display(), dest_file.display()),
+    )
+    .expect("write config");
+
+    let run = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+            .args(args)
+            .env("XDG_STATE_HOME", &state_home)
+            .env("NO_COLOR", "1")
+            .current_dir(workspace.path())
+            .stdin(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .output()
+            .expect("run dbdm")
+    };
+
+    let synced = run(&["sync", "--non-interactive", "--on-conflict", "backup"]);
+    assert!(synced.status.success());
+
+    let registry = temp_env::with_var("XDG_STATE_HOME", Some(&state_home), backups::load)
+        .expect("load registry");
+    assert_eq!(registry.len(), 1);
+    assert_eq!(registry[0].original, dest_file);
+    assert_eq!(registry[0].run, Some(1));
+    assert!(registry[0].backup.exists());
+
+    let show = run(&["history", "show", "1"]);
+    assert!(show.status.success());
+    let show = String::from_utf8(show.stdout).expect("utf8 stdout");
+    assert!(show.contains("BACKUP"));
+    assert!(show.contains(&registry[0].backup.display().to_string()));
+}
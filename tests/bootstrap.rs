@@ -0,0 +1,83 @@
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = std::process::Command::new("git")
+        .args(["-c", "user.name=dbdm", "-c", "user.email=dbdm@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .expect("run git");
+    assert!(status.success());
+}
+
+#[test]
+fn bootstrap_clones_and_syncs() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    // The dotfiles repository, with the config one directory down
+    let origin = workspace.path().join("origin");
+    fs::create_dir_all(origin.join("dots")).expect("create origin");
+    let home = workspace.path().join("home");
+    fs::create_dir(&home).expect("create home");
+    fs::write(origin.join("dots/vimrc"), "set number").expect("write source");
+    fs::write(
+        origin.join("dots/dbdm.conf"),
+        format!("link = !here/vimrc {}/.vimrc\n", home.display()),
+    )
+    .expect("write config");
+    git(&origin, &["init", "-q"]);
+    git(&origin, &["add", "."]);
+    git(&origin, &["commit", "-q", "-m", "dotfiles"]);
+
+    let clone = workspace.path().join("clone");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("bootstrap")
+        .arg(&origin)
+        .arg(&clone)
+        .arg("--non-interactive")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .expect("run dbdm bootstrap");
+    assert!(status.success());
+
+    let target = fs::read_link(home.join(".vimrc")).expect("read link");
+    assert_eq!(
+        fs::canonicalize(target).expect("canonicalize target"),
+        fs::canonicalize(clone.join("dots/vimrc")).expect("canonicalize source")
+    );
+}
+
+#[test]
+fn bootstrap_fails_without_a_config() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let origin = workspace.path().join("origin");
+    fs::create_dir(&origin).expect("create origin");
+    fs::write(origin.join("README"), "no config").expect("write readme");
+    git(&origin, &["init", "-q"]);
+    git(&origin, &["add", "."]);
+    git(&origin, &["commit", "-q", "-m", "dotfiles"]);
+
+    // Without a destination, the clone is named after the repository
+    let machine = workspace.path().join("machine");
+    fs::create_dir(&machine).expect("create machine dir");
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("bootstrap")
+        .arg(&origin)
+        .arg("--non-interactive")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(&machine)
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .expect("run dbdm bootstrap");
+    assert!(!status.success());
+    assert!(machine.join("origin/README").exists());
+}
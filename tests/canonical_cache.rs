@@ -0,0 +1,98 @@
+use dbdm::{CanonicalCache, canonicalize_or_fallback, normalize_lexically, parallel_map};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+#[test]
+fn cached_paths_match_canonicalize() {
+    let workspace = tempdir().expect("create temp workspace");
+    let real_dir = workspace.path().join("real");
+    fs::create_dir(&real_dir).expect("create real dir");
+    fs::write(real_dir.join("a.txt"), "a").expect("write a");
+    fs::write(real_dir.join("b.txt"), "b").expect("write b");
+    std::os::unix::fs::symlink(&real_dir, workspace.path().join("alias")).expect("link dir");
+    std::os::unix::fs::symlink(real_dir.join("a.txt"), real_dir.join("to_a")).expect("link file");
+
+    let cache = CanonicalCache::default();
+    for path in [
+        workspace.path().join("alias/a.txt"),
+        workspace.path().join("alias/b.txt"),
+        workspace.path().join("alias/to_a"),
+        workspace.path().join("alias/../real/b.txt"),
+        workspace.path().join("alias"),
+    ] {
+        assert_eq!(
+            cache
+                .canonicalize(&path)
+                .expect("canonicalize through cache"),
+            fs::canonicalize(&path).expect("canonicalize"),
+            "{}",
+            path.display()
+        );
+    }
+
+    assert!(
+        cache
+            .canonicalize(&workspace.path().join("alias/gone.txt"))
+            .is_err()
+    );
+    assert!(
+        cache
+            .canonicalize(&workspace.path().join("gone/a.txt"))
+            .is_err()
+    );
+}
+
+#[test]
+fn missing_paths_compare_equal_however_they_are_written() {
+    let workspace = tempdir().expect("create temp workspace");
+    let real_dir = workspace.path().join("real");
+    fs::create_dir(&real_dir).expect("create real dir");
+    std::os::unix::fs::symlink(&real_dir, workspace.path().join("alias")).expect("link dir");
+    let expected = fs::canonicalize(&real_dir)
+        .expect("canonicalize real dir")
+        .join("new/init.lua");
+
+    let cache = CanonicalCache::default();
+    for path in [
+        workspace.path().join("real/new/init.lua"),
+        workspace.path().join("alias/new/init.lua"),
+        workspace.path().join("alias//new/./init.lua"),
+        workspace.path().join("real/new/gone/../init.lua"),
+        workspace.path().join("other/../alias/new/init.lua"),
+    ] {
+        assert_eq!(
+            canonicalize_or_fallback(&path),
+            expected,
+            "{}",
+            path.display()
+        );
+        assert_eq!(
+            cache.canonicalize_or_fallback(&path),
+            expected,
+            "{}",
+            path.display()
+        );
+    }
+}
+
+#[test]
+fn lexical_normalization_never_looks_at_the_filesystem() {
+    assert_eq!(
+        normalize_lexically(Path::new("/a/./b//c/../d")),
+        Path::new("/a/b/d")
+    );
+    assert_eq!(normalize_lexically(Path::new("/..")), Path::new("/"));
+    assert!(normalize_lexically(Path::new("a/b")).is_absolute());
+}
+
+#[test]
+fn parallel_map_keeps_the_order() {
+    let items: Vec<usize> = (0..1000).collect();
+    let doubled = parallel_map(&items, |item| item * 2);
+    assert_eq!(
+        doubled,
+        items.iter().map(|item| item * 2).collect::<Vec<_>>()
+    );
+    assert!(parallel_map(&[] as &[usize], |item| *item).is_empty());
+}
@@ -0,0 +1,155 @@
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn reports_broken_links() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let dest_file = workspace.path().join("linked.txt");
+    std::os::unix::fs::symlink(workspace.path().join("gone.txt"), &dest_file)
+        .expect("create broken link");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!("link = {} {}\n", source_file.display(), dest_file.display());
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("check")
+        .current_dir(workspace.path())
+        .output()
+        .expect("run dbdm check");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    assert!(stdout.contains("(broken)"));
+}
+
+#[test]
+fn fix_repairs_only_safe_mismatches() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let missing_dest = workspace.path().join("missing.txt");
+    let broken_dest = workspace.path().join("broken.txt");
+    let real_dest = workspace.path().join("real.txt");
+    std::os::unix::fs::symlink(workspace.path().join("gone.txt"), &broken_dest)
+        .expect("create broken link");
+    fs::write(&real_dest, "keep me").expect("write real file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {}\nlink = {} {}\nlink = {} {}\n",
+        source_file.display(),
+        missing_dest.display(),
+        source_file.display(),
+        broken_dest.display(),
+        source_file.display(),
+        real_dest.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("check")
+        .arg("--fix")
+        .current_dir(workspace.path())
+        .status()
+        .expect("run dbdm check");
+    assert!(status.success());
+
+    assert_eq!(
+        fs::read_link(&missing_dest).expect("read link"),
+        source_file
+    );
+    assert_eq!(fs::read_link(&broken_dest).expect("read link"), source_file);
+    let contents = fs::read_to_string(&real_dest).expect("read real file");
+    assert_eq!(contents, "keep me");
+}
+
+#[test]
+fn links_are_reported_in_config_order() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let mut config_contents = String::new();
+    let mut dests = Vec::new();
+    for idx in 0..40 {
+        let dest = workspace.path().join(format!("dest{}.txt", idx));
+        if idx % 2 == 0 {
+            std::os::unix::fs::symlink(&source_file, &dest).expect("create link");
+        }
+        config_contents.push_str(&format!(
+            "link = {} {}\n",
+            source_file.display(),
+            dest.display()
+        ));
+        dests.push(dest);
+    }
+    fs::write(workspace.path().join("dbdm.conf"), config_contents).expect("write config");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("check")
+        .env("NO_COLOR", "1")
+        .current_dir(workspace.path())
+        .output()
+        .expect("run dbdm check");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    let lines: Vec<&str> = stdout.lines().collect();
+    // Every link shares the source, which is listed once at the end
+    let (lines, shared) = lines.split_at(dests.len());
+    assert_eq!(
+        shared.last(),
+        Some(&format!("source {} -> 40 destinations", source_file.display()).as_str())
+    );
+    // Linked destinations resolve to the source, the others are printed as they are
+    for (idx, (line, dest)) in lines.iter().zip(&dests).enumerate() {
+        let expected = if idx % 2 == 0 { &source_file } else { dest };
+        assert!(line.ends_with(&expected.display().to_string()), "{}", line);
+    }
+}
+
+#[test]
+fn fix_leaves_system_links_to_sync() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let system_dest = workspace.path().join("system.txt");
+    let home_dest = workspace.path().join("home.txt");
+
+    let config_contents = format!(
+        "system = {} {}\nlink = {} {}\n",
+        source_file.display(),
+        system_dest.display(),
+        source_file.display(),
+        home_dest.display()
+    );
+    fs::write(workspace.path().join("dbdm.conf"), config_contents).expect("write config");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["check", "--fix"])
+        .env("NO_COLOR", "1")
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("run dbdm check");
+    assert!(output.status.success());
+
+    // Nothing is asked, and only the `link` line is fixed
+    assert!(fs::symlink_metadata(&system_dest).is_err());
+    assert_eq!(fs::read_link(&home_dest).expect("read link"), source_file);
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    assert!(
+        stdout.contains("not fixed, system links are only changed by sync"),
+        "{}",
+        stdout
+    );
+}
@@ -0,0 +1,58 @@
+use dbdm::check_containment;
+
+#[test]
+fn rejects_destination_inside_source() {
+    let temp = tempfile::tempdir().expect("tempdir should be created");
+    let from = temp.path().join("nvim");
+    std::fs::create_dir(&from).expect("create_dir should succeed");
+
+    let err = check_containment(&from, &from.join("nested")).expect_err("should be rejected");
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(err.to_string().contains("destination is inside the source"));
+}
+
+#[test]
+fn rejects_source_inside_destination() {
+    let temp = tempfile::tempdir().expect("tempdir should be created");
+    let from = temp.path().join("dotfiles").join("nvim");
+    std::fs::create_dir_all(&from).expect("create_dir_all should succeed");
+
+    let err =
+        check_containment(&from, &temp.path().join("dotfiles")).expect_err("should be rejected");
+    assert!(err.to_string().contains("source is inside the destination"));
+}
+
+#[test]
+fn accepts_existing_link_to_source() {
+    let temp = tempfile::tempdir().expect("tempdir should be created");
+    let from = temp.path().join("nvim");
+    let to = temp.path().join("config");
+    std::fs::create_dir(&from).expect("create_dir should succeed");
+    std::os::unix::fs::symlink(&from, &to).expect("symlink should succeed");
+
+    check_containment(&from, &to).expect("should be accepted");
+}
+
+#[test]
+fn rejects_destination_that_is_the_source() {
+    let temp = tempfile::tempdir().expect("tempdir should be created");
+    let from = temp.path().join("data.txt");
+    std::fs::write(&from, "data").expect("write should succeed");
+
+    let err = check_containment(&from, &from).expect_err("should be rejected");
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(err.to_string().contains("destination is the source"));
+}
+
+#[test]
+fn rejects_source_reached_through_linked_parent() {
+    let temp = tempfile::tempdir().expect("tempdir should be created");
+    let from = temp.path().join("dotfiles/config/nvim");
+    std::fs::create_dir_all(&from).expect("create_dir_all should succeed");
+    let home_config = temp.path().join("home-config");
+    std::os::unix::fs::symlink(temp.path().join("dotfiles/config"), &home_config)
+        .expect("symlink should succeed");
+
+    let err = check_containment(&from, &home_config.join("nvim")).expect_err("should be rejected");
+    assert!(err.to_string().contains("destination is the source"));
+}
@@ -0,0 +1,47 @@
+#![cfg(feature = "notify")]
+
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::time::Duration;
+use tempfile::tempdir;
+
+#[test]
+fn changes_to_destinations_are_reported() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source = workspace.path().join("zshrc");
+    fs::write(&source, "# zsh").expect("write source");
+    let dest = workspace.path().join("home/.zshrc");
+    fs::create_dir(workspace.path().join("home")).expect("create home");
+    std::os::unix::fs::symlink(&source, &dest).expect("link destination");
+    fs::write(
+        workspace.path().join("dbdm.conf"),
+        format!("link = {} {}\n", source.display(), dest.display()),
+    )
+    .expect("write config");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["check", "--watch"])
+        .env("NO_COLOR", "1")
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .expect("spawn dbdm check --watch");
+    std::thread::sleep(Duration::from_millis(500));
+
+    // An app saving its config the usual way, renaming a new file over the old one
+    let saved = workspace.path().join("home/.zshrc.tmp");
+    fs::write(&saved, "# rewritten").expect("write new file");
+    fs::rename(&saved, &dest).expect("rename over the link");
+
+    let mut line = String::new();
+    BufReader::new(child.stdout.take().expect("stdout"))
+        .read_line(&mut line)
+        .expect("read report");
+    child.kill().expect("stop dbdm");
+    let _ = child.wait();
+
+    assert!(line.starts_with(&dest.display().to_string()));
+    assert!(line.contains("replaced, now a file"));
+}
@@ -0,0 +1,133 @@
+use dbdm::BackupNaming;
+use dbdm::commands::clean::{Backup, backups_of, clean, expired};
+use dbdm::commands::{Detail, RunMode};
+use dbdm::config_parser::{Config, Link, Retention};
+use std::fs;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+use tempfile::tempdir;
+
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+fn mode() -> RunMode {
+    RunMode {
+        interactive: false,
+        ndjson: false,
+        preview_lines: 0,
+        answers: std::cell::Cell::new(0),
+        detail: Detail::Full,
+    }
+}
+
+#[test]
+fn backups_are_found_by_destination_name() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source = workspace.path().join("zshrc");
+    fs::write(&source, "# zsh").expect("write source");
+    let dest = workspace.path().join(".zshrc");
+    let naming = BackupNaming::default();
+
+    for name in [
+        ".zshrc.bak.dbdm",
+        ".zshrc.bak.dbdm.1",
+        ".zshrc.20240102T0304.bak.dbdm",
+        ".zshrc.old",
+        ".zshrc.local.bak.dbdm",
+        ".bashrc.bak.dbdm",
+    ] {
+        fs::write(workspace.path().join(name), "").expect("write backup");
+    }
+
+    let mut found: Vec<PathBuf> = backups_of(&source, &dest, &[], &naming)
+        .expect("find backups")
+        .into_iter()
+        .map(|backup| backup.path)
+        .collect();
+    found.sort();
+    assert_eq!(
+        found,
+        vec![
+            workspace.path().join(".zshrc.20240102T0304.bak.dbdm"),
+            workspace.path().join(".zshrc.bak.dbdm"),
+            workspace.path().join(".zshrc.bak.dbdm.1"),
+        ]
+    );
+}
+
+#[test]
+fn backups_past_either_limit_expire() {
+    let now = SystemTime::now();
+    let backups: Vec<Backup> = (0..4)
+        .map(|age| Backup {
+            path: PathBuf::from(format!("nvim.bak.dbdm.{}", age)),
+            made: now - DAY * age * 10,
+        })
+        .collect();
+    let paths = |expired: Vec<&Backup>| -> Vec<PathBuf> {
+        expired
+            .into_iter()
+            .map(|backup| backup.path.clone())
+            .collect()
+    };
+
+    let keep_two = Retention {
+        keep: Some(2),
+        keep_days: None,
+    };
+    assert_eq!(
+        paths(expired(&backups, &keep_two, now)),
+        vec![
+            PathBuf::from("nvim.bak.dbdm.2"),
+            PathBuf::from("nvim.bak.dbdm.3")
+        ]
+    );
+
+    let keep_a_fortnight = Retention {
+        keep: Some(3),
+        keep_days: Some(14),
+    };
+    assert_eq!(
+        paths(expired(&backups, &keep_a_fortnight, now)),
+        vec![
+            PathBuf::from("nvim.bak.dbdm.2"),
+            PathBuf::from("nvim.bak.dbdm.3")
+        ]
+    );
+    assert!(expired(&backups, &Retention::default(), now).is_empty());
+}
+
+#[test]
+fn clean_keeps_the_newest_backups() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source = workspace.path().join("vimrc");
+    fs::write(&source, "set number").expect("write source");
+    let dest = workspace.path().join(".vimrc");
+    for name in [".vimrc.bak.dbdm", ".vimrc.bak.dbdm.1", ".vimrc.bak.dbdm.2"] {
+        fs::write(workspace.path().join(name), "").expect("write backup");
+        std::thread::sleep(Duration::from_millis(20));
+    }
+    let config = Config {
+        links: vec![Link::new(source, dest)],
+        ..Default::default()
+    };
+    let retention = Retention {
+        keep: Some(1),
+        keep_days: None,
+    };
+
+    let listed = clean(&config, &mode(), &retention, &BackupNaming::default(), true);
+    assert_eq!(listed.len(), 2);
+    assert!(workspace.path().join(".vimrc.bak.dbdm").exists());
+
+    let removed = clean(
+        &config,
+        &mode(),
+        &retention,
+        &BackupNaming::default(),
+        false,
+    );
+    assert_eq!(removed, listed);
+    assert!(!workspace.path().join(".vimrc.bak.dbdm").exists());
+    assert!(!workspace.path().join(".vimrc.bak.dbdm.1").exists());
+    assert!(workspace.path().join(".vimrc.bak.dbdm.2").exists());
+}
@@ -0,0 +1,443 @@
+use dbdm::commands::RunMode;
+use dbdm::commands::check::inspect_link;
+use dbdm::commands::sync::{
+    SkipReason, SyncAction, SyncOptions, expand_typed_path, link_order, plan_item, sync,
+};
+use dbdm::config_parser::{Config, Link, LinkKind, LinkOptions};
+use dbdm::{BackupNaming, CanonicalCache, ConflictPolicy};
+use std::fs;
+use tempfile::tempdir;
+
+fn options() -> SyncOptions {
+    SyncOptions {
+        force: false,
+        allow_dangerous: false,
+        tui: None,
+        backup_naming: BackupNaming::default(),
+        sudo: false,
+        skip_conflicts: false,
+        on_conflict: ConflictPolicy::Ask,
+        require_clean: false,
+        modules: Vec::new(),
+        dir_mode: None,
+        fail_fast: false,
+        atomic: false,
+        confirm_always: false,
+        yes: false,
+    }
+}
+
+#[test]
+fn plan_item_picks_an_action_for_each_destination() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let plan = |dest: &str| {
+        plan_item(
+            source_file.clone(),
+            workspace.path().join(dest),
+            LinkKind::Link,
+            &options(),
+        )
+    };
+
+    assert_eq!(plan("missing.txt").action, SyncAction::Create);
+
+    fs::write(workspace.path().join("conflict.txt"), "conflict").expect("write conflict");
+    assert_eq!(plan("conflict.txt").action, SyncAction::Pending);
+
+    fs::write(workspace.path().join("same.txt"), "example").expect("write copy");
+    assert_eq!(plan("same.txt").action, SyncAction::Identical);
+
+    std::os::unix::fs::symlink(&source_file, workspace.path().join("linked.txt"))
+        .expect("create link");
+    assert_eq!(plan("linked.txt").action, SyncAction::Ignore);
+
+    std::os::unix::fs::symlink(
+        workspace.path().join("gone.txt"),
+        workspace.path().join("broken.txt"),
+    )
+    .expect("create broken link");
+    let item = plan("broken.txt");
+    assert_eq!(item.action, SyncAction::Retarget);
+    assert_eq!(
+        item.reason,
+        Some(SkipReason::BrokenLink(workspace.path().join("gone.txt")))
+    );
+}
+
+#[test]
+fn only_creating_a_link_is_not_destructive() {
+    assert!(!SyncAction::Create.is_destructive());
+    assert!(!SyncAction::Ignore.is_destructive());
+    assert!(!SyncAction::Skip.is_destructive());
+    for action in [
+        SyncAction::Replace,
+        SyncAction::Identical,
+        SyncAction::Retarget,
+        SyncAction::BackupReplace,
+    ] {
+        assert!(action.is_destructive(), "{}", action.as_str());
+    }
+}
+
+#[test]
+fn plan_item_resolves_conflicts_with_force() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let dest_file = workspace.path().join("conflict.txt");
+    fs::write(&dest_file, "conflict").expect("write conflict");
+
+    let forced = SyncOptions {
+        force: true,
+        ..options()
+    };
+    let item = plan_item(source_file, dest_file.clone(), LinkKind::Link, &forced);
+    assert_eq!(item.action, SyncAction::Replace);
+    assert_eq!(item.to, dest_file);
+    assert!(item.snapshot.expect("snapshot").exists());
+}
+
+#[test]
+fn inspect_link_only_marks_lossless_fixes_as_safe() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let cache = CanonicalCache::default();
+
+    let inspect = |dest: &str| {
+        inspect_link(
+            &Link::new(source_file.clone(), workspace.path().join(dest)),
+            &cache,
+            false,
+        )
+    };
+
+    assert!(inspect("missing.txt").safe);
+
+    std::os::unix::fs::symlink(
+        workspace.path().join("gone.txt"),
+        workspace.path().join("broken.txt"),
+    )
+    .expect("create broken link");
+    let finding = inspect("broken.txt");
+    assert!(finding.safe);
+    assert_eq!(finding.label, Some("broken"));
+
+    fs::write(workspace.path().join("real.txt"), "keep me").expect("write real file");
+    assert!(!inspect("real.txt").safe);
+
+    std::os::unix::fs::symlink(&source_file, workspace.path().join("linked.txt"))
+        .expect("create link");
+    let finding = inspect("linked.txt");
+    assert!(!finding.safe);
+    assert!(finding.label.is_none());
+}
+
+#[test]
+fn inspect_link_tells_symlink_chains_apart() {
+    let workspace = tempdir().expect("create temp workspace");
+    let repo = workspace.path().join("repo");
+    fs::create_dir(&repo).expect("create repo");
+    let source_file = repo.join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    // Like `~/.dotfiles-current`, pointing at the repository in use
+    let current = workspace.path().join("current");
+    std::os::unix::fs::symlink(&repo, &current).expect("create current link");
+    let dest = workspace.path().join("linked.txt");
+    std::os::unix::fs::symlink(current.join("source.txt"), &dest).expect("create link");
+    let link = Link::new(source_file.clone(), dest.clone());
+
+    let finding = inspect_link(&link, &CanonicalCache::default(), false);
+    assert_eq!(finding.label, Some("indirectly linked"));
+    assert!(!finding.safe);
+
+    let finding = inspect_link(&link, &CanonicalCache::default(), true);
+    assert_eq!(finding.label, Some("indirect"));
+    assert!(finding.safe);
+}
+
+#[test]
+fn sync_reports_what_it_did_and_what_failed() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let linked = workspace.path().join("linked.txt");
+    // The parent is missing, so the link can't be made
+    let unreachable = workspace.path().join("missing/linked.txt");
+    let config = Config {
+        links: vec![
+            Link::new(source_file.clone(), linked.clone()),
+            Link::new(source_file.clone(), unreachable.clone()),
+        ],
+        ..Default::default()
+    };
+    let mode = RunMode {
+        interactive: false,
+        ndjson: true,
+        preview_lines: 0,
+        answers: Default::default(),
+        detail: Default::default(),
+    };
+
+    let report = temp_env::with_var("XDG_RUNTIME_DIR", Some(workspace.path()), || {
+        sync(&config, &mode, &options())
+    });
+
+    assert!(!report.aborted);
+    assert_eq!(report.executed.len(), 2);
+    assert_eq!(report.executed[0].item.action, SyncAction::Create);
+    assert_eq!(report.executed[1].item.action, SyncAction::Skip);
+    assert_eq!(report.count(SyncAction::Skip), 1);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].to, unreachable);
+    assert_eq!(report.errors[0].action, SyncAction::Create);
+    assert_eq!(report.errors[0].error.kind(), std::io::ErrorKind::NotFound);
+    assert_eq!(fs::read_link(&linked).expect("read link"), source_file);
+}
+
+#[test]
+fn fail_fast_stops_at_the_first_failed_item() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let unreachable = workspace.path().join("missing/linked.txt");
+    let linked = workspace.path().join("linked.txt");
+    let config = Config {
+        links: vec![
+            Link::new(source_file.clone(), unreachable.clone()),
+            Link::new(source_file.clone(), linked.clone()),
+        ],
+        ..Default::default()
+    };
+    let mode = RunMode {
+        interactive: false,
+        ndjson: true,
+        preview_lines: 0,
+        answers: Default::default(),
+        detail: Default::default(),
+    };
+    let options = SyncOptions {
+        fail_fast: true,
+        ..options()
+    };
+
+    let report = temp_env::with_var("XDG_RUNTIME_DIR", Some(workspace.path()), || {
+        sync(&config, &mode, &options)
+    });
+
+    assert!(!report.aborted);
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].to, unreachable);
+    assert_eq!(report.executed[1].item.action, SyncAction::Skip);
+    assert_eq!(
+        report.executed[1].item.reason,
+        Some(SkipReason::EarlierFailure)
+    );
+    assert!(!linked.exists());
+}
+
+#[test]
+fn atomic_sync_puts_back_what_it_changed_when_an_item_fails() {
+    let workspace = tempdir().expect("create temp workspace");
+    let dotfiles = workspace.path().join("dotfiles");
+    fs::create_dir(&dotfiles).expect("create dotfiles");
+    fs::write(dotfiles.join("zshrc"), "# zsh").expect("write zshrc");
+    fs::write(dotfiles.join("gitconfig"), "[user]").expect("write gitconfig");
+    let zshrc = workspace.path().join(".zshrc");
+    fs::write(&zshrc, "# old zsh").expect("write old zshrc");
+    let gitconfig = workspace.path().join(".gitconfig");
+    let unreachable = workspace.path().join("missing/linked.txt");
+    let config = Config {
+        links: vec![
+            Link::new(dotfiles.join("zshrc"), zshrc.clone()),
+            Link::new(dotfiles.join("gitconfig"), gitconfig.clone()),
+            Link::new(dotfiles.join("zshrc"), unreachable.clone()),
+        ],
+        ..Default::default()
+    };
+    let mode = RunMode {
+        interactive: false,
+        ndjson: true,
+        preview_lines: 0,
+        answers: Default::default(),
+        detail: Default::default(),
+    };
+    let options = SyncOptions {
+        on_conflict: ConflictPolicy::Backup,
+        atomic: true,
+        ..options()
+    };
+
+    let report = temp_env::with_vars(
+        [
+            ("XDG_RUNTIME_DIR", Some(workspace.path())),
+            ("XDG_STATE_HOME", Some(workspace.path())),
+        ],
+        || sync(&config, &mode, &options),
+    );
+
+    assert_eq!(report.errors.len(), 1);
+    assert_eq!(report.errors[0].to, unreachable);
+    assert!(
+        report
+            .executed
+            .iter()
+            .all(|executed| executed.item.action == SyncAction::Skip && executed.backup.is_none())
+    );
+    assert_eq!(fs::read_to_string(&zshrc).expect("read zshrc"), "# old zsh");
+    assert!(fs::symlink_metadata(&gitconfig).is_err());
+    assert!(!dotfiles.join(".zshrc.bak.dbdm").exists());
+    assert!(!workspace.path().join("dbdm/rollback").exists());
+}
+
+#[test]
+fn link_order_puts_links_after_what_they_depend_on() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_dir = workspace.path().join("foo");
+    fs::create_dir(&source_dir).expect("create source dir");
+    let source_file = workspace.path().join("extra.conf");
+    fs::write(&source_file, "extra").expect("write source file");
+    let config_dir = workspace.path().join("config/foo");
+
+    let after = |link: Link, after: &std::path::Path| Link {
+        options: LinkOptions {
+            after: vec![after.to_path_buf()],
+            ..Default::default()
+        },
+        ..link
+    };
+    let nested = Link::new(source_file.clone(), config_dir.join("extra.conf"));
+    let dir = Link::new(source_dir.clone(), config_dir.clone());
+    let other = Link::new(source_file.clone(), workspace.path().join("other.conf"));
+    let last = after(
+        Link::new(source_file.clone(), workspace.path().join("last.conf")),
+        &workspace.path().join("other.conf"),
+    );
+
+    // Nested destinations come after the directory without being told to
+    let links = [&last, &nested, &dir, &other];
+    assert_eq!(link_order(&links), Ok(vec![2, 1, 3, 0]));
+
+    let cycle = [&after(dir, &config_dir.join("extra.conf")), &nested];
+    let err = link_order(&cycle).expect_err("cycle");
+    assert!(err.message.contains("cycle"));
+
+    let unknown = [&after(other, &workspace.path().join("nowhere"))];
+    let err = link_order(&unknown).expect_err("unknown destination");
+    assert_eq!(err.link, 0);
+    assert!(err.message.contains("nowhere"));
+}
+
+#[test]
+fn sync_applies_links_into_a_directory_after_it() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_dir = workspace.path().join("dotfiles/foo");
+    fs::create_dir_all(&source_dir).expect("create source dir");
+    let source_file = workspace.path().join("dotfiles/extra.conf");
+    fs::write(&source_file, "extra").expect("write source file");
+    let config_dir = workspace.path().join("foo");
+
+    fs::write(
+        workspace.path().join("dbdm.conf"),
+        format!(
+            "link = {} {} after={}\nlink = {} {}\n",
+            source_file.display(),
+            config_dir.join("extra.conf").display(),
+            config_dir.display(),
+            source_dir.display(),
+            config_dir.display()
+        ),
+    )
+    .expect("write config");
+    let config =
+        dbdm::config_parser::read_config(&workspace.path().join("dbdm.conf")).expect("read config");
+    let mode = RunMode {
+        interactive: false,
+        ndjson: true,
+        preview_lines: 0,
+        answers: Default::default(),
+        detail: Default::default(),
+    };
+
+    let report = temp_env::with_var("XDG_RUNTIME_DIR", Some(workspace.path()), || {
+        sync(&config, &mode, &options())
+    });
+
+    assert!(report.errors.is_empty());
+    assert_eq!(report.executed[0].item.to, config_dir);
+    assert_eq!(
+        fs::read_link(&config_dir).expect("read dir link"),
+        source_dir
+    );
+    assert_eq!(
+        fs::read_link(source_dir.join("extra.conf")).expect("read nested link"),
+        source_file
+    );
+}
+
+#[test]
+fn sync_only_touches_the_picked_modules() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let in_module = |to: &str, module: Option<&str>| Link {
+        module: module.map(String::from),
+        ..Link::new(source_file.clone(), workspace.path().join(to))
+    };
+    let config = Config {
+        links: vec![
+            in_module("plain.txt", None),
+            in_module("nvim.txt", Some("nvim")),
+            in_module("zsh.txt", Some("zsh")),
+        ],
+        ..Default::default()
+    };
+    let mode = RunMode {
+        interactive: false,
+        ndjson: true,
+        preview_lines: 0,
+        answers: Default::default(),
+        detail: Default::default(),
+    };
+    let options = SyncOptions {
+        modules: vec![String::from("nvim")],
+        ..options()
+    };
+
+    let report = temp_env::with_var("XDG_RUNTIME_DIR", Some(workspace.path()), || {
+        sync(&config, &mode, &options)
+    });
+
+    assert_eq!(report.executed.len(), 1);
+    assert_eq!(report.executed[0].item.module.as_deref(), Some("nvim"));
+    assert!(workspace.path().join("nvim.txt").exists());
+    assert!(!workspace.path().join("plain.txt").exists());
+    assert!(!workspace.path().join("zsh.txt").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn typed_paths_are_expanded_and_absolute() {
+    temp_env::with_var("HOME", Some("/home/user"), || {
+        assert_eq!(
+            expand_typed_path("~/backups").expect("expand tilde"),
+            std::path::PathBuf::from("/home/user/backups")
+        );
+        assert_eq!(
+            expand_typed_path("!home/.config").expect("expand keyword"),
+            std::path::PathBuf::from("/home/user/.config")
+        );
+        assert_eq!(
+            expand_typed_path("/tmp/backups").expect("keep absolute"),
+            std::path::PathBuf::from("/tmp/backups")
+        );
+        // Only a leading `~` of its own is the home directory
+        let err = expand_typed_path("~user/backups").expect_err("refuse relative");
+        assert!(err.contains("is relative"), "{}", err);
+        assert!(expand_typed_path("backups").is_err());
+        assert!(expand_typed_path("!nope/backups").is_err());
+    });
+}
@@ -1,4 +1,8 @@
-use dbdm::config_parser::{Link, read_config};
+use dbdm::config_parser::{
+    Config, FormatOptions, Link, LinkKind, LinkOptions, MergeStrategy, Retention, SortBy, format,
+    format_with, migrate, parse_config, read_config, read_config_with,
+};
+use dbdm::{BackupLocation, ConflictPolicy};
 use std::fs;
 use std::path::PathBuf;
 use tempfile::tempdir;
@@ -26,14 +30,8 @@ fn parse_valid_config_file_to_config() {
     let config = read_config(&config_path).expect("read config");
 
     let expected_links = vec![
-        Link {
-            from: PathBuf::from(&db_dir),
-            to: PathBuf::from(&notes_dir),
-        },
-        Link {
-            from: PathBuf::from(&notes_dir),
-            to: PathBuf::from(&db_dir),
-        },
+        Link::new(PathBuf::from(&db_dir), PathBuf::from(&notes_dir)),
+        Link::new(PathBuf::from(&notes_dir), PathBuf::from(&db_dir)),
     ];
 
     assert_eq!(config.links, expected_links);
@@ -53,10 +51,10 @@ fn parsing_config_with_invalid_kind() {
     let config_contents = format!("lonk = {} {}\n", db_dir.display(), notes_dir.display());
     fs::write(&config_path, config_contents).expect("write config");
 
-    let err = read_config(&config_path).expect_err("read config");
+    let err = read_config_with(&config_path, true).expect_err("read config");
     assert_eq!(
         err,
-        "Invalid path syntax on line 0. The supported syntax is '<kind> = <from> <to>'"
+        "Invalid path syntax on line 1. The supported syntax is '<kind> = <from> <to>'"
     )
 }
 
@@ -84,7 +82,7 @@ fn parsing_config_with_more_than_2_arguments() {
     let err = read_config(&config_path).expect_err("read config");
     assert_eq!(
         err,
-        "Invalid number of values on line 0. The supported syntax is '<kind> = <from> <to>'. Found 3 args"
+        "Invalid number of values on line 1. The supported syntax is '<kind> = <from> <to>'. Found 3 args"
     );
 }
 
@@ -105,7 +103,7 @@ fn parsing_config_with_less_than_2_arguments() {
     let err = read_config(&config_path).expect_err("read config");
     assert_eq!(
         err,
-        "Invalid number of values on line 0. The supported syntax is '<kind> = <from> <to>'. Found 1 args"
+        "Invalid number of values on line 1. The supported syntax is '<kind> = <from> <to>'. Found 1 args"
     );
 }
 
@@ -139,18 +137,9 @@ fn keywords_are_expanded_correctly() {
             let config = read_config(&config_path).expect("read config");
 
             let expected_links = vec![
-                Link {
-                    from: PathBuf::from(&xdg_conf_dir),
-                    to: PathBuf::from(&home_dir),
-                },
-                Link {
-                    from: PathBuf::from(&here_dir),
-                    to: PathBuf::from(&xdg_conf_dir),
-                },
-                Link {
-                    from: PathBuf::from(&home_dir),
-                    to: PathBuf::from(&here_dir),
-                },
+                Link::new(PathBuf::from(&xdg_conf_dir), PathBuf::from(&home_dir)),
+                Link::new(PathBuf::from(&here_dir), PathBuf::from(&xdg_conf_dir)),
+                Link::new(PathBuf::from(&home_dir), PathBuf::from(&here_dir)),
             ];
 
             assert_eq!(config.links, expected_links);
@@ -159,3 +148,1135 @@ fn keywords_are_expanded_correctly() {
         },
     );
 }
+
+#[test]
+fn quoted_and_escaped_paths_keep_spaces() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let prefs_dir = root_dir.join("Library Prefs");
+    let support_dir = root_dir.join("Application Support");
+    let it_dir = root_dir.join("it's");
+
+    fs::create_dir_all(&prefs_dir).expect("create prefs dir");
+    fs::create_dir_all(&support_dir).expect("create support dir");
+    fs::create_dir_all(&it_dir).expect("create quote dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "link = \"{}\" '{}'\nlink = {} \"{}\"\n",
+        prefs_dir.display(),
+        support_dir.display(),
+        support_dir.display().to_string().replace(' ', "\\ "),
+        it_dir.display(),
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let config = read_config(&config_path).expect("read config");
+
+    let expected_links = vec![
+        Link::new(PathBuf::from(&prefs_dir), PathBuf::from(&support_dir)),
+        Link::new(PathBuf::from(&support_dir), PathBuf::from(&it_dir)),
+    ];
+
+    assert_eq!(config.links, expected_links);
+}
+
+#[test]
+fn parsing_config_with_unterminated_quote() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let db_dir = root_dir.join("db");
+
+    fs::create_dir_all(&db_dir).expect("create db dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!("link = \"{} {}\n", db_dir.display(), db_dir.display());
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let err = read_config(&config_path).expect_err("read config");
+    assert_eq!(err, "Unterminated quote on line 1");
+}
+
+#[test]
+fn extra_keywords_are_expanded_correctly() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let data_dir = root_dir.join("data");
+    let cache_dir = root_dir.join("cache");
+    let home_dir = root_dir.join("home");
+    let state_dir = home_dir.join(".local/state");
+
+    fs::create_dir_all(data_dir.join("box")).expect("create data dir");
+    fs::create_dir_all(&cache_dir).expect("create cache dir");
+    fs::create_dir_all(&state_dir).expect("create state dir");
+
+    temp_env::with_vars(
+        [
+            ("XDG_DATA_HOME", Some(data_dir.as_os_str())),
+            ("XDG_CACHE_HOME", Some(cache_dir.as_os_str())),
+            ("XDG_STATE_HOME", None),
+            ("HOME", Some(home_dir.as_os_str())),
+            ("HOSTNAME", Some("box".as_ref())),
+            ("USER", Some("dan".as_ref())),
+        ],
+        || {
+            let config_path = root_dir.join("dbdm.conf");
+            let config_contents =
+                "link = !xdg_data/!hostname !xdg_cache/!user\nlink = !xdg_state !xdg_data/state\n";
+            fs::write(&config_path, config_contents).expect("write config");
+
+            let config = read_config(&config_path).expect("read config");
+
+            let expected_links = vec![
+                Link::new(data_dir.join("box"), cache_dir.join("dan")),
+                Link::new(PathBuf::from(&state_dir), data_dir.join("state")),
+            ];
+
+            assert_eq!(config.links, expected_links);
+        },
+    );
+}
+
+#[test]
+fn parsing_config_with_unknown_keyword() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    fs::create_dir_all(&root_dir).expect("create root dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    fs::write(&config_path, "link = !home/a !xdg_music/a\n").expect("write config");
+
+    let err = read_config_with(&config_path, true).expect_err("read config");
+    assert_eq!(err, "Invalid keyword in !xdg_music/a on line 1");
+}
+
+#[test]
+fn includes_are_parsed_in_place() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let modules_dir = root_dir.join("modules");
+    let db_dir = root_dir.join("db");
+    let notes_dir = root_dir.join("notes");
+    let dest_dir = root_dir.join("dest");
+
+    fs::create_dir_all(&modules_dir).expect("create modules dir");
+    fs::create_dir_all(&db_dir).expect("create db dir");
+    fs::create_dir_all(&notes_dir).expect("create notes dir");
+    fs::create_dir_all(&dest_dir).expect("create dest dir");
+
+    fs::write(
+        modules_dir.join("b.conf"),
+        format!(
+            "link = {} {}\n",
+            notes_dir.display(),
+            dest_dir.join("notes").display()
+        ),
+    )
+    .expect("write b module");
+    fs::write(
+        modules_dir.join("a.conf"),
+        format!(
+            "link = {} {}\n",
+            db_dir.display(),
+            dest_dir.join("db").display()
+        ),
+    )
+    .expect("write a module");
+
+    let config_path = root_dir.join("dbdm.conf");
+    fs::write(&config_path, "include = modules/*.conf\n").expect("write config");
+
+    let config = read_config(&config_path).expect("read config");
+
+    let expected_links = vec![
+        Link::new(PathBuf::from(&db_dir), dest_dir.join("db")),
+        Link::new(PathBuf::from(&notes_dir), dest_dir.join("notes")),
+    ];
+
+    assert_eq!(config.links, expected_links);
+}
+
+#[test]
+fn errors_in_included_files_name_the_file() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    fs::create_dir_all(&root_dir).expect("create root dir");
+
+    let module_path = root_dir.join("nvim.conf");
+    fs::write(&module_path, "\nlonk = a b\n").expect("write module");
+
+    let config_path = root_dir.join("dbdm.conf");
+    fs::write(&config_path, "include = nvim.conf\n").expect("write config");
+
+    let err = read_config_with(&config_path, true).expect_err("read config");
+    assert_eq!(
+        err,
+        format!(
+            "{}: Invalid path syntax on line 2. The supported syntax is '<kind> = <from> <to>'",
+            module_path.display()
+        )
+    );
+}
+
+#[test]
+fn include_cycles_are_detected() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    fs::create_dir_all(&root_dir).expect("create root dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    fs::write(&config_path, "include = other.conf\n").expect("write config");
+    fs::write(root_dir.join("other.conf"), "include = dbdm.conf\n").expect("write other");
+
+    let err = read_config(&config_path).expect_err("read config");
+    assert_eq!(
+        err,
+        format!(
+            "{}: Include cycle on line 1: {} is already being included",
+            root_dir.join("other.conf").display(),
+            config_path.display()
+        )
+    );
+}
+
+#[test]
+fn links_are_filtered_by_os_condition() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let db_dir = root_dir.join("db");
+    let notes_dir = root_dir.join("notes");
+
+    fs::create_dir_all(&db_dir).expect("create db dir");
+    fs::create_dir_all(&notes_dir).expect("create notes dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {} when os={}\nlink = /does/not/exist /nowhere when os!={}\n",
+        db_dir.display(),
+        notes_dir.display(),
+        std::env::consts::OS,
+        std::env::consts::OS
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let config = read_config(&config_path).expect("read config");
+
+    let expected_links = vec![Link::new(PathBuf::from(&db_dir), PathBuf::from(&notes_dir))];
+
+    assert_eq!(config.links, expected_links);
+}
+
+#[test]
+fn parsing_config_with_unknown_condition() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    fs::create_dir_all(&root_dir).expect("create root dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    fs::write(&config_path, "link = a b when arch=arm\n").expect("write config");
+
+    let err = read_config(&config_path).expect_err("read config");
+    assert_eq!(err, "Unknown condition arch=arm on line 1");
+}
+
+#[test]
+fn links_are_filtered_by_command_condition() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let bin_dir = root_dir.join("bin");
+    let db_dir = root_dir.join("db");
+    let notes_dir = root_dir.join("notes");
+
+    fs::create_dir_all(&bin_dir).expect("create bin dir");
+    fs::create_dir_all(&db_dir).expect("create db dir");
+    fs::create_dir_all(&notes_dir).expect("create notes dir");
+
+    let nvim = bin_dir.join("nvim");
+    fs::write(&nvim, "#!/bin/sh\n").expect("write fake binary");
+    let mut perms = fs::metadata(&nvim).expect("stat binary").permissions();
+    std::os::unix::fs::PermissionsExt::set_mode(&mut perms, 0o755);
+    fs::set_permissions(&nvim, perms).expect("make binary executable");
+
+    temp_env::with_var("PATH", Some(bin_dir.as_os_str()), || {
+        let config_path = root_dir.join("dbdm.conf");
+        let config_contents = format!(
+            "link = {} {} when command(nvim)\nlink = /does/not/exist /nowhere when command(emacs)\n",
+            db_dir.display(),
+            notes_dir.display(),
+        );
+        fs::write(&config_path, config_contents).expect("write config");
+
+        let config = read_config(&config_path).expect("read config");
+
+        let expected_links = vec![Link::new(PathBuf::from(&db_dir), PathBuf::from(&notes_dir))];
+
+        assert_eq!(config.links, expected_links);
+    });
+}
+
+#[test]
+fn local_config_overrides_and_extends_shared_config() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let db_dir = root_dir.join("db");
+    let notes_dir = root_dir.join("notes");
+    let dest_dir = root_dir.join("dest");
+    let work_dir = root_dir.join("work");
+
+    fs::create_dir_all(&db_dir).expect("create db dir");
+    fs::create_dir_all(&notes_dir).expect("create notes dir");
+    fs::create_dir_all(&dest_dir).expect("create dest dir");
+    fs::create_dir_all(&work_dir).expect("create work dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {}\nlink = {} {}\n",
+        db_dir.display(),
+        dest_dir.join("db").display(),
+        notes_dir.display(),
+        dest_dir.join("notes").display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let local_contents = format!(
+        "link = {} {}\nlink = {} {}\n",
+        db_dir.display(),
+        work_dir.join("db").display(),
+        dest_dir.display(),
+        work_dir.join("dest").display()
+    );
+    fs::write(root_dir.join("dbdm.local.conf"), local_contents).expect("write local config");
+
+    let config = read_config(&config_path).expect("read config");
+
+    let expected_links = vec![
+        Link::new(PathBuf::from(&db_dir), work_dir.join("db")),
+        Link::new(PathBuf::from(&notes_dir), dest_dir.join("notes")),
+        Link::new(PathBuf::from(&dest_dir), work_dir.join("dest")),
+    ];
+
+    assert_eq!(config.links, expected_links);
+}
+
+#[test]
+fn parsing_config_with_link_options() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let ssh_dir = root_dir.join("ssh");
+    let dest_dir = root_dir.join("dest");
+
+    fs::create_dir_all(&ssh_dir).expect("create ssh dir");
+    fs::create_dir_all(&dest_dir).expect("create dest dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {} mode=0700 owner=root:root\n",
+        ssh_dir.display(),
+        dest_dir.join("ssh").display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let config = read_config(&config_path).expect("read config");
+
+    assert_eq!(config.links.len(), 1);
+    assert_eq!(config.links[0].to, dest_dir.join("ssh"));
+    assert_eq!(
+        config.links[0].options,
+        LinkOptions {
+            mode: Some(0o700),
+            owner: Some(String::from("root:root")),
+            root: false,
+            after: Vec::new(),
+            on_conflict: None,
+            protect: false,
+            readonly: false,
+        }
+    );
+}
+
+#[test]
+fn parsing_config_with_on_conflict_option() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let app_dir = root_dir.join("app");
+
+    fs::create_dir_all(&app_dir).expect("create app dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let dest = root_dir.join("dest");
+    fs::write(
+        &config_path,
+        format!(
+            "link = {} {} on_conflict=backup\n",
+            app_dir.display(),
+            dest.display()
+        ),
+    )
+    .expect("write config");
+    let config = read_config(&config_path).expect("read config");
+    assert_eq!(
+        config.links[0].options.on_conflict,
+        Some(ConflictPolicy::Backup)
+    );
+
+    fs::write(
+        &config_path,
+        format!(
+            "link = {} {} on_conflict=merge\n",
+            app_dir.display(),
+            dest.display()
+        ),
+    )
+    .expect("write config");
+    let err = read_config(&config_path).expect_err("read config");
+    assert_eq!(err, "Invalid on_conflict value merge on line 1");
+}
+
+#[test]
+fn parsing_config_with_unknown_option() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let ssh_dir = root_dir.join("ssh");
+
+    fs::create_dir_all(&ssh_dir).expect("create ssh dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {} colour=red\n",
+        ssh_dir.display(),
+        root_dir.join("dest").display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let err = read_config_with(&config_path, true).expect_err("read config");
+    assert_eq!(err, "Unknown option colour=red on line 1");
+}
+
+#[test]
+fn parsing_config_with_system_kind() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let db_dir = root_dir.join("db");
+    let etc_dir = root_dir.join("etc");
+
+    fs::create_dir_all(&db_dir).expect("create db dir");
+    fs::create_dir_all(&etc_dir).expect("create etc dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "system = {} {}\n",
+        db_dir.display(),
+        etc_dir.join("db").display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let config = read_config(&config_path).expect("read config");
+
+    assert_eq!(config.links.len(), 1);
+    assert_eq!(config.links[0].kind, LinkKind::System);
+}
+
+#[test]
+fn parsing_config_with_encrypted_kind_and_secrets() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    fs::create_dir_all(&root_dir).expect("create root dir");
+    fs::write(root_dir.join("token.age"), "ciphertext").expect("write source");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "encrypted = {} {}\n\n[secrets]\nidentity = keys.txt\ngpg_key = ABCD1234\n",
+        root_dir.join("token.age").display(),
+        root_dir.join("token").display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let config = read_config(&config_path).expect("read config");
+
+    assert_eq!(config.links.len(), 1);
+    assert_eq!(config.links[0].kind, LinkKind::Encrypted);
+    assert_eq!(config.secrets.identity, Some(root_dir.join("keys.txt")));
+    assert_eq!(config.secrets.gpg_key.as_deref(), Some("ABCD1234"));
+}
+
+#[test]
+fn parsing_config_with_backups_section() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    fs::create_dir_all(&root_dir).expect("create root dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    fs::write(&config_path, "[backups]\nkeep = 3\nkeep_days = 30\n").expect("write config");
+    let config = read_config(&config_path).expect("read config");
+    assert_eq!(
+        config.retention,
+        Retention {
+            keep: Some(3),
+            keep_days: Some(30),
+        }
+    );
+    assert_eq!(config.backup_location, None);
+
+    fs::write(&config_path, "[backups]\nlocation = state\n").expect("write config");
+    let config = read_config(&config_path).expect("read config");
+    assert_eq!(config.backup_location, Some(BackupLocation::State));
+
+    fs::write(&config_path, "[backups]\nkeep = 0\n").expect("write config");
+    let err = read_config(&config_path).expect_err("read config");
+    assert_eq!(
+        err,
+        "Invalid keep value 0 on line 2, expected a number above 0"
+    );
+
+    fs::write(&config_path, "[backups]\nlocation = home\n").expect("write config");
+    let err = read_config(&config_path).expect_err("read config");
+    assert_eq!(
+        err,
+        "Invalid location value home on line 2, expected source or state"
+    );
+}
+
+#[test]
+fn parsing_config_with_modules() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    fs::create_dir_all(root_dir.join("nvim")).expect("create nvim dir");
+    fs::write(root_dir.join("zshrc"), "# zsh").expect("write zshrc");
+    fs::write(root_dir.join("tmux.conf"), "# tmux").expect("write tmux.conf");
+    fs::write(
+        root_dir.join("tmux.dbdm"),
+        format!(
+            "link = {} {}\n",
+            root_dir.join("tmux.conf").display(),
+            root_dir.join(".tmux.conf").display()
+        ),
+    )
+    .expect("write included config");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {}\n[module nvim]\nlink = {} {}\ninclude = {}\n",
+        root_dir.join("zshrc").display(),
+        root_dir.join(".zshrc").display(),
+        root_dir.join("nvim").display(),
+        root_dir.join(".nvim").display(),
+        root_dir.join("tmux.dbdm").display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let config = read_config(&config_path).expect("read config");
+    let modules: Vec<Option<&str>> = config
+        .links
+        .iter()
+        .map(|link| link.module.as_deref())
+        .collect();
+    assert_eq!(modules, vec![None, Some("nvim"), Some("nvim")]);
+
+    fs::write(&config_path, "[module my nvim]\n").expect("write config");
+    let err = read_config(&config_path).expect_err("read config");
+    assert_eq!(err, "Invalid module name my nvim on line 1");
+}
+
+#[test]
+fn parsing_config_with_unencrypted_source_for_encrypted_kind() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    fs::create_dir_all(&root_dir).expect("create root dir");
+    fs::write(root_dir.join("token.txt"), "plaintext").expect("write source");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "encrypted = {} {}\n",
+        root_dir.join("token.txt").display(),
+        root_dir.join("token").display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let err = read_config(&config_path).expect_err("read config");
+    assert_eq!(
+        err,
+        "Encrypted sources have to be .age or .gpg files on line 1"
+    );
+}
+
+#[test]
+fn parsing_config_with_unknown_section() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    fs::create_dir_all(&root_dir).expect("create root dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    fs::write(&config_path, "[keys]\n").expect("write config");
+
+    let err = read_config_with(&config_path, true).expect_err("read config");
+    assert_eq!(err, "Unknown section [keys] on line 1");
+}
+
+#[test]
+fn building_config_in_code() {
+    let tmp = tempdir().expect("tempdir");
+    let vimrc = tmp.path().join("vimrc");
+    let secret = tmp.path().join("token.age");
+    fs::write(&vimrc, "set number").expect("write vimrc");
+    fs::write(&secret, "").expect("write secret");
+
+    let config = Config::builder()
+        .link(&vimrc, tmp.path().join(".vimrc"))
+        .encrypted(&secret, tmp.path().join("token"))
+        .build()
+        .expect("build config");
+
+    assert_eq!(config.links.len(), 2);
+    assert_eq!(config.links[0], Link::new(vimrc, tmp.path().join(".vimrc")));
+    assert_eq!(config.links[1].kind, LinkKind::Encrypted);
+}
+
+#[test]
+fn building_config_checks_links_like_the_parser() {
+    let tmp = tempdir().expect("tempdir");
+    let vimrc = tmp.path().join("vimrc");
+    fs::write(&vimrc, "set number").expect("write vimrc");
+    let missing = tmp.path().join("missing");
+
+    let err = Config::builder()
+        .link(&vimrc, tmp.path().join(".vimrc"))
+        .link(&missing, tmp.path().join(".zshrc"))
+        .build()
+        .expect_err("missing source");
+    assert_eq!(
+        err,
+        format!(
+            "Invalid link {} -> {}: <from> path doesn't contain any object",
+            missing.display(),
+            tmp.path().join(".zshrc").display()
+        )
+    );
+
+    let err = Config::builder()
+        .encrypted(&vimrc, tmp.path().join("vimrc.plain"))
+        .build()
+        .expect_err("not an encrypted source");
+    assert!(err.ends_with("Encrypted sources have to be .age or .gpg files"));
+}
+
+#[test]
+fn merging_configs_with_each_strategy() {
+    let tmp = tempdir().expect("tempdir");
+    let shared = tmp.path().join("shared.zshrc");
+    let work = tmp.path().join("work.zshrc");
+    let vimrc = tmp.path().join("vimrc");
+    for file in [&shared, &work, &vimrc] {
+        fs::write(file, "").expect("write source");
+    }
+    let zshrc = tmp.path().join(".zshrc");
+    let base = || {
+        Config::builder()
+            .link(&shared, &zshrc)
+            .build()
+            .expect("base")
+    };
+    let other = || {
+        Config::builder()
+            .link(&work, &zshrc)
+            .link(&vimrc, tmp.path().join(".vimrc"))
+            .build()
+            .expect("other")
+    };
+
+    let mut config = base();
+    let err = config
+        .merge(other(), MergeStrategy::Error)
+        .expect_err("same destination");
+    assert_eq!(
+        err,
+        format!("Destination {} is already declared", zshrc.display())
+    );
+    assert_eq!(config.links.len(), 1);
+
+    let mut config = base();
+    config
+        .merge(other(), MergeStrategy::PreferLast)
+        .expect("prefer last");
+    assert_eq!(config.links[0].from, work);
+    assert_eq!(config.links[1].from, vimrc);
+
+    let mut config = base();
+    config
+        .merge(other(), MergeStrategy::PreferFirst)
+        .expect("prefer first");
+    assert_eq!(config.links[0].from, shared);
+    assert_eq!(config.links.len(), 2);
+}
+
+#[test]
+fn included_links_to_a_declared_destination_are_refused() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let db_dir = root_dir.join("db");
+    let notes_dir = root_dir.join("notes");
+    let dest = root_dir.join("dest");
+
+    fs::create_dir_all(&db_dir).expect("create db dir");
+    fs::create_dir_all(&notes_dir).expect("create notes dir");
+
+    fs::write(
+        root_dir.join("notes.conf"),
+        format!("link = {} {}\n", notes_dir.display(), dest.display()),
+    )
+    .expect("write included config");
+    let config_path = root_dir.join("dbdm.conf");
+    fs::write(
+        &config_path,
+        format!(
+            "link = {} {}\ninclude = notes.conf\n",
+            db_dir.display(),
+            dest.display()
+        ),
+    )
+    .expect("write config");
+
+    let err = read_config(&config_path).expect_err("clashing include");
+    assert_eq!(
+        err,
+        format!(
+            "{}: Destination {} on line 1 is already declared at {}:1",
+            root_dir.join("notes.conf").display(),
+            dest.display(),
+            config_path.display()
+        )
+    );
+}
+
+#[test]
+fn local_links_replace_shared_links_to_the_same_destination() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let db_dir = root_dir.join("db");
+    let notes_dir = root_dir.join("notes");
+    let dest = root_dir.join("dest");
+
+    fs::create_dir_all(&db_dir).expect("create db dir");
+    fs::create_dir_all(&notes_dir).expect("create notes dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    fs::write(
+        &config_path,
+        format!("link = {} {}\n", db_dir.display(), dest.display()),
+    )
+    .expect("write config");
+    fs::write(
+        root_dir.join("dbdm.local.conf"),
+        format!("link = {} {}\n", notes_dir.display(), dest.display()),
+    )
+    .expect("write local config");
+
+    let config = read_config(&config_path).expect("read config");
+
+    assert_eq!(config.links, vec![Link::new(notes_dir, dest)]);
+}
+
+#[cfg(unix)]
+#[test]
+fn parsing_config_with_paths_that_are_not_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let dest_dir = root_dir.join("dest");
+    fs::create_dir_all(&dest_dir).expect("create dest dir");
+
+    // "café" in Latin-1, which isn't valid UTF-8
+    let name = OsStr::from_bytes(b"caf\xe9");
+    fs::write(root_dir.join(name), "").expect("write source file");
+
+    let mut content = b"link = ".to_vec();
+    content.extend_from_slice(root_dir.as_os_str().as_bytes());
+    content.extend_from_slice(b"/caf\xe9 \"");
+    content.extend_from_slice(dest_dir.as_os_str().as_bytes());
+    content.extend_from_slice(b"/my caf\xe9\"\n");
+    let config_path = root_dir.join("dbdm.conf");
+    fs::write(&config_path, content).expect("write config");
+
+    let config = read_config(&config_path).expect("read config");
+    let mut dest_name = b"my ".to_vec();
+    dest_name.extend_from_slice(name.as_bytes());
+    assert_eq!(
+        config.links,
+        vec![Link::new(
+            root_dir.join(name),
+            dest_dir.join(OsStr::from_bytes(&dest_name))
+        )]
+    );
+}
+
+#[test]
+fn unknown_directives_are_skipped_outside_of_strict_mode() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let db_dir = root_dir.join("db");
+    let notes_dir = root_dir.join("notes");
+    fs::create_dir_all(&db_dir).expect("create db dir");
+    fs::create_dir_all(&notes_dir).expect("create notes dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "tree = {db} {notes}\nlink = {db} {notes} colour=red\nlink = !newdir/db {notes}\n[hooks]\nlink = {db} {notes}\nlink = {db} {notes}/db\n",
+        db = db_dir.display(),
+        notes = notes_dir.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let config = read_config(&config_path).expect("read config");
+    assert!(config.links.is_empty());
+
+    let parsed = parse_config(&config_path).expect("parse config");
+    assert!(parsed.errors.is_empty());
+    let lines: Vec<usize> = parsed.warnings.iter().map(|warning| warning.line).collect();
+    assert_eq!(lines, vec![1, 2, 3, 4]);
+}
+
+#[test]
+fn strict_setting_makes_unknown_directives_errors() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let db_dir = root_dir.join("db");
+    let notes_dir = root_dir.join("notes");
+    fs::create_dir_all(&db_dir).expect("create db dir");
+    fs::create_dir_all(&notes_dir).expect("create notes dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {} colour=red\nstrict = true\n",
+        db_dir.display(),
+        notes_dir.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let err = read_config(&config_path).expect_err("read config");
+    assert_eq!(err, "Unknown option colour=red on line 1");
+}
+
+#[test]
+fn configs_for_a_newer_version_are_refused() {
+    let tmp = tempdir().expect("tempdir");
+    let config_path = tmp.path().join("dbdm.conf");
+
+    fs::write(&config_path, "version = 1\n").expect("write config");
+    assert!(read_config(&config_path).is_ok());
+
+    fs::write(&config_path, "version = 2\ntree = a b\n").expect("write config");
+    let err = read_config(&config_path).expect_err("read config");
+    assert_eq!(
+        err,
+        "The config is written for version 2 on line 1, but this dbdm only reads up to version 1. Upgrade dbdm to use it"
+    );
+
+    fs::write(&config_path, "version = one\n").expect("write config");
+    let err = read_config(&config_path).expect_err("read config");
+    assert_eq!(err, "Invalid version one on line 1");
+}
+
+#[test]
+fn migrating_adds_the_version_line_once() {
+    let migrated = migrate(b"link = a b\n").expect("migrate config");
+    assert_eq!(migrated, Some(b"version = 1\nlink = a b\n".to_vec()));
+
+    let again = migrate(&migrated.expect("migrated contents")).expect("migrate again");
+    assert_eq!(again, None);
+
+    assert!(migrate(b"version = 3\n").is_err());
+}
+
+#[test]
+fn config_lines_read_back_as_the_same_link() {
+    let tmp = tempdir().expect("tempdir");
+    let source_dir = tmp.path().join("my app");
+    fs::create_dir_all(&source_dir).expect("create source dir");
+    fs::create_dir_all(tmp.path().join("dest")).expect("create dest dir");
+
+    let config_path = tmp.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = \"{}\" {} mode=0700 after={} on_conflict=backup protect=true readonly=true\n",
+        source_dir.display(),
+        tmp.path().join("dest/app").display(),
+        tmp.path().join("dest/other").display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+    let config = read_config(&config_path).expect("read config");
+
+    let line = config.links[0].config_line().expect("write config line");
+    fs::write(&config_path, line.as_encoded_bytes()).expect("write config line");
+    let again = read_config(&config_path).expect("read config line");
+    assert_eq!(again.links, config.links);
+}
+
+#[test]
+fn config_show_prints_every_link_with_its_line() {
+    let tmp = tempdir().expect("tempdir");
+    fs::create_dir_all(tmp.path().join("nvim")).expect("create source dir");
+    fs::create_dir_all(tmp.path().join("zsh")).expect("create other source dir");
+
+    fs::write(
+        tmp.path().join("dbdm.conf"),
+        "include = extra.conf\n[module editor]\nlink = !here/nvim !here/out-nvim mode=0700\n[backups]\nkeep = 3\n",
+    )
+    .expect("write config");
+    fs::write(
+        tmp.path().join("extra.conf"),
+        "link = !here/zsh !here/out-zsh\nlink = !here/zsh !here/elsewhere when os=none\n",
+    )
+    .expect("write included config");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["config", "show"])
+        .current_dir(tmp.path())
+        .output()
+        .expect("run dbdm config show");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    let root = tmp.path().display();
+    assert_eq!(
+        stdout,
+        format!(
+            "{root}/extra.conf:1: link = {root}/zsh {root}/out-zsh\n\
+             {root}/dbdm.conf:3: link = {root}/nvim {root}/out-nvim mode=0700 (module editor)\n\
+             [backups]\nkeep = 3\n"
+        )
+    );
+
+    // The JSON report counts lines the same way
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["config", "show", "--format", "json"])
+        .current_dir(tmp.path())
+        .output()
+        .expect("run dbdm config show --format json");
+    assert!(output.status.success());
+    let report: serde_json::Value = serde_json::from_slice(&output.stdout).expect("parse report");
+    let lines: Vec<u64> = report["links"]
+        .as_array()
+        .expect("links array")
+        .iter()
+        .map(|link| link["line"].as_u64().expect("line number"))
+        .collect();
+    assert_eq!(lines, vec![1, 3]);
+}
+
+#[test]
+fn config_explain_shows_how_a_line_is_expanded() {
+    let tmp = tempdir().expect("tempdir");
+    fs::create_dir_all(tmp.path().join("nvim")).expect("create source dir");
+    fs::write(
+        tmp.path().join("dbdm.conf"),
+        "[module editor]\nlink = !here/nvim !here/out mode=0700 when os!=none\nlink = !here/gone !here/out-gone\n",
+    )
+    .expect("write config");
+
+    let explain = |line: &str| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+            .args(["config", "explain", line])
+            .current_dir(tmp.path())
+            .output()
+            .expect("run dbdm config explain")
+    };
+    let root = tmp.path().display();
+
+    // Lines are counted from 1, like in error messages
+    let output = explain("2");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    assert_eq!(
+        stdout,
+        format!(
+            "{root}/dbdm.conf:2: link = !here/nvim !here/out mode=0700 when os!=none\n  \
+             section  [module editor]\n  \
+             from     !here/nvim\n           \
+             !here -> {root} (the directory of the config)\n           \
+             = {root}/nvim (directory)\n  \
+             to       !here/out\n           \
+             !here -> {root} (the directory of the config)\n           \
+             = {root}/out (missing)\n  \
+             when     os!=none holds\n  \
+             read as  link = {root}/nvim {root}/out mode=0700\n"
+        )
+    );
+
+    let output = explain("3");
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    assert!(
+        stdout.contains("error    <from> path doesn't contain any object on line 3"),
+        "{}",
+        stdout
+    );
+
+    let output = explain("1");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    assert!(
+        stdout.ends_with("not a link line, nothing to expand\n"),
+        "{}",
+        stdout
+    );
+
+    let output = explain("0");
+    assert_eq!(output.status.code(), Some(64));
+    let stderr = String::from_utf8(output.stderr).expect("utf8 stderr");
+    assert!(stderr.contains("Invalid line 0"), "{}", stderr);
+}
+
+#[test]
+fn format_sorts_and_aligns_links() {
+    let content = b"\n\nversion=1\n  [ module   editor ]\nlink = !here/zsh   !home/.zshrc   when os=linux\nsystem=mode=0700 !here/etc  \"!home/my dir\"\n  link = \"!here/my app\" !here/a\\ b  \n\n\n\n[backups]\nkeep =   3\n[future]\n  x  =  y   z\n\n";
+    let formatted = format(content);
+    assert_eq!(
+        String::from_utf8(formatted.clone()).expect("utf8"),
+        "version = 1\n\
+         [module editor]\n\
+         system = !here/etc      \"!home/my dir\" mode=0700\n\
+         link   = \"!here/my app\" !here/a\\ b\n\
+         link   = !here/zsh      !home/.zshrc   when os=linux\n\
+         \n\
+         [backups]\n\
+         keep = 3\n\
+         [future]\n\
+         x  =  y   z\n"
+    );
+    assert_eq!(format(&formatted), formatted);
+}
+
+#[test]
+fn config_fmt_check_fails_until_formatted() {
+    let tmp = tempdir().expect("tempdir");
+    fs::create_dir_all(tmp.path().join("nvim")).expect("create source dir");
+    fs::create_dir_all(tmp.path().join("git")).expect("create other source dir");
+    let root = tmp.path().display();
+    let config_path = tmp.path().join("dbdm.conf");
+    fs::write(
+        &config_path,
+        format!("link = {root}/nvim {root}/out-nvim\nlink =   {root}/git {root}/out-git\n"),
+    )
+    .expect("write config");
+    let before = read_config(&config_path).expect("read config");
+
+    let fmt = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+            .args(["config", "fmt"])
+            .args(args)
+            .current_dir(tmp.path())
+            .output()
+            .expect("run dbdm config fmt")
+            .status
+            .code()
+    };
+    assert_eq!(fmt(&["--check"]), Some(1));
+    assert_eq!(fmt(&[]), Some(0));
+    assert_eq!(fmt(&["--check"]), Some(0));
+
+    assert_eq!(
+        fs::read_to_string(&config_path).expect("read formatted config"),
+        format!("link = {root}/git  {root}/out-git\nlink = {root}/nvim {root}/out-nvim\n")
+    );
+    let after = read_config(&config_path).expect("read formatted config");
+    assert_eq!(after.links.len(), before.links.len());
+    assert!(after.links.iter().all(|link| before.links.contains(link)));
+}
+
+#[test]
+fn format_finds_duplicates_and_sorts_by_destination() {
+    let content = b"link = !here/zsh !here/b mode=0600 root=true\nlink = !here/nvim !here/c\nlink  =  !here/zsh   !here/b\n\nlink = !here/zsh !here/b root=true mode=0600\n[module x]\nlink = !here/zsh !here/b root=true mode=0600\n";
+
+    let kept = format_with(
+        content,
+        &FormatOptions {
+            sort: SortBy::Destination,
+            dedupe: false,
+        },
+    );
+    // Only the options are in another order on line 5, line 7 is in another section
+    assert_eq!(kept.duplicates, vec![(5, 1)]);
+    assert_eq!(
+        String::from_utf8(kept.content).expect("utf8"),
+        "link = !here/zsh  !here/b mode=0600 root=true\n\
+         link = !here/zsh  !here/b\n\
+         link = !here/nvim !here/c\n\
+         \n\
+         link = !here/zsh !here/b root=true mode=0600\n\
+         [module x]\n\
+         link = !here/zsh !here/b root=true mode=0600\n"
+    );
+
+    let removed = format_with(
+        content,
+        &FormatOptions {
+            sort: SortBy::Source,
+            dedupe: true,
+        },
+    );
+    assert_eq!(removed.duplicates, vec![(5, 1)]);
+    assert_eq!(
+        String::from_utf8(removed.content).expect("utf8"),
+        "link = !here/nvim !here/c\n\
+         link = !here/zsh  !here/b mode=0600 root=true\n\
+         link = !here/zsh  !here/b\n\
+         \n\
+         [module x]\n\
+         link = !here/zsh !here/b root=true mode=0600\n"
+    );
+    assert_eq!(SortBy::parse("by-dest"), Some(SortBy::Destination));
+    assert_eq!(SortBy::parse("by-name"), None);
+}
+
+#[test]
+fn comments_are_skipped_and_kept_by_format() {
+    let tmp = tempdir().expect("tempdir");
+    fs::create_dir_all(tmp.path().join("nvim")).expect("create source dir");
+    fs::create_dir_all(tmp.path().join("zsh")).expect("create other source dir");
+    let root = tmp.path().display();
+    let config_path = tmp.path().join("dbdm.conf");
+    let content = format!(
+        "# My dotfiles\nversion = 1\n  # editors  \nlink = {root}/zsh   {root}/out-zsh\n# ---\nlink = {root}/nvim {root}/out-nvim\n# ---\n[backups]\n# keep a few\nkeep =  3\n"
+    );
+    fs::write(&config_path, &content).expect("write config");
+
+    // Comments still count as lines
+    let parsed = parse_config(&config_path).expect("parse config");
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    let lines: Vec<usize> = parsed.entries.iter().map(|entry| entry.line).collect();
+    assert_eq!(lines, vec![4, 6]);
+    assert_eq!(parsed.retention.keep, Some(3));
+    assert!(
+        dbdm::validate::validate(&config_path, false)
+            .expect("validate config")
+            .is_empty()
+    );
+    let explanation = dbdm::config_parser::explain_line(&config_path, 0).expect("explain comment");
+    assert!(explanation.paths.is_empty());
+    assert!(explanation.link.is_none() && explanation.error.is_none());
+
+    // They break up runs of links, and the same comment twice isn't a duplicate
+    let formatted = format_with(
+        content.as_bytes(),
+        &FormatOptions {
+            sort: SortBy::Source,
+            dedupe: true,
+        },
+    );
+    assert!(formatted.duplicates.is_empty());
+    assert_eq!(
+        String::from_utf8(formatted.content.clone()).expect("utf8"),
+        format!(
+            "# My dotfiles\nversion = 1\n# editors\nlink = {root}/zsh {root}/out-zsh\n# ---\nlink = {root}/nvim {root}/out-nvim\n# ---\n[backups]\n# keep a few\nkeep = 3\n"
+        )
+    );
+    assert_eq!(format(&formatted.content), formatted.content);
+
+    let before = read_config(&config_path).expect("read config");
+    fs::write(&config_path, &formatted.content).expect("write formatted config");
+    assert_eq!(
+        read_config(&config_path)
+            .expect("read formatted config")
+            .links,
+        before.links
+    );
+}
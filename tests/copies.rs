@@ -0,0 +1,197 @@
+use dbdm::commands::check::inspect_link;
+use dbdm::commands::sync::{SkipReason, SyncAction, SyncOptions, plan_item};
+use dbdm::config_parser::{Link, LinkKind};
+use dbdm::copies::{self, State};
+use dbdm::{BackupNaming, BackupOutcome, CanonicalCache, ConflictPolicy};
+use std::fs;
+use tempfile::tempdir;
+
+fn options() -> SyncOptions {
+    SyncOptions {
+        force: false,
+        allow_dangerous: false,
+        tui: None,
+        backup_naming: BackupNaming::default(),
+        sudo: false,
+        skip_conflicts: false,
+        on_conflict: ConflictPolicy::Ask,
+        require_clean: false,
+        modules: Vec::new(),
+        dir_mode: None,
+        fail_fast: false,
+        atomic: false,
+        confirm_always: false,
+        yes: false,
+    }
+}
+
+#[test]
+fn copies_are_tracked_by_hash() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_dir = workspace.path().join("nvim");
+    fs::create_dir_all(&source_dir).expect("create source dir");
+    fs::write(source_dir.join("init.lua"), "-- nvim").expect("write source file");
+    let dest = workspace.path().join(".config/nvim");
+    fs::create_dir_all(workspace.path().join(".config")).expect("create config dir");
+
+    temp_env::with_var("XDG_STATE_HOME", Some(workspace.path()), || {
+        assert_eq!(copies::state(&source_dir, &dest).expect("state"), None);
+        assert!(copies::symlinks_supported(&dest));
+
+        copies::install(&source_dir, &dest).expect("copy");
+        assert!(!fs::symlink_metadata(&dest).expect("stat").is_symlink());
+        assert_eq!(
+            copies::state(&source_dir, &dest).expect("state"),
+            Some(State::UpToDate)
+        );
+
+        fs::write(source_dir.join("init.lua"), "-- changed").expect("change source");
+        assert_eq!(
+            copies::state(&source_dir, &dest).expect("state"),
+            Some(State::Stale)
+        );
+
+        fs::write(dest.join("extra.lua"), "").expect("change copy");
+        assert_eq!(
+            copies::state(&source_dir, &dest).expect("state"),
+            Some(State::Diverged)
+        );
+
+        fs::write(source_dir.join("init.lua"), "-- nvim").expect("change source back");
+        assert_eq!(
+            copies::state(&source_dir, &dest).expect("state"),
+            Some(State::Modified)
+        );
+    });
+}
+
+#[test]
+fn copies_are_backed_up_unless_identical() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("bashrc");
+    fs::write(&source_file, "alias ll='ls -l'").expect("write source file");
+    let dest = workspace.path().join(".bashrc");
+    fs::write(&dest, "local changes").expect("write destination");
+    let backups = workspace.path().join("backups");
+
+    temp_env::with_var("XDG_STATE_HOME", Some(workspace.path()), || {
+        let outcome =
+            copies::backup_and_install(&source_file, &dest, &backups, &BackupNaming::default())
+                .expect("backup and copy");
+        let BackupOutcome::BackedUp(backup) = outcome else {
+            panic!("expected a backup, got {:?}", outcome);
+        };
+        assert_eq!(
+            fs::read_to_string(backup).expect("read backup"),
+            "local changes"
+        );
+        assert_eq!(
+            fs::read_to_string(&dest).expect("read copy"),
+            "alias ll='ls -l'"
+        );
+
+        let outcome =
+            copies::backup_and_install(&source_file, &dest, &backups, &BackupNaming::default())
+                .expect("copy again");
+        assert_eq!(outcome, BackupOutcome::Identical);
+    });
+}
+
+#[test]
+fn sync_and_check_keep_copies_up_to_date() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("vimrc");
+    fs::write(&source_file, "set number").expect("write source file");
+    let dest = workspace.path().join(".vimrc");
+
+    temp_env::with_var("XDG_STATE_HOME", Some(workspace.path()), || {
+        copies::install(&source_file, &dest).expect("copy");
+        let plan = || {
+            plan_item(
+                source_file.clone(),
+                dest.clone(),
+                LinkKind::Link,
+                &options(),
+            )
+        };
+        let inspect = || {
+            inspect_link(
+                &Link::new(source_file.clone(), dest.clone()),
+                &CanonicalCache::default(),
+                false,
+            )
+        };
+
+        assert_eq!(plan().action, SyncAction::Ignore);
+        let finding = inspect();
+        assert_eq!(finding.label, Some("copy"));
+        assert!(!finding.safe);
+
+        fs::write(&source_file, "set relativenumber").expect("change source");
+        assert_eq!(plan().action, SyncAction::Replace);
+        let finding = inspect();
+        assert_eq!(finding.label, Some("stale copy"));
+        assert!(finding.safe);
+    });
+}
+
+#[test]
+fn edited_copies_are_merged_with_their_source() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("gitconfig");
+    fs::write(&source_file, "[user]\nname = dan\n\n[core]\neditor = vim\n")
+        .expect("write source file");
+    let dest = workspace.path().join(".gitconfig");
+
+    temp_env::with_var("XDG_STATE_HOME", Some(workspace.path()), || {
+        copies::install(&source_file, &dest).expect("copy");
+        let plan = || {
+            plan_item(
+                source_file.clone(),
+                dest.clone(),
+                LinkKind::Link,
+                &options(),
+            )
+        };
+
+        fs::write(&dest, "[user]\nname = dan\n\n[core]\neditor = nvim\n").expect("edit copy");
+        let item = plan();
+        assert_eq!(item.action, SyncAction::Pending);
+        assert_eq!(item.reason, Some(SkipReason::EditedCopy));
+
+        fs::write(
+            &source_file,
+            "[user]\nname = dan z\n\n[core]\neditor = vim\n",
+        )
+        .expect("change source");
+        let item = plan();
+        assert_eq!(item.action, SyncAction::Pending);
+        assert_eq!(item.reason, Some(SkipReason::DivergedCopy));
+        assert!(copies::can_merge(&dest));
+
+        copies::merge(&source_file, &dest).expect("merge");
+        assert_eq!(
+            fs::read_to_string(&dest).expect("read copy"),
+            "[user]\nname = dan z\n\n[core]\neditor = nvim\n"
+        );
+        // The merged copy keeps its edits, so it's left alone until the source changes again
+        assert_eq!(plan().action, SyncAction::Ignore);
+
+        fs::write(
+            &source_file,
+            "[user]\nname = dan z\n\n[core]\neditor = emacs\n",
+        )
+        .expect("change source again");
+        fs::write(&dest, "[user]\nname = dan z\n\n[core]\neditor = helix\n")
+            .expect("edit copy again");
+        let merge = copies::merge_preview(&source_file, &dest)
+            .expect("preview merge")
+            .expect("a base to merge from");
+        assert_eq!(merge.conflicts, 1);
+        assert!(copies::merge(&source_file, &dest).is_err());
+        assert_eq!(
+            fs::read_to_string(&dest).expect("read copy"),
+            "[user]\nname = dan z\n\n[core]\neditor = helix\n"
+        );
+    });
+}
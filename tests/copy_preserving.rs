@@ -0,0 +1,37 @@
+use dbdm::copy_preserving;
+use std::os::unix::fs::PermissionsExt;
+use std::time::{Duration, SystemTime};
+
+#[test]
+fn keeps_mode_and_times_of_copied_tree() {
+    let temp = tempfile::tempdir().expect("tempdir should be created");
+    let from = temp.path().join("nvim");
+    let to = temp.path().join("nvim.bak.dbdm");
+    let past = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+
+    std::fs::create_dir(&from).expect("create_dir should succeed");
+    let file = from.join("init.lua");
+    std::fs::write(&file, "config").expect("write should succeed");
+    std::fs::File::options()
+        .write(true)
+        .open(&file)
+        .expect("open should succeed")
+        .set_modified(past)
+        .expect("set_modified should succeed");
+    std::fs::set_permissions(&file, std::fs::Permissions::from_mode(0o640))
+        .expect("set_permissions should succeed");
+    std::os::unix::fs::symlink("init.lua", from.join("link.lua")).expect("symlink should succeed");
+
+    copy_preserving(&from, &to).expect("copy should succeed");
+
+    let meta = std::fs::metadata(to.join("init.lua")).expect("metadata should exist");
+    assert_eq!(meta.permissions().mode() & 0o7777, 0o640);
+    assert_eq!(meta.modified().expect("mtime should exist"), past);
+    assert_eq!(
+        std::fs::read_to_string(to.join("init.lua")).expect("read should succeed"),
+        "config"
+    );
+
+    let target = std::fs::read_link(to.join("link.lua")).expect("read_link should succeed");
+    assert_eq!(target, std::path::PathBuf::from("init.lua"));
+}
@@ -0,0 +1,88 @@
+use dbdm::created_dirs;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn only_missing_directories_are_created() {
+    let workspace = tempdir().expect("create temp workspace");
+    let existing = workspace.path().join("backups");
+    fs::create_dir(&existing).expect("create existing dir");
+
+    let created =
+        created_dirs::create(&existing.join("nvim/old"), None).expect("create directories");
+    assert_eq!(
+        created,
+        vec![existing.join("nvim"), existing.join("nvim/old")]
+    );
+    assert!(existing.join("nvim/old").is_dir());
+
+    let again = created_dirs::create(&existing.join("nvim/old"), None).expect("create again");
+    assert!(again.is_empty());
+}
+
+#[cfg(unix)]
+#[test]
+fn created_directories_get_the_mode_whatever_the_umask() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let workspace = tempdir().expect("create temp workspace");
+    let dir = workspace.path().join("private/backups");
+
+    created_dirs::create(&dir, Some(0o700)).expect("create directories");
+    for path in [workspace.path().join("private"), dir] {
+        let mode = fs::metadata(&path).expect("stat dir").permissions().mode();
+        assert_eq!(mode & 0o7777, 0o700);
+    }
+}
+
+#[test]
+fn created_directories_are_recorded_and_forgotten() {
+    let workspace = tempdir().expect("create temp workspace");
+    let dirs = vec![workspace.path().join("a"), workspace.path().join("a/b")];
+
+    temp_env::with_var("XDG_STATE_HOME", Some(workspace.path()), || {
+        assert!(
+            created_dirs::load()
+                .expect("load empty registry")
+                .is_empty()
+        );
+
+        created_dirs::record(&dirs).expect("record directories");
+        assert_eq!(created_dirs::load().expect("load registry"), dirs);
+
+        created_dirs::forget(&dirs[1..]).expect("forget directory");
+        assert_eq!(created_dirs::load().expect("load registry"), dirs[..1]);
+    });
+}
+
+#[test]
+fn only_empty_directories_dbdm_created_are_removed() {
+    let workspace = tempdir().expect("create temp workspace");
+    let existing = workspace.path().join("backups");
+    fs::create_dir(&existing).expect("create existing dir");
+    let backup_dir = existing.join("nvim/old");
+
+    temp_env::with_var("XDG_STATE_HOME", Some(workspace.path()), || {
+        let created = created_dirs::create(&backup_dir, None).expect("create directories");
+        created_dirs::record(&created).expect("record directories");
+        let backup = backup_dir.join("init.lua.bak.dbdm");
+        fs::write(&backup, "-- old").expect("write backup");
+        fs::write(existing.join("nvim/notes"), "keep").expect("write other file");
+
+        fs::remove_file(&backup).expect("remove backup");
+        let removed = created_dirs::remove_empty_parents(&backup).expect("remove empty dirs");
+        assert_eq!(removed, vec![backup_dir.clone()]);
+        assert!(!backup_dir.exists());
+        assert!(existing.join("nvim").exists());
+        assert_eq!(
+            created_dirs::load().expect("load registry"),
+            vec![existing.join("nvim")]
+        );
+
+        fs::remove_file(existing.join("nvim/notes")).expect("remove other file");
+        let removed =
+            created_dirs::remove_empty_parents(&existing.join("nvim/notes")).expect("remove");
+        assert_eq!(removed, vec![existing.join("nvim")]);
+        assert!(existing.exists());
+    });
+}
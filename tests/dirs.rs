@@ -0,0 +1,96 @@
+use dbdm::dirs::{
+    backup_dir, backup_dir_for, cache_dir, history_file, journal_dir, runtime_dir, state_dir,
+};
+use std::path::PathBuf;
+
+#[cfg(unix)]
+#[test]
+fn base_directories_follow_xdg() {
+    temp_env::with_vars(
+        [
+            ("HOME", Some("/home/user")),
+            ("XDG_STATE_HOME", Some("/xdg/state")),
+            ("XDG_CACHE_HOME", Some("/xdg/cache")),
+            ("XDG_RUNTIME_DIR", Some("/run/user/1000")),
+        ],
+        || {
+            assert_eq!(
+                state_dir().expect("resolve state dir"),
+                PathBuf::from("/xdg/state/dbdm")
+            );
+            assert_eq!(
+                cache_dir().expect("resolve cache dir"),
+                PathBuf::from("/xdg/cache/dbdm")
+            );
+            assert_eq!(
+                runtime_dir().expect("resolve runtime dir"),
+                PathBuf::from("/run/user/1000/dbdm")
+            );
+            assert_eq!(
+                history_file().expect("resolve history file"),
+                PathBuf::from("/xdg/state/dbdm/history.jsonl")
+            );
+            assert_eq!(
+                journal_dir().expect("resolve journal dir"),
+                PathBuf::from("/xdg/state/dbdm/rollback")
+            );
+            assert_eq!(
+                backup_dir().expect("resolve backup dir"),
+                PathBuf::from("/xdg/state/dbdm/backups")
+            );
+            // The directory of the destination is mirrored
+            assert_eq!(
+                backup_dir_for(std::path::Path::new("/home/user/.config/nvim"))
+                    .expect("resolve backup dir of destination"),
+                PathBuf::from("/xdg/state/dbdm/backups/home/user/.config")
+            );
+        },
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn base_directories_fall_back_to_home() {
+    // Empty and relative values are ignored, as the spec says
+    temp_env::with_vars(
+        [
+            ("HOME", Some("/home/user")),
+            ("XDG_STATE_HOME", Some("")),
+            ("XDG_CACHE_HOME", Some("relative/cache")),
+            ("XDG_RUNTIME_DIR", None),
+        ],
+        || {
+            assert_eq!(
+                state_dir().expect("resolve state dir"),
+                PathBuf::from("/home/user/.local/state/dbdm")
+            );
+            assert_eq!(
+                cache_dir().expect("resolve cache dir"),
+                PathBuf::from("/home/user/.cache/dbdm")
+            );
+            assert_eq!(
+                runtime_dir().expect("resolve runtime dir"),
+                PathBuf::from("/home/user/.cache/dbdm")
+            );
+        },
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn base_directories_need_a_home() {
+    temp_env::with_vars(
+        [
+            ("HOME", None::<&str>),
+            ("XDG_STATE_HOME", None),
+            ("XDG_CACHE_HOME", None),
+            ("XDG_RUNTIME_DIR", None),
+        ],
+        || {
+            let err = state_dir().unwrap_err();
+            assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+            assert!(err.to_string().contains("XDG_STATE_HOME"));
+            assert!(runtime_dir().is_err());
+        },
+    );
+}
@@ -0,0 +1,136 @@
+use dbdm::config_parser::Config;
+use dbdm::edit::source_of;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn destinations_map_back_to_their_sources() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_dir = workspace.path().join("nvim");
+    fs::create_dir_all(source_dir.join("lua")).expect("create source dir");
+    let source_file = workspace.path().join("gitconfig");
+    fs::write(&source_file, "[user]").expect("write source file");
+    let themes = workspace.path().join("themes");
+    fs::create_dir(&themes).expect("create nested source dir");
+
+    let dest_dir = workspace.path().join("config/nvim");
+    fs::create_dir_all(&dest_dir).expect("create dest dir");
+    let config = Config::builder()
+        .link(&source_dir, &dest_dir)
+        .link(&themes, dest_dir.join("themes"))
+        .link(&source_file, workspace.path().join(".gitconfig"))
+        .build()
+        .expect("build config");
+
+    let (link, source) =
+        source_of(&config, &workspace.path().join(".gitconfig")).expect("find file source");
+    assert_eq!(link.from, source_file);
+    assert_eq!(source, source_file);
+
+    // Nothing has to be synced yet for paths inside a linked directory to be found
+    let (_, source) =
+        source_of(&config, &dest_dir.join("lua/init.lua")).expect("find nested source");
+    assert_eq!(source, source_dir.join("lua/init.lua"));
+
+    // The innermost link wins
+    let (link, source) =
+        source_of(&config, &dest_dir.join("themes/dark.lua")).expect("find innermost source");
+    assert_eq!(link.from, themes);
+    assert_eq!(source, themes.join("dark.lua"));
+
+    assert!(source_of(&config, &workspace.path().join(".gitconfig/extra")).is_none());
+    assert!(source_of(&config, &workspace.path().join("config/helix")).is_none());
+}
+
+#[cfg(unix)]
+#[test]
+fn paths_through_symlinks_are_followed() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_dir = workspace.path().join("nvim");
+    fs::create_dir_all(&source_dir).expect("create source dir");
+    fs::create_dir(workspace.path().join("config")).expect("create dest root");
+    std::os::unix::fs::symlink(
+        workspace.path().join("config"),
+        workspace.path().join("dot-config"),
+    )
+    .expect("link dest root");
+
+    let config = Config::builder()
+        .link(&source_dir, workspace.path().join("config/nvim"))
+        .build()
+        .expect("build config");
+    std::os::unix::fs::symlink(&source_dir, workspace.path().join("config/nvim"))
+        .expect("link source");
+
+    let (_, source) = source_of(&config, &workspace.path().join("dot-config/nvim/init.lua"))
+        .expect("find source through symlink");
+    assert_eq!(
+        source,
+        fs::canonicalize(&source_dir)
+            .expect("canonicalize source")
+            .join("init.lua")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn edit_opens_the_source_in_the_editor() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("zshrc");
+    fs::write(&source_file, "export A=1\n").expect("write source file");
+    fs::set_permissions(&source_file, fs::Permissions::from_mode(0o444))
+        .expect("make source read-only");
+    let dest_file = workspace.path().join(".zshrc");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {} readonly=true\n",
+        source_file.display(),
+        dest_file.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    // An editor that appends a line to the file it is given
+    let editor = workspace.path().join("editor.sh");
+    fs::write(&editor, "#!/bin/sh\necho 'export B=2' >> \"$1\"\n").expect("write editor");
+    fs::set_permissions(&editor, fs::Permissions::from_mode(0o755)).expect("make editor runnable");
+
+    let run = |dest: &str, extra: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+            .args(["edit", dest, "--non-interactive"])
+            .args(extra)
+            .env("XDG_STATE_HOME", workspace.path().join("state"))
+            .env_remove("VISUAL")
+            .env("EDITOR", &editor)
+            .current_dir(workspace.path())
+            .stdin(std::process::Stdio::null())
+            .output()
+            .expect("run dbdm edit")
+    };
+
+    let output = run(".zshrc", &["--sync"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    let contents = fs::read_to_string(&source_file).expect("read source");
+    assert_eq!(contents, "export A=1\nexport B=2\n");
+    // Read-only again after the edit
+    let mode = fs::metadata(&source_file)
+        .expect("stat source")
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o222, 0);
+    // And linked by the sync afterwards
+    assert_eq!(fs::read_link(&dest_file).expect("read link"), source_file);
+
+    let output = run("unknown", &[]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        fs::read_to_string(&source_file).expect("read source"),
+        contents
+    );
+}
@@ -0,0 +1,123 @@
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn run(dir: &Path, args: &[&str]) -> std::process::Output {
+    std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(args)
+        .env("NO_COLOR", "1")
+        .current_dir(dir)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("run dbdm")
+}
+
+#[test]
+fn usage_errors_exit_with_64() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let output = run(workspace.path(), &["check", "--color", "purple"]);
+    assert_eq!(output.status.code(), Some(64));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Invalid --color value purple"));
+
+    let output = run(workspace.path(), &["frobnicate"]);
+    assert_eq!(output.status.code(), Some(64));
+    let output = run(workspace.path(), &["sync", "--preview-lines", "many"]);
+    assert_eq!(output.status.code(), Some(64));
+
+    // A config that can't be read isn't a usage error
+    fs::create_dir(workspace.path().join("dbdm.conf")).expect("create unreadable config");
+    let output = run(workspace.path(), &["config", "validate"]);
+    assert_eq!(output.status.code(), Some(3));
+}
+
+#[test]
+fn failures_exit_with_1_and_help_succeeds() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    assert!(run(workspace.path(), &["help"]).status.success());
+
+    let output = run(workspace.path(), &["check"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("dbdm.conf doesn't exist in"));
+
+    fs::write(workspace.path().join("dbdm.conf"), "link = nowhere\n").expect("write config");
+    let output = run(workspace.path(), &["check"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Error in config"));
+}
+
+#[test]
+fn syncing_an_unknown_module_is_a_usage_error() {
+    let workspace = tempdir().expect("create temp workspace");
+    fs::write(workspace.path().join("dbdm.conf"), "").expect("write config");
+
+    let output = run(workspace.path(), &["sync", "nvim", "--non-interactive"]);
+    assert_eq!(output.status.code(), Some(64));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("No links of module nvim in the config"));
+}
+
+#[test]
+fn configs_for_a_newer_version_fail_until_dbdm_is_upgraded() {
+    let workspace = tempdir().expect("create temp workspace");
+    let config_path = workspace.path().join("dbdm.conf");
+
+    fs::write(&config_path, "version = 99\n").expect("write config");
+    let output = run(workspace.path(), &["check"]);
+    assert_eq!(output.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("Upgrade dbdm to use it"));
+
+    fs::write(&config_path, "").expect("write config");
+    assert!(
+        run(workspace.path(), &["config", "migrate"])
+            .status
+            .success()
+    );
+    assert_eq!(
+        fs::read_to_string(&config_path).expect("read config"),
+        "version = 1\n"
+    );
+    assert!(run(workspace.path(), &["check"]).status.success());
+}
+
+#[test]
+fn sync_exit_codes_tell_failures_apart() {
+    let workspace = tempdir().expect("create temp workspace");
+    let state = workspace.path().join("state");
+    let sync = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+            .arg("sync")
+            .args(args)
+            .env("NO_COLOR", "1")
+            .env("XDG_STATE_HOME", &state)
+            .current_dir(workspace.path())
+            .stdin(std::process::Stdio::null())
+            .output()
+            .expect("run dbdm sync")
+    };
+
+    let output = sync(&["--non-interactive"]);
+    assert_eq!(output.status.code(), Some(3));
+
+    fs::write(workspace.path().join("source"), "example").expect("write source");
+    fs::write(
+        workspace.path().join("dbdm.conf"),
+        format!(
+            "link = !here/source {}\n",
+            workspace.path().join("linked").display()
+        ),
+    )
+    .expect("write config");
+    fs::write(workspace.path().join("linked"), "conflict").expect("write conflict");
+    // Nobody is there to answer the prompt
+    assert_eq!(sync(&[]).status.code(), Some(2));
+
+    assert_eq!(sync(&["--non-interactive"]).status.code(), Some(0));
+    fs::write(workspace.path().join("dbdm.conf"), "link = nowhere\n").expect("write config");
+    assert_eq!(sync(&["--non-interactive"]).status.code(), Some(3));
+}
@@ -0,0 +1,135 @@
+use dbdm::expand::{Step, VarMap, expand, explain};
+
+#[test]
+fn expand_uses_custom_variables() {
+    let mut vars = VarMap::default();
+    vars.set("!repo", "/srv/dotfiles");
+
+    assert_eq!(
+        expand("!repo/nvim", &vars).expect("expand custom variable"),
+        "/srv/dotfiles/nvim"
+    );
+}
+
+#[test]
+fn expand_lets_callers_override_builtins() {
+    let mut vars = VarMap::default();
+    vars.set("!home", "/home/test").set("!xdg_conf", "/etc/xdg");
+
+    assert_eq!(
+        expand("!home/.zshrc !xdg_conf/nvim", &vars).expect("expand overrides"),
+        "/home/test/.zshrc /etc/xdg/nvim"
+    );
+}
+
+#[test]
+fn expand_rejects_unknown_and_removed_variables() {
+    let mut vars = VarMap::empty();
+    vars.set("!repo", "/srv/dotfiles");
+    assert!(!vars.contains("!home"));
+
+    assert_eq!(
+        expand("!home/.zshrc", &vars).expect_err("no built-ins"),
+        "Invalid keyword in !home/.zshrc"
+    );
+    vars.remove("!repo");
+    assert!(expand("!repo/nvim", &vars).is_err());
+}
+
+#[test]
+fn expand_prefers_the_longest_variable() {
+    let mut vars = VarMap::empty();
+    vars.set("!repo", "/srv/dotfiles")
+        .set("!repo_work", "/srv/work");
+
+    assert_eq!(
+        expand("!repo_work/git !repo/git", &vars).expect("expand longest"),
+        "/srv/work/git /srv/dotfiles/git"
+    );
+}
+
+#[test]
+fn expand_does_not_expand_values_again() {
+    let mut vars = VarMap::empty();
+    vars.set("!repo", "/srv/!home");
+
+    assert_eq!(expand("!repo", &vars).expect("expand once"), "/srv/!home");
+    assert_eq!(expand("no keywords", &vars).expect("plain"), "no keywords");
+}
+
+#[test]
+fn expand_resolves_app_support_per_platform() {
+    temp_env::with_vars(
+        [
+            ("HOME", Some("/home/test")),
+            ("XDG_CONFIG_HOME", Some("/home/test/conf")),
+            ("XDG_DATA_HOME", None),
+        ],
+        || {
+            let vars = VarMap::default();
+            let expected = if cfg!(target_os = "macos") {
+                "/home/test/Library/Application Support/Code /home/test/Library/Fonts"
+            } else {
+                "/home/test/conf/Code /home/test/.local/share/Fonts"
+            };
+
+            assert_eq!(
+                expand("!app_support/Code !library/Fonts", &vars).expect("expand"),
+                expected
+            );
+        },
+    );
+}
+
+#[test]
+fn explain_tells_where_every_keyword_comes_from() {
+    temp_env::with_vars(
+        [
+            ("HOME", Some("/home/test")),
+            ("XDG_CONFIG_HOME", None),
+            ("XDG_DATA_HOME", Some("/home/test/data")),
+        ],
+        || {
+            let mut vars = VarMap::default();
+            vars.set("!repo", "/srv/dotfiles");
+
+            let (expanded, steps) = explain("!repo:!xdg_conf:!xdg_data", &vars).expect("explain");
+            assert_eq!(expanded, "/srv/dotfiles:/home/test/.config:/home/test/data");
+            assert_eq!(
+                steps,
+                vec![
+                    Step {
+                        keyword: String::from("!repo"),
+                        origin: String::from("set by dbdm"),
+                        value: "/srv/dotfiles".into(),
+                    },
+                    Step {
+                        keyword: String::from("!xdg_conf"),
+                        origin: String::from("$HOME/.config, as $XDG_CONFIG_HOME is unset"),
+                        value: "/home/test/.config".into(),
+                    },
+                    Step {
+                        keyword: String::from("!xdg_data"),
+                        origin: String::from("$XDG_DATA_HOME"),
+                        value: "/home/test/data".into(),
+                    },
+                ]
+            );
+        },
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn expand_keeps_bytes_that_are_not_utf8() {
+    use std::ffi::OsStr;
+    use std::os::unix::ffi::OsStrExt;
+
+    let mut vars = VarMap::empty();
+    vars.set("!repo", OsStr::from_bytes(b"/srv/caf\xe9"));
+
+    assert_eq!(
+        expand(OsStr::from_bytes(b"!repo/\xff"), &vars).expect("expand bytes"),
+        OsStr::from_bytes(b"/srv/caf\xe9/\xff")
+    );
+}
@@ -0,0 +1,113 @@
+use dbdm::config_parser::{Config, Link, LinkKind};
+use dbdm::export::{Content, Entry, Format, export, write};
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use tempfile::tempdir;
+
+// A repository with a zsh config at the top and an nvim directory
+fn dotfiles(root: &Path) -> (PathBuf, PathBuf, Config) {
+    let repo = root.join("dots");
+    let home = root.join("home");
+    fs::create_dir_all(repo.join("nvim/lua")).expect("create repo");
+    fs::create_dir_all(&home).expect("create home");
+    fs::write(repo.join("zshrc"), "export EDITOR=nvim").expect("write zshrc");
+    fs::write(repo.join("nvim/init.lua"), "require('opts')").expect("write init.lua");
+    fs::write(repo.join("nvim/lua/opts.lua"), "-- opts").expect("write opts.lua");
+    fs::write(repo.join("nvim/.luarc.json"), "{}").expect("write luarc");
+    fs::write(repo.join("hosts"), "127.0.0.1").expect("write hosts");
+
+    let mut system = Link::new(repo.join("hosts"), root.join("etc/hosts"));
+    system.kind = LinkKind::System;
+    let config = Config {
+        links: vec![
+            Link::new(repo.join("zshrc"), home.join(".zshrc")),
+            Link::new(repo.join("nvim"), home.join(".config/nvim")),
+            Link::new(repo.join("zshrc"), root.join("elsewhere")),
+            system,
+        ],
+        ..Default::default()
+    };
+    (repo, home, config)
+}
+
+fn paths(entries: &[Entry]) -> Vec<String> {
+    entries
+        .iter()
+        .map(|entry| entry.path.display().to_string())
+        .collect()
+}
+
+#[test]
+fn stow_puts_directories_into_their_own_package() {
+    let tmp = tempdir().expect("tempdir");
+    let (repo, home, config) = dotfiles(tmp.path());
+
+    let exported = export(&config, &repo, &home, Format::Stow);
+
+    assert_eq!(
+        paths(&exported.entries),
+        [
+            "dotfiles/.zshrc",
+            "nvim/.config/nvim/.luarc.json",
+            "nvim/.config/nvim/init.lua",
+            "nvim/.config/nvim/lua/opts.lua",
+        ]
+    );
+    assert_eq!(
+        exported.entries[0].content,
+        Content::Copy(repo.join("zshrc"))
+    );
+    assert_eq!(exported.skipped.len(), 2);
+}
+
+#[test]
+fn chezmoi_encodes_attributes_in_names() {
+    let tmp = tempdir().expect("tempdir");
+    let (repo, home, mut config) = dotfiles(tmp.path());
+    fs::create_dir(repo.join("bin")).expect("create bin");
+    fs::write(repo.join("bin/hello"), "#!/bin/sh").expect("write script");
+    fs::set_permissions(repo.join("bin/hello"), fs::Permissions::from_mode(0o755))
+        .expect("chmod script");
+    std::os::unix::fs::symlink("hello", repo.join("bin/hi")).expect("link script");
+    config
+        .links
+        .push(Link::new(repo.join("bin"), home.join(".local/bin")));
+
+    let exported = export(&config, &repo, &home, Format::Chezmoi);
+
+    assert_eq!(
+        paths(&exported.entries),
+        [
+            "dot_zshrc",
+            "dot_config/nvim/dot_luarc.json",
+            "dot_config/nvim/init.lua",
+            "dot_config/nvim/lua/opts.lua",
+            "dot_local/bin/executable_hello",
+            "dot_local/bin/symlink_hi",
+        ]
+    );
+    assert_eq!(
+        exported.entries[5].content,
+        Content::Text(b"hello".to_vec())
+    );
+}
+
+#[test]
+fn write_creates_the_layout() {
+    let tmp = tempdir().expect("tempdir");
+    let (repo, home, config) = dotfiles(tmp.path());
+    let out = tmp.path().join("stow");
+
+    let exported = export(&config, &repo, &home, Format::Stow);
+    write(&exported, &out).expect("write export");
+
+    assert_eq!(
+        fs::read_to_string(out.join("nvim/.config/nvim/lua/opts.lua")).expect("read export"),
+        "-- opts"
+    );
+    assert_eq!(
+        fs::read_to_string(out.join("dotfiles/.zshrc")).expect("read export"),
+        "export EDITOR=nvim"
+    );
+}
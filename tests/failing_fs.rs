@@ -0,0 +1,122 @@
+use dbdm::fs::{FailingFs, Fs, MemoryFs};
+use dbdm::{
+    BackupNaming, BackupOutcome, FsSnapshot, backup_and_replace_resolved_with,
+    replace_snapshot_with,
+};
+use std::path::{Path, PathBuf};
+
+// Helper to set up a destination with local changes that differ from the source
+fn conflicting_home() -> MemoryFs {
+    let fs = MemoryFs::new();
+    fs.add_file(Path::new("/repo/bashrc"), b"alias ll='ls -l'");
+    fs.add_file(Path::new("/home/.bashrc"), b"local changes");
+    fs
+}
+
+fn backup_and_replace(fs: &dyn Fs) -> std::io::Result<BackupOutcome> {
+    backup_and_replace_resolved_with(
+        fs,
+        Path::new("/repo/bashrc"),
+        Path::new("/home/.bashrc"),
+        Path::new("/backups"),
+        &BackupNaming::default(),
+    )
+}
+
+#[test]
+fn failing_fs_fails_only_the_nth_call() {
+    let fs = FailingFs::new(conflicting_home()).fail_nth(2);
+
+    assert!(fs.stat(Path::new("/repo/bashrc")).is_ok());
+    let err = fs
+        .stat(Path::new("/home/.bashrc"))
+        .expect_err("second call fails");
+    assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+    assert!(err.to_string().contains("/home/.bashrc"));
+    assert!(fs.stat(Path::new("/home/.bashrc")).is_ok());
+    assert_eq!(fs.calls(), 3);
+}
+
+#[test]
+fn failing_fs_fails_calls_below_a_path() {
+    let fs = FailingFs::new(conflicting_home())
+        .fail_on(Path::new("/home"))
+        .with_error(std::io::ErrorKind::ReadOnlyFilesystem);
+
+    let err = fs
+        .read(Path::new("/home/.bashrc"))
+        .expect_err("below the path");
+    assert_eq!(err.kind(), std::io::ErrorKind::ReadOnlyFilesystem);
+    assert!(fs.read(Path::new("/repo/bashrc")).is_ok());
+    // `/homework` isn't below `/home`
+    assert!(fs.create_dir_all(Path::new("/homework")).is_ok());
+}
+
+#[test]
+fn backup_failure_leaves_the_destination_alone() {
+    let fs = FailingFs::new(conflicting_home()).fail_on(Path::new("/backups"));
+
+    let err = backup_and_replace(&fs).expect_err("backup dir can't be made");
+    assert_eq!(err.kind(), std::io::ErrorKind::PermissionDenied);
+    assert_eq!(
+        fs.inner()
+            .read(Path::new("/home/.bashrc"))
+            .expect("read destination"),
+        b"local changes"
+    );
+}
+
+#[test]
+fn link_failure_after_backup_keeps_the_backup() {
+    let succeeding = FailingFs::new(conflicting_home());
+    backup_and_replace(&succeeding).expect("backup and replace");
+    // The symlink is the last call
+    let fs = FailingFs::new(conflicting_home()).fail_nth(succeeding.calls());
+
+    backup_and_replace(&fs).expect_err("symlink fails");
+    assert!(fs.inner().stat(Path::new("/home/.bashrc")).is_err());
+    assert_eq!(
+        fs.inner()
+            .read(Path::new("/backups/.bashrc.bak.dbdm"))
+            .expect("read backup"),
+        b"local changes"
+    );
+}
+
+#[test]
+fn no_single_failure_loses_the_destination() {
+    let succeeding = FailingFs::new(conflicting_home());
+    backup_and_replace(&succeeding).expect("backup and replace");
+
+    for n in 1..=succeeding.calls() {
+        let fs = FailingFs::new(conflicting_home()).fail_nth(n);
+        // A failed look only makes the destination count as different
+        let _ = backup_and_replace(&fs);
+
+        let kept = [
+            PathBuf::from("/home/.bashrc"),
+            PathBuf::from("/backups/.bashrc.bak.dbdm"),
+        ]
+        .iter()
+        .any(|path| fs.inner().read(path).ok().as_deref() == Some(b"local changes".as_slice()));
+        assert!(kept, "call {} lost the destination", n);
+    }
+}
+
+#[test]
+fn replace_failure_keeps_the_old_link() {
+    let home = MemoryFs::new();
+    home.add_file(Path::new("/repo/gitconfig"), b"[user]");
+    home.add_symlink(Path::new("/home/.gitconfig"), Path::new("/old/gitconfig"));
+    let snapshot = FsSnapshot::capture_with(&home, Path::new("/home/.gitconfig"));
+    let fs = FailingFs::new(home).fail_nth(2);
+
+    // The destination is looked at again, then removed
+    replace_snapshot_with(&fs, Path::new("/repo/gitconfig"), &snapshot).expect_err("remove fails");
+    assert_eq!(
+        fs.inner()
+            .read_link(Path::new("/home/.gitconfig"))
+            .expect("read old link"),
+        PathBuf::from("/old/gitconfig")
+    );
+}
@@ -0,0 +1,63 @@
+use dbdm::{FsSnapshot, replace_snapshot, snapshot_link_destination};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn snapshot_describes_the_destination() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let missing = FsSnapshot::capture(&workspace.path().join("missing.txt"));
+    assert!(!missing.exists());
+    assert!(missing.link_target.is_none());
+
+    let linked = workspace.path().join("linked.txt");
+    std::os::unix::fs::symlink(&source_file, &linked).expect("create link");
+    let snapshot = FsSnapshot::capture(&linked);
+    assert!(snapshot.exists());
+    assert_eq!(snapshot.link_target, Some(source_file.clone()));
+    assert_eq!(
+        snapshot.target_full,
+        Some(fs::canonicalize(&source_file).expect("canonicalize source"))
+    );
+
+    let broken = workspace.path().join("broken.txt");
+    std::os::unix::fs::symlink(workspace.path().join("gone.txt"), &broken)
+        .expect("create broken link");
+    let snapshot = FsSnapshot::capture(&broken);
+    assert!(snapshot.link_target.is_some());
+    assert!(snapshot.target_full.is_none());
+}
+
+#[test]
+fn snapshot_follows_destination_resolution() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let dest_dir = workspace.path().join("dest");
+    fs::create_dir(&dest_dir).expect("create dest dir");
+    fs::write(dest_dir.join("source.txt"), "conflict").expect("write conflict");
+
+    let snapshot = snapshot_link_destination(&source_file, &dest_dir).expect("snapshot");
+    assert_eq!(snapshot.path, dest_dir.join("source.txt"));
+    assert!(snapshot.meta.expect("destination exists").is_file());
+}
+
+#[test]
+fn replace_snapshot_fails_if_the_destination_appeared() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let dest_file = workspace.path().join("linked.txt");
+
+    let snapshot = snapshot_link_destination(&source_file, &dest_file).expect("snapshot");
+    fs::write(&dest_file, "written after planning").expect("write dest");
+
+    let err = replace_snapshot(&source_file, &snapshot).expect_err("destination changed");
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+    assert_eq!(
+        fs::read_to_string(&dest_file).expect("read dest"),
+        "written after planning"
+    );
+}
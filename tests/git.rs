@@ -0,0 +1,109 @@
+use dbdm::git::{HOOK_MARKER, default_clone_dir, repo_root, status, sync_hook};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn git(dir: &Path, args: &[&str]) {
+    let status = std::process::Command::new("git")
+        .args(["-c", "user.name=dbdm", "-c", "user.email=dbdm@example.com"])
+        .args(args)
+        .current_dir(dir)
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .expect("run git");
+    assert!(status.success());
+}
+
+fn commit_all(dir: &Path) {
+    git(dir, &["add", "."]);
+    git(dir, &["commit", "-q", "-m", "update"]);
+}
+
+#[test]
+fn clone_dir_is_the_last_path_segment() {
+    assert_eq!(
+        default_clone_dir("https://github.com/me/dotfiles.git").as_deref(),
+        Some("dotfiles")
+    );
+    assert_eq!(
+        default_clone_dir("git@github.com:me/dotfiles").as_deref(),
+        Some("dotfiles")
+    );
+    assert_eq!(
+        default_clone_dir("git@host:dots.git").as_deref(),
+        Some("dots")
+    );
+    assert_eq!(
+        default_clone_dir("/srv/git/dotfiles/").as_deref(),
+        Some("dotfiles")
+    );
+}
+
+#[test]
+fn clone_dir_needs_a_name() {
+    assert_eq!(default_clone_dir("https://host/.git"), None);
+    assert_eq!(default_clone_dir(""), None);
+}
+
+#[test]
+fn status_counts_uncommitted_changes() {
+    let tmp = tempdir().expect("tempdir");
+    let repo = tmp.path().join("dots");
+    fs::create_dir_all(repo.join("nvim")).expect("create repo");
+    fs::write(repo.join("nvim/init.lua"), "config").expect("write file");
+    git(&repo, &["init", "-q"]);
+    commit_all(&repo);
+
+    let root = repo_root(&repo.join("nvim/init.lua")).expect("find repo root");
+    assert_eq!(
+        fs::canonicalize(&root).expect("canonicalize root"),
+        fs::canonicalize(&repo).expect("canonicalize repo")
+    );
+    assert!(status(&root).expect("get status").is_clean());
+
+    fs::write(repo.join("nvim/init.lua"), "changed").expect("change file");
+    fs::write(repo.join("zshrc"), "new").expect("add file");
+    let found = status(&root).expect("get status");
+    assert_eq!(found.changed, 2);
+    assert_eq!(found.behind, 0);
+}
+
+#[test]
+fn status_counts_commits_behind_upstream() {
+    let tmp = tempdir().expect("tempdir");
+    let origin = tmp.path().join("origin");
+    fs::create_dir(&origin).expect("create origin");
+    fs::write(origin.join("zshrc"), "one").expect("write file");
+    git(&origin, &["init", "-q"]);
+    commit_all(&origin);
+    git(tmp.path(), &["clone", "-q", "origin", "clone"]);
+
+    fs::write(origin.join("zshrc"), "two").expect("change file");
+    commit_all(&origin);
+    let clone = tmp.path().join("clone");
+    git(&clone, &["fetch", "-q"]);
+
+    let found = status(&clone).expect("get status");
+    assert_eq!(found.changed, 0);
+    assert_eq!(found.behind, 1);
+}
+
+#[test]
+fn paths_outside_a_repository_have_no_root() {
+    let tmp = tempdir().expect("tempdir");
+    fs::write(tmp.path().join("zshrc"), "config").expect("write file");
+
+    assert_eq!(repo_root(&tmp.path().join("zshrc")), None);
+}
+
+#[test]
+fn sync_hook_quotes_paths() {
+    let hook = sync_hook(Path::new("/usr/bin/dbdm"), Path::new("/home/me/it's dots"));
+
+    assert!(hook.starts_with("#!/bin/sh\n"));
+    assert!(hook.contains(HOOK_MARKER));
+    assert!(hook.contains(
+        "cd '/home/me/it'\\''s dots' && exec '/usr/bin/dbdm' sync --non-interactive --on-conflict skip\n"
+    ));
+}
@@ -0,0 +1,102 @@
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+use tempfile::tempdir;
+
+fn install(dir: &Path, extra: &[&str]) -> bool {
+    std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["git-hooks", "install"])
+        .args(extra)
+        .current_dir(dir)
+        .stderr(std::process::Stdio::null())
+        .status()
+        .expect("run dbdm git-hooks install")
+        .success()
+}
+
+#[test]
+fn installed_hooks_sync_the_config() {
+    let workspace = tempdir().expect("create temp workspace");
+    let repo = workspace.path().join("dots");
+    fs::create_dir_all(repo.join("config")).expect("create repo");
+    let status = std::process::Command::new("git")
+        .args(["init", "-q"])
+        .current_dir(&repo)
+        .status()
+        .expect("run git init");
+    assert!(status.success());
+
+    let source_file = repo.join("config/zshrc");
+    fs::write(&source_file, "example").expect("write source file");
+    let new_dest = workspace.path().join("zshrc");
+    let conflict_dest = workspace.path().join("conflict");
+    fs::write(&conflict_dest, "conflict").expect("create conflicting dest file");
+    fs::write(
+        repo.join("config/dbdm.conf"),
+        format!(
+            "link = {} {}\nlink = {} {}\n",
+            source_file.display(),
+            new_dest.display(),
+            source_file.display(),
+            conflict_dest.display()
+        ),
+    )
+    .expect("write config");
+
+    assert!(install(&repo.join("config"), &[]));
+
+    for hook in ["post-merge", "post-checkout"] {
+        let path = repo.join(".git/hooks").join(hook);
+        let mode = fs::metadata(&path).expect("stat hook").permissions().mode();
+        assert_eq!(mode & 0o111, 0o111);
+    }
+
+    // Running it from anywhere syncs, and conflicts are left alone
+    let status = std::process::Command::new(repo.join(".git/hooks/post-merge"))
+        .current_dir(workspace.path())
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .stdin(std::process::Stdio::null())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .expect("run hook");
+    assert!(status.success());
+    assert_eq!(fs::read_link(&new_dest).expect("read link"), source_file);
+    assert_eq!(
+        fs::read_to_string(&conflict_dest).expect("read conflict"),
+        "conflict"
+    );
+}
+
+#[test]
+fn foreign_hooks_need_force() {
+    let workspace = tempdir().expect("create temp workspace");
+    let repo = workspace.path();
+    let status = std::process::Command::new("git")
+        .args(["init", "-q"])
+        .current_dir(repo)
+        .status()
+        .expect("run git init");
+    assert!(status.success());
+    fs::write(repo.join("dbdm.conf"), "").expect("write config");
+
+    let hook = repo.join(".git/hooks/post-merge");
+    fs::create_dir_all(hook.parent().expect("hooks dir")).expect("create hooks dir");
+    fs::write(&hook, "#!/bin/sh\necho mine\n").expect("write foreign hook");
+
+    assert!(!install(repo, &[]));
+    assert_eq!(
+        fs::read_to_string(&hook).expect("read hook"),
+        "#!/bin/sh\necho mine\n"
+    );
+
+    assert!(install(repo, &["--force"]));
+    assert!(
+        fs::read_to_string(&hook)
+            .expect("read hook")
+            .contains("sync --non-interactive")
+    );
+
+    // Hooks written by dbdm are replaced without asking
+    assert!(install(repo, &[]));
+}
@@ -0,0 +1,53 @@
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn syncs_are_listed_and_shown_in_history() {
+    let workspace = tempdir().expect("create temp workspace");
+    let state_home = workspace.path().join("state");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let dest_file = workspace.path().join("linked.txt");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    fs::write(
+        &config_path,
+        format!("link = {} {}\n", source_file.display(), dest_file.display()),
+    )
+    .expect("write config");
+
+    let run = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+            .args(args)
+            .env("XDG_STATE_HOME", &state_home)
+            .env("NO_COLOR", "1")
+            .current_dir(workspace.path())
+            .stdin(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .output()
+            .expect("run dbdm")
+    };
+
+    assert!(run(&["sync", "--non-interactive"]).status.success());
+    assert!(run(&["sync", "--non-interactive"]).status.success());
+
+    let list = run(&["history"]);
+    assert!(list.status.success());
+    let list = String::from_utf8(list.stdout).expect("utf8 stdout");
+    let rows: Vec<&str> = list.lines().skip(1).collect();
+    assert_eq!(rows.len(), 2);
+    assert!(rows[0].starts_with('1'));
+    assert!(rows[0].contains("1 create"));
+    assert!(rows[1].starts_with('2'));
+    assert!(rows[1].contains("1 ignore"));
+
+    let show = run(&["history", "show", "1"]);
+    assert!(show.status.success());
+    let show = String::from_utf8(show.stdout).expect("utf8 stdout");
+    assert!(show.starts_with("sync #1 at "));
+    assert!(show.contains(&dest_file.display().to_string()));
+    assert!(show.contains("create"));
+
+    assert!(!run(&["history", "show", "3"]).status.success());
+}
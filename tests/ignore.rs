@@ -0,0 +1,53 @@
+use dbdm::ignore::Ignore;
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+#[test]
+fn names_without_a_slash_match_at_any_depth() {
+    let ignore = Ignore::parse("# editor leftovers\n\n*.swp\n__pycache__/\n");
+
+    assert!(ignore.is_ignored(Path::new("init.lua.swp"), false));
+    assert!(ignore.is_ignored(Path::new("lua/plugins/init.lua.swp"), false));
+    assert!(ignore.is_ignored(Path::new("lua/__pycache__"), true));
+    assert!(!ignore.is_ignored(Path::new("lua/init.lua"), false));
+    // Only directories match a trailing slash
+    assert!(!ignore.is_ignored(Path::new("__pycache__"), false));
+}
+
+#[test]
+fn patterns_with_a_slash_match_from_the_top() {
+    let ignore = Ignore::parse("/.git\ndocs/*.md\nlua/**/test\n");
+
+    assert!(ignore.is_ignored(Path::new(".git"), true));
+    assert!(!ignore.is_ignored(Path::new("lua/.git"), true));
+    assert!(ignore.is_ignored(Path::new("docs/notes.md"), false));
+    assert!(!ignore.is_ignored(Path::new("docs/old/notes.md"), false));
+    assert!(ignore.is_ignored(Path::new("lua/test"), true));
+    assert!(ignore.is_ignored(Path::new("lua/a/b/test"), true));
+}
+
+#[test]
+fn contents_of_ignored_directories_are_ignored() {
+    let ignore = Ignore::parse(".git\n");
+
+    assert!(ignore.is_ignored(Path::new(".git/config"), false));
+    assert!(ignore.is_ignored(Path::new("plugins/.git/HEAD"), false));
+}
+
+#[test]
+fn negated_patterns_bring_paths_back() {
+    let ignore = Ignore::parse("*.log\n!keep.log\n");
+
+    assert!(ignore.is_ignored(Path::new("debug.log"), false));
+    assert!(!ignore.is_ignored(Path::new("logs/keep.log"), false));
+}
+
+#[test]
+fn missing_file_ignores_nothing() {
+    let tmp = tempdir().expect("tempdir");
+    assert!(!Ignore::load(tmp.path()).is_ignored(Path::new("anything"), false));
+
+    fs::write(tmp.path().join(".dbdmignore"), "anything\n").expect("write ignore file");
+    assert!(Ignore::load(tmp.path()).is_ignored(Path::new("anything"), false));
+}
@@ -0,0 +1,182 @@
+use dbdm::catalog;
+use dbdm::import::{Found, Suggestion, config_line, scan, suggest, suggestion_line};
+use std::fs;
+use std::os::unix::fs::symlink;
+use std::path::Path;
+use tempfile::tempdir;
+
+#[test]
+fn finds_symlinks_into_the_repository() {
+    let tmp = tempdir().expect("tempdir");
+    let root = fs::canonicalize(tmp.path()).expect("canonicalize tempdir");
+    let repo = root.join("dots");
+    let home = root.join("home");
+    fs::create_dir_all(repo.join("nvim")).expect("create repo");
+    fs::write(repo.join("zshrc"), "config").expect("write source");
+    fs::create_dir_all(home.join(".config")).expect("create home");
+    fs::write(root.join("elsewhere"), "other").expect("write other file");
+
+    symlink(repo.join("zshrc"), home.join(".zshrc")).expect("link zshrc");
+    symlink("../../dots/nvim", home.join(".config/nvim")).expect("link nvim");
+    symlink(root.join("elsewhere"), home.join(".other")).expect("link other");
+
+    let found = scan(&home, &repo, 4).expect("scan home");
+
+    assert_eq!(
+        found,
+        vec![
+            Found {
+                link: home.join(".config/nvim"),
+                target: repo.join("nvim"),
+            },
+            Found {
+                link: home.join(".zshrc"),
+                target: repo.join("zshrc"),
+            },
+        ]
+    );
+}
+
+#[test]
+fn stops_at_the_depth() {
+    let tmp = tempdir().expect("tempdir");
+    let root = fs::canonicalize(tmp.path()).expect("canonicalize tempdir");
+    let repo = root.join("dots");
+    let home = root.join("home");
+    fs::create_dir_all(&repo).expect("create repo");
+    fs::write(repo.join("init.lua"), "config").expect("write source");
+    fs::create_dir_all(home.join("a/b")).expect("create home");
+    symlink(repo.join("init.lua"), home.join("a/b/init.lua")).expect("link deep file");
+
+    assert!(scan(&home, &repo, 2).expect("scan home").is_empty());
+    assert_eq!(scan(&home, &repo, 3).expect("scan home").len(), 1);
+}
+
+#[test]
+fn lines_use_keywords_and_quotes() {
+    let found = Found {
+        link: "/home/me/.config/my app".into(),
+        target: "/home/me/dots/app".into(),
+    };
+
+    assert_eq!(
+        config_line(
+            &found,
+            Path::new("/home/me/dots"),
+            Some(Path::new("/home/me"))
+        ),
+        Some("link = !here/app \"!home/.config/my app\"".into())
+    );
+    assert_eq!(
+        config_line(&found, Path::new("/home/me/dots"), None),
+        Some("link = !here/app \"/home/me/.config/my app\"".into())
+    );
+
+    let bang = Found {
+        link: "/home/me/hey!".into(),
+        target: "/home/me/dots/app".into(),
+    };
+    assert_eq!(config_line(&bang, Path::new("/home/me/dots"), None), None);
+}
+
+#[test]
+fn scan_leaves_out_configured_links() {
+    let tmp = tempdir().expect("tempdir");
+    let root = fs::canonicalize(tmp.path()).expect("canonicalize tempdir");
+    let repo = root.join("dots");
+    let home = root.join("home");
+    fs::create_dir_all(&repo).expect("create repo");
+    fs::create_dir_all(&home).expect("create home");
+    fs::write(repo.join("zshrc"), "config").expect("write source");
+    fs::write(repo.join("vimrc"), "config").expect("write source");
+    symlink(repo.join("zshrc"), home.join(".zshrc")).expect("link zshrc");
+    symlink(repo.join("vimrc"), home.join(".vimrc")).expect("link vimrc");
+    fs::write(repo.join("dbdm.conf"), "link = !here/zshrc !home/.zshrc\n").expect("write config");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["import", "scan"])
+        .arg(&home)
+        .env("HOME", &home)
+        .current_dir(&repo)
+        .output()
+        .expect("run dbdm import scan");
+    assert!(output.status.success());
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "link = !here/vimrc !home/.vimrc\n"
+    );
+}
+
+#[test]
+fn known_configs_are_suggested_per_os() {
+    let tmp = tempdir().expect("tempdir");
+    let repo = tmp.path().join("dots");
+    fs::create_dir_all(repo.join("kitty")).expect("create kitty");
+    fs::create_dir_all(repo.join("hypr")).expect("create hypr");
+    fs::write(repo.join(".zshrc"), "# zsh").expect("write zshrc");
+    fs::write(repo.join("README.md"), "my dots").expect("write readme");
+
+    let suggestions = suggest(&repo, "linux").expect("suggest");
+    assert_eq!(
+        suggestions,
+        vec![
+            Suggestion {
+                source: repo.join(".zshrc"),
+                destination: "!home/.zshrc",
+            },
+            Suggestion {
+                source: repo.join("hypr"),
+                destination: "!xdg_conf/hypr",
+            },
+            Suggestion {
+                source: repo.join("kitty"),
+                destination: "!xdg_conf/kitty",
+            },
+        ]
+    );
+    assert_eq!(
+        suggestion_line(&suggestions[2], &repo),
+        Some("link = !here/kitty !xdg_conf/kitty".into())
+    );
+
+    // Hyprland doesn't run on macOS
+    let on_macos: Vec<_> = suggest(&repo, "macos")
+        .expect("suggest")
+        .into_iter()
+        .map(|suggestion| suggestion.destination)
+        .collect();
+    assert_eq!(on_macos, vec!["!home/.zshrc", "!xdg_conf/kitty"]);
+}
+
+#[test]
+fn ignored_configs_are_not_suggested() {
+    let tmp = tempdir().expect("tempdir");
+    let repo = tmp.path().join("dots");
+    fs::create_dir_all(repo.join("kitty")).expect("create kitty");
+    fs::write(repo.join(".zshrc"), "# zsh").expect("write zshrc");
+    fs::write(repo.join(".dbdmignore"), "/kitty/\n").expect("write ignore file");
+
+    let suggestions = suggest(&repo, "linux").expect("suggest");
+    assert_eq!(
+        suggestions,
+        vec![Suggestion {
+            source: repo.join(".zshrc"),
+            destination: "!home/.zshrc",
+        }]
+    );
+}
+
+#[test]
+fn catalog_names_are_unique() {
+    for (idx, app) in catalog::APPS.iter().enumerate() {
+        assert!(
+            catalog::APPS[idx + 1..]
+                .iter()
+                .all(|other| other.name != app.name),
+            "{} is in the catalog twice",
+            app.name
+        );
+        assert!(!app.name.starts_with('.'), "{} has a leading dot", app.name);
+    }
+}
@@ -0,0 +1,63 @@
+use dbdm::lock::{acquire, lock_path};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn lock_is_exclusive_per_config() {
+    let workspace = tempdir().expect("create temp workspace");
+    let runtime_dir = workspace.path().join("runtime");
+    let config_dir = workspace.path().join("dots");
+    let other_dir = workspace.path().join("other");
+    fs::create_dir(&config_dir).expect("create config dir");
+    fs::create_dir(&other_dir).expect("create other dir");
+
+    temp_env::with_var("XDG_RUNTIME_DIR", Some(&runtime_dir), || {
+        let lock = acquire(&config_dir).expect("take the lock");
+        assert!(
+            lock_path(&config_dir)
+                .expect("lock path")
+                .starts_with(&runtime_dir)
+        );
+
+        let err = acquire(&config_dir).expect_err("lock is held");
+        assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+        assert!(err.to_string().contains(&std::process::id().to_string()));
+
+        // Other configs have a lock of their own
+        acquire(&other_dir).expect("take the other lock");
+
+        drop(lock);
+        acquire(&config_dir).expect("take the lock again");
+    });
+}
+
+#[test]
+fn sync_refuses_to_run_while_locked() {
+    let workspace = tempdir().expect("create temp workspace");
+    let runtime_dir = workspace.path().join("runtime");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let dest_file = workspace.path().join("linked.txt");
+    fs::write(
+        workspace.path().join("dbdm.conf"),
+        format!("link = {} {}\n", source_file.display(), dest_file.display()),
+    )
+    .expect("write config");
+
+    temp_env::with_var("XDG_RUNTIME_DIR", Some(&runtime_dir), || {
+        let _lock = acquire(workspace.path()).expect("take the lock");
+
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+            .args(["sync", "--non-interactive"])
+            .env("XDG_RUNTIME_DIR", &runtime_dir)
+            .env("XDG_STATE_HOME", workspace.path().join("state"))
+            .current_dir(workspace.path())
+            .stdin(std::process::Stdio::null())
+            .output()
+            .expect("run dbdm sync");
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("another dbdm process is running"));
+        assert!(fs::symlink_metadata(&dest_file).is_err());
+    });
+}
@@ -0,0 +1,223 @@
+use dbdm::fs::{FileKind, Fs, MemoryFs};
+use dbdm::{
+    BackupNaming, BackupOutcome, FsSnapshot, backup_and_replace_resolved_with,
+    replace_snapshot_with, same_content_with, unique_backup_path_with,
+};
+use std::path::{Path, PathBuf};
+
+#[test]
+fn memory_fs_tracks_files_dirs_and_symlinks() {
+    let fs = MemoryFs::new();
+    fs.add_file(Path::new("/repo/vimrc"), b"set number");
+    fs.add_dir(Path::new("/home/.config"));
+    fs.symlink(Path::new("/repo/vimrc"), Path::new("/home/.vimrc"))
+        .expect("create link");
+
+    assert_eq!(
+        fs.stat(Path::new("/repo/vimrc")).expect("stat file").len,
+        10
+    );
+    assert_eq!(
+        fs.stat(Path::new("/home/.vimrc")).expect("stat link").kind,
+        FileKind::Symlink
+    );
+    assert_eq!(
+        fs.read_link(Path::new("/home/.vimrc")).expect("read link"),
+        PathBuf::from("/repo/vimrc")
+    );
+    assert_eq!(
+        fs.read(Path::new("/home/.vimrc"))
+            .expect("read through link"),
+        b"set number"
+    );
+    assert_eq!(
+        fs.read_dir(Path::new("/home")).expect("read home"),
+        vec![".config", ".vimrc"]
+    );
+
+    let err = fs
+        .symlink(Path::new("/repo/vimrc"), Path::new("/home/.vimrc"))
+        .expect_err("link over an existing path");
+    assert_eq!(err.kind(), std::io::ErrorKind::AlreadyExists);
+    let err = fs
+        .symlink(Path::new("/repo/vimrc"), Path::new("/missing/.vimrc"))
+        .expect_err("link in a missing directory");
+    assert_eq!(err.kind(), std::io::ErrorKind::NotFound);
+}
+
+#[test]
+fn memory_fs_renames_and_removes_whole_trees() {
+    let fs = MemoryFs::new();
+    fs.add_file(Path::new("/home/.config/nvim/init.lua"), b"-- nvim");
+    fs.add_dir(Path::new("/backups"));
+
+    fs.rename(
+        Path::new("/home/.config/nvim"),
+        Path::new("/backups/nvim.bak"),
+    )
+    .expect("rename dir");
+    assert!(fs.stat(Path::new("/home/.config/nvim")).is_err());
+    assert_eq!(
+        fs.read(Path::new("/backups/nvim.bak/init.lua"))
+            .expect("read moved file"),
+        b"-- nvim"
+    );
+
+    fs.remove(Path::new("/backups")).expect("remove dir");
+    assert_eq!(
+        fs.paths(),
+        vec![PathBuf::from("/home"), PathBuf::from("/home/.config")]
+    );
+}
+
+#[test]
+fn memory_fs_canonicalizes_relative_and_chained_links() {
+    let fs = MemoryFs::new();
+    fs.add_file(Path::new("/repo/shell/zshrc"), b"");
+    fs.add_symlink(Path::new("/home/dotfiles"), Path::new("../repo"));
+    fs.add_symlink(Path::new("/home/.zshrc"), Path::new("dotfiles/shell/zshrc"));
+
+    assert_eq!(
+        fs.canonicalize(Path::new("/home/.zshrc"))
+            .expect("canonicalize chain"),
+        PathBuf::from("/repo/shell/zshrc")
+    );
+
+    fs.add_symlink(Path::new("/loop/a"), Path::new("b"));
+    fs.add_symlink(Path::new("/loop/b"), Path::new("a"));
+    assert!(fs.canonicalize(Path::new("/loop/a")).is_err());
+}
+
+#[test]
+fn replace_snapshot_with_replaces_what_was_planned() {
+    let fs = MemoryFs::new();
+    fs.add_file(Path::new("/repo/gitconfig"), b"[user]");
+    fs.add_symlink(Path::new("/home/.gitconfig"), Path::new("/old/gitconfig"));
+
+    let snapshot = FsSnapshot::capture_with(&fs, Path::new("/home/.gitconfig"));
+    assert_eq!(snapshot.link_target, Some(PathBuf::from("/old/gitconfig")));
+    assert!(snapshot.target_full.is_none());
+
+    replace_snapshot_with(&fs, Path::new("/repo/gitconfig"), &snapshot).expect("replace link");
+    assert_eq!(
+        fs.read_link(Path::new("/home/.gitconfig"))
+            .expect("read new link"),
+        PathBuf::from("/repo/gitconfig")
+    );
+}
+
+#[test]
+fn replace_snapshot_with_refuses_a_destination_that_changed_kind() {
+    let fs = MemoryFs::new();
+    fs.add_file(Path::new("/repo/tmux.conf"), b"");
+    fs.add_symlink(Path::new("/home/.tmux.conf"), Path::new("/old/tmux.conf"));
+    let snapshot = FsSnapshot::capture_with(&fs, Path::new("/home/.tmux.conf"));
+
+    fs.remove(Path::new("/home/.tmux.conf"))
+        .expect("remove link");
+    fs.add_file(Path::new("/home/.tmux.conf"), b"written since");
+
+    replace_snapshot_with(&fs, Path::new("/repo/tmux.conf"), &snapshot)
+        .expect_err("destination changed");
+    assert_eq!(
+        fs.read(Path::new("/home/.tmux.conf"))
+            .expect("read untouched file"),
+        b"written since"
+    );
+}
+
+#[test]
+fn backup_and_replace_resolved_with_backs_up_different_content() {
+    let fs = MemoryFs::new();
+    fs.add_file(Path::new("/repo/bashrc"), b"alias ll='ls -l'");
+    fs.add_file(Path::new("/home/.bashrc"), b"local changes");
+    fs.add_file(Path::new("/backups/.bashrc.bak.dbdm"), b"older backup");
+
+    let outcome = backup_and_replace_resolved_with(
+        &fs,
+        Path::new("/repo/bashrc"),
+        Path::new("/home/.bashrc"),
+        Path::new("/backups"),
+        &BackupNaming::default(),
+    )
+    .expect("backup and replace");
+
+    let backup = PathBuf::from("/backups/.bashrc.bak.dbdm.1");
+    assert_eq!(outcome, BackupOutcome::BackedUp(backup.clone()));
+    assert_eq!(fs.read(&backup).expect("read backup"), b"local changes");
+    assert_eq!(
+        fs.read_link(Path::new("/home/.bashrc")).expect("read link"),
+        PathBuf::from("/repo/bashrc")
+    );
+}
+
+#[test]
+fn backup_and_replace_resolved_with_refuses_the_source_itself() {
+    let fs = MemoryFs::new();
+    fs.add_file(Path::new("/repo/config/a.toml"), b"a = 1");
+    // The destination directory is a link into the repository
+    fs.add_dir(Path::new("/home"));
+    fs.add_symlink(Path::new("/home/.config"), Path::new("/repo/config"));
+
+    let err = backup_and_replace_resolved_with(
+        &fs,
+        Path::new("/repo/config/a.toml"),
+        Path::new("/home/.config/a.toml"),
+        Path::new("/backups"),
+        &BackupNaming::default(),
+    )
+    .expect_err("refuse the source itself");
+
+    assert_eq!(err.kind(), std::io::ErrorKind::InvalidInput);
+    assert!(err.to_string().contains("destination is the source"));
+    assert_eq!(
+        fs.read(Path::new("/repo/config/a.toml"))
+            .expect("read source"),
+        b"a = 1"
+    );
+}
+
+#[test]
+fn backup_and_replace_resolved_with_skips_identical_content() {
+    let fs = MemoryFs::new();
+    fs.add_file(Path::new("/repo/config/a.toml"), b"a = 1");
+    fs.add_file(Path::new("/home/.config/app/a.toml"), b"a = 1");
+
+    assert!(
+        same_content_with(
+            &fs,
+            Path::new("/repo/config"),
+            Path::new("/home/.config/app")
+        )
+        .expect("compare dirs")
+    );
+    let outcome = backup_and_replace_resolved_with(
+        &fs,
+        Path::new("/repo/config"),
+        Path::new("/home/.config/app"),
+        Path::new("/backups"),
+        &BackupNaming::default(),
+    )
+    .expect("replace identical dir");
+
+    assert_eq!(outcome, BackupOutcome::Identical);
+    assert!(fs.stat(Path::new("/backups")).is_err());
+    assert!(fs.stat(Path::new("/home/.config/app/a.toml")).is_err());
+}
+
+#[test]
+fn unique_backup_path_with_skips_taken_names() {
+    let fs = MemoryFs::new();
+    fs.add_file(Path::new("/backups/zshrc.bak.dbdm"), b"");
+    fs.add_file(Path::new("/backups/zshrc.bak.dbdm.1"), b"");
+
+    assert_eq!(
+        unique_backup_path_with(
+            &fs,
+            Path::new("/backups"),
+            "zshrc",
+            &BackupNaming::default()
+        ),
+        PathBuf::from("/backups/zshrc.bak.dbdm.2")
+    );
+}
@@ -0,0 +1,62 @@
+use dbdm::output::ColorChoice;
+
+#[test]
+fn auto_color_respects_no_color_and_terminal() {
+    temp_env::with_vars(
+        [("NO_COLOR", None::<&str>), ("TERM", Some("xterm"))],
+        || {
+            assert!(ColorChoice::Auto.resolve(true));
+            assert!(!ColorChoice::Auto.resolve(false));
+        },
+    );
+
+    temp_env::with_var("NO_COLOR", Some("1"), || {
+        assert!(!ColorChoice::Auto.resolve(true));
+        assert!(ColorChoice::Always.resolve(true));
+    });
+}
+
+#[test]
+fn color_choice_parses_flag_values() {
+    assert_eq!(ColorChoice::parse("always"), Some(ColorChoice::Always));
+    assert_eq!(ColorChoice::parse("never"), Some(ColorChoice::Never));
+    assert_eq!(ColorChoice::parse("auto"), Some(ColorChoice::Auto));
+    assert_eq!(ColorChoice::parse("sometimes"), None);
+}
+
+#[test]
+fn table_aligns_columns_on_plain_text() {
+    use dbdm::output::{Cell, Color, table};
+
+    let rows = vec![
+        vec![
+            Cell::plain("/a"),
+            Cell::colored("replace", Color::Yellow),
+            Cell::plain(""),
+        ],
+        vec![
+            Cell::plain("/longer/path"),
+            Cell::colored("skip", Color::Dim),
+            Cell::plain("failed"),
+        ],
+    ];
+
+    let lines = table(&["SOURCE", "ACTION", "REASON"], &rows);
+    assert_eq!(
+        lines,
+        vec![
+            "SOURCE        ACTION   REASON",
+            "/a            replace",
+            "/longer/path  skip     failed",
+        ]
+    );
+}
+
+#[test]
+fn sizes_are_formatted_in_binary_units() {
+    use dbdm::output::format_size;
+
+    assert_eq!(format_size(512), "512 B");
+    assert_eq!(format_size(1536), "1.5 KiB");
+    assert_eq!(format_size(3 * 1024 * 1024), "3.0 MiB");
+}
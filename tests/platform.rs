@@ -0,0 +1,43 @@
+use dbdm::platform::{ends_with_separator, is_command, set_mode, symlink};
+use std::ffi::OsStr;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn platform_symlinks_files_and_dirs() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_dir = workspace.path().join("nvim");
+    fs::create_dir_all(&source_dir).expect("create source dir");
+    fs::write(source_dir.join("init.lua"), "-- nvim").expect("write source file");
+
+    symlink(&source_dir, &workspace.path().join("linked")).expect("link dir");
+    symlink(
+        std::path::Path::new("nvim/init.lua"),
+        &workspace.path().join("init.lua"),
+    )
+    .expect("link file relatively");
+
+    assert_eq!(
+        fs::read_to_string(workspace.path().join("linked/init.lua")).expect("read through dir"),
+        "-- nvim"
+    );
+    assert_eq!(
+        fs::read_to_string(workspace.path().join("init.lua")).expect("read through file"),
+        "-- nvim"
+    );
+}
+
+#[test]
+fn platform_tells_commands_from_plain_files() {
+    let workspace = tempdir().expect("create temp workspace");
+    let script = workspace.path().join("hook");
+    fs::write(&script, "#!/bin/sh\n").expect("write script");
+
+    assert!(!is_command(&script));
+    set_mode(&script, 0o755).expect("make executable");
+    assert!(is_command(&script));
+    assert!(!is_command(workspace.path()));
+
+    assert!(ends_with_separator(OsStr::new("~/.config/")));
+    assert!(!ends_with_separator(OsStr::new("~/.config")));
+}
@@ -0,0 +1,125 @@
+use dbdm::service::{ServiceCommand, units};
+use std::fs;
+use std::path::Path;
+use tempfile::tempdir;
+
+#[test]
+fn sync_service_runs_in_the_config_directory() {
+    let units = units(
+        Path::new("/usr/bin/dbdm"),
+        Path::new("/home/me/dotfiles"),
+        ServiceCommand::Sync,
+        None,
+        false,
+    );
+
+    assert_eq!(units.len(), 1);
+    assert_eq!(units[0].name, "dbdm.service");
+    assert!(units[0].contents.contains("Type=oneshot\n"));
+    assert!(
+        units[0]
+            .contents
+            .contains("WorkingDirectory=/home/me/dotfiles\n")
+    );
+    assert!(
+        units[0]
+            .contents
+            .contains("ExecStart=/usr/bin/dbdm sync --non-interactive\n")
+    );
+}
+
+#[test]
+fn watch_service_ignores_timer_and_path_units() {
+    let units = units(
+        Path::new("/usr/bin/dbdm"),
+        Path::new("/home/me/dotfiles"),
+        ServiceCommand::Watch,
+        Some("1h"),
+        true,
+    );
+
+    assert_eq!(units.len(), 1);
+    assert!(
+        units[0]
+            .contents
+            .contains("ExecStart=/usr/bin/dbdm watch\n")
+    );
+    assert!(units[0].contents.contains("Restart=on-failure\n"));
+}
+
+#[test]
+fn timer_and_path_units_start_the_service() {
+    let units = units(
+        Path::new("/usr/bin/dbdm"),
+        Path::new("/home/me/dotfiles"),
+        ServiceCommand::Sync,
+        Some("30min"),
+        true,
+    );
+
+    let names: Vec<&str> = units.iter().map(|unit| unit.name).collect();
+    assert_eq!(names, ["dbdm.service", "dbdm.timer", "dbdm.path"]);
+    assert!(units[1].contents.contains("OnUnitActiveSec=30min\n"));
+    assert!(units[1].contents.contains("Unit=dbdm.service\n"));
+    assert!(
+        units[2]
+            .contents
+            .contains("PathChanged=/home/me/dotfiles/dbdm.conf\n")
+    );
+}
+
+#[test]
+fn quotes_binary_paths_with_spaces() {
+    let units = units(
+        Path::new("/opt/my tools/dbdm"),
+        Path::new("/home/me/100%"),
+        ServiceCommand::Watch,
+        None,
+        false,
+    );
+
+    assert!(
+        units[0]
+            .contents
+            .contains("ExecStart=\"/opt/my tools/dbdm\" watch\n")
+    );
+    assert!(
+        units[0]
+            .contents
+            .contains("WorkingDirectory=/home/me/100%%\n")
+    );
+}
+
+#[test]
+fn install_writes_units_and_keeps_changed_ones() {
+    let workspace = tempdir().expect("create temp workspace");
+    let config_home = workspace.path().join("config");
+    fs::write(workspace.path().join("dbdm.conf"), "").expect("write config");
+
+    let install = |extra: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+            .args(["service", "install"])
+            .args(extra)
+            .env("XDG_CONFIG_HOME", &config_home)
+            .current_dir(workspace.path())
+            .output()
+            .expect("run dbdm service install")
+    };
+
+    assert!(install(&["--timer", "1h"]).status.success());
+    let unit_dir = config_home.join("systemd/user");
+    let service = fs::read_to_string(unit_dir.join("dbdm.service")).expect("read service");
+    assert!(service.contains("sync --non-interactive"));
+    assert!(unit_dir.join("dbdm.timer").exists());
+
+    // A different unit isn't replaced without --force
+    assert!(!install(&["--watch"]).status.success());
+    assert_eq!(
+        fs::read_to_string(unit_dir.join("dbdm.service")).expect("read service"),
+        service
+    );
+
+    assert!(install(&["--watch", "--force"]).status.success());
+    let service = fs::read_to_string(unit_dir.join("dbdm.service")).expect("read service");
+    assert!(service.contains(" watch\n"));
+}
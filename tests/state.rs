@@ -0,0 +1,68 @@
+use dbdm::config_parser::Link;
+use dbdm::state::{LinkState, link_state};
+use std::fs;
+use std::os::unix::fs::symlink;
+use tempfile::tempdir;
+
+#[test]
+fn link_state_tells_every_destination_apart() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("source.txt");
+    let other_file = workspace.path().join("other.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    fs::write(&other_file, "other").expect("write other file");
+
+    let state =
+        |dest: &str| link_state(&Link::new(source_file.clone(), workspace.path().join(dest)));
+
+    assert_eq!(state("missing.txt"), LinkState::Missing);
+
+    symlink(&source_file, workspace.path().join("linked.txt")).expect("create link");
+    assert_eq!(state("linked.txt"), LinkState::LinkedCorrectly);
+
+    symlink(&other_file, workspace.path().join("elsewhere.txt")).expect("create link");
+    assert_eq!(
+        state("elsewhere.txt"),
+        LinkState::WrongTarget(fs::canonicalize(&other_file).expect("canonicalize"))
+    );
+
+    symlink(
+        workspace.path().join("gone.txt"),
+        workspace.path().join("broken.txt"),
+    )
+    .expect("create broken link");
+    assert_eq!(state("broken.txt"), LinkState::BrokenSymlink);
+
+    fs::write(workspace.path().join("real.txt"), "keep me").expect("write real file");
+    assert_eq!(state("real.txt"), LinkState::RegularFileConflict);
+}
+
+#[test]
+fn link_state_looks_inside_directories_and_at_the_source() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_dir = workspace.path().join("nvim");
+    fs::create_dir_all(&source_dir).expect("create source dir");
+    let config_dir = workspace.path().join(".config/nvim");
+    fs::create_dir_all(&config_dir).expect("create config dir");
+
+    assert_eq!(
+        link_state(&Link::new(source_dir.clone(), config_dir)),
+        LinkState::DirectoryConflict
+    );
+    assert_eq!(
+        link_state(&Link::new(
+            workspace.path().join("gone"),
+            workspace.path().join(".gone")
+        )),
+        LinkState::SourceMissing
+    );
+
+    // A file linked into a directory ends up inside it
+    let source_file = workspace.path().join("init.lua");
+    fs::write(&source_file, "-- nvim").expect("write source file");
+    symlink(&source_file, workspace.path().join(".config/init.lua")).expect("create link");
+    assert_eq!(
+        link_state(&Link::new(source_file, workspace.path().join(".config"))),
+        LinkState::LinkedCorrectly
+    );
+}
@@ -32,7 +32,7 @@ fn perform_sync_when_targets_dont_exist() {
     let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"));
     let mut child = command
         .arg("sync")
-        .arg("--test-mode")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
         .current_dir(workspace.path())
         .stdin(std::process::Stdio::piped())
         .spawn()
@@ -92,7 +92,8 @@ fn perform_sync_when_targets_are_empty() {
 
     let status = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
         .arg("sync")
-        .arg("--test-mode")
+        .arg("--non-interactive")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
         .current_dir(workspace.path())
         .status()
         .expect("run dbdm sync");
@@ -145,6 +146,7 @@ fn perform_sync_when_targets_exist_with_backup() {
     let mut child = command
         .arg("sync")
         .current_dir(workspace.path())
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
         .stdin(std::process::Stdio::piped())
         .spawn()
         .expect("spawn dbdm sync");
@@ -193,7 +195,7 @@ fn perform_sync_when_targets_exist_with_backup() {
     let file_backup_contents = fs::read_to_string(&file_backup).expect("read file backup");
     assert_eq!(file_backup_contents, "old file");
 
-    let dir_backup = source_dir.join("linked_dir.bak.dbdm");
+    let dir_backup = workspace.path().join("linked_dir.bak.dbdm");
     let dir_backup_file = dir_backup.join("old.txt");
     let dir_backup_contents = fs::read_to_string(&dir_backup_file).expect("read dir backup");
     assert_eq!(dir_backup_contents, "old dir");
@@ -231,7 +233,7 @@ fn perform_sync_when_targets_exist_without_backup() {
     let mut command = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"));
     let mut child = command
         .arg("sync")
-        .arg("--test-mode")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
         .current_dir(workspace.path())
         .stdin(std::process::Stdio::piped())
         .spawn()
@@ -305,7 +307,8 @@ fn perform_sync_with_force_flag() {
     let status = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
         .arg("sync")
         .arg("--force")
-        .arg("--test-mode")
+        .arg("--non-interactive")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
         .current_dir(workspace.path())
         .status()
         .expect("run dbdm sync --force");
@@ -344,3 +347,1732 @@ fn perform_sync_with_force_flag() {
         fs::read_to_string(source_dir.join("nested.txt")).expect("read source dir file");
     assert_eq!(dir_contents, "nested");
 }
+
+#[test]
+fn perform_sync_with_ndjson_events() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let dest_root = workspace.path().join("dest");
+    fs::create_dir(&dest_root).expect("create dest root");
+
+    let dest_file = dest_root.join("linked.txt");
+    fs::write(&dest_file, "conflict").expect("create conflicting dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!("link = {} {}\n", source_file.display(), dest_file.display());
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .arg("--format")
+        .arg("ndjson")
+        .current_dir(workspace.path())
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn dbdm sync");
+
+    {
+        let stdin = child.stdin.as_mut().expect("open stdin");
+        std::io::Write::write_all(stdin, b"r\ny\n").expect("select replace option");
+    }
+
+    let output = child.wait_with_output().expect("wait for dbdm sync");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("every line is json"))
+        .collect();
+
+    let names: Vec<&str> = events
+        .iter()
+        .map(|event| event["event"].as_str().expect("event name"))
+        .collect();
+    assert_eq!(
+        names,
+        vec![
+            "planned", "conflict", "prompt", "decided", "confirm", "executed", "done"
+        ]
+    );
+    assert!(events.iter().all(|event| event["ts"].is_u64()));
+
+    let executed = &events[5];
+    assert_eq!(executed["action"], "replace");
+    assert_eq!(executed["outcome"], "ok");
+    assert_eq!(
+        executed["to"].as_str().expect("destination"),
+        dest_file.display().to_string()
+    );
+
+    let done = &events[6];
+    assert_eq!(done["counts"]["replace"], 1);
+    assert_eq!(done["counts"]["create"], 0);
+    assert_eq!(done["counts"]["skip"], 0);
+    assert_eq!(done["destructive"], 1);
+    assert_eq!(done["bytes_backed_up"], 0);
+    assert_eq!(done["prompts_answered"], 2);
+    assert!(done["elapsed_ms"].is_u64());
+}
+
+#[test]
+fn quitting_at_prompt_aborts_sync() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let dest_root = workspace.path().join("dest");
+    fs::create_dir(&dest_root).expect("create dest root");
+
+    let missing_dest = dest_root.join("missing.txt");
+    let dest_file = dest_root.join("linked.txt");
+    fs::write(&dest_file, "conflict").expect("create conflicting dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {}\nlink = {} {}\n",
+        source_file.display(),
+        missing_dest.display(),
+        source_file.display(),
+        dest_file.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn dbdm sync");
+
+    {
+        let stdin = child.stdin.as_mut().expect("open stdin");
+        std::io::Write::write_all(stdin, b"q\n").expect("select quit option");
+    }
+
+    let status = child.wait().expect("wait for dbdm sync");
+    assert_eq!(status.code(), Some(2));
+
+    assert!(!missing_dest.exists());
+    let contents = fs::read_to_string(&dest_file).expect("read dest file");
+    assert_eq!(contents, "conflict");
+}
+
+// Runs sync over a link to create and a conflict, with the given answers on stdin
+//
+// @return (Option<i32>, bool) - the exit code, and if the link was created
+fn sync_creating_a_link(args: &[&str], answers: &[u8]) -> (Option<i32>, bool) {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let dest_root = workspace.path().join("dest");
+    fs::create_dir(&dest_root).expect("create dest root");
+    let conflict = dest_root.join("conflict.txt");
+    fs::write(&conflict, "conflict").expect("write conflicting file");
+    let dest_file = dest_root.join("linked.txt");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {}\nlink = {} {}\n",
+        source_file.display(),
+        conflict.display(),
+        source_file.display(),
+        dest_file.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .args(args)
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn dbdm sync");
+    {
+        let stdin = child.stdin.as_mut().expect("open stdin");
+        std::io::Write::write_all(stdin, answers).expect("answer the prompts");
+    }
+
+    let output = child.wait_with_output().expect("wait for dbdm sync");
+    let contents = fs::read_to_string(&conflict).expect("read conflicting file");
+    assert_eq!(contents, "conflict");
+    // The answers given here never quit, so an abort is always the input running out
+    if output.status.code() == Some(2) {
+        let stderr = String::from_utf8(output.stderr).expect("utf8 stderr");
+        assert!(stderr.contains("Input closed, aborting"), "{}", stderr);
+    }
+    (
+        output.status.code(),
+        fs::symlink_metadata(&dest_file).is_ok(),
+    )
+}
+
+#[test]
+fn creating_links_needs_no_confirmation() {
+    // The conflict is skipped, and stdin closes before any confirmation
+    let (code, linked) = sync_creating_a_link(&[], b"s\n");
+    assert_eq!(code, Some(0));
+    assert!(linked);
+}
+
+#[test]
+fn confirm_always_asks_before_creating_links() {
+    let (code, linked) = sync_creating_a_link(&["--confirm-always"], b"s\n");
+    assert_eq!(code, Some(2));
+    assert!(!linked);
+
+    let (code, linked) = sync_creating_a_link(&["--confirm-always"], b"s\ny\n");
+    assert_eq!(code, Some(0));
+    assert!(linked);
+}
+
+#[test]
+fn yes_answers_the_confirmation_but_not_conflicts() {
+    let (code, linked) = sync_creating_a_link(&["--confirm-always", "--yes"], b"s\n");
+    assert_eq!(code, Some(0));
+    assert!(linked);
+
+    // The conflict is still asked about, and closed input aborts at it
+    let (code, linked) = sync_creating_a_link(&["-y"], b"");
+    assert_eq!(code, Some(2));
+    assert!(!linked);
+}
+
+#[test]
+fn a_conflict_can_be_changed_before_confirming() {
+    // Replaced at first, then skipped instead by its number, after trying the
+    // number of the link to create, which wasn't a conflict
+    let (code, linked) = sync_creating_a_link(&[], b"r\n2\n1\ns\ny\n");
+    assert_eq!(code, Some(0));
+    assert!(linked);
+}
+
+#[test]
+fn plan_is_shown_before_conflicts_and_changes_before_confirming() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let conflict = workspace.path().join("conflict.txt");
+    fs::write(&conflict, "conflict").expect("write conflicting file");
+    let dest_file = workspace.path().join("linked.txt");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {}\nlink = {} {}\n",
+        source_file.display(),
+        conflict.display(),
+        source_file.display(),
+        dest_file.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn dbdm sync");
+    {
+        let stdin = child.stdin.as_mut().expect("open stdin");
+        std::io::Write::write_all(stdin, b"r\ny\n").expect("answer the prompts");
+    }
+    let output = child.wait_with_output().expect("wait for dbdm sync");
+    assert_eq!(output.status.code(), Some(0));
+
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    let position = |text: &str| stdout.find(text).expect(text);
+    assert!(position("Planned actions") < position("Conflict at"));
+    assert!(position("Conflict at") < position("Changes"));
+    assert!(position("Changes") < position("Proceed?"));
+    assert!(stdout.contains(&format!(
+        "~ {} -> {} (replace)",
+        source_file.display(),
+        conflict.display()
+    )));
+    assert!(stdout.contains(&format!(
+        "+ {} -> {} (create)",
+        source_file.display(),
+        dest_file.display()
+    )));
+}
+
+// Runs sync over a conflict whose backup can't be made, as its directory would
+// go under a file, and a link that can be made
+//
+// @return (Option<i32>, bool) - the exit code, and whether the second link was made
+fn sync_with_failing_item(answers: &[u8]) -> (Option<i32>, bool) {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let blocker = workspace.path().join("blocker");
+    fs::write(&blocker, "not a directory").expect("write blocking file");
+    let dest_root = workspace.path().join("dest");
+    fs::create_dir(&dest_root).expect("create dest root");
+    let conflict = dest_root.join("conflict.txt");
+    fs::write(&conflict, "conflict").expect("write conflicting file");
+    let dest_file = dest_root.join("linked.txt");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {}\nlink = {} {}\n",
+        source_file.display(),
+        conflict.display(),
+        source_file.display(),
+        dest_file.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn dbdm sync");
+
+    {
+        let stdin = child.stdin.as_mut().expect("open stdin");
+        let backup_location = format!("e\nl\n{}\ny\n", blocker.join("backups").display());
+        std::io::Write::write_all(stdin, backup_location.as_bytes())
+            .expect("pick a backup location");
+        std::io::Write::write_all(stdin, answers).expect("answer the error prompt");
+    }
+
+    let output = child.wait_with_output().expect("wait for dbdm sync");
+    let stderr = String::from_utf8(output.stderr).expect("utf8 stderr");
+    assert!(
+        stderr.contains(&format!(
+            "{}: backup+replace failed: Not a directory",
+            conflict.display()
+        )),
+        "{}",
+        stderr
+    );
+    (
+        output.status.code(),
+        fs::symlink_metadata(&dest_file).is_ok(),
+    )
+}
+
+#[test]
+fn skipping_a_failed_item_goes_on_with_the_rest() {
+    let (code, linked) = sync_with_failing_item(b"s\n");
+    assert_eq!(code, Some(1));
+    assert!(linked);
+}
+
+#[test]
+fn aborting_after_a_retry_leaves_the_rest() {
+    let (code, linked) = sync_with_failing_item(b"r\na\n");
+    assert_eq!(code, Some(1));
+    assert!(!linked);
+}
+
+#[test]
+fn closed_input_aborts_sync() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let dest_file = workspace.path().join("linked.txt");
+    fs::write(&dest_file, "conflict").expect("create conflicting dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!("link = {} {}\n", source_file.display(), dest_file.display());
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("run dbdm sync");
+    assert_eq!(output.status.code(), Some(2));
+
+    let stderr = String::from_utf8(output.stderr).expect("utf8 stderr");
+    assert!(stderr.contains("Input closed"));
+
+    let contents = fs::read_to_string(&dest_file).expect("read dest file");
+    assert_eq!(contents, "conflict");
+}
+
+#[test]
+fn editing_destination_at_prompt() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let dest_file = workspace.path().join("linked.txt");
+    let edited_dest = workspace.path().join("edited.txt");
+    fs::write(&dest_file, "conflict").expect("create conflicting dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!("link = {} {}\n", source_file.display(), dest_file.display());
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn dbdm sync");
+
+    {
+        let stdin = child.stdin.as_mut().expect("open stdin");
+        let answers = format!("e\nd\n{}\ny\n", edited_dest.display());
+        std::io::Write::write_all(stdin, answers.as_bytes()).expect("edit destination");
+    }
+
+    let status = child.wait().expect("wait for dbdm sync");
+    assert!(status.success());
+
+    let contents = fs::read_to_string(&dest_file).expect("read dest file");
+    assert_eq!(contents, "conflict");
+    let link_target = fs::read_link(&edited_dest).expect("read edited link");
+    assert_eq!(link_target, source_file);
+}
+
+#[test]
+fn editing_destination_keeps_the_module() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let dest_file = workspace.path().join("linked.txt");
+    let edited_dest = workspace.path().join("edited.txt");
+    fs::write(&dest_file, "conflict").expect("create conflicting dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "[module editor]\nlink = {} {}\n",
+        source_file.display(),
+        dest_file.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["sync", "--color", "never"])
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn dbdm sync");
+
+    {
+        let stdin = child.stdin.as_mut().expect("open stdin");
+        let answers = format!("e\nd\n{}\ny\n", edited_dest.display());
+        std::io::Write::write_all(stdin, answers.as_bytes()).expect("edit destination");
+    }
+
+    let output = child.wait_with_output().expect("wait for dbdm sync");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    assert!(stdout.contains("Outcome, module editor"), "{}", stdout);
+    assert_eq!(
+        fs::read_link(&edited_dest).expect("read edited link"),
+        source_file
+    );
+}
+
+#[test]
+fn editing_destination_of_protected_link_still_asks() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let dest_file = workspace.path().join("linked.txt");
+    let edited_dest = workspace.path().join("edited.txt");
+    fs::write(&dest_file, "conflict").expect("create conflicting dest file");
+    fs::write(&edited_dest, "keep me").expect("create conflicting edited file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {} protect=true\n",
+        source_file.display(),
+        dest_file.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["sync", "--force"])
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .spawn()
+        .expect("spawn dbdm sync");
+
+    {
+        // The new destination is a conflict too, so it is asked about again and skipped
+        let stdin = child.stdin.as_mut().expect("open stdin");
+        let answers = format!("e\nd\n{}\ns\ny\n", edited_dest.display());
+        std::io::Write::write_all(stdin, answers.as_bytes()).expect("edit destination");
+    }
+
+    let status = child.wait().expect("wait for dbdm sync");
+    assert!(status.success());
+
+    let contents = fs::read_to_string(&edited_dest).expect("read edited dest");
+    assert_eq!(contents, "keep me");
+    let contents = fs::read_to_string(&dest_file).expect("read dest file");
+    assert_eq!(contents, "conflict");
+}
+
+#[test]
+fn editing_backup_location_at_prompt() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let dest_file = workspace.path().join("linked.txt");
+    let backup_dir = workspace.path().join("backups");
+    fs::write(&dest_file, "conflict").expect("create conflicting dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!("link = {} {}\n", source_file.display(), dest_file.display());
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn dbdm sync");
+
+    {
+        let stdin = child.stdin.as_mut().expect("open stdin");
+        let answers = format!("e\nl\n{}\ny\n", backup_dir.display());
+        std::io::Write::write_all(stdin, answers.as_bytes()).expect("edit backup location");
+    }
+
+    let status = child.wait().expect("wait for dbdm sync");
+    assert!(status.success());
+
+    let link_target = fs::read_link(&dest_file).expect("read link");
+    assert_eq!(link_target, source_file);
+    let backup = fs::read_to_string(backup_dir.join("linked.txt.bak.dbdm")).expect("read backup");
+    assert_eq!(backup, "conflict");
+}
+
+#[test]
+fn refuses_dangerous_destinations() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let home_dir = workspace.path().join("home");
+    fs::create_dir(&home_dir).expect("create home dir");
+    fs::write(home_dir.join("notes.txt"), "keep me").expect("write home file");
+
+    let source_dir = workspace.path().join("dotfiles").join("nvim");
+    fs::create_dir_all(&source_dir).expect("create source dir");
+    fs::write(source_dir.join("init.lua"), "config").expect("write source file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} !home\nlink = {} {}\n",
+        source_dir.display(),
+        source_dir.display(),
+        workspace.path().join("dotfiles").display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .arg("--force")
+        .arg("--non-interactive")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .env("HOME", &home_dir)
+        .current_dir(workspace.path())
+        .output()
+        .expect("run dbdm sync");
+    assert!(output.status.success());
+
+    let stderr = String::from_utf8(output.stderr).expect("utf8 stderr");
+    assert!(stderr.contains("--allow-dangerous"));
+
+    let contents = fs::read_to_string(home_dir.join("notes.txt")).expect("read home file");
+    assert_eq!(contents, "keep me");
+    let contents = fs::read_to_string(source_dir.join("init.lua")).expect("read source file");
+    assert_eq!(contents, "config");
+}
+
+#[test]
+fn adopts_identical_destinations_without_prompt() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let dest_file = workspace.path().join("linked.txt");
+    fs::write(&dest_file, "example").expect("create identical dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!("link = {} {}\n", source_file.display(), dest_file.display());
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .arg("--format")
+        .arg("ndjson")
+        .current_dir(workspace.path())
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("run dbdm sync");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("parse event"))
+        .collect();
+    assert!(events.iter().all(|event| event["event"] != "prompt"));
+    let planned = events
+        .iter()
+        .find(|event| event["event"] == "planned")
+        .expect("planned event");
+    assert_eq!(planned["action"], "identical");
+
+    let link_target = fs::read_link(&dest_file).expect("read link");
+    assert_eq!(link_target, source_file);
+}
+
+#[test]
+fn retargets_symlinks_without_prompt() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    let other_file = workspace.path().join("other.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    fs::write(&other_file, "other").expect("write other file");
+
+    let dest_file = workspace.path().join("linked.txt");
+    std::os::unix::fs::symlink(&other_file, &dest_file).expect("create wrong link");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!("link = {} {}\n", source_file.display(), dest_file.display());
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .arg("--format")
+        .arg("ndjson")
+        .current_dir(workspace.path())
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("run dbdm sync");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    let events: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("parse event"))
+        .collect();
+    assert!(events.iter().all(|event| event["event"] != "prompt"));
+    let planned = events
+        .iter()
+        .find(|event| event["event"] == "planned")
+        .expect("planned event");
+    assert_eq!(planned["action"], "retarget");
+    assert_eq!(planned["reason"], format!("was {}", other_file.display()));
+
+    let link_target = fs::read_link(&dest_file).expect("read link");
+    assert_eq!(link_target, source_file);
+    let contents = fs::read_to_string(&other_file).expect("read other file");
+    assert_eq!(contents, "other");
+}
+
+#[test]
+fn repairs_broken_symlinks_without_prompt() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let dest_file = workspace.path().join("linked.txt");
+    std::os::unix::fs::symlink(workspace.path().join("gone.txt"), &dest_file)
+        .expect("create broken link");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!("link = {} {}\n", source_file.display(), dest_file.display());
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .arg("--non-interactive")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("run dbdm sync");
+    assert!(output.status.success());
+
+    let link_target = fs::read_link(&dest_file).expect("read link");
+    assert_eq!(link_target, source_file);
+}
+
+#[test]
+fn applies_mode_option_to_source() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("config");
+    fs::write(&source_file, "Host *").expect("write source file");
+    fs::set_permissions(&source_file, fs::Permissions::from_mode(0o644)).expect("set source mode");
+
+    let dest_file = workspace.path().join("linked");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {} mode=0600\n",
+        source_file.display(),
+        dest_file.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .arg("--non-interactive")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::null())
+        .status()
+        .expect("run dbdm sync");
+    assert!(status.success());
+
+    let meta = fs::metadata(&source_file).expect("stat source file");
+    assert_eq!(meta.permissions().mode() & 0o7777, 0o600);
+    assert_eq!(fs::read_link(&dest_file).expect("read link"), source_file);
+}
+
+#[cfg(unix)]
+#[test]
+fn readonly_option_takes_write_permission_from_sources() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("config");
+    fs::write(&source_file, "Host *").expect("write source file");
+    let source_dir = workspace.path().join("app");
+    fs::create_dir_all(source_dir.join("themes")).expect("create source dir");
+    fs::write(source_dir.join("themes/dark.toml"), "dark").expect("write nested file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {} mode=0640 readonly=true\nlink = {} {} readonly=true\n",
+        source_file.display(),
+        workspace.path().join("linked").display(),
+        source_dir.display(),
+        workspace.path().join("linked_app").display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let run = |command: &str| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+            .args([command, "--non-interactive"])
+            .env("XDG_STATE_HOME", workspace.path().join("state"))
+            .current_dir(workspace.path())
+            .stdin(std::process::Stdio::null())
+            .output()
+            .expect("run dbdm")
+    };
+    assert!(run("sync").status.success());
+
+    let mode = |path: &std::path::Path| {
+        fs::metadata(path)
+            .expect("stat source")
+            .permissions()
+            .mode()
+            & 0o7777
+    };
+    assert_eq!(mode(&source_file), 0o440);
+    assert_eq!(mode(&source_dir.join("themes/dark.toml")) & 0o222, 0);
+    // Files can still be added to the directory
+    assert_ne!(mode(&source_dir.join("themes")) & 0o200, 0);
+
+    let stdout = String::from_utf8(run("check").stdout).expect("utf8 stdout");
+    assert!(!stdout.contains("writable"), "{}", stdout);
+    assert!(!stdout.contains("expected"), "{}", stdout);
+}
+
+#[test]
+fn root_links_go_through_sudo() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let workspace = tempdir().expect("create temp workspace");
+
+    // A stand-in for sudo that logs and runs the command as the current user
+    let bin_dir = workspace.path().join("bin");
+    fs::create_dir(&bin_dir).expect("create bin dir");
+    let sudo_log = workspace.path().join("sudo.log");
+    let sudo_path = bin_dir.join("sudo");
+    fs::write(
+        &sudo_path,
+        format!(
+            "#!/bin/sh\necho \"$1\" >> {}\nexec \"$@\"\n",
+            sudo_log.display()
+        ),
+    )
+    .expect("write fake sudo");
+    fs::set_permissions(&sudo_path, fs::Permissions::from_mode(0o755)).expect("chmod fake sudo");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let dest_file = workspace.path().join("linked.txt");
+    fs::write(&dest_file, "conflict").expect("create conflicting dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {} root=true\n",
+        source_file.display(),
+        dest_file.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .arg("--force")
+        .arg("--non-interactive")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .env("PATH", path)
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::null())
+        .status()
+        .expect("run dbdm sync");
+    assert!(status.success());
+
+    let log = fs::read_to_string(&sudo_log).expect("read sudo log");
+    assert_eq!(log, "rm\nln\n");
+    assert_eq!(fs::read_link(&dest_file).expect("read link"), source_file);
+}
+
+#[test]
+fn system_links_are_always_backed_up() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("pacman.conf");
+    fs::write(&source_file, "[options]").expect("write source file");
+
+    let etc_dir = workspace.path().join("etc");
+    fs::create_dir(&etc_dir).expect("create etc dir");
+    let dest_file = etc_dir.join("pacman.conf");
+    fs::write(&dest_file, "conflict").expect("create conflicting dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "system = {} {}\n",
+        source_file.display(),
+        dest_file.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn dbdm sync");
+
+    {
+        let stdin = child.stdin.as_mut().expect("open stdin");
+        std::io::Write::write_all(stdin, b"r\ny\ny\n").expect("replace and confirm twice");
+    }
+
+    let status = child.wait().expect("wait for dbdm sync");
+    assert!(status.success());
+
+    assert_eq!(fs::read_link(&dest_file).expect("read link"), source_file);
+    let backup =
+        fs::read_to_string(workspace.path().join("pacman.conf.bak.dbdm")).expect("read backup");
+    assert_eq!(backup, "conflict");
+}
+
+#[test]
+fn non_interactive_sync_skips_conflicts() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let new_dest = workspace.path().join("new.txt");
+    let conflict_dest = workspace.path().join("conflict.txt");
+    fs::write(&conflict_dest, "conflict").expect("create conflicting dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {}\nlink = {} {}\n",
+        source_file.display(),
+        new_dest.display(),
+        source_file.display(),
+        conflict_dest.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .arg("--non-interactive")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::null())
+        .status()
+        .expect("run dbdm sync");
+    assert!(status.success());
+
+    assert_eq!(fs::read_link(&new_dest).expect("read link"), source_file);
+    assert_eq!(
+        fs::read_to_string(&conflict_dest).expect("read conflict"),
+        "conflict"
+    );
+}
+
+#[test]
+fn non_interactive_sync_never_reads_stdin() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let conflict_dest = workspace.path().join("conflict.txt");
+    fs::write(&conflict_dest, "conflict").expect("create conflicting dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {}\n",
+        source_file.display(),
+        conflict_dest.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .arg("--non-interactive")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn dbdm sync");
+
+    {
+        let stdin = child.stdin.as_mut().expect("open stdin");
+        std::io::Write::write_all(stdin, b"r\ny\n").expect("write answers");
+    }
+
+    let output = child.wait_with_output().expect("wait for dbdm sync");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    assert!(!stdout.contains("Action ["));
+    assert!(!stdout.contains("Proceed?"));
+    assert_eq!(
+        fs::read_to_string(&conflict_dest).expect("read conflict"),
+        "conflict"
+    );
+}
+
+#[cfg(feature = "desktop-notify")]
+#[test]
+fn desktop_notify_summarizes_the_sync() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let workspace = tempdir().expect("create temp workspace");
+
+    // A stand-in for notify-send that logs its arguments
+    let bin_dir = workspace.path().join("bin");
+    fs::create_dir(&bin_dir).expect("create bin dir");
+    let notify_log = workspace.path().join("notify.log");
+    let notify_path = bin_dir.join("notify-send");
+    fs::write(
+        &notify_path,
+        format!(
+            "#!/bin/sh\nprintf '%s\\n' \"$@\" >> {}\n",
+            notify_log.display()
+        ),
+    )
+    .expect("write fake notify-send");
+    fs::set_permissions(&notify_path, fs::Permissions::from_mode(0o755))
+        .expect("chmod fake notify-send");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let new_dest = workspace.path().join("new.txt");
+    let conflict_dest = workspace.path().join("conflict.txt");
+    fs::write(&conflict_dest, "conflict").expect("create conflicting dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {}\nlink = {} {}\n",
+        source_file.display(),
+        new_dest.display(),
+        source_file.display(),
+        conflict_dest.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .arg("--non-interactive")
+        .arg("--desktop-notify")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .env("PATH", path)
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::null())
+        .status()
+        .expect("run dbdm sync");
+    assert!(status.success());
+
+    let log = fs::read_to_string(&notify_log).expect("read notify log");
+    assert!(log.contains("dbdm linked 1 destination and skipped 1 link"));
+    assert!(log.contains(&new_dest.display().to_string()));
+    assert!(log.contains(&conflict_dest.display().to_string()));
+}
+
+#[test]
+fn require_clean_refuses_dirty_sources() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let repo = workspace.path().join("dots");
+    fs::create_dir(&repo).expect("create repo");
+    let source_file = repo.join("zshrc");
+    fs::write(&source_file, "example").expect("write source file");
+    let status = std::process::Command::new("git")
+        .args(["init", "-q"])
+        .current_dir(&repo)
+        .status()
+        .expect("run git init");
+    assert!(status.success());
+
+    let dest_file = workspace.path().join("linked.txt");
+    let config_path = workspace.path().join("dbdm.conf");
+    fs::write(
+        &config_path,
+        format!("link = {} {}\n", source_file.display(), dest_file.display()),
+    )
+    .expect("write config");
+
+    let run = |extra: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+            .arg("sync")
+            .arg("--non-interactive")
+            .env("XDG_STATE_HOME", workspace.path().join("state"))
+            .args(extra)
+            .current_dir(workspace.path())
+            .stdin(std::process::Stdio::null())
+            .stderr(std::process::Stdio::null())
+            .status()
+            .expect("run dbdm sync")
+    };
+
+    // The source is untracked, so the repository isn't clean
+    run(&["--require-clean"]);
+    assert!(fs::symlink_metadata(&dest_file).is_err());
+
+    // Without the flag it is only a warning
+    assert!(run(&[]).success());
+    assert_eq!(fs::read_link(&dest_file).expect("read link"), source_file);
+}
+
+// Runs sync over a protected and an unprotected conflict
+//
+// @return (Option<i32>, bool, bool) - the exit code, if the protected destination was backed up
+// and linked, and if the other one was linked
+fn sync_protected(args: &[&str], answers: &[u8]) -> (Option<i32>, bool, bool) {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let protected = workspace.path().join("protected.txt");
+    fs::write(&protected, "keys").expect("create conflicting dest file");
+    let other = workspace.path().join("other.txt");
+    fs::write(&other, "other").expect("create conflicting dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    fs::write(
+        &config_path,
+        format!(
+            "link = {} {} protect=true\nlink = {} {}\n",
+            source_file.display(),
+            protected.display(),
+            source_file.display(),
+            other.display()
+        ),
+    )
+    .expect("write config");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .args(args)
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .spawn()
+        .expect("spawn dbdm sync");
+    {
+        let stdin = child.stdin.as_mut().expect("open stdin");
+        std::io::Write::write_all(stdin, answers).expect("answer the prompts");
+    }
+    let status = child.wait().expect("wait for dbdm sync");
+
+    let backed_up = fs::read_to_string(workspace.path().join("protected.txt.bak.dbdm"))
+        .is_ok_and(|contents| contents == "keys");
+    (
+        status.code(),
+        fs::symlink_metadata(&protected).is_ok_and(|meta| meta.is_symlink()) && backed_up,
+        fs::symlink_metadata(&other).is_ok_and(|meta| meta.is_symlink()),
+    )
+}
+
+#[test]
+fn protected_links_are_not_forced() {
+    let (code, protected, other) = sync_protected(&["--force", "--non-interactive"], b"");
+    assert_eq!(code, Some(0));
+    assert!(!protected);
+    assert!(other);
+}
+
+#[test]
+fn protected_links_are_asked_about_despite_on_conflict_replace() {
+    let (code, protected, other) = sync_protected(&["--on-conflict", "replace"], b"");
+    assert_eq!(code, Some(2));
+    assert!(!protected);
+    assert!(!other);
+
+    let (code, protected, other) = sync_protected(&["--on-conflict", "replace"], b"b\ny\n");
+    assert_eq!(code, Some(0));
+    assert!(protected);
+    assert!(other);
+}
+
+#[test]
+fn on_conflict_backup_resolves_without_asking() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let dest_file = workspace.path().join("linked.txt");
+    fs::write(&dest_file, "conflict").expect("create conflicting dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    fs::write(
+        &config_path,
+        format!("link = {} {}\n", source_file.display(), dest_file.display()),
+    )
+    .expect("write config");
+
+    // Stdin is closed, so any prompt would abort the sync
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["sync", "--on-conflict", "backup", "--non-interactive"])
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .expect("run dbdm sync");
+    assert!(status.success());
+
+    assert_eq!(fs::read_link(&dest_file).expect("read link"), source_file);
+    assert_eq!(
+        fs::read_to_string(workspace.path().join("linked.txt.bak.dbdm")).expect("read backup"),
+        "conflict"
+    );
+}
+
+#[test]
+fn on_conflict_option_resolves_its_link_before_the_flag() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let regenerated = workspace.path().join("regenerated.txt");
+    fs::write(&regenerated, "regenerated").expect("create conflicting dest file");
+    let other = workspace.path().join("other.txt");
+    fs::write(&other, "other").expect("create conflicting dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    fs::write(
+        &config_path,
+        format!(
+            "link = {} {} on_conflict=backup\nlink = {} {}\n",
+            source_file.display(),
+            regenerated.display(),
+            source_file.display(),
+            other.display()
+        ),
+    )
+    .expect("write config");
+
+    // Stdin is closed, so any prompt would abort the sync
+    let status = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["sync", "--on-conflict", "skip", "--non-interactive"])
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .status()
+        .expect("run dbdm sync");
+    assert!(status.success());
+
+    assert_eq!(fs::read_link(&regenerated).expect("read link"), source_file);
+    assert_eq!(
+        fs::read_to_string(workspace.path().join("regenerated.txt.bak.dbdm")).expect("read backup"),
+        "regenerated"
+    );
+    assert_eq!(fs::read_to_string(&other).expect("read other"), "other");
+}
+
+#[test]
+fn done_event_counts_backed_up_bytes() {
+    let workspace = tempdir().expect("create temp workspace");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let dest_file = workspace.path().join("linked.txt");
+    fs::write(&dest_file, "conflict").expect("create conflicting dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    fs::write(
+        &config_path,
+        format!("link = {} {}\n", source_file.display(), dest_file.display()),
+    )
+    .expect("write config");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["sync", "--on-conflict", "backup", "--format", "ndjson"])
+        .current_dir(workspace.path())
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .stdin(std::process::Stdio::null())
+        .stderr(std::process::Stdio::null())
+        .output()
+        .expect("run dbdm sync");
+    assert!(output.status.success());
+
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    let done: serde_json::Value = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).expect("every line is json"))
+        .find(|event| event["event"] == "done")
+        .expect("done event");
+    assert_eq!(done["counts"]["backup_replace"], 1);
+    assert_eq!(done["bytes_backed_up"], "conflict".len());
+    assert_eq!(done["prompts_answered"], 0);
+}
+
+#[test]
+fn encrypted_links_are_decrypted_and_refreshed() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let workspace = tempdir().expect("create temp workspace");
+
+    // A stand-in for age that "decrypts" by printing the file it is given
+    let bin_dir = workspace.path().join("bin");
+    fs::create_dir(&bin_dir).expect("create bin dir");
+    let age_path = bin_dir.join("age");
+    fs::write(
+        &age_path,
+        "#!/bin/sh\nfor last; do :; done\ncat \"$last\"\n",
+    )
+    .expect("write fake age");
+    fs::set_permissions(&age_path, fs::Permissions::from_mode(0o755)).expect("chmod fake age");
+
+    let source_file = workspace.path().join("token.age");
+    fs::write(&source_file, "secret").expect("write source file");
+    let dest_file = workspace.path().join("token");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "encrypted = {} {}\n[secrets]\nidentity = key.txt\n",
+        source_file.display(),
+        dest_file.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let path = format!(
+        "{}:{}",
+        bin_dir.display(),
+        std::env::var("PATH").unwrap_or_default()
+    );
+    let state_home = workspace.path().join("state");
+    let run = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+            .args(args)
+            .env("PATH", &path)
+            .env("XDG_STATE_HOME", &state_home)
+            .current_dir(workspace.path())
+            .stdin(std::process::Stdio::null())
+            .output()
+            .expect("run dbdm")
+    };
+
+    assert!(run(&["sync", "--non-interactive"]).status.success());
+    assert_eq!(fs::read_to_string(&dest_file).expect("read dest"), "secret");
+    let mode = fs::metadata(&dest_file)
+        .expect("stat dest")
+        .permissions()
+        .mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    // A changed source shows up as stale and is decrypted again
+    fs::write(&source_file, "rotated").expect("rewrite source file");
+    let check = String::from_utf8_lossy(&run(&["check"]).stdout).to_string();
+    assert!(check.contains("(stale)"));
+    assert!(run(&["sync", "--non-interactive"]).status.success());
+    assert_eq!(
+        fs::read_to_string(&dest_file).expect("read dest"),
+        "rotated"
+    );
+    assert!(!String::from_utf8_lossy(&run(&["check"]).stdout).contains("(stale)"));
+}
+
+#[test]
+fn flags_can_be_set_through_the_environment() {
+    let workspace = tempdir().expect("create temp workspace");
+    let dotfiles = workspace.path().join("dotfiles");
+    fs::create_dir(&dotfiles).expect("create dotfiles");
+    fs::write(dotfiles.join("zshrc"), "# zsh").expect("write source file");
+    let dest_file = workspace.path().join(".zshrc");
+    fs::write(&dest_file, "# old zsh").expect("write dest file");
+    fs::write(
+        dotfiles.join("dbdm.conf"),
+        format!("link = !here/zshrc {}\n", dest_file.display()),
+    )
+    .expect("write config");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .env("DBDM_CONFIG", dotfiles.join("dbdm.conf"))
+        .env("DBDM_NON_INTERACTIVE", "1")
+        .env("DBDM_ON_CONFLICT", "backup")
+        .env("DBDM_COLOR", "never")
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("run dbdm sync");
+
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_link(&dest_file).expect("read link"),
+        dotfiles.join("zshrc")
+    );
+    assert!(dotfiles.join(".zshrc.bak.dbdm").exists());
+
+    // The command line wins over the environment
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["check", "--color", "purple"])
+        .env("DBDM_COLOR", "never")
+        .current_dir(&dotfiles)
+        .output()
+        .expect("run dbdm check");
+    assert_eq!(output.status.code(), Some(64));
+}
+
+#[test]
+fn summary_and_quiet_leave_out_the_listings() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+
+    let run = |flag: &str, dest: &str| {
+        let config_contents = format!(
+            "link = {} {}\n",
+            source_file.display(),
+            workspace.path().join(dest).display()
+        );
+        fs::write(workspace.path().join("dbdm.conf"), config_contents).expect("write config");
+        let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+            .arg("sync")
+            .arg(flag)
+            .env("NO_COLOR", "1")
+            .env("XDG_STATE_HOME", workspace.path().join("state"))
+            .current_dir(workspace.path())
+            .stdin(std::process::Stdio::null())
+            .output()
+            .expect("run dbdm sync");
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).expect("utf8 stdout")
+    };
+
+    let summary = run("--summary", "summary.txt");
+    let lines: Vec<&str> = summary.lines().collect();
+    assert_eq!(lines.len(), 2, "{}", summary);
+    assert_eq!(lines[0], "1 items: 1 create");
+    assert!(lines[1].starts_with("Done in"));
+
+    assert_eq!(run("--quiet", "quiet.txt"), "");
+    assert!(
+        fs::symlink_metadata(workspace.path().join("quiet.txt"))
+            .expect("stat quiet link")
+            .file_type()
+            .is_symlink()
+    );
+}
+
+#[test]
+fn ndjson_events_carry_a_reason_code() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let blocker = workspace.path().join("blocker");
+    fs::write(&blocker, "not a directory").expect("write blocking file");
+    let other_file = workspace.path().join("other.txt");
+    fs::write(&other_file, "other").expect("write other file");
+    let retargeted = workspace.path().join("retargeted.txt");
+    std::os::unix::fs::symlink(&other_file, &retargeted).expect("create link");
+
+    let config_contents = format!(
+        "link = {} {}\nlink = {} {}\n",
+        source_file.display(),
+        blocker.join("linked.txt").display(),
+        source_file.display(),
+        retargeted.display()
+    );
+    fs::write(workspace.path().join("dbdm.conf"), config_contents).expect("write config");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["sync", "--non-interactive", "--format", "ndjson"])
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .output()
+        .expect("run dbdm sync");
+    assert_eq!(output.status.code(), Some(1));
+
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    let executed: Vec<serde_json::Value> = stdout
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).expect("parse event"))
+        .filter(|event| event["event"] == "executed")
+        .collect();
+    assert_eq!(executed.len(), 2);
+    // A path under a file isn't missing, nothing can be created there
+    assert_eq!(executed[0]["action"], "skip");
+    assert_eq!(executed[0]["reason_code"], "unresolvable");
+    assert_eq!(executed[1]["reason_code"], "linked_elsewhere");
+    assert_eq!(
+        executed[1]["reason"],
+        format!("was {}", other_file.display())
+    );
+}
+
+#[test]
+fn backups_go_to_the_state_directory_with_location_state() {
+    let workspace = tempdir().expect("create temp workspace");
+    let state = workspace.path().join("state");
+
+    let source_file = workspace.path().join("source.txt");
+    fs::write(&source_file, "example").expect("write source file");
+    let dest_file = workspace.path().join("linked.txt");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {}\n[backups]\nlocation = state\n",
+        source_file.display(),
+        dest_file.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let run = |args: &[&str]| {
+        std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+            .args(args)
+            .arg("--non-interactive")
+            .env("XDG_STATE_HOME", &state)
+            .current_dir(workspace.path())
+            .stdin(std::process::Stdio::null())
+            .output()
+            .expect("run dbdm")
+    };
+
+    // The directory of the destination is mirrored in the backup directory
+    let backup_dir = workspace
+        .path()
+        .components()
+        .filter_map(|component| match component {
+            std::path::Component::Normal(name) => Some(name),
+            _ => None,
+        })
+        .fold(state.join("dbdm/backups"), |dir, name| dir.join(name));
+    for contents in ["first", "second"] {
+        let _ = fs::remove_file(&dest_file);
+        fs::write(&dest_file, contents).expect("create conflicting dest file");
+        let output = run(&["sync", "--on-conflict", "backup"]);
+        assert!(
+            output.status.success(),
+            "{}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    assert_eq!(fs::read_link(&dest_file).expect("read link"), source_file);
+    assert!(!workspace.path().join("linked.txt.bak.dbdm").exists());
+    assert_eq!(
+        fs::read_to_string(backup_dir.join("linked.txt.bak.dbdm")).expect("read backup"),
+        "first"
+    );
+    assert_eq!(
+        fs::read_to_string(backup_dir.join("linked.txt.bak.dbdm.1")).expect("read backup"),
+        "second"
+    );
+
+    // clean finds them there
+    let output = run(&["clean", "--keep", "1"]);
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    assert!(!backup_dir.join("linked.txt.bak.dbdm").exists());
+    assert!(backup_dir.join("linked.txt.bak.dbdm.1").exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn sync_never_replaces_the_source_with_itself() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_file = workspace.path().join("data.txt");
+    fs::write(&source_file, "data").expect("write source file");
+    let source_dir = workspace.path().join("config/nvim");
+    fs::create_dir_all(&source_dir).expect("create source dir");
+    fs::write(source_dir.join("init.lua"), "-- init").expect("write nested file");
+    // The destination directory is a link into the repository
+    std::os::unix::fs::symlink(
+        workspace.path().join("config"),
+        workspace.path().join("dot-config"),
+    )
+    .expect("link dest root");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "link = ./data.txt ./data.txt\nlink = {} {}\n",
+        source_dir.display(),
+        workspace.path().join("dot-config/nvim").display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["sync", "--non-interactive", "--color", "never"])
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("run dbdm sync");
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    assert!(stdout.contains("destination is the source"), "{}", stdout);
+
+    assert_eq!(
+        fs::read_to_string(&source_file).expect("read source file"),
+        "data"
+    );
+    assert_eq!(
+        fs::read_to_string(source_dir.join("init.lua")).expect("read nested file"),
+        "-- init"
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn typed_backup_locations_are_expanded_and_checked() {
+    let workspace = tempdir().expect("create temp workspace");
+    let home = workspace.path().join("home");
+    fs::create_dir(&home).expect("create home");
+    let source_dir = workspace.path().join("nvim");
+    fs::create_dir(&source_dir).expect("create source dir");
+    fs::write(source_dir.join("init.lua"), "-- init").expect("write source file");
+    let dest_dir = workspace.path().join("config-nvim");
+    fs::create_dir(&dest_dir).expect("create dest dir");
+    fs::write(dest_dir.join("init.lua"), "-- local").expect("write dest file");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!("link = {} {}\n", source_dir.display(), dest_dir.display());
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["sync", "--color", "never"])
+        .env("HOME", &home)
+        .env("XDG_STATE_HOME", workspace.path().join("state"))
+        .current_dir(workspace.path())
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn dbdm sync");
+
+    {
+        // A relative path is asked for again, a directory inside the source is
+        // refused, and `~` is the home directory
+        let stdin = child.stdin.as_mut().expect("open stdin");
+        let answers = format!(
+            "e\nl\nbk\n{}\nl\n~/bk\ny\n",
+            source_dir.join("bk").display()
+        );
+        std::io::Write::write_all(stdin, answers.as_bytes()).expect("edit backup location");
+    }
+
+    let output = child.wait_with_output().expect("wait for dbdm sync");
+    let stdout = String::from_utf8(output.stdout).expect("utf8 stdout");
+    assert!(output.status.success(), "{}", stdout);
+    assert!(stdout.contains("bk is relative"), "{}", stdout);
+    assert!(
+        stdout.contains("directory is inside the source"),
+        "{}",
+        stdout
+    );
+
+    assert_eq!(fs::read_link(&dest_dir).expect("read link"), source_dir);
+    assert_eq!(
+        fs::read_to_string(home.join("bk/config-nvim.bak.dbdm/init.lua")).expect("read backup"),
+        "-- local"
+    );
+    assert!(!workspace.path().join("~").exists());
+    assert!(!workspace.path().join("bk").exists());
+    assert!(!source_dir.join("bk").exists());
+}
+
+// Runs sync over a single conflict at `dest` and quits at its prompt
+//
+// @param workspace: &std::path::Path - the directory the config is written to
+// @param dest: &std::path::Path - the conflicting destination, already in place
+// @param args: &[&str] - extra flags for sync
+// @return String - what sync printed, the preview of the destination included
+fn conflict_preview(workspace: &std::path::Path, dest: &std::path::Path, args: &[&str]) -> String {
+    // A directory is only a conflict for a directory, for a file it's where the link goes
+    let source = workspace.join("source");
+    if dest.is_dir() {
+        fs::create_dir_all(&source).expect("create source dir");
+    } else {
+        fs::write(&source, "example").expect("write source file");
+    }
+    fs::write(
+        workspace.join("dbdm.conf"),
+        format!("link = {} {}\n", source.display(), dest.display()),
+    )
+    .expect("write config");
+
+    let mut child = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .arg("sync")
+        .args(args)
+        .env("NO_COLOR", "1")
+        .env("XDG_STATE_HOME", workspace.join("state"))
+        .current_dir(workspace)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .stderr(std::process::Stdio::piped())
+        .spawn()
+        .expect("spawn dbdm sync");
+    std::io::Write::write_all(child.stdin.as_mut().expect("open stdin"), b"q\n")
+        .expect("select quit option");
+    let output = child.wait_with_output().expect("wait for dbdm sync");
+    assert_eq!(output.status.code(), Some(2));
+    String::from_utf8(output.stdout).expect("utf8 stdout")
+}
+
+#[test]
+fn file_previews_are_cut_after_preview_lines() {
+    let workspace = tempdir().expect("create temp workspace");
+    let dest = workspace.path().join("notes.txt");
+    fs::write(&dest, "one\r\ntwo\r\nthree\r\nfour\n").expect("write conflicting file");
+
+    // Windows line endings are trimmed along with the newline
+    let stdout = conflict_preview(workspace.path(), &dest, &["--preview-lines", "2"]);
+    assert!(
+        stdout.contains("\none\ntwo\n… truncated after 2 lines\n"),
+        "{}",
+        stdout
+    );
+    assert!(!stdout.contains('\r'));
+    assert!(!stdout.contains("three"));
+
+    // A file that fits isn't marked as truncated
+    let stdout = conflict_preview(workspace.path(), &dest, &["--preview-lines", "4"]);
+    assert!(stdout.contains("\none\ntwo\nthree\nfour\n"), "{}", stdout);
+    assert!(!stdout.contains("truncated"));
+}
+
+#[test]
+fn file_previews_detect_binary_content() {
+    let workspace = tempdir().expect("create temp workspace");
+    let dest = workspace.path().join("data");
+
+    fs::write(&dest, b"text\0more text\n").expect("write file with NUL");
+    let stdout = conflict_preview(workspace.path(), &dest, &[]);
+    assert!(stdout.contains("BINARY FILE"), "{}", stdout);
+
+    fs::write(&dest, b"text \xff more text\n").expect("write invalid UTF-8");
+    let stdout = conflict_preview(workspace.path(), &dest, &[]);
+    assert!(stdout.contains("BINARY FILE"), "{}", stdout);
+
+    // An `é` split by the end of the 8 KiB sample is still text
+    let mut content = vec![b'a'; 8 * 1024 - 1];
+    content.extend_from_slice("é\n".as_bytes());
+    fs::write(&dest, &content).expect("write file with split character");
+    let stdout = conflict_preview(workspace.path(), &dest, &[]);
+    assert!(!stdout.contains("BINARY FILE"), "{}", stdout);
+    assert!(stdout.contains("aé\n"), "{}", stdout);
+}
+
+#[test]
+fn directory_previews_are_cut_at_depth_and_width() {
+    let workspace = tempdir().expect("create temp workspace");
+    let dest = workspace.path().join("config");
+    fs::create_dir_all(dest.join("a/b/c")).expect("create nested dirs");
+    fs::write(dest.join("a/b/c/deep.txt"), "deep").expect("write deep file");
+    fs::create_dir(dest.join("many")).expect("create wide dir");
+    for idx in 0..25 {
+        fs::write(dest.join(format!("many/f{:02}", idx)), "x").expect("write file");
+    }
+    fs::write(dest.join("top.txt"), "hello").expect("write top file");
+    // Left behind by an older version, not part of the contents
+    fs::write(dest.join("old.bak.dbdm"), vec![b'x'; 1000]).expect("write backup");
+
+    let stdout = conflict_preview(workspace.path(), &dest, &[]);
+    let mut expected = format!(
+        "DIRECTORY: {} (27 files, 4 dirs, 34 B)\n\
+         ├── a/ (1 files, 2 dirs, 4 B)\n\
+         │   └── b/ (1 files, 1 dirs, 4 B)\n\
+         ├── many/ (25 files, 0 dirs, 25 B)\n",
+        dest.display()
+    );
+    for idx in 0..20 {
+        expected.push_str(&format!("│   ├── f{:02} (1 B)\n", idx));
+    }
+    expected.push_str("│   └── … 5 more\n└── top.txt (5 B)\n");
+    assert!(stdout.contains(&expected), "{}", stdout);
+    assert!(!stdout.contains("deep.txt"));
+    assert!(!stdout.contains("bak.dbdm"));
+}
@@ -1,17 +1,71 @@
-use dbdm::unique_backup_path;
+use dbdm::{BackupNaming, BackupNumbering, is_backup_name, unique_backup_path};
+use std::ffi::OsStr;
 
 #[test]
 fn increments_backup_suffix_when_conflict_exists() {
     let temp = tempfile::tempdir().expect("tempdir should be created");
     let dir = temp.path();
+    let naming = BackupNaming::default();
 
     let base_path = dir.join("nvim.bak.dbdm");
     std::fs::write(&base_path, "existing").expect("write should succeed");
 
-    let candidate = unique_backup_path(dir, "nvim");
+    let candidate = unique_backup_path(dir, "nvim", &naming);
     assert_eq!(candidate, dir.join("nvim.bak.dbdm.1"));
 
     std::fs::write(&candidate, "existing").expect("write should succeed");
-    let next_candidate = unique_backup_path(dir, "nvim");
+    let next_candidate = unique_backup_path(dir, "nvim", &naming);
     assert_eq!(next_candidate, dir.join("nvim.bak.dbdm.2"));
 }
+
+#[test]
+fn recognizes_backup_names() {
+    let naming = BackupNaming::default();
+    assert!(is_backup_name(OsStr::new("nvim.bak.dbdm"), &naming));
+    assert!(is_backup_name(OsStr::new("nvim.bak.dbdm.12"), &naming));
+    assert!(!is_backup_name(OsStr::new("nvim"), &naming));
+    assert!(!is_backup_name(OsStr::new("nvim.bak.dbdm.old"), &naming));
+}
+
+#[test]
+fn uses_custom_suffix_and_timestamp() {
+    let temp = tempfile::tempdir().expect("tempdir should be created");
+    let dir = temp.path();
+    let naming = BackupNaming {
+        suffix: String::from(".bak"),
+        numbering: BackupNumbering::Timestamp,
+    };
+
+    let candidate = unique_backup_path(dir, "nvim", &naming);
+    let name = candidate
+        .file_name()
+        .expect("file name")
+        .to_string_lossy()
+        .to_string();
+    let stamp = name
+        .strip_prefix("nvim.")
+        .and_then(|rest| rest.strip_suffix(".bak"))
+        .expect("timestamp between name and suffix");
+    assert_eq!(stamp.len(), 13);
+    assert_eq!(&stamp[8..9], "T");
+    assert!(is_backup_name(OsStr::new(&name), &naming));
+
+    std::fs::write(&candidate, "existing").expect("write should succeed");
+    let next_candidate = unique_backup_path(dir, "nvim", &naming);
+    assert_ne!(next_candidate, candidate);
+    assert!(!next_candidate.exists());
+}
+
+#[cfg(unix)]
+#[test]
+fn backup_names_keep_bytes_that_are_not_utf8() {
+    use std::os::unix::ffi::OsStrExt;
+
+    let temp = tempfile::tempdir().expect("tempdir should be created");
+    let naming = BackupNaming::default();
+
+    let candidate = unique_backup_path(temp.path(), OsStr::from_bytes(b"caf\xe9"), &naming);
+    let name = candidate.file_name().expect("file name");
+    assert_eq!(name, OsStr::from_bytes(b"caf\xe9.bak.dbdm"));
+    assert!(is_backup_name(name, &naming));
+}
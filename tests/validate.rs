@@ -0,0 +1,144 @@
+use dbdm::validate::{IssueKind, validate};
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn reports_every_parse_error_in_one_go() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let db_dir = root_dir.join("db");
+    let notes_dir = root_dir.join("notes");
+
+    fs::create_dir_all(&db_dir).expect("create db dir");
+    fs::create_dir_all(&notes_dir).expect("create notes dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "lonk = {} {}\nlink = {} {}\nlink = {}\n",
+        db_dir.display(),
+        notes_dir.display(),
+        db_dir.display(),
+        notes_dir.display(),
+        db_dir.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let issues = validate(&config_path, true).expect("validate config");
+
+    let lines: Vec<usize> = issues.iter().map(|issue| issue.line).collect();
+    assert_eq!(lines, vec![1, 3]);
+    assert!(issues.iter().all(|issue| issue.kind == IssueKind::Parse));
+}
+
+#[test]
+fn reports_duplicate_destinations() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let db_dir = root_dir.join("db");
+    let notes_dir = root_dir.join("notes");
+    let dest = root_dir.join("dest");
+
+    fs::create_dir_all(&db_dir).expect("create db dir");
+    fs::create_dir_all(&notes_dir).expect("create notes dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {}\nlink = {} {}\n",
+        db_dir.display(),
+        dest.display(),
+        notes_dir.display(),
+        dest.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let issues = validate(&config_path, false).expect("validate config");
+
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].line, 2);
+    assert_eq!(issues[0].kind, IssueKind::Duplicate);
+    // Lines are counted from 1, so editors jump to the right one
+    assert_eq!(
+        issues[0].message,
+        format!(
+            "Destination {} on line 2 is already declared at {}:1",
+            dest.display(),
+            config_path.display()
+        )
+    );
+}
+
+#[test]
+fn reports_cycles_between_links() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let db_dir = root_dir.join("db");
+    let notes_dir = root_dir.join("notes");
+
+    fs::create_dir_all(&db_dir).expect("create db dir");
+    fs::create_dir_all(&notes_dir).expect("create notes dir");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {}\nlink = {} {}\n",
+        db_dir.display(),
+        notes_dir.display(),
+        notes_dir.display(),
+        db_dir.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let issues = validate(&config_path, false).expect("validate config");
+
+    let kinds: Vec<IssueKind> = issues.iter().map(|issue| issue.kind).collect();
+    assert_eq!(kinds, vec![IssueKind::Cycle, IssueKind::Cycle]);
+}
+
+#[test]
+fn reports_links_after_unknown_destinations() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    let source = root_dir.join("zshrc");
+
+    fs::create_dir_all(&root_dir).expect("create root dir");
+    fs::write(&source, "# zsh").expect("write source");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "link = {} {} after={}\n",
+        source.display(),
+        root_dir.join(".zshrc").display(),
+        root_dir.join(".zshenv").display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let issues = validate(&config_path, false).expect("validate config");
+
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].kind, IssueKind::Order);
+    assert_eq!(issues[0].line, 1);
+}
+
+#[test]
+fn reports_sources_both_decrypted_and_linked() {
+    let tmp = tempdir().expect("tempdir");
+    let root_dir = tmp.path().join("root");
+    fs::create_dir_all(&root_dir).expect("create root dir");
+    let secret = root_dir.join("token.age");
+    fs::write(&secret, "encrypted").expect("write secret");
+
+    let config_path = root_dir.join("dbdm.conf");
+    let config_contents = format!(
+        "link = {0} {1}\nsystem = {0} {2}\nencrypted = {0} {3}\n",
+        secret.display(),
+        root_dir.join("a").display(),
+        root_dir.join("b").display(),
+        root_dir.join("c").display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let issues = validate(&config_path, false).expect("validate config");
+
+    assert_eq!(issues.len(), 1);
+    assert_eq!(issues[0].line, 3);
+    assert_eq!(issues[0].kind, IssueKind::MixedKinds);
+}
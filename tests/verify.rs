@@ -0,0 +1,61 @@
+use dbdm::verify::verify_link;
+use std::fs;
+use tempfile::tempdir;
+
+#[test]
+fn accepts_linked_directory_tree() {
+    let tmp = tempdir().expect("tempdir");
+    let source = tmp.path().join("nvim");
+    let dest = tmp.path().join("config");
+    fs::create_dir_all(source.join("lua")).expect("create source tree");
+    fs::write(source.join("lua/init.lua"), "config").expect("write source file");
+    std::os::unix::fs::symlink(&source, &dest).expect("create link");
+
+    assert!(verify_link(&source, &dest).is_empty());
+}
+
+#[test]
+fn reports_broken_links_inside_the_tree() {
+    let tmp = tempdir().expect("tempdir");
+    let source = tmp.path().join("nvim");
+    let dest = tmp.path().join("config");
+    fs::create_dir_all(source.join("lua")).expect("create source tree");
+    std::os::unix::fs::symlink(tmp.path().join("gone.lua"), source.join("lua/plugins.lua"))
+        .expect("create broken link");
+    std::os::unix::fs::symlink(&source, &dest).expect("create link");
+
+    let problems = verify_link(&source, &dest);
+
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].path, dest.join("lua/plugins.lua"));
+}
+
+#[test]
+fn reports_copies_that_differ() {
+    let tmp = tempdir().expect("tempdir");
+    let source = tmp.path().join("nvim");
+    let dest = tmp.path().join("config");
+    fs::create_dir_all(&source).expect("create source dir");
+    fs::create_dir_all(&dest).expect("create dest dir");
+    fs::write(source.join("init.lua"), "new").expect("write source file");
+    fs::write(dest.join("init.lua"), "old").expect("write dest file");
+
+    let problems = verify_link(&source, &dest);
+
+    assert_eq!(problems.len(), 1);
+    assert_eq!(problems[0].path, dest);
+}
+
+#[test]
+fn ignored_paths_are_not_verified() {
+    let tmp = tempdir().expect("tempdir");
+    let source = tmp.path().join("nvim");
+    let dest = tmp.path().join("config");
+    fs::create_dir_all(source.join("lua")).expect("create source tree");
+    fs::write(source.join(".dbdmignore"), "*.swp\n").expect("write ignore file");
+    std::os::unix::fs::symlink(tmp.path().join("gone"), source.join("lua/init.lua.swp"))
+        .expect("create broken link");
+    std::os::unix::fs::symlink(&source, &dest).expect("create link");
+
+    assert!(verify_link(&source, &dest).is_empty());
+}
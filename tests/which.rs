@@ -0,0 +1,68 @@
+use std::fs;
+use tempfile::tempdir;
+
+// Helper to run `dbdm which` from the workspace
+fn which(workspace: &std::path::Path, path: &str) -> (Option<i32>, String) {
+    let output = std::process::Command::new(env!("CARGO_BIN_EXE_dbdm"))
+        .args(["which", path, "--non-interactive", "--color", "never"])
+        .env("XDG_STATE_HOME", workspace.join("state"))
+        .current_dir(workspace)
+        .stdin(std::process::Stdio::null())
+        .output()
+        .expect("run dbdm which");
+    (
+        output.status.code(),
+        String::from_utf8(output.stdout).expect("utf8 stdout"),
+    )
+}
+
+#[test]
+fn which_tells_where_a_managed_path_comes_from() {
+    let workspace = tempdir().expect("create temp workspace");
+    let source_dir = workspace.path().join("nvim");
+    fs::create_dir_all(source_dir.join("lua")).expect("create source dir");
+    fs::write(source_dir.join("lua/init.lua"), "-- init").expect("write source file");
+    let dest_dir = workspace.path().join("config-nvim");
+
+    let config_path = workspace.path().join("dbdm.conf");
+    let config_contents = format!(
+        "[module editor]\nlink = {} {}\n",
+        source_dir.display(),
+        dest_dir.display()
+    );
+    fs::write(&config_path, config_contents).expect("write config");
+
+    let (code, stdout) = which(workspace.path(), "config-nvim/lua/init.lua");
+    assert_eq!(code, Some(0), "{}", stdout);
+    assert!(stdout.contains("is managed by dbdm"), "{}", stdout);
+    assert!(
+        stdout.contains(&format!(
+            "link = {} {}",
+            source_dir.display(),
+            dest_dir.display()
+        )),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("module  editor"), "{}", stdout);
+    assert!(
+        stdout.contains(&format!(
+            "source  {}\n",
+            source_dir.join("lua/init.lua").display()
+        )),
+        "{}",
+        stdout
+    );
+    assert!(stdout.contains("state   not synced yet"), "{}", stdout);
+
+    #[cfg(unix)]
+    {
+        std::os::unix::fs::symlink(&source_dir, &dest_dir).expect("link source");
+        let (_, stdout) = which(workspace.path(), "config-nvim/lua/init.lua");
+        assert!(stdout.contains("state   linked"), "{}", stdout);
+    }
+
+    let (code, stdout) = which(workspace.path(), "dbdm.conf");
+    assert_eq!(code, Some(1));
+    assert!(stdout.contains("is not managed by dbdm"), "{}", stdout);
+}